@@ -0,0 +1,12 @@
+use crossbeam::channel::Receiver;
+use liketrain_core::ui::{UiCommand, UiEvent};
+
+/// A way to reach the controller from outside of its process, e.g. over the network.
+/// Every frontend gets all ui events and hands the commands of its clients to the controller.
+pub trait Frontend: Send + Sync {
+    /// Tell the clients about an event of the controller, if it concerns them.
+    fn broadcast(&self, event: &UiEvent);
+
+    /// The commands sent by the clients, to be forwarded to the controller.
+    fn commands(&self) -> &Receiver<UiCommand>;
+}
@@ -0,0 +1,298 @@
+use std::{
+    fs::{DirBuilder, Permissions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use liketrain_core::ui::{UiCommand, UiControllerEvent, UiEvent};
+use serde::Serialize;
+
+use crate::{
+    ClientMessage, Envelope, Frontend, PROTOCOL_VERSION, ServerMessage,
+    session::{ApiClients, ApiSession, SessionAccess},
+};
+
+/// Serves the controller to a ui running in another process, over a Unix socket.
+/// It speaks the protocol of the WebSocket API, one JSON message per line.
+/// Unlike clients on the network, the ui may override the interlocking.
+pub struct IpcServer {
+    path: PathBuf,
+    clients: Arc<ApiClients>,
+    command_rx: Receiver<UiCommand>,
+}
+
+impl IpcServer {
+    /// How long a client waits for a message, before it sends the messages queued for it.
+    const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+    /// Start listening for uis in the background. A socket left behind by a previous run is replaced,
+    /// but not one another controller is still listening on, or a file that isn't a socket.
+    /// Only the owner may connect, because the ui may override the interlocking.
+    pub fn bind(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and isn't a socket", path.display()),
+                ));
+            }
+
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("another controller is listening on {}", path.display()),
+                ));
+            }
+
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = bind_private(&path)?;
+
+        let clients = Arc::new(ApiClients::default());
+        let (command_tx, command_rx) = crossbeam::channel::unbounded();
+
+        let accept_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("failed to accept a ui: {}", err);
+                        continue;
+                    }
+                };
+
+                let clients = accept_clients.clone();
+                let command_tx = command_tx.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve_client(stream, &clients, command_tx) {
+                        log::warn!("ui connection failed: {}", err);
+                    }
+                });
+            }
+        });
+
+        log::info!("ipc listening on {}", path.display());
+
+        Ok(Self {
+            path,
+            clients,
+            command_rx,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Bind the socket in a directory only the owner can enter and move it into place,
+/// so nobody else can connect before its permissions are restricted.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let Some(file_name) = path.file_name() else {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} isn't a file name", path.display()),
+        ));
+    };
+
+    let dir = path.with_file_name(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let bound = dir.join("s");
+    let result = UnixListener::bind(&bound).and_then(|listener| {
+        std::fs::set_permissions(&bound, Permissions::from_mode(0o600))?;
+        std::fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+
+    let _ = std::fs::remove_file(&bound);
+    let _ = std::fs::remove_dir(&dir);
+
+    result
+}
+
+impl Frontend for IpcServer {
+    fn broadcast(&self, event: &UiEvent) {
+        self.clients.broadcast(event);
+    }
+
+    fn commands(&self) -> &Receiver<UiCommand> {
+        &self.command_rx
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn serve_client(
+    stream: UnixStream,
+    clients: &ApiClients,
+    command_tx: Sender<UiCommand>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(IpcServer::POLL_INTERVAL))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    log::info!("ui connected");
+
+    write_message(&mut writer, &ServerMessage::Hello)?;
+    let session = ApiSession::new(clients, command_tx, SessionAccess::Override);
+
+    let mut line = Vec::new();
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                let message = String::from_utf8_lossy(&line).into_owned();
+                line.clear();
+
+                if let Err(message) = session.handle_message(&message) {
+                    write_message(&mut writer, &ServerMessage::Error { message })?;
+                }
+            }
+            // a partial line stays in the buffer, until the rest arrives
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err),
+        }
+
+        for message in session.outgoing() {
+            write_message(&mut writer, &message)?;
+        }
+    }
+
+    log::info!("ui disconnected");
+
+    Ok(())
+}
+
+/// A ui's connection to a controller running in another process, served by an `IpcServer`.
+pub struct IpcClient {
+    path: PathBuf,
+    connected: Arc<AtomicBool>,
+}
+
+impl IpcClient {
+    /// Connect to the controller. Like `Controller::new`, the events of the controller are sent to
+    /// `event_tx` and the commands received on `command_rx` are executed. The current state of the
+    /// controller is sent first, as `UiControllerEvent::Snapshot`, the events before it are dropped.
+    ///
+    /// The connection is closed, once `command_rx` disconnects.
+    pub fn connect(
+        path: impl Into<PathBuf>,
+        event_tx: mpsc::Sender<UiEvent>,
+        command_rx: Receiver<UiCommand>,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let stream = UnixStream::connect(&path)?;
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let reader = BufReader::new(stream.try_clone()?);
+        let reader_connected = connected.clone();
+        thread::spawn(move || {
+            receive_events(reader, &event_tx);
+
+            log::warn!("lost the connection to the controller");
+            reader_connected.store(false, Ordering::Relaxed);
+        });
+
+        thread::spawn(move || {
+            let mut writer = stream;
+            for command in command_rx {
+                if let Err(err) = write_message(&mut writer, &ClientMessage::Command { command }) {
+                    log::error!("failed to send a command to the controller: {}", err);
+                    break;
+                }
+            }
+
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+
+        log::info!("connected to the controller at {}", path.display());
+
+        Ok(Self { path, connected })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the controller is still reachable.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+/// Forward the events of the controller, until the connection or the ui is closed.
+fn receive_events(reader: impl BufRead, event_tx: &mpsc::Sender<UiEvent>) {
+    let mut synced = false;
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            return;
+        };
+
+        let envelope: Envelope<ServerMessage> = match serde_json::from_str(&line) {
+            Ok(envelope) => envelope,
+            Err(err) => {
+                log::error!("invalid message from the controller: {}", err);
+                continue;
+            }
+        };
+
+        if envelope.version != PROTOCOL_VERSION {
+            log::error!(
+                "the controller speaks protocol version {}, the ui speaks version {}",
+                envelope.version,
+                PROTOCOL_VERSION
+            );
+            return;
+        }
+
+        let event = match envelope.message {
+            ServerMessage::Hello => continue,
+            // the snapshot already contains what happened before it was taken
+            ServerMessage::Event { .. } if !synced => continue,
+            ServerMessage::Event { event } => event,
+            ServerMessage::Snapshot { snapshot } => {
+                synced = true;
+                UiControllerEvent::Snapshot(snapshot).into()
+            }
+            ServerMessage::Error { message } => {
+                log::warn!("the controller rejected a message: {}", message);
+                continue;
+            }
+        };
+
+        if event_tx.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, &Envelope::new(message))?;
+    writeln!(writer)
+}
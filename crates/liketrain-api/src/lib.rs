@@ -0,0 +1,22 @@
+//! Access to the controller from outside of its process. The WebSocket API streams every ui event to its
+//! clients as JSON and accepts ui commands, the IPC socket does the same for a ui running in another
+//! process, and the WiThrottle server lets phones drive the trains and set the switches.
+
+mod protocol;
+pub use protocol::*;
+
+mod frontend;
+pub use frontend::*;
+
+mod session;
+
+mod server;
+pub use server::*;
+
+#[cfg(unix)]
+mod ipc;
+#[cfg(unix)]
+pub use ipc::*;
+
+mod withrottle;
+pub use withrottle::*;
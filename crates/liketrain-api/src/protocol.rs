@@ -0,0 +1,60 @@
+use liketrain_core::ui::{UiCommand, UiEvent, UiSnapshot};
+use serde::{Deserialize, Serialize};
+
+/// Changes, whenever a message changes in a way older clients don't understand.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Every message carries the version of the protocol it was written for.
+///
+/// ```json
+/// { "version": 1, "type": "command", "command": "EmergencyStop" }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub version: u32,
+
+    #[serde(flatten)]
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(message: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message,
+        }
+    }
+}
+
+/// A message from the server to a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent right after connecting, followed by a snapshot once the client sent the token, if the
+    /// server has one.
+    Hello,
+
+    /// An event of the controller, sent to every client.
+    Event { event: UiEvent },
+
+    /// The current state of the controller, sent to the client that asked for it.
+    Snapshot { snapshot: Box<UiSnapshot> },
+
+    /// The last message of the client was not accepted.
+    Error { message: String },
+}
+
+/// A message from a client to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Authenticate with the token of the server, if it has one. Until then, nothing else is
+    /// accepted.
+    Hello { token: String },
+
+    /// Send a command to the controller. Overriding the interlocking is only possible from the ui.
+    Command { command: UiCommand },
+
+    /// Ask for the current state of the controller.
+    Snapshot,
+}
@@ -0,0 +1,225 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use liketrain_core::ui::{UiCommand, UiEvent};
+use subtle::ConstantTimeEq;
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    ClientMessage, Envelope, Frontend, ServerMessage,
+    session::{ApiClients, ApiSession, SessionAccess},
+};
+
+/// Who may do what over the WebSocket API.
+#[derive(Clone, Default)]
+pub struct ApiAccess {
+    /// The token clients have to send in their `hello`, before they get any event.
+    /// Without it, every client that can connect is accepted.
+    pub token: Option<String>,
+
+    /// Clients can only watch the controller and ask for snapshots, their commands are rejected.
+    pub read_only: bool,
+}
+
+// the token is not logged
+impl std::fmt::Debug for ApiAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiAccess")
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+/// Accepts WebSocket connections, streams the ui events to every client and collects their commands.
+/// Clients on the network can't override the interlocking.
+pub struct ApiServer {
+    local_addr: SocketAddr,
+    clients: Arc<ApiClients>,
+    command_rx: Receiver<UiCommand>,
+}
+
+impl ApiServer {
+    /// How long a client waits for a message, before it sends the messages queued for it.
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// How long a client gets to send its token, before it is disconnected.
+    const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Start listening for clients in the background.
+    pub fn bind(addr: impl ToSocketAddrs, access: ApiAccess) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+
+        let clients = Arc::new(ApiClients::default());
+        let (command_tx, command_rx) = crossbeam::channel::unbounded();
+
+        let accept_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("failed to accept a client: {}", err);
+                        continue;
+                    }
+                };
+
+                let clients = accept_clients.clone();
+                let command_tx = command_tx.clone();
+                let access = access.clone();
+                thread::spawn(move || {
+                    let Ok(peer) = stream.peer_addr() else {
+                        return;
+                    };
+
+                    if let Err(err) = serve_client(stream, peer, &clients, command_tx, &access) {
+                        log::warn!("api client {} failed: {}", peer, err);
+                    }
+                });
+            }
+        });
+
+        log::info!("api listening on ws://{}", local_addr);
+
+        Ok(Self {
+            local_addr,
+            clients,
+            command_rx,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Frontend for ApiServer {
+    fn broadcast(&self, event: &UiEvent) {
+        self.clients.broadcast(event);
+    }
+
+    fn commands(&self) -> &Receiver<UiCommand> {
+        &self.command_rx
+    }
+}
+
+fn serve_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    clients: &ApiClients,
+    command_tx: Sender<UiCommand>,
+    access: &ApiAccess,
+) -> tungstenite::Result<()> {
+    // a client that never finishes the handshake doesn't keep its thread forever
+    stream.set_read_timeout(Some(ApiServer::HELLO_TIMEOUT))?;
+
+    let mut socket = tungstenite::accept(stream).map_err(|err| match err {
+        tungstenite::HandshakeError::Failure(err) => err,
+        tungstenite::HandshakeError::Interrupted(_) => {
+            tungstenite::Error::Io(ErrorKind::WouldBlock.into())
+        }
+    })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(ApiServer::POLL_INTERVAL))?;
+
+    log::info!("api client {} connected", peer);
+
+    send(&mut socket, &ServerMessage::Hello)?;
+
+    if let Some(token) = access.token.as_deref()
+        && !authenticate(&mut socket, token)?
+    {
+        log::warn!("api client {} didn't authenticate", peer);
+        return Ok(());
+    }
+
+    let session_access = if access.read_only {
+        SessionAccess::ReadOnly
+    } else {
+        SessionAccess::Commands
+    };
+    let session = ApiSession::new(clients, command_tx, session_access);
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if let Err(message) = session.handle_message(&text) {
+                    send(&mut socket, &ServerMessage::Error { message })?;
+                }
+            }
+            Ok(Message::Binary(_)) => {
+                let message = "only text messages are supported".to_string();
+                send(&mut socket, &ServerMessage::Error { message })?;
+            }
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err),
+        }
+
+        for message in session.outgoing() {
+            send(&mut socket, &message)?;
+        }
+    }
+
+    log::info!("api client {} disconnected", peer);
+
+    Ok(())
+}
+
+/// Wait for the `hello` of the client. Returns whether it sent the right token in time.
+fn authenticate(socket: &mut WebSocket<TcpStream>, token: &str) -> tungstenite::Result<bool> {
+    let deadline = Instant::now() + ApiServer::HELLO_TIMEOUT;
+
+    while Instant::now() < deadline {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                match serde_json::from_str::<Envelope<ClientMessage>>(&text) {
+                    Ok(Envelope {
+                        message:
+                            ClientMessage::Hello {
+                                token: client_token,
+                            },
+                        ..
+                    }) => {
+                        // doesn't tell how much of the token was right by how long it takes
+                        if bool::from(client_token.as_bytes().ct_eq(token.as_bytes())) {
+                            return Ok(true);
+                        }
+
+                        let message = "wrong token".to_string();
+                        send(socket, &ServerMessage::Error { message })?;
+                        return Ok(false);
+                    }
+                    _ => {
+                        let message = "send a hello with the token first".to_string();
+                        send(socket, &ServerMessage::Error { message })?;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(false),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(false)
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: &ServerMessage) -> tungstenite::Result<()> {
+    let json =
+        serde_json::to_string(&Envelope::new(message)).expect("messages can always be serialized");
+
+    socket.send(Message::text(json))
+}
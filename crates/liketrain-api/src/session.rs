@@ -0,0 +1,143 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use liketrain_core::ui::{UiCommand, UiControllerEvent, UiEvent};
+
+use crate::{ClientMessage, Envelope, PROTOCOL_VERSION, ServerMessage};
+
+/// A connected client, as seen by the server.
+struct ApiClient {
+    tx: Sender<ServerMessage>,
+
+    /// The client asked for a snapshot and didn't get it yet.
+    wants_snapshot: Arc<AtomicBool>,
+}
+
+/// The clients of a server, that speaks the JSON protocol.
+#[derive(Default)]
+pub(crate) struct ApiClients {
+    clients: Mutex<Vec<ApiClient>>,
+}
+
+impl ApiClients {
+    /// Send an event of the controller to the clients.
+    /// Snapshots are only sent to the clients, that asked for one.
+    pub(crate) fn broadcast(&self, event: &UiEvent) {
+        let mut clients = self.clients.lock().unwrap();
+
+        clients.retain(|client| {
+            let message = match event {
+                UiEvent::UiControllerEvent(UiControllerEvent::Snapshot(snapshot)) => {
+                    if !client.wants_snapshot.swap(false, Ordering::Relaxed) {
+                        return true;
+                    }
+
+                    ServerMessage::Snapshot {
+                        snapshot: snapshot.clone(),
+                    }
+                }
+                event => ServerMessage::Event {
+                    event: event.clone(),
+                },
+            };
+
+            // the client disconnected
+            client.tx.send(message).is_ok()
+        });
+    }
+}
+
+/// What a client may send to the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionAccess {
+    /// Only ask for snapshots.
+    ReadOnly,
+
+    /// Send commands, that have to pass the interlocking.
+    Commands,
+
+    /// Also override the interlocking.
+    Override,
+}
+
+/// The connection to a single client, independent of the transport.
+pub(crate) struct ApiSession {
+    rx: Receiver<ServerMessage>,
+    wants_snapshot: Arc<AtomicBool>,
+    command_tx: Sender<UiCommand>,
+    access: SessionAccess,
+}
+
+impl ApiSession {
+    /// Register a new client and ask the controller for the snapshot it starts with.
+    pub(crate) fn new(
+        clients: &ApiClients,
+        command_tx: Sender<UiCommand>,
+        access: SessionAccess,
+    ) -> Self {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let wants_snapshot = Arc::new(AtomicBool::new(true));
+
+        clients.clients.lock().unwrap().push(ApiClient {
+            tx,
+            wants_snapshot: wants_snapshot.clone(),
+        });
+
+        let _ = command_tx.send(UiCommand::RequestSnapshot);
+
+        Self {
+            rx,
+            wants_snapshot,
+            command_tx,
+            access,
+        }
+    }
+
+    /// The messages queued for the client.
+    pub(crate) fn outgoing(&self) -> impl Iterator<Item = ServerMessage> + '_ {
+        self.rx.try_iter()
+    }
+
+    /// Handle a message of the client. Returns why it was rejected, if it was.
+    pub(crate) fn handle_message(&self, text: &str) -> Result<(), String> {
+        let envelope: Envelope<ClientMessage> =
+            serde_json::from_str(text).map_err(|err| format!("invalid message: {}", err))?;
+
+        if envelope.version != PROTOCOL_VERSION {
+            return Err(format!(
+                "unsupported protocol version {}, the server speaks version {}",
+                envelope.version, PROTOCOL_VERSION
+            ));
+        }
+
+        let command = match envelope.message {
+            // the client already authenticated, when the session was created
+            ClientMessage::Hello { .. } => return Ok(()),
+            ClientMessage::Snapshot
+            | ClientMessage::Command {
+                command: UiCommand::RequestSnapshot,
+            } => UiCommand::RequestSnapshot,
+            ClientMessage::Command { .. } if self.access == SessionAccess::ReadOnly => {
+                return Err("this client may only watch the controller".to_string());
+            }
+            ClientMessage::Command {
+                command: UiCommand::Override { .. },
+            } if self.access != SessionAccess::Override => {
+                return Err("the interlocking can only be overridden from the ui".to_string());
+            }
+            ClientMessage::Command { command } => command,
+        };
+
+        if let UiCommand::RequestSnapshot = command {
+            self.wants_snapshot.store(true, Ordering::Relaxed);
+        }
+
+        // the controller stopped, the daemon is shutting down
+        let _ = self.command_tx.send(command);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,649 @@
+//! A server for WiThrottle apps, e.g. Engine Driver or WiThrottle on a phone.
+//!
+//! Every train of the roster can be acquired by its id as the loco address. Speed steps are mapped
+//! to the speeds of the train, the direction is given by its route. Turnout commands go through the
+//! interlocking like every other manual command, "closed" is the left and "thrown" the right state.
+//!
+//! The throttles can only stop the layout, resuming it is left to the operator.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use liketrain_core::{
+    Controller, SwitchId, SwitchState, Train, TrainId, TrainSpeed, TrainState,
+    ui::{UiCommand, UiControllerEvent, UiEvent, UiSwitchEvent, UiTrainEvent},
+};
+
+use crate::Frontend;
+use protocol::{LocoKey, MAX_SPEED_STEP, ThrottleAction, ThrottleMessage};
+
+mod protocol;
+
+/// What the throttles are told about a train.
+#[derive(Debug, Clone)]
+pub struct WiThrottleTrain {
+    pub name: String,
+    pub speed: TrainSpeed,
+    pub state: TrainState,
+}
+
+impl WiThrottleTrain {
+    /// The speed step shown on the throttles. Trains held by the operator stand still.
+    fn speed_step(&self) -> u8 {
+        if self.state.is_held() {
+            0
+        } else {
+            protocol::step_from_speed(self.speed)
+        }
+    }
+}
+
+impl From<&Train> for WiThrottleTrain {
+    fn from(train: &Train) -> Self {
+        Self {
+            name: train.data().name.clone(),
+            speed: train.speed(),
+            state: train.state(),
+        }
+    }
+}
+
+/// The trains and switches, as the throttles know them. Kept up to date with the ui events.
+#[derive(Debug, Clone, Default)]
+pub struct WiThrottleLayout {
+    pub trains: BTreeMap<TrainId, WiThrottleTrain>,
+
+    /// The state of each switch, `None` until the controller has set it.
+    pub switches: BTreeMap<SwitchId, Option<SwitchState>>,
+
+    pub halted: bool,
+}
+
+impl WiThrottleLayout {
+    pub fn from_controller(controller: &Controller) -> Self {
+        let switch_states = controller.switch_states().collect::<HashMap<_, _>>();
+
+        Self {
+            trains: controller
+                .trains()
+                .map(|(train_id, train)| (train_id, train.into()))
+                .collect(),
+            switches: controller
+                .track()
+                .switches()
+                .map(|(switch_id, _)| (switch_id.clone(), switch_states.get(switch_id).copied()))
+                .collect(),
+            halted: controller.is_halted(),
+        }
+    }
+
+    /// Apply a ui event, returns what the throttles have to be told about it.
+    fn apply(&mut self, event: &UiEvent) -> Option<WiThrottleUpdate> {
+        match event {
+            UiEvent::UiTrainEvent(UiTrainEvent::SpeedChanged { train_id, speed }) => {
+                self.trains.get_mut(train_id)?.speed = *speed;
+                Some(WiThrottleUpdate::Train(*train_id))
+            }
+            UiEvent::UiTrainEvent(UiTrainEvent::StateChanged { train_id, state }) => {
+                self.trains.get_mut(train_id)?.state = *state;
+                Some(WiThrottleUpdate::Train(*train_id))
+            }
+            UiEvent::UiTrainEvent(UiTrainEvent::DataChanged { train_id, data }) => {
+                self.trains.get_mut(train_id)?.name = data.name.clone();
+                Some(WiThrottleUpdate::Roster)
+            }
+            UiEvent::UiTrainEvent(UiTrainEvent::Added { train_id, train }) => {
+                self.trains.insert(*train_id, train.as_ref().into());
+                Some(WiThrottleUpdate::Roster)
+            }
+            UiEvent::UiTrainEvent(UiTrainEvent::Removed { train_id }) => {
+                self.trains.remove(train_id);
+                Some(WiThrottleUpdate::Roster)
+            }
+            UiEvent::UiSwitchEvent(UiSwitchEvent::SetState { id, state }) => {
+                self.switches.insert(id.clone(), Some(*state));
+                Some(WiThrottleUpdate::Switch(id.clone()))
+            }
+            UiEvent::UiControllerEvent(UiControllerEvent::Halted) => {
+                self.halted = true;
+                Some(WiThrottleUpdate::Power)
+            }
+            UiEvent::UiControllerEvent(UiControllerEvent::Resumed) => {
+                self.halted = false;
+                Some(WiThrottleUpdate::Power)
+            }
+            UiEvent::UiControllerEvent(UiControllerEvent::CommandRejected {
+                violation, ..
+            }) => Some(WiThrottleUpdate::Alert(violation.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// What changed in the layout. The clients read the new state from the layout.
+#[derive(Debug, Clone)]
+enum WiThrottleUpdate {
+    Train(TrainId),
+    Switch(SwitchId),
+    Power,
+    Roster,
+    Alert(String),
+}
+
+/// Accepts WiThrottle connections, tells the throttles about the layout and collects their commands.
+pub struct WiThrottleServer {
+    local_addr: SocketAddr,
+    layout: Arc<Mutex<WiThrottleLayout>>,
+    clients: Arc<Mutex<Vec<Sender<WiThrottleUpdate>>>>,
+    command_rx: Receiver<UiCommand>,
+}
+
+impl WiThrottleServer {
+    /// The port WiThrottle apps connect to by default.
+    pub const DEFAULT_PORT: u16 = 12090;
+
+    /// How long a client waits for a line, before it sends the updates queued for it.
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// How often throttles with an enabled heartbeat have to send something.
+    const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Start listening for throttles in the background.
+    pub fn bind(addr: impl ToSocketAddrs, layout: WiThrottleLayout) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+
+        let layout = Arc::new(Mutex::new(layout));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (command_tx, command_rx) = crossbeam::channel::unbounded();
+
+        let accept_layout = layout.clone();
+        let accept_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("failed to accept a throttle: {}", err);
+                        continue;
+                    }
+                };
+
+                let layout = accept_layout.clone();
+                let clients = accept_clients.clone();
+                let command_tx = command_tx.clone();
+                thread::spawn(move || {
+                    let Ok(peer) = stream.peer_addr() else {
+                        return;
+                    };
+
+                    if let Err(err) = serve_client(stream, peer, &layout, &clients, command_tx) {
+                        log::warn!("throttle {} failed: {}", peer, err);
+                    }
+                });
+            }
+        });
+
+        log::info!("withrottle listening on {}", local_addr);
+
+        Ok(Self {
+            local_addr,
+            layout,
+            clients,
+            command_rx,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Frontend for WiThrottleServer {
+    fn broadcast(&self, event: &UiEvent) {
+        let Some(update) = self.layout.lock().unwrap().apply(event) else {
+            return;
+        };
+
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(update.clone()).is_ok());
+    }
+
+    fn commands(&self) -> &Receiver<UiCommand> {
+        &self.command_rx
+    }
+}
+
+/// A loco acquired by one of the throttles of a client.
+#[derive(Debug, Clone)]
+struct AcquiredLoco {
+    throttle: char,
+
+    /// The key the throttle used, it expects it back in every message about the loco.
+    key: String,
+
+    train_id: TrainId,
+}
+
+/// The connection to a single device, which can have multiple throttles.
+struct WiThrottleSession<'a> {
+    stream: TcpStream,
+    peer: SocketAddr,
+    layout: &'a Mutex<WiThrottleLayout>,
+    command_tx: Sender<UiCommand>,
+
+    locos: Vec<AcquiredLoco>,
+
+    /// The trains this session paused. Only these are started again, when a throttle is turned up,
+    /// trains held by the operator or a script stay where they are.
+    paused: HashSet<TrainId>,
+
+    heartbeat: bool,
+    last_message: Instant,
+
+    /// The locos were paused, because the heartbeat was missed.
+    heartbeat_missed: bool,
+
+    quit: bool,
+}
+
+fn serve_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    layout: &Mutex<WiThrottleLayout>,
+    clients: &Mutex<Vec<Sender<WiThrottleUpdate>>>,
+    command_tx: Sender<UiCommand>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(WiThrottleServer::POLL_INTERVAL))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let (tx, rx) = crossbeam::channel::unbounded();
+    clients.lock().unwrap().push(tx);
+
+    log::info!("throttle {} connected", peer);
+
+    let mut session = WiThrottleSession {
+        stream,
+        peer,
+        layout,
+        command_tx,
+        locos: Vec::new(),
+        paused: HashSet::new(),
+        heartbeat: false,
+        last_message: Instant::now(),
+        heartbeat_missed: false,
+        quit: false,
+    };
+
+    let result = session.run(&mut reader, &rx);
+
+    // a throttle that disappears must not leave its trains driving
+    session.pause_locos();
+    log::info!("throttle {} disconnected", peer);
+
+    result
+}
+
+impl WiThrottleSession<'_> {
+    fn run(
+        &mut self,
+        reader: &mut impl BufRead,
+        updates: &Receiver<WiThrottleUpdate>,
+    ) -> io::Result<()> {
+        self.greet()?;
+
+        let mut line = Vec::new();
+        while !self.quit {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    let message = String::from_utf8_lossy(&line).into_owned();
+                    line.clear();
+
+                    self.handle_line(&message)?;
+                }
+                // a partial line stays in the buffer, until the rest arrives
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(err) => return Err(err),
+            }
+
+            self.check_heartbeat()?;
+
+            for update in updates.try_iter() {
+                self.send_update(update)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tell a new device about the layout.
+    fn greet(&mut self) -> io::Result<()> {
+        let lines = {
+            let layout = self.layout.lock().unwrap();
+
+            [
+                protocol::version(),
+                roster(&layout),
+                protocol::power(!layout.halted),
+                protocol::turnout_titles(),
+                protocol::turnouts(
+                    layout
+                        .switches
+                        .iter()
+                        .map(|(switch_id, state)| (switch_id, *state)),
+                ),
+                protocol::heartbeat(WiThrottleServer::HEARTBEAT_INTERVAL.as_secs()),
+            ]
+        };
+
+        for line in lines {
+            self.write_line(&line)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_line(&mut self, line: &str) -> io::Result<()> {
+        self.last_message = Instant::now();
+        self.heartbeat_missed = false;
+
+        let Some(message) = ThrottleMessage::parse(line) else {
+            if !line.trim().is_empty() {
+                log::debug!("ignoring throttle message {:?}", line.trim());
+            }
+            return Ok(());
+        };
+
+        match message {
+            ThrottleMessage::Name(name) => log::info!("throttle {} is {}", self.peer, name),
+            ThrottleMessage::HardwareId(_) | ThrottleMessage::Heartbeat => {}
+            ThrottleMessage::EnableHeartbeat(enable) => self.heartbeat = enable,
+            ThrottleMessage::Power(true) => {
+                let halted = self.layout.lock().unwrap().halted;
+                if halted {
+                    self.alert("The layout can only be resumed by the operator")?;
+                }
+                self.write_line(&protocol::power(!halted))?;
+            }
+            ThrottleMessage::Power(false) => self.send_command(UiCommand::EmergencyStop),
+            ThrottleMessage::Turnout { action, switch_id } => {
+                let current = self
+                    .layout
+                    .lock()
+                    .unwrap()
+                    .switches
+                    .get(&switch_id)
+                    .copied();
+
+                let Some(current) = current else {
+                    return self.alert(&format!("There is no turnout {}", switch_id));
+                };
+
+                self.send_command(UiCommand::SetSwitchState {
+                    state: protocol::switch_state(action, current),
+                    switch_id,
+                });
+            }
+            ThrottleMessage::Throttle {
+                throttle,
+                loco,
+                action,
+            } => self.handle_throttle(throttle, loco, action)?,
+            ThrottleMessage::Quit => self.quit = true,
+        }
+
+        Ok(())
+    }
+
+    fn handle_throttle(
+        &mut self,
+        throttle: char,
+        loco: LocoKey,
+        action: ThrottleAction,
+    ) -> io::Result<()> {
+        if let ThrottleAction::Acquire = action {
+            return self.acquire(throttle, loco);
+        }
+
+        let locos = self
+            .locos
+            .iter()
+            .filter(|acquired| {
+                acquired.throttle == throttle
+                    && match &loco {
+                        LocoKey::All => true,
+                        LocoKey::Loco { key, .. } => *key == acquired.key,
+                    }
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for acquired in locos {
+            match action {
+                ThrottleAction::Acquire => {}
+                ThrottleAction::Release => {
+                    self.locos.retain(|loco| {
+                        loco.throttle != acquired.throttle || loco.key != acquired.key
+                    });
+                    self.write_line(&protocol::throttle_released(throttle, &acquired.key))?;
+                }
+                ThrottleAction::Speed(step) if step > 0 => {
+                    let step = step.min(MAX_SPEED_STEP as i16) as u8;
+                    self.set_speed(acquired.train_id, step);
+                }
+                ThrottleAction::Speed(_) | ThrottleAction::Idle => {
+                    self.pause_train(acquired.train_id);
+                }
+                // cuts the power right away, like the power button
+                ThrottleAction::EmergencyStop => self.send_command(UiCommand::EmergencyStop),
+                // the direction is given by the route of the train
+                ThrottleAction::Direction(_) | ThrottleAction::Query('R') => {
+                    self.write_line(&protocol::throttle_state(throttle, &acquired.key, "R1"))?;
+                }
+                ThrottleAction::Query('V') => self.send_speed(&acquired)?,
+                ThrottleAction::Query(_) | ThrottleAction::Unsupported => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn acquire(&mut self, throttle: char, loco: LocoKey) -> io::Result<()> {
+        let LocoKey::Loco { key, train_id } = loco else {
+            return Ok(());
+        };
+
+        if !self.layout.lock().unwrap().trains.contains_key(&train_id) {
+            return self.alert(&format!("There is no train with the address {}", key));
+        }
+
+        let acquired = AcquiredLoco {
+            throttle,
+            key,
+            train_id,
+        };
+
+        self.write_line(&protocol::throttle_acquired(throttle, &acquired.key))?;
+        self.send_speed(&acquired)?;
+        self.write_line(&protocol::throttle_state(throttle, &acquired.key, "R1"))?;
+        // 128 speed steps
+        self.write_line(&protocol::throttle_state(throttle, &acquired.key, "s1"))?;
+
+        self.locos.push(acquired);
+
+        Ok(())
+    }
+
+    /// Drive the train at the speed, starting it, if this session paused it.
+    fn set_speed(&mut self, train_id: TrainId, step: u8) {
+        let Some(speed) = protocol::speed_from_step(step) else {
+            return;
+        };
+
+        let held = self
+            .layout
+            .lock()
+            .unwrap()
+            .trains
+            .get(&train_id)
+            .is_some_and(|train| train.state.is_held());
+
+        // a running train was started again by someone else, a later stop isn't ours to undo
+        let paused = self.paused.remove(&train_id);
+
+        self.send_command(UiCommand::SetTrainSpeed { train_id, speed });
+        if held && paused {
+            self.send_command(UiCommand::StartTrain { train_id });
+        }
+    }
+
+    /// Pause the train, remembering that this session held it, unless it was held already.
+    fn pause_train(&mut self, train_id: TrainId) {
+        let held = self
+            .layout
+            .lock()
+            .unwrap()
+            .trains
+            .get(&train_id)
+            .is_some_and(|train| train.state.is_held());
+
+        if !held {
+            self.paused.insert(train_id);
+        }
+        self.send_command(UiCommand::PauseTrain { train_id });
+    }
+
+    fn pause_locos(&mut self) {
+        let train_ids = self
+            .locos
+            .iter()
+            .map(|loco| loco.train_id)
+            .collect::<Vec<_>>();
+
+        for train_id in train_ids {
+            self.pause_train(train_id);
+        }
+    }
+
+    /// Pause the trains of a throttle, that stopped sending heartbeats, e.g. because the phone lost the connection.
+    fn check_heartbeat(&mut self) -> io::Result<()> {
+        // give the throttle some slack, heartbeats are usually sent close to the interval
+        if !self.heartbeat
+            || self.heartbeat_missed
+            || self.last_message.elapsed() < WiThrottleServer::HEARTBEAT_INTERVAL * 2
+        {
+            return Ok(());
+        }
+
+        log::warn!("throttle {} missed its heartbeat", self.peer);
+        self.heartbeat_missed = true;
+        self.pause_locos();
+
+        self.alert("The heartbeat was missed, your trains were stopped")
+    }
+
+    fn send_update(&mut self, update: WiThrottleUpdate) -> io::Result<()> {
+        match update {
+            WiThrottleUpdate::Train(train_id) => {
+                let locos = self
+                    .locos
+                    .iter()
+                    .filter(|loco| loco.train_id == train_id)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                for loco in locos {
+                    self.send_speed(&loco)?;
+                }
+            }
+            WiThrottleUpdate::Switch(switch_id) => {
+                let state = self
+                    .layout
+                    .lock()
+                    .unwrap()
+                    .switches
+                    .get(&switch_id)
+                    .copied()
+                    .flatten();
+
+                self.write_line(&protocol::turnout(&switch_id, state))?;
+            }
+            WiThrottleUpdate::Power => {
+                let halted = self.layout.lock().unwrap().halted;
+                self.write_line(&protocol::power(!halted))?;
+            }
+            WiThrottleUpdate::Roster => {
+                let (roster, removed) = {
+                    let layout = self.layout.lock().unwrap();
+                    let removed = self
+                        .locos
+                        .iter()
+                        .filter(|loco| !layout.trains.contains_key(&loco.train_id))
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    (roster(&layout), removed)
+                };
+
+                for loco in removed {
+                    self.locos
+                        .retain(|acquired| acquired.train_id != loco.train_id);
+                    self.write_line(&protocol::throttle_released(loco.throttle, &loco.key))?;
+                }
+
+                self.write_line(&roster)?;
+            }
+            WiThrottleUpdate::Alert(message) => self.alert(&message)?,
+        }
+
+        Ok(())
+    }
+
+    fn send_speed(&mut self, loco: &AcquiredLoco) -> io::Result<()> {
+        let step = self
+            .layout
+            .lock()
+            .unwrap()
+            .trains
+            .get(&loco.train_id)
+            .map_or(0, WiThrottleTrain::speed_step);
+
+        self.write_line(&protocol::throttle_state(
+            loco.throttle,
+            &loco.key,
+            &format!("V{}", step),
+        ))
+    }
+
+    fn send_command(&self, command: UiCommand) {
+        // the controller stopped, the daemon is shutting down
+        let _ = self.command_tx.send(command);
+    }
+
+    fn alert(&mut self, message: &str) -> io::Result<()> {
+        self.write_line(&protocol::alert(message))
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stream, "{}", line)
+    }
+}
+
+fn roster(layout: &WiThrottleLayout) -> String {
+    protocol::roster(
+        layout
+            .trains
+            .iter()
+            .map(|(train_id, train)| (*train_id, train.name.as_str())),
+    )
+}
@@ -0,0 +1,319 @@
+//! Parsing and formatting of the lines of the WiThrottle protocol.
+
+use liketrain_core::{SwitchId, SwitchState, TrainId, TrainSpeed};
+
+/// Separates the loco from the action in throttle messages, e.g. `MTAS3<;>V50`.
+const SEPARATOR: &str = "<;>";
+
+/// Loco addresses above this are long addresses.
+const MAX_SHORT_ADDRESS: usize = 127;
+
+/// The highest speed step of a throttle, in 128 speed step mode.
+pub(super) const MAX_SPEED_STEP: u8 = 126;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ThrottleMessage {
+    /// `N<name>`, the name of the device.
+    Name(String),
+
+    /// `HU<id>`, a unique id of the device.
+    HardwareId(String),
+
+    /// `*+` or `*-`, the throttle wants the server to stop its locos, if it stops sending heartbeats.
+    EnableHeartbeat(bool),
+
+    /// `*`
+    Heartbeat,
+
+    /// `PPA<0|1>`
+    Power(bool),
+
+    /// `PTA<C|T|2><switch>`
+    Turnout {
+        action: TurnoutAction,
+        switch_id: SwitchId,
+    },
+
+    /// `M<throttle><action><loco><;><action>`, e.g. `MT+S3<;>S3`
+    Throttle {
+        throttle: char,
+        loco: LocoKey,
+        action: ThrottleAction,
+    },
+
+    /// `Q`
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum TurnoutAction {
+    Close,
+    Throw,
+    Toggle,
+}
+
+/// The locos a throttle message is meant for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum LocoKey {
+    /// `*`, all locos of the throttle.
+    All,
+
+    /// `S3` or `L341`. The key is sent back exactly as the throttle sent it.
+    Loco { key: String, train_id: TrainId },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ThrottleAction {
+    Acquire,
+    Release,
+
+    /// A speed step between 0 and 126, negative for an emergency stop.
+    Speed(i16),
+
+    /// `true` for forward.
+    Direction(bool),
+    EmergencyStop,
+    Idle,
+
+    /// `qV` or `qR`, the throttle asks for the speed or the direction.
+    Query(char),
+
+    /// Functions, speed step modes, ... that don't exist for analog trains.
+    Unsupported,
+}
+
+impl ThrottleMessage {
+    /// Parse a line sent by a throttle. Returns `None` for lines, that aren't understood.
+    pub(super) fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+
+        if let Some(name) = line.strip_prefix("HU") {
+            return Some(Self::HardwareId(name.to_string()));
+        }
+
+        if let Some(name) = line.strip_prefix('N') {
+            return Some(Self::Name(name.to_string()));
+        }
+
+        if let Some(heartbeat) = line.strip_prefix('*') {
+            return match heartbeat {
+                "+" => Some(Self::EnableHeartbeat(true)),
+                "-" => Some(Self::EnableHeartbeat(false)),
+                _ => Some(Self::Heartbeat),
+            };
+        }
+
+        if let Some(power) = line.strip_prefix("PPA") {
+            return match power {
+                "0" => Some(Self::Power(false)),
+                "1" => Some(Self::Power(true)),
+                _ => None,
+            };
+        }
+
+        if let Some(turnout) = line.strip_prefix("PTA") {
+            let mut chars = turnout.chars();
+            let action = match chars.next()? {
+                'C' => TurnoutAction::Close,
+                'T' => TurnoutAction::Throw,
+                '2' => TurnoutAction::Toggle,
+                _ => return None,
+            };
+
+            let switch_id = chars.as_str();
+            if switch_id.is_empty() {
+                return None;
+            }
+
+            return Some(Self::Turnout {
+                action,
+                switch_id: switch_id.into(),
+            });
+        }
+
+        if let Some(throttle) = line.strip_prefix('M') {
+            return Self::parse_throttle(throttle);
+        }
+
+        if line == "Q" {
+            return Some(Self::Quit);
+        }
+
+        None
+    }
+
+    fn parse_throttle(message: &str) -> Option<Self> {
+        let mut chars = message.chars();
+        let throttle = chars.next()?;
+        let kind = chars.next()?;
+
+        let (loco, action) = chars.as_str().split_once(SEPARATOR)?;
+        let loco = LocoKey::parse(loco)?;
+
+        let action = match kind {
+            // stealing a loco from another throttle is the same as acquiring it
+            '+' | 'S' => ThrottleAction::Acquire,
+            '-' => ThrottleAction::Release,
+            'A' => ThrottleAction::parse(action)?,
+            _ => return None,
+        };
+
+        Some(Self::Throttle {
+            throttle,
+            loco,
+            action,
+        })
+    }
+}
+
+impl LocoKey {
+    fn parse(key: &str) -> Option<Self> {
+        if key == "*" {
+            return Some(Self::All);
+        }
+
+        let address = key.strip_prefix(['S', 'L'])?.parse::<usize>().ok()?;
+
+        Some(Self::Loco {
+            key: key.to_string(),
+            train_id: address.into(),
+        })
+    }
+
+    /// The key of the train in the roster. Trains use their id as address.
+    pub(super) fn of_train(train_id: TrainId) -> String {
+        let address = train_id.as_usize();
+        let kind = if address > MAX_SHORT_ADDRESS {
+            'L'
+        } else {
+            'S'
+        };
+
+        format!("{}{}", kind, address)
+    }
+}
+
+impl ThrottleAction {
+    fn parse(action: &str) -> Option<Self> {
+        let mut chars = action.chars();
+
+        let action = match chars.next()? {
+            'V' => Self::Speed(chars.as_str().parse().ok()?),
+            'R' => Self::Direction(chars.as_str() != "0"),
+            'X' => Self::EmergencyStop,
+            'I' => Self::Idle,
+            'q' => Self::Query(chars.next()?),
+            _ => Self::Unsupported,
+        };
+
+        Some(action)
+    }
+}
+
+/// The speed of a throttle, `None` if the train should stop.
+pub(super) fn speed_from_step(step: u8) -> Option<TrainSpeed> {
+    match step {
+        0 => None,
+        1..=31 => Some(TrainSpeed::Slow),
+        32..=63 => Some(TrainSpeed::Medium),
+        64..=95 => Some(TrainSpeed::AlmostFast),
+        _ => Some(TrainSpeed::Fast),
+    }
+}
+
+/// The highest speed step, that maps to the speed.
+pub(super) fn step_from_speed(speed: TrainSpeed) -> u8 {
+    match speed {
+        TrainSpeed::Slow => 31,
+        TrainSpeed::Medium => 63,
+        TrainSpeed::AlmostFast => 95,
+        TrainSpeed::Fast => MAX_SPEED_STEP,
+    }
+}
+
+pub(super) fn version() -> String {
+    "VN2.0".to_string()
+}
+
+pub(super) fn heartbeat(seconds: u64) -> String {
+    format!("*{}", seconds)
+}
+
+pub(super) fn alert(message: &str) -> String {
+    format!("HM{}", message)
+}
+
+pub(super) fn power(on: bool) -> String {
+    format!("PPA{}", if on { 1 } else { 0 })
+}
+
+/// `RL<count>]\[<name>}|{<address>}|{<S|L>...`
+pub(super) fn roster<'a>(trains: impl ExactSizeIterator<Item = (TrainId, &'a str)>) -> String {
+    let mut line = format!("RL{}", trains.len());
+
+    for (train_id, name) in trains {
+        let key = LocoKey::of_train(train_id);
+        let (kind, address) = key.split_at(1);
+        line.push_str(&format!("]\\[{}}}|{{{}}}|{{{}", name, address, kind));
+    }
+
+    line
+}
+
+/// The names of the turnout states, the states are sent as numbers.
+pub(super) fn turnout_titles() -> String {
+    "PTT]\\[Turnouts}|{Turnout]\\[Closed}|{2]\\[Thrown}|{4".to_string()
+}
+
+/// `PTL]\[<id>}|{<name>}|{<state>...`
+pub(super) fn turnouts<'a>(
+    switches: impl Iterator<Item = (&'a SwitchId, Option<SwitchState>)>,
+) -> String {
+    let mut line = "PTL".to_string();
+
+    for (switch_id, state) in switches {
+        line.push_str(&format!(
+            "]\\[{}}}|{{{}}}|{{{}",
+            switch_id,
+            switch_id,
+            turnout_state(state)
+        ));
+    }
+
+    line
+}
+
+pub(super) fn turnout(switch_id: &SwitchId, state: Option<SwitchState>) -> String {
+    format!("PTA{}{}", turnout_state(state), switch_id)
+}
+
+/// Closed is the left and thrown the right state of a switch.
+fn turnout_state(state: Option<SwitchState>) -> u8 {
+    match state {
+        Some(SwitchState::Left) => 2,
+        Some(SwitchState::Right) => 4,
+        None => 1,
+    }
+}
+
+pub(super) fn switch_state(action: TurnoutAction, current: Option<SwitchState>) -> SwitchState {
+    match (action, current) {
+        (TurnoutAction::Close, _) | (TurnoutAction::Toggle, Some(SwitchState::Right)) => {
+            SwitchState::Left
+        }
+        (TurnoutAction::Throw, _) | (TurnoutAction::Toggle, _) => SwitchState::Right,
+    }
+}
+
+pub(super) fn throttle_acquired(throttle: char, key: &str) -> String {
+    format!("M{}+{}{}", throttle, key, SEPARATOR)
+}
+
+pub(super) fn throttle_released(throttle: char, key: &str) -> String {
+    format!("M{}-{}{}", throttle, key, SEPARATOR)
+}
+
+/// `M<throttle>A<loco><;><state>`, e.g. `MTAS3<;>V50`
+pub(super) fn throttle_state(throttle: char, key: &str, state: &str) -> String {
+    format!("M{}A{}{}{}", throttle, key, SEPARATOR, state)
+}
@@ -0,0 +1,620 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    sync::{Arc, mpsc},
+    thread,
+    time::Duration,
+};
+
+use crossbeam::channel::Receiver;
+use liketrain_api::{
+    ApiAccess, ApiServer, ClientMessage, Envelope, Frontend, IpcClient, IpcServer,
+    PROTOCOL_VERSION, ServerMessage, WiThrottleLayout, WiThrottleServer, WiThrottleTrain,
+};
+use liketrain_core::{
+    Controller, ControllerConfig, InterlockingViolation, SwitchState, TrackGeometry, TrainSpeed,
+    TrainState,
+    comm::SimHardwareCommunication,
+    parser::{Parser, eval::Evaluator, parser},
+    ui::{UiCommand, UiControllerEvent, UiEvent, UiSwitchEvent, UiTrainEvent},
+};
+use tungstenite::{Message, WebSocket, stream::MaybeTlsStream};
+
+type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn connect(server: &ApiServer) -> Client {
+    let (client, _) = tungstenite::connect(format!("ws://{}", server.local_addr())).unwrap();
+    if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+    }
+
+    client
+}
+
+fn receive(client: &mut Client) -> ServerMessage {
+    loop {
+        if let Message::Text(text) = client.read().unwrap() {
+            let envelope: Envelope<ServerMessage> = serde_json::from_str(&text).unwrap();
+            assert_eq!(envelope.version, PROTOCOL_VERSION);
+            return envelope.message;
+        }
+    }
+}
+
+fn send(client: &mut Client, message: ClientMessage) {
+    let json = serde_json::to_string(&Envelope::new(message)).unwrap();
+    client.send(Message::text(json)).unwrap();
+}
+
+fn receive_command(commands: &Receiver<UiCommand>) -> UiCommand {
+    commands.recv_timeout(Duration::from_secs(1)).unwrap()
+}
+
+#[test]
+fn test_api_server() {
+    let server = ApiServer::bind("127.0.0.1:0", ApiAccess::default()).unwrap();
+    let mut client = connect(&server);
+
+    // a new client gets the current state
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+
+    server.broadcast(&UiControllerEvent::Snapshot(Box::default()).into());
+    assert!(matches!(
+        receive(&mut client),
+        ServerMessage::Snapshot { .. }
+    ));
+
+    // events are streamed
+    server.broadcast(&UiEvent::UiSwitchEvent(UiSwitchEvent::SetState {
+        id: "S1".into(),
+        state: SwitchState::Left,
+    }));
+    assert!(matches!(
+        receive(&mut client),
+        ServerMessage::Event {
+            event: UiEvent::UiSwitchEvent(UiSwitchEvent::SetState {
+                state: SwitchState::Left,
+                ..
+            })
+        }
+    ));
+
+    // commands are forwarded
+    send(
+        &mut client,
+        ClientMessage::Command {
+            command: UiCommand::EmergencyStop,
+        },
+    );
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::EmergencyStop
+    ));
+
+    // only the clients that asked get the snapshot
+    let mut other = connect(&server);
+    assert!(matches!(receive(&mut other), ServerMessage::Hello));
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+
+    server.broadcast(&UiControllerEvent::Snapshot(Box::default()).into());
+    server.broadcast(&UiControllerEvent::Halted.into());
+
+    assert!(matches!(
+        receive(&mut other),
+        ServerMessage::Snapshot { .. }
+    ));
+    for client in [&mut client, &mut other] {
+        assert!(matches!(
+            receive(client),
+            ServerMessage::Event {
+                event: UiEvent::UiControllerEvent(UiControllerEvent::Halted)
+            }
+        ));
+    }
+
+    send(&mut client, ClientMessage::Snapshot);
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+
+    server.broadcast(&UiControllerEvent::Snapshot(Box::default()).into());
+    server.broadcast(&UiControllerEvent::Resumed.into());
+
+    assert!(matches!(
+        receive(&mut client),
+        ServerMessage::Snapshot { .. }
+    ));
+    for client in [&mut client, &mut other] {
+        assert!(matches!(
+            receive(client),
+            ServerMessage::Event {
+                event: UiEvent::UiControllerEvent(UiControllerEvent::Resumed)
+            }
+        ));
+    }
+}
+
+#[test]
+fn test_api_server_rejects() {
+    let server = ApiServer::bind("127.0.0.1:0", ApiAccess::default()).unwrap();
+    let mut client = connect(&server);
+
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+    receive_command(server.commands());
+
+    // the interlocking can't be overridden over the network
+    send(
+        &mut client,
+        ClientMessage::Command {
+            command: UiCommand::Override {
+                command: Box::new(UiCommand::Resume),
+                reason: "maintenance".to_string(),
+            },
+        },
+    );
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+
+    // other versions of the protocol are not understood
+    let json = serde_json::to_string(&Envelope {
+        version: PROTOCOL_VERSION + 1,
+        message: ClientMessage::Snapshot,
+    })
+    .unwrap();
+    client.send(Message::text(json)).unwrap();
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+
+    client.send(Message::text("not json")).unwrap();
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+
+    assert!(server.commands().is_empty());
+}
+
+#[test]
+fn test_api_server_token() {
+    let access = ApiAccess {
+        token: Some("secret".to_string()),
+        read_only: false,
+    };
+    let server = ApiServer::bind("127.0.0.1:0", access).unwrap();
+
+    // nothing is accepted before the token
+    let mut client = connect(&server);
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+
+    send(&mut client, ClientMessage::Snapshot);
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+
+    send(
+        &mut client,
+        ClientMessage::Hello {
+            token: "guess".to_string(),
+        },
+    );
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+    assert!(client.read().is_err());
+    assert!(server.commands().is_empty());
+
+    let mut client = connect(&server);
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+    send(
+        &mut client,
+        ClientMessage::Hello {
+            token: "secret".to_string(),
+        },
+    );
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+
+    send(
+        &mut client,
+        ClientMessage::Command {
+            command: UiCommand::EmergencyStop,
+        },
+    );
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::EmergencyStop
+    ));
+}
+
+#[test]
+fn test_api_server_read_only() {
+    let access = ApiAccess {
+        token: None,
+        read_only: true,
+    };
+    let server = ApiServer::bind("127.0.0.1:0", access).unwrap();
+    let mut client = connect(&server);
+
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+    receive_command(server.commands());
+
+    send(
+        &mut client,
+        ClientMessage::Command {
+            command: UiCommand::Resume,
+        },
+    );
+    assert!(matches!(receive(&mut client), ServerMessage::Error { .. }));
+
+    // watching is still possible
+    send(&mut client, ClientMessage::Snapshot);
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+    assert!(server.commands().is_empty());
+}
+
+/// Run a controller with the simulated hardware behind an api server, like the daemon does.
+fn serve_controller() -> (Arc<ApiServer>, thread::JoinHandle<()>) {
+    let track_defs = parser()
+        .parse(include_str!("../../../resources/track.ltt"))
+        .into_result()
+        .unwrap();
+    let mut track = Evaluator::default().evaluate(track_defs).unwrap();
+    let track_geo: TrackGeometry =
+        serde_json::from_str(include_str!("../../../resources/geo.json")).unwrap();
+    track.set_geometry(track_geo);
+
+    let server = Arc::new(ApiServer::bind("127.0.0.1:0", ApiAccess::default()).unwrap());
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let controller = Controller::new(
+        ControllerConfig::new(track, HashMap::new()),
+        SimHardwareCommunication::new(Vec::new()),
+        ui_event_tx,
+        server.commands().clone(),
+    );
+    let controller = thread::spawn(move || controller.start().unwrap());
+
+    let event_server = server.clone();
+    thread::spawn(move || {
+        for event in ui_event_rx {
+            event_server.broadcast(&event);
+        }
+    });
+
+    (server, controller)
+}
+
+/// Receive messages, until one matches.
+fn receive_until(client: &mut Client, matches: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+    for _ in 0..100 {
+        let message = receive(client);
+        if matches(&message) {
+            return message;
+        }
+    }
+
+    panic!("the message didn't arrive");
+}
+
+#[test]
+fn test_api_server_unknown_switch() {
+    let (server, controller) = serve_controller();
+    let mut client = connect(&server);
+
+    assert!(matches!(receive(&mut client), ServerMessage::Hello));
+    receive_until(&mut client, |message| {
+        matches!(message, ServerMessage::Snapshot { .. })
+    });
+
+    // neither a switch that doesn't exist nor an id that doesn't fit the hardware stop the controller
+    for switch_id in ["Z".to_string(), "S".repeat(33)] {
+        send(
+            &mut client,
+            ClientMessage::Command {
+                command: UiCommand::SetSwitchState {
+                    switch_id: switch_id.as_str().into(),
+                    state: SwitchState::Right,
+                },
+            },
+        );
+
+        let rejected = receive_until(&mut client, |message| {
+            matches!(
+                message,
+                ServerMessage::Event {
+                    event: UiEvent::UiControllerEvent(UiControllerEvent::CommandRejected { .. })
+                }
+            )
+        });
+        assert!(matches!(
+            rejected,
+            ServerMessage::Event {
+                event: UiEvent::UiControllerEvent(UiControllerEvent::CommandRejected {
+                    violation: InterlockingViolation::UnknownSwitch { .. },
+                    ..
+                })
+            }
+        ));
+    }
+
+    // the controller still answers
+    send(&mut client, ClientMessage::Snapshot);
+    receive_until(&mut client, |message| {
+        matches!(message, ServerMessage::Snapshot { .. })
+    });
+    assert!(!controller.is_finished());
+}
+
+#[test]
+fn test_ipc() {
+    let path = std::env::temp_dir().join(format!("liketrain-test-{}.sock", std::process::id()));
+    let server = IpcServer::bind(&path).unwrap();
+
+    // only the owner may connect, the socket is never open to others
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let leftovers = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&format!(".liketrain-test-{}.sock.", std::process::id()))
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let (command_tx, command_rx) = crossbeam::channel::unbounded();
+    let client = IpcClient::connect(&path, event_tx, command_rx).unwrap();
+    assert!(client.is_connected());
+
+    // the ui starts with the current state
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::RequestSnapshot
+    ));
+    // the snapshot already contains the events before it
+    server.broadcast(&UiControllerEvent::Resumed.into());
+    server.broadcast(&UiControllerEvent::Snapshot(Box::default()).into());
+    server.broadcast(&UiControllerEvent::Halted.into());
+
+    let event = event_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert!(matches!(
+        event,
+        UiEvent::UiControllerEvent(UiControllerEvent::Snapshot(_))
+    ));
+    let event = event_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert!(matches!(
+        event,
+        UiEvent::UiControllerEvent(UiControllerEvent::Halted)
+    ));
+
+    // unlike clients on the network, the ui may override the interlocking
+    command_tx
+        .send(UiCommand::Override {
+            command: Box::new(UiCommand::Resume),
+            reason: "maintenance".to_string(),
+        })
+        .unwrap();
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::Override { .. }
+    ));
+
+    // a second controller can't take over the socket
+    assert!(IpcServer::bind(&path).is_err());
+
+    drop(server);
+    assert!(!path.exists());
+
+    // a file, that isn't a socket, is left alone
+    std::fs::write(&path, "not a socket").unwrap();
+    assert!(IpcServer::bind(&path).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    std::fs::remove_file(&path).unwrap();
+
+    // a socket left behind by a previous run is replaced
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    drop(IpcServer::bind(&path).unwrap());
+    assert!(!path.exists());
+}
+
+/// A scripted WiThrottle client.
+struct Throttle {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Throttle {
+    fn connect(server: &WiThrottleServer) -> Self {
+        let writer = TcpStream::connect(server.local_addr()).unwrap();
+        writer
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        Self {
+            reader: BufReader::new(writer.try_clone().unwrap()),
+            writer,
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.writer, "{}", line).unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+}
+
+fn withrottle_layout() -> WiThrottleLayout {
+    WiThrottleLayout {
+        trains: [(
+            3_usize.into(),
+            WiThrottleTrain {
+                name: "RE5".to_string(),
+                speed: TrainSpeed::Fast,
+                state: TrainState::Stopped,
+            },
+        )]
+        .into(),
+        switches: [("A".into(), Some(SwitchState::Left)), ("B".into(), None)].into(),
+        halted: false,
+    }
+}
+
+#[test]
+fn test_withrottle_server() {
+    let server = WiThrottleServer::bind("127.0.0.1:0", withrottle_layout()).unwrap();
+    let mut throttle = Throttle::connect(&server);
+
+    let greeting = (0..6).map(|_| throttle.receive()).collect::<Vec<_>>();
+    assert_eq!(
+        greeting,
+        [
+            "VN2.0",
+            "RL1]\\[RE5}|{3}|{S",
+            "PPA1",
+            "PTT]\\[Turnouts}|{Turnout]\\[Closed}|{2]\\[Thrown}|{4",
+            "PTL]\\[A}|{A}|{2]\\[B}|{B}|{1",
+            "*10",
+        ]
+    );
+
+    throttle.send("NPhone");
+    throttle.send("HU1234");
+
+    // acquire the train
+    throttle.send("MT+S3<;>S3");
+    for expected in ["MT+S3<;>", "MTAS3<;>V0", "MTAS3<;>R1", "MTAS3<;>s1"] {
+        assert_eq!(throttle.receive(), expected);
+    }
+
+    throttle.send("MT+S9<;>S9");
+    assert!(throttle.receive().starts_with("HM"));
+
+    // a train stopped by the operator isn't started by the throttle
+    throttle.send("MTAS3<;>V50");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::SetTrainSpeed {
+            speed: TrainSpeed::Medium,
+            ..
+        }
+    ));
+    assert!(
+        server
+            .commands()
+            .recv_timeout(Duration::from_millis(100))
+            .is_err()
+    );
+
+    server.broadcast(&UiEvent::UiTrainEvent(UiTrainEvent::StateChanged {
+        train_id: 3_usize.into(),
+        state: TrainState::Default,
+    }));
+    assert_eq!(throttle.receive(), "MTAS3<;>V126");
+
+    server.broadcast(&UiEvent::UiTrainEvent(UiTrainEvent::SpeedChanged {
+        train_id: 3_usize.into(),
+        speed: TrainSpeed::Medium,
+    }));
+    assert_eq!(throttle.receive(), "MTAS3<;>V63");
+
+    throttle.send("MTA*<;>V0");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::PauseTrain { .. }
+    ));
+
+    server.broadcast(&UiEvent::UiTrainEvent(UiTrainEvent::StateChanged {
+        train_id: 3_usize.into(),
+        state: TrainState::Paused,
+    }));
+    assert_eq!(throttle.receive(), "MTAS3<;>V0");
+
+    // the train paused by the throttle is started, when the throttle is turned up again
+    throttle.send("MTAS3<;>V50");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::SetTrainSpeed { .. }
+    ));
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::StartTrain { .. }
+    ));
+
+    // the emergency stop of a loco cuts the power right away
+    throttle.send("MTAS3<;>X");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::EmergencyStop
+    ));
+
+    // turnouts go through the interlocking of the controller
+    throttle.send("PTA2A");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::SetSwitchState {
+            state: SwitchState::Right,
+            ..
+        }
+    ));
+
+    server.broadcast(&UiEvent::UiSwitchEvent(UiSwitchEvent::SetState {
+        id: "A".into(),
+        state: SwitchState::Right,
+    }));
+    assert_eq!(throttle.receive(), "PTA4A");
+
+    server.broadcast(
+        &UiControllerEvent::CommandRejected {
+            command: Box::new(UiCommand::SetSwitchState {
+                switch_id: "A".into(),
+                state: SwitchState::Left,
+            }),
+            violation: InterlockingViolation::Halted,
+        }
+        .into(),
+    );
+    assert!(throttle.receive().starts_with("HM"));
+
+    throttle.send("PTACX");
+    assert!(throttle.receive().starts_with("HM"));
+
+    throttle.send("PPA0");
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::EmergencyStop
+    ));
+
+    server.broadcast(&UiControllerEvent::Halted.into());
+    assert_eq!(throttle.receive(), "PPA0");
+
+    // only the operator can resume the layout
+    throttle.send("PPA1");
+    assert!(throttle.receive().starts_with("HM"));
+    assert_eq!(throttle.receive(), "PPA0");
+    assert!(server.commands().is_empty());
+
+    // a throttle that disappears doesn't leave its trains driving
+    drop(throttle);
+    assert!(matches!(
+        receive_command(server.commands()),
+        UiCommand::PauseTrain { .. }
+    ));
+    assert!(server.commands().is_empty());
+}
@@ -50,7 +50,11 @@ impl CommandExt for HardwareCommand {
 
                 Ok(false) // return false, so the command will be forwared to the slaves
             }
-            Self::SetSectionPower { section_id, power } => {
+            Self::SetSectionPower {
+                section_id,
+                power,
+                polarity,
+            } => {
                 let Some(section) = ctx
                     .sections
                     .iter_mut()
@@ -60,7 +64,7 @@ impl CommandExt for HardwareCommand {
                 };
 
                 section
-                    .set_power(power)
+                    .set_power(power, polarity)
                     .map_err(CommandExecutionError::SectionError)?;
 
                 Ok(true)
@@ -88,6 +88,7 @@ fn main() -> ! {
         power_b: pins.d5.into_output(),
         power_c: pins.d6.into_output(),
         power_d: pins.d7.into_output(),
+        polarity: pins.d27.into_output(),
         train_detection: pins.d8,
     };
 
@@ -96,6 +97,7 @@ fn main() -> ! {
         power_b: pins.d10.into_output(),
         power_c: pins.d11.into_output(),
         power_d: pins.d12.into_output(),
+        polarity: pins.d28.into_output(),
         train_detection: pins.d26,
     };
 
@@ -104,6 +106,7 @@ fn main() -> ! {
         power_b: pins.d22.into_output(),
         power_c: pins.d23.into_output(),
         power_d: pins.d24.into_output(),
+        polarity: pins.d29.into_output(),
         train_detection: pins.d25,
     };
 
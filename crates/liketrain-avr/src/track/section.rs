@@ -1,6 +1,8 @@
 use alloc::{boxed::Box, vec::Vec};
 use embedded_hal::digital::{InputPin, OutputPin};
-use liketrain_hardware::event::{HardwareEvent, HardwareSectionPower, SectionEvent};
+use liketrain_hardware::event::{
+    HardwareEvent, HardwareSectionPolarity, HardwareSectionPower, SectionEvent,
+};
 
 type BoxedOutputPin = Box<dyn OutputPin<Error = core::convert::Infallible>>;
 type BoxedInputPin = Box<dyn InputPin<Error = core::convert::Infallible>>;
@@ -15,12 +17,14 @@ pub struct SectionPins<
     PB: OutputPin<Error = core::convert::Infallible> + 'static,
     PC: OutputPin<Error = core::convert::Infallible> + 'static,
     PD: OutputPin<Error = core::convert::Infallible> + 'static,
+    PP: OutputPin<Error = core::convert::Infallible> + 'static,
     TD: InputPin<Error = core::convert::Infallible> + 'static,
 > {
     pub power_a: PA,
     pub power_b: PB,
     pub power_c: PC,
     pub power_d: PD,
+    pub polarity: PP,
     pub train_detection: TD,
 }
 
@@ -29,16 +33,18 @@ pub struct BoxedSectionPins {
     pub power_b: BoxedOutputPin,
     pub power_c: BoxedOutputPin,
     pub power_d: BoxedOutputPin,
+    pub polarity: BoxedOutputPin,
     pub train_detection: BoxedInputPin,
 }
 
 impl BoxedSectionPins {
-    pub fn new<PA, PB, PC, PD, TD>(pins: SectionPins<PA, PB, PC, PD, TD>) -> Self
+    pub fn new<PA, PB, PC, PD, PP, TD>(pins: SectionPins<PA, PB, PC, PD, PP, TD>) -> Self
     where
         PA: OutputPin<Error = core::convert::Infallible> + 'static,
         PB: OutputPin<Error = core::convert::Infallible> + 'static,
         PC: OutputPin<Error = core::convert::Infallible> + 'static,
         PD: OutputPin<Error = core::convert::Infallible> + 'static,
+        PP: OutputPin<Error = core::convert::Infallible> + 'static,
         TD: InputPin<Error = core::convert::Infallible> + 'static,
     {
         Self {
@@ -46,20 +52,22 @@ impl BoxedSectionPins {
             power_b: Box::new(pins.power_b),
             power_c: Box::new(pins.power_c),
             power_d: Box::new(pins.power_d),
+            polarity: Box::new(pins.polarity),
             train_detection: Box::new(pins.train_detection),
         }
     }
 }
 
-impl<PA, PB, PC, PD, TD> From<SectionPins<PA, PB, PC, PD, TD>> for BoxedSectionPins
+impl<PA, PB, PC, PD, PP, TD> From<SectionPins<PA, PB, PC, PD, PP, TD>> for BoxedSectionPins
 where
     PA: OutputPin<Error = core::convert::Infallible> + 'static,
     PB: OutputPin<Error = core::convert::Infallible> + 'static,
     PC: OutputPin<Error = core::convert::Infallible> + 'static,
     PD: OutputPin<Error = core::convert::Infallible> + 'static,
+    PP: OutputPin<Error = core::convert::Infallible> + 'static,
     TD: InputPin<Error = core::convert::Infallible> + 'static,
 {
-    fn from(pins: SectionPins<PA, PB, PC, PD, TD>) -> Self {
+    fn from(pins: SectionPins<PA, PB, PC, PD, PP, TD>) -> Self {
         Self::new(pins)
     }
 }
@@ -158,11 +166,48 @@ impl SectionPowerRelais {
     }
 }
 
+/// A DPDT relais, that swaps the polarity of the section.
+pub struct SectionPolarityRelais {
+    /// Pin for the relais, high means reversed polarity
+    pin: BoxedOutputPin,
+
+    current_polarity: HardwareSectionPolarity,
+}
+
+impl SectionPolarityRelais {
+    pub fn new(mut pin: BoxedOutputPin) -> Result<Self, SectionError> {
+        pin.set_low().map_err(|_| SectionError::PinError)?;
+
+        Ok(Self {
+            pin,
+            current_polarity: HardwareSectionPolarity::Normal,
+        })
+    }
+
+    pub fn current_polarity(&self) -> HardwareSectionPolarity {
+        self.current_polarity
+    }
+
+    /// Only call this while the section is unpowered.
+    pub fn set_polarity(&mut self, polarity: HardwareSectionPolarity) -> Result<(), SectionError> {
+        if polarity == self.current_polarity {
+            return Ok(());
+        }
+
+        self.current_polarity = polarity;
+        self.pin
+            .set_state((polarity == HardwareSectionPolarity::Reversed).into())
+            .map_err(|_| SectionError::PinError)
+    }
+}
+
 pub struct Section {
     section_id: u32,
 
     /// The 4-relais board for powering the section
     power_relais: SectionPowerRelais,
+    /// The relais for swapping the polarity of the section
+    polarity_relais: SectionPolarityRelais,
     /// The pin for detecting a train on the section
     train_detection: BoxedInputPin,
 
@@ -175,24 +220,34 @@ pub trait SectionDelegate {
     fn is_occupied(&self) -> bool;
 
     fn current_power(&self) -> HardwareSectionPower;
-    fn set_power(&mut self, power: HardwareSectionPower) -> Result<(), SectionError>;
+    fn current_polarity(&self) -> HardwareSectionPolarity;
+    fn set_power(
+        &mut self,
+        power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
+    ) -> Result<(), SectionError>;
 
     fn reset(&mut self) -> Result<(), SectionError> {
-        self.set_power(HardwareSectionPower::Off)
+        self.set_power(HardwareSectionPower::Off, HardwareSectionPolarity::Normal)
     }
 
     fn update(&mut self, event_list: &mut Vec<HardwareEvent>) -> Result<(), SectionError>;
 }
 
 impl Section {
+    /// When swapping the polarity, wait 10ms before powering the section again
+    const POLARITY_SWITCHING_DELAY: u32 = 10;
+
     pub fn new(
         section_id: u32,
         power_relais: SectionPowerRelais,
+        polarity_relais: SectionPolarityRelais,
         train_detection: BoxedInputPin,
     ) -> Self {
         Section {
             section_id,
             power_relais,
+            polarity_relais,
             train_detection,
             is_occupied: false,
         }
@@ -205,6 +260,7 @@ impl Section {
             section_id,
             SectionPowerRelais::new(pins.power_a, pins.power_b, pins.power_c, pins.power_d)
                 .unwrap(),
+            SectionPolarityRelais::new(pins.polarity).unwrap(),
             pins.train_detection,
         )
     }
@@ -223,7 +279,25 @@ impl SectionDelegate for Section {
         self.power_relais.current_power()
     }
 
-    fn set_power(&mut self, power: HardwareSectionPower) -> Result<(), SectionError> {
+    fn current_polarity(&self) -> HardwareSectionPolarity {
+        self.polarity_relais.current_polarity()
+    }
+
+    fn set_power(
+        &mut self,
+        power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
+    ) -> Result<(), SectionError> {
+        if polarity != self.polarity_relais.current_polarity() {
+            // never swap the polarity under load, so unpower the section first
+            self.power_relais.set_power(HardwareSectionPower::Off)?;
+            self.polarity_relais.set_polarity(polarity)?;
+
+            if power != HardwareSectionPower::Off {
+                arduino_hal::delay_ms(Self::POLARITY_SWITCHING_DELAY);
+            }
+        }
+
         self.power_relais.set_power(power)
     }
 
@@ -0,0 +1,87 @@
+use std::time;
+
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{Controller, SectionId, TrainId, ui::UiTrainEvent};
+
+/// When a train entered its current section and with which power it has been driving since.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct SectionTiming {
+    section_id: SectionId,
+    entered_at: time::Instant,
+
+    /// `None`, if the power of the train changed while driving through the section.
+    power: Option<HardwareSectionPower>,
+}
+
+impl Controller {
+    /// The power of the train changed, so the time it takes for the current section can't be used for calibration.
+    pub(super) fn invalidate_section_timing(&mut self, train_id: TrainId) {
+        if let Some(timing) = self.section_timings.get_mut(&train_id) {
+            timing.power = None;
+        }
+    }
+
+    /// The train entered a new section. If it drove through the previous section with a constant power,
+    /// its speed at this power is calculated from the length of the previous section.
+    pub(super) fn record_section_timing(&mut self, train_id: TrainId, section_id: SectionId) {
+        let now = self.scheduler.now();
+
+        let previous_timing = self.section_timings.insert(
+            train_id,
+            SectionTiming {
+                section_id,
+                entered_at: now,
+                power: Some(self.train_power(train_id)),
+            },
+        );
+
+        if !self.record_calibration {
+            return;
+        }
+
+        let Some(SectionTiming {
+            section_id: previous_section_id,
+            entered_at,
+            power: Some(power),
+        }) = previous_timing
+        else {
+            return;
+        };
+
+        if power.is_off() || previous_section_id == section_id {
+            return;
+        }
+
+        let Some(section_geo) = self.track.section_geo(&previous_section_id) else {
+            return;
+        };
+
+        let elapsed = now - entered_at;
+        if elapsed.is_zero() {
+            return;
+        }
+
+        let speed = section_geo.length / elapsed.as_secs_f32();
+
+        let Some(train) = self.trains.get_mut(&train_id) else {
+            return;
+        };
+
+        log::debug!(
+            "train {} drove through section {} with {:?} at {:.2} m/s",
+            train_id,
+            previous_section_id,
+            power,
+            speed
+        );
+
+        train.calibration_mut().record(power, speed);
+        let calibration = train.calibration().clone();
+
+        self.emit_ui(UiTrainEvent::CalibrationChanged {
+            train_id,
+            calibration,
+        });
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Where the controller, the scheduler and the simulation get the current time from.
+/// The real hardware runs with the `SystemClock`, simulations can use a `ManualClock` to run faster than real time.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+
+    /// Wait until the duration passed on this clock.
+    fn sleep(&self, duration: Duration);
+
+    /// Whether the clock moves on its own. The controller and the simulated hardware only wait on
+    /// such a clock, when they run in their own threads.
+    fn is_real_time(&self) -> bool {
+        true
+    }
+}
+
+/// The time of the operating system.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves, when it is advanced. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    /// Nobody else moves the clock, so sleeping advances it.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn is_real_time(&self) -> bool {
+        false
+    }
+}
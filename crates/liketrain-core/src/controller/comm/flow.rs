@@ -7,6 +7,10 @@ use liketrain_hardware::command::HardwareCommand;
 
 pub struct SerialFlowController {
     command_buffer: VecDeque<HardwareCommand>,
+
+    /// Commands that are sent before any command of the `command_buffer`.
+    priority_buffer: VecDeque<HardwareCommand>,
+
     awaiting_ack: Option<Instant>,
 }
 
@@ -16,6 +20,7 @@ impl SerialFlowController {
     pub fn new() -> Self {
         SerialFlowController {
             command_buffer: VecDeque::new(),
+            priority_buffer: VecDeque::new(),
             awaiting_ack: None,
         }
     }
@@ -24,6 +29,14 @@ impl SerialFlowController {
         self.command_buffer.push_back(command);
     }
 
+    /// Send the command before all buffered commands. Buffered commands, that would
+    /// power a section, are dropped, so they can't undo the priority command.
+    pub fn push_priority_command(&mut self, command: HardwareCommand) {
+        self.command_buffer
+            .retain(|command| !matches!(command, HardwareCommand::SetSectionPower { .. }));
+        self.priority_buffer.push_back(command);
+    }
+
     pub fn ack_received(&mut self) {
         self.awaiting_ack = None;
     }
@@ -37,7 +50,11 @@ impl SerialFlowController {
             self.awaiting_ack = None;
         }
 
-        if let Some(command) = self.command_buffer.pop_front() {
+        if let Some(command) = self
+            .priority_buffer
+            .pop_front()
+            .or_else(|| self.command_buffer.pop_front())
+        {
             self.awaiting_ack = Some(Instant::now() + Self::ACK_TIMEOUT);
             return Some(command);
         }
@@ -13,6 +13,10 @@ use crate::ControllerError;
 pub struct ControllerHardwareCommunicationChannels {
     pub event_tx: crossbeam::channel::Sender<HardwareEvent>,
     pub command_rx: crossbeam::channel::Receiver<HardwareCommand>,
+
+    /// Commands that have to be sent before any other queued commands, e.g. during an emergency stop.
+    /// Queued commands, that power sections, are dropped.
+    pub priority_command_rx: crossbeam::channel::Receiver<HardwareCommand>,
 }
 
 pub trait ControllerHardwareCommunication: 'static + Send {
@@ -49,6 +49,16 @@ impl ControllerHardwareCommunication for SerialControllerHardwareCommunication {
                             flow.push_command(command);
                         }
                     }
+                    recv(channels.priority_command_rx) -> command => {
+                        if let Ok(command) = command {
+                            // commands, that were sent before, are buffered first
+                            for command in channels.command_rx.try_iter() {
+                                flow.push_command(command);
+                            }
+
+                            flow.push_priority_command(command);
+                        }
+                    }
                     recv(ticker) -> _ => {
                         serial.update().unwrap();
 
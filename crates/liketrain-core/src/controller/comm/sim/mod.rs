@@ -1,24 +1,52 @@
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use crossbeam::{channel::tick, select};
-use liketrain_hardware::{command::HardwareCommand, event::HardwareEvent};
+use crossbeam::{
+    channel::{Receiver, tick},
+    select,
+};
+use liketrain_hardware::{
+    command::HardwareCommand,
+    event::{HardwareEvent, HardwareSectionPower},
+};
 
 mod train;
 pub use train::*;
 
-use crate::{SectionId, SwitchId, SwitchState, comm::ControllerHardwareCommunication};
+mod simulation;
+pub use simulation::*;
+
+use crate::{
+    Clock, SectionId, SwitchId, SwitchState, SystemClock, comm::ControllerHardwareCommunication,
+};
 
-#[derive(Default)]
 pub struct SimHardwareCommunication {
     trains: Vec<SimTrain>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for SimHardwareCommunication {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
 }
 
 impl SimHardwareCommunication {
+    /// How often the simulated trains move.
+    const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
     pub fn new(trains: impl IntoIterator<Item = SimTrain>) -> Self {
         Self {
             trains: trains.into_iter().collect(),
+            clock: Arc::new(SystemClock),
         }
     }
+
+    /// Move the trains according to this clock, it has to be the clock of the controller.
+    /// The trains are moved in real time, so the clock has to move on its own.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 impl ControllerHardwareCommunication for SimHardwareCommunication {
@@ -26,50 +54,34 @@ impl ControllerHardwareCommunication for SimHardwareCommunication {
         &self,
         channels: super::ControllerHardwareCommunicationChannels,
     ) -> Result<(), crate::ControllerError> {
-        let mut trains = self.trains.clone();
-
-        std::thread::spawn(move || {
-            let mut section_states = HashMap::new();
-            let mut switch_states = HashMap::new();
+        if !self.clock.is_real_time() {
+            return Err(crate::ControllerError::ClockNotRealTime);
+        }
 
-            let ticker = tick(Duration::from_millis(10));
+        let mut hardware = SimHardware::new(self.trains.clone());
+        let clock = self.clock.clone();
 
-            let mut events = Vec::new();
+        std::thread::spawn(move || {
+            let ticker = tick(Self::TICK_INTERVAL);
 
             loop {
                 select! {
                     recv(channels.command_rx) -> command => {
                         if let Ok(command) = command {
-                            match command {
-                                HardwareCommand::ResetAll => {
-                                    section_states.clear();
-                                    switch_states.clear();
-                                }
-                                HardwareCommand::GetSlaves => {
-                                    events.push(HardwareEvent::Slaves { n_slaves: 0 });
-                                }
-                                HardwareCommand::Ping { slave_id, seq } => if slave_id == 0 {
-                                    events.push(HardwareEvent::Pong { slave_id, seq });
-                                },
-                                HardwareCommand::SetSectionPower { section_id, power } => {
-                                    section_states.insert(SectionId::from(section_id), power);
-                                    events.push(HardwareEvent::SectionPowerChanged { section_id, power });
-                                }
-                                HardwareCommand::SetSwitchState { switch_id, state } => {
-                                    switch_states.insert(SwitchId::from_hardware_id(&switch_id), SwitchState::from(state));
-                                    events.push(HardwareEvent::SwitchStateChanged { switch_id, state });
-                                }
-                            }
+                            hardware.handle_command(command);
                         }
                     }
-                    recv(ticker) -> _ => {
-                        for train in trains.iter_mut() {
-                            train.update(&section_states, &switch_states, &mut events);
+                    recv(channels.priority_command_rx) -> command => {
+                        if let Ok(command) = command {
+                            hardware.handle_priority_command(command, &channels.command_rx);
                         }
+                    }
+                    recv(ticker) -> _ => {
+                        hardware.update(clock.now());
 
-                       for event in events.drain(..)  {
-                           let _ = channels.event_tx.send(event);
-                       }
+                        for event in hardware.events.drain(..) {
+                            let _ = channels.event_tx.send(event);
+                        }
                     }
                 }
             }
@@ -78,3 +90,91 @@ impl ControllerHardwareCommunication for SimHardwareCommunication {
         Ok(())
     }
 }
+
+/// The simulated hardware: the power of the sections, the state of the switches and the trains driving on them.
+pub(super) struct SimHardware {
+    trains: Vec<SimTrain>,
+    section_states: HashMap<SectionId, HardwareSectionPower>,
+    switch_states: HashMap<SwitchId, SwitchState>,
+
+    /// The events, that weren't sent to the controller yet.
+    events: Vec<HardwareEvent>,
+}
+
+impl SimHardware {
+    pub(super) fn new(trains: Vec<SimTrain>) -> Self {
+        Self {
+            trains,
+            section_states: HashMap::new(),
+            switch_states: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Move the trains to where they are at `now`.
+    pub(super) fn update(&mut self, now: std::time::Instant) {
+        for train in self.trains.iter_mut() {
+            train.update(
+                now,
+                &self.section_states,
+                &self.switch_states,
+                &mut self.events,
+            );
+        }
+    }
+
+    /// Commands, that were sent before, are handled first. Like with the
+    /// serial flow control, the ones that would power a section are dropped.
+    pub(super) fn handle_priority_command(
+        &mut self,
+        command: HardwareCommand,
+        command_rx: &Receiver<HardwareCommand>,
+    ) {
+        for command in command_rx.try_iter() {
+            if !matches!(command, HardwareCommand::SetSectionPower { .. }) {
+                self.handle_command(command);
+            }
+        }
+
+        self.handle_command(command);
+    }
+
+    /// Apply a command to the simulated hardware, like the real hardware would.
+    pub(super) fn handle_command(&mut self, command: HardwareCommand) {
+        match command {
+            HardwareCommand::ResetAll => {
+                self.section_states.clear();
+                self.switch_states.clear();
+            }
+            HardwareCommand::GetSlaves => {
+                self.events.push(HardwareEvent::Slaves { n_slaves: 0 });
+            }
+            HardwareCommand::Ping { slave_id, seq } => {
+                if slave_id == 0 {
+                    self.events.push(HardwareEvent::Pong { slave_id, seq });
+                }
+            }
+            HardwareCommand::SetSectionPower {
+                section_id,
+                power,
+                polarity,
+            } => {
+                self.section_states
+                    .insert(SectionId::from(section_id), power);
+                self.events.push(HardwareEvent::SectionPowerChanged {
+                    section_id,
+                    power,
+                    polarity,
+                });
+            }
+            HardwareCommand::SetSwitchState { switch_id, state } => {
+                self.switch_states.insert(
+                    SwitchId::from_hardware_id(&switch_id),
+                    SwitchState::from(state),
+                );
+                self.events
+                    .push(HardwareEvent::SwitchStateChanged { switch_id, state });
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use liketrain_hardware::{command::HardwareCommand, event::HardwareEvent};
+
+use crate::{
+    Clock, Controller, ControllerConfig, ControllerError, ControllerSnapshot, ManualClock,
+    comm::SimHardwareCommunication,
+    controller::{
+        EventExecutionContext,
+        journal::{JournalEntry, JournalWriter},
+    },
+    ui::{UiCommand, UiEvent},
+};
+
+use super::{SimHardware, SimTrain};
+
+/// Runs a controller with simulated hardware on a `ManualClock`, in the calling thread.
+/// The time only moves, when the simulation is stepped, so a long show runs in seconds
+/// and the same simulation always produces the same events.
+pub struct Simulation {
+    controller: Controller,
+    hardware: SimHardware,
+    clock: ManualClock,
+    started: Instant,
+    channels: SimulationChannels,
+
+    /// The commands aren't applied to the simulated hardware, because the hardware events
+    /// are replayed from a journal.
+    replaying: bool,
+}
+
+/// What the controller talks to the simulated hardware through.
+struct SimulationChannels {
+    command_tx: Sender<HardwareCommand>,
+    command_rx: Receiver<HardwareCommand>,
+    priority_command_tx: Sender<HardwareCommand>,
+    priority_command_rx: Receiver<HardwareCommand>,
+    event_rx: Receiver<HardwareEvent>,
+
+    journal: Option<JournalWriter>,
+}
+
+impl SimulationChannels {
+    fn ctx(&self) -> EventExecutionContext<'_> {
+        EventExecutionContext {
+            command_tx: &self.command_tx,
+            priority_command_tx: &self.priority_command_tx,
+            event_rx: &self.event_rx,
+            journal: self.journal.as_ref(),
+        }
+    }
+}
+
+impl Simulation {
+    /// How far the clock is advanced with every step.
+    pub const TICK: Duration = Duration::from_millis(10);
+
+    /// Initialize a controller with the config, driving the simulated trains.
+    /// The clock of the config is replaced by the clock of the simulation.
+    pub fn new(
+        config: ControllerConfig,
+        trains: impl IntoIterator<Item = SimTrain>,
+        ui_event_tx: mpsc::Sender<UiEvent>,
+    ) -> Result<Self, ControllerError> {
+        Self::start(config, None, trains, ui_event_tx, false)
+    }
+
+    /// Initialize a controller like `new`, that continues where the snapshot left off.
+    pub fn restore(
+        config: ControllerConfig,
+        snapshot: ControllerSnapshot,
+        trains: impl IntoIterator<Item = SimTrain>,
+        ui_event_tx: mpsc::Sender<UiEvent>,
+    ) -> Result<Self, ControllerError> {
+        Self::start(config, Some(snapshot), trains, ui_event_tx, false)
+    }
+
+    /// Initialize a controller like `new`, without simulated hardware. The hardware events are
+    /// sent with `hardware_event` and the journal of the run is kept in memory.
+    pub(in crate::controller) fn replay(
+        config: ControllerConfig,
+        ui_event_tx: mpsc::Sender<UiEvent>,
+    ) -> Result<Self, ControllerError> {
+        Self::start(config, None, [], ui_event_tx, true)
+    }
+
+    fn start(
+        mut config: ControllerConfig,
+        snapshot: Option<ControllerSnapshot>,
+        trains: impl IntoIterator<Item = SimTrain>,
+        ui_event_tx: mpsc::Sender<UiEvent>,
+        replaying: bool,
+    ) -> Result<Self, ControllerError> {
+        let clock = ManualClock::new();
+        config.clock = Arc::new(clock.clone());
+
+        let journal = if replaying {
+            Some(JournalWriter::in_memory(config.clock.clone()))
+        } else {
+            config
+                .journal
+                .as_deref()
+                .map(|path| JournalWriter::create(path, config.clock.clone()))
+                .transpose()?
+        };
+
+        // the commands are handled by the simulation itself, the ui sends them with `command`
+        let mut controller = Controller::new(
+            config,
+            SimHardwareCommunication::default(),
+            ui_event_tx,
+            crossbeam::channel::never(),
+        );
+
+        if let Some(snapshot) = snapshot {
+            controller.restore(snapshot)?;
+        }
+
+        let (command_tx, command_rx) = crossbeam::channel::unbounded();
+        let (priority_command_tx, priority_command_rx) = crossbeam::channel::unbounded();
+        let (event_tx, event_rx) = crossbeam::channel::unbounded();
+
+        let mut simulation = Self {
+            controller,
+            hardware: SimHardware::new(trains.into_iter().collect()),
+            started: clock.now(),
+            clock,
+            channels: SimulationChannels {
+                command_tx,
+                command_rx,
+                priority_command_tx,
+                priority_command_rx,
+                event_rx,
+                journal,
+            },
+            replaying,
+        };
+
+        // the controller waits for the answers to its handshake. The simulated hardware always
+        // answers the same, so they are sent right away.
+        for event in [
+            HardwareEvent::Slaves { n_slaves: 0 },
+            HardwareEvent::Pong {
+                slave_id: 0,
+                seq: Controller::PING_SEQ,
+            },
+        ] {
+            let _ = event_tx.send(event);
+        }
+
+        let ctx = simulation.channels.ctx();
+        simulation.controller.init(ctx)?;
+
+        for command in simulation.channels.command_rx.try_iter() {
+            if !replaying
+                && !matches!(
+                    command,
+                    HardwareCommand::GetSlaves | HardwareCommand::Ping { .. }
+                )
+            {
+                simulation.hardware.handle_command(command);
+            }
+        }
+
+        Ok(simulation)
+    }
+
+    pub fn controller(&self) -> &Controller {
+        &self.controller
+    }
+
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    /// Execute a command, like it was sent by the ui.
+    pub fn command(&mut self, command: UiCommand) -> Result<(), ControllerError> {
+        let ctx = self.channels.ctx();
+        self.controller.handle_ui_command(command, ctx)?;
+        self.controller.run_scripts(ctx)?;
+
+        self.deliver_commands();
+
+        Ok(())
+    }
+
+    /// Handle an event, like it was sent by the hardware, e.g. to simulate a fault.
+    pub fn hardware_event(&mut self, event: HardwareEvent) -> Result<(), ControllerError> {
+        let ctx = self.channels.ctx();
+        self.controller.handle_event(event, ctx)?;
+        self.controller.run_scripts(ctx)?;
+
+        self.deliver_commands();
+
+        Ok(())
+    }
+
+    /// Let a test work with the internals of the controller, as if it handled an event.
+    #[cfg(test)]
+    pub(in crate::controller) fn with_controller<T>(
+        &mut self,
+        f: impl FnOnce(&mut Controller, EventExecutionContext) -> Result<T, ControllerError>,
+    ) -> Result<T, ControllerError> {
+        let result = f(&mut self.controller, self.channels.ctx())?;
+        self.deliver_commands();
+
+        Ok(result)
+    }
+
+    /// Advance the clock by one tick: move the trains, let the controller handle the events
+    /// of the hardware and the scheduled events, and apply its commands to the hardware.
+    pub fn step(&mut self) -> Result<(), ControllerError> {
+        self.clock.advance(Self::TICK);
+        self.hardware.update(self.clock.now());
+
+        let events = std::mem::take(&mut self.hardware.events);
+
+        let ctx = self.channels.ctx();
+        for event in events {
+            self.controller.handle_event(event, ctx)?;
+            self.controller.run_scripts(ctx)?;
+        }
+
+        self.controller.resolve_pending_events(ctx)?;
+        self.controller.run_scripts(ctx)?;
+
+        self.deliver_commands();
+
+        Ok(())
+    }
+
+    /// Step the simulation to the time since it started. The scheduled events of the last tick
+    /// aren't handled yet, so the hardware events sent at that time are handled first.
+    pub(in crate::controller) fn advance_to(
+        &mut self,
+        at: Duration,
+    ) -> Result<(), ControllerError> {
+        let target = self.started + at;
+        if self.clock.now() >= target {
+            return Ok(());
+        }
+
+        while self.clock.now() + Self::TICK < target {
+            self.step()?;
+        }
+
+        self.clock.advance(target - self.clock.now());
+
+        Ok(())
+    }
+
+    /// Handle the scheduled events, that are due, like `step` does after the hardware events.
+    pub(in crate::controller) fn finish_tick(&mut self) -> Result<(), ControllerError> {
+        let ctx = self.channels.ctx();
+        self.controller.resolve_pending_events(ctx)?;
+        self.controller.run_scripts(ctx)?;
+
+        self.deliver_commands();
+
+        Ok(())
+    }
+
+    /// The journal of a replay.
+    pub(in crate::controller) fn journal(&self) -> Vec<JournalEntry> {
+        self.channels
+            .journal
+            .as_ref()
+            .map(JournalWriter::entries)
+            .unwrap_or_default()
+    }
+
+    /// Step the simulation, until the duration passed on its clock.
+    pub fn run_for(&mut self, duration: Duration) -> Result<(), ControllerError> {
+        let until = self.clock.now() + duration;
+        while self.clock.now() < until {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    fn deliver_commands(&mut self) {
+        if self.replaying {
+            // they are in the journal already
+            self.channels.priority_command_rx.try_iter().count();
+            self.channels.command_rx.try_iter().count();
+            return;
+        }
+
+        while let Ok(command) = self.channels.priority_command_rx.try_recv() {
+            self.hardware
+                .handle_priority_command(command, &self.channels.command_rx);
+        }
+
+        for command in self.channels.command_rx.try_iter() {
+            self.hardware.handle_command(command);
+        }
+    }
+}
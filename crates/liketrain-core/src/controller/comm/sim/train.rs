@@ -1,10 +1,15 @@
-use std::{collections::HashMap, time};
+use std::{
+    collections::{HashMap, VecDeque},
+    time,
+};
 
 use liketrain_hardware::event::{
     HardwareEvent, HardwareSectionPower, SectionEvent, SectionEventType,
 };
 
-use crate::{Route, SectionId, SectionTransition, SwitchId, SwitchState, Track};
+use crate::{
+    Route, SectionId, SectionTransition, SwitchId, SwitchState, Track, Train, TrainCalibration,
+};
 
 #[derive(Clone)]
 pub struct SimTrainVia {
@@ -13,31 +18,37 @@ pub struct SimTrainVia {
     /// The transition into this section, if any.
     transition: Option<SectionTransition>,
 
-    time_to_travel: time::Duration,
+    /// The length of this section in meters (already in respect to the tracks scale)
+    length: f32,
 }
 
 #[derive(Clone)]
 struct SimTrainCurrentViaOn {
     idx: usize,
     last_update: time::Instant,
-    time_traveled: time::Duration,
+
+    /// The distance traveled in this section in meters
+    distance_traveled: f32,
 }
 
 impl SimTrainCurrentViaOn {
-    fn get_power_multiplier(current_power: HardwareSectionPower) -> f64 {
-        match current_power {
-            HardwareSectionPower::Off => 0.0,
-            HardwareSectionPower::Quarter => 0.25,
-            HardwareSectionPower::Half => 0.5,
-            HardwareSectionPower::ThreeQuarters => 0.75,
-            HardwareSectionPower::Full => 1.0,
-        }
-    }
-
-    pub fn update_now(&mut self, current_power: HardwareSectionPower) {
-        let delta = self.last_update.elapsed();
-        self.last_update = time::Instant::now();
-        self.time_traveled += delta.mul_f64(Self::get_power_multiplier(current_power));
+    /// Returns the distance traveled since the last update in meters.
+    pub fn update(
+        &mut self,
+        now: time::Instant,
+        current_power: HardwareSectionPower,
+        calibration: &TrainCalibration,
+    ) -> f32 {
+        let delta = now.saturating_duration_since(self.last_update);
+        self.last_update = now;
+
+        let speed = calibration
+            .estimate_speed(current_power)
+            .unwrap_or_default();
+        let distance = delta.as_secs_f32() * speed;
+        self.distance_traveled += distance;
+
+        distance
     }
 }
 
@@ -60,11 +71,21 @@ impl Default for SimTrainCurrentVia {
 pub struct SimTrain {
     vias: Vec<SimTrainVia>,
 
+    /// The speed of the simulated train at each power level.
+    calibration: TrainCalibration,
+
+    /// The length of the train in meters (already in respect to the tracks scale)
+    length: f32,
+
+    /// The sections the head already left, but the tail is still in,
+    /// with the distance the train still has to travel until the tail left them.
+    tail: VecDeque<(SectionId, f32)>,
+
     current_via: SimTrainCurrentVia,
 }
 
 impl SimTrain {
-    pub fn new<I, V>(vias: I) -> Self
+    pub fn new<I, V>(vias: I, calibration: TrainCalibration) -> Self
     where
         I: IntoIterator<Item = V>,
         V: Into<SimTrainVia>,
@@ -77,13 +98,67 @@ impl SimTrain {
             SimTrainCurrentVia::Transitioning { to: 0 }
         };
 
-        Self { vias, current_via }
+        Self {
+            vias,
+            calibration,
+            length: 0.0,
+            tail: VecDeque::new(),
+            current_via,
+        }
+    }
+
+    /// The sections are only freed, once the whole train left them.
+    ///
+    /// * `length` - the length of the train in meters (already in respect to the tracks scale)
+    pub fn with_length(mut self, length: f32) -> Self {
+        self.length = length;
+        self
+    }
+
+    /// Move the tail of the train forward and free the sections it left.
+    fn advance_tail(&mut self, distance: f32, events: &mut Vec<HardwareEvent>) {
+        for (_, remaining) in self.tail.iter_mut() {
+            *remaining -= distance;
+        }
+
+        while let Some(&(section_id, _)) =
+            self.tail.front().filter(|(_, remaining)| *remaining <= 0.0)
+        {
+            self.tail.pop_front();
+            events.push(HardwareEvent::SectionEvent(SectionEvent::freed(
+                section_id.as_u32(),
+            )));
+        }
+    }
+
+    /// Get a `SimTrain` driving the route of the given train. Calibrated trains drive with their
+    /// calibrated speeds, all others with a default speed. Returns `None`, if the train has no route.
+    pub fn from_train(train: &Train, track: &Track) -> Option<Self> {
+        let route = train.route()?;
+
+        let sim_train = if train.calibration().is_empty() {
+            Self::from_route(route, track, TrainCalibration::NOMINAL_FULL_SPEED)
+        } else {
+            Self::from_route_with_calibration(route, track, train.calibration().clone())
+        };
+
+        Some(sim_train.with_length(train.data().length))
     }
 
     /// Get a `SimTrain` from a `Route` and `Track`, with the given speed.
+    /// The speed grows linearly with the power of the section.
     ///
-    /// * `speed` - the speed of the train in meters per second (already in respect to the tracks scale)
+    /// * `speed` - the speed of the train at full power in meters per second (already in respect to the tracks scale)
     pub fn from_route(route: &Route, track: &Track, speed: f32) -> Self {
+        Self::from_route_with_calibration(route, track, TrainCalibration::linear(speed))
+    }
+
+    /// Get a `SimTrain` from a `Route` and `Track`, driving with the speeds of the given calibration.
+    pub fn from_route_with_calibration(
+        route: &Route,
+        track: &Track,
+        calibration: TrainCalibration,
+    ) -> Self {
         let vias = route
             .vias()
             .iter()
@@ -91,29 +166,23 @@ impl SimTrain {
             .enumerate()
             .map(|(idx, section_id)| {
                 let section_geo = track.section_geo(&section_id).expect("When using .from_route() please make sure each Section is linked to a TrackSectionGeometry");
-                let seconds_to_travel = (section_geo.length / speed) as u64;
-
                 let transition = (idx > 0).then(|| route.transition(idx - 1).cloned()).flatten();
 
                 SimTrainVia {
                     section_id,
                     transition,
-                    time_to_travel: time::Duration::from_secs(seconds_to_travel),
+                    length: section_geo.length,
                 }
             })
             .collect::<Vec<_>>();
 
-        let current_via = if vias.is_empty() {
-            SimTrainCurrentVia::Stopped
-        } else {
-            SimTrainCurrentVia::Transitioning { to: 0 }
-        };
-
-        Self { vias, current_via }
+        Self::new(vias, calibration)
     }
 
+    /// Move the train to where it is at `now`.
     pub(super) fn update(
         &mut self,
+        now: time::Instant,
         section_states: &HashMap<SectionId, HardwareSectionPower>,
         switch_states: &HashMap<SwitchId, SwitchState>,
         events: &mut Vec<HardwareEvent>,
@@ -153,8 +222,8 @@ impl SimTrain {
 
                 self.current_via = SimTrainCurrentVia::On(SimTrainCurrentViaOn {
                     idx: *to,
-                    last_update: time::Instant::now(),
-                    time_traveled: time::Duration::from_secs(0),
+                    last_update: now,
+                    distance_traveled: 0.0,
                 });
             }
             SimTrainCurrentVia::On(current_via) => {
@@ -164,22 +233,23 @@ impl SimTrain {
                     .copied()
                     .unwrap_or_default();
 
-                current_via.update_now(current_section_power);
+                let distance = current_via.update(now, current_section_power, &self.calibration);
 
-                if current_via.time_traveled < current_section.time_to_travel {
-                    return;
-                }
+                let current_section_id = current_section.section_id;
+                let current_idx = current_via.idx;
+                let at_end = current_via.distance_traveled >= current_section.length;
+
+                self.advance_tail(distance, events);
 
-                if current_section_power.is_off() {
+                if !at_end || current_section_power.is_off() {
                     return;
                 }
 
-                // we are at the end of the current section
-                events.push(HardwareEvent::SectionEvent(SectionEvent::freed(
-                    current_section.section_id.as_u32(),
-                )));
+                // we are at the end of the current section, the tail of the train will follow
+                self.tail.push_back((current_section_id, self.length));
+                self.advance_tail(0.0, events);
 
-                let next_via_idx = (current_via.idx + 1) % self.vias.len();
+                let next_via_idx = (current_idx + 1) % self.vias.len();
                 self.current_via = SimTrainCurrentVia::Transitioning { to: next_via_idx };
             }
         }
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Controller, ControllerError, Route, SectionId, TrainId, TrainState, ui::UiControllerEvent,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+/// Trains waiting for each other in a cycle, so none of them can ever continue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deadlock {
+    /// Each train waits for the next one, the last one waits for the first one.
+    pub trains: Vec<TrainId>,
+
+    /// The section each train is waiting for.
+    pub sections: Vec<SectionId>,
+}
+
+impl std::fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let waits = self
+            .trains
+            .iter()
+            .zip(&self.sections)
+            .map(|(train_id, section_id)| format!("train {} waits for S{}", train_id, section_id))
+            .join(", ");
+
+        write!(f, "Deadlock: {}", waits)
+    }
+}
+
+/// How the controller tries to resolve a deadlock.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DeadlockResolution {
+    /// Only report the deadlock.
+    #[default]
+    Report,
+
+    /// Send one of the trains to the end of its route, avoiding the section it is waiting for.
+    Reroute,
+
+    /// Like `Reroute`, but if no train can be rerouted, one of them is reversed
+    /// and backs out into the section it came from.
+    RerouteOrBackOut,
+}
+
+impl Controller {
+    /// For each waiting train, the section it is waiting for and the trains holding that section.
+    fn wait_for_graph(&self) -> HashMap<TrainId, (SectionId, Vec<TrainId>)> {
+        let mut graph = HashMap::new();
+
+        for (&section_id, queue) in &self.section_queues {
+            let holders = self
+                .train_covering_section(section_id)
+                .into_iter()
+                .chain(self.section_reservation(section_id))
+                .unique()
+                .collect::<Vec<_>>();
+
+            for &train_id in queue {
+                let holders = holders
+                    .iter()
+                    .copied()
+                    .filter(|&holder| holder != train_id)
+                    .collect();
+
+                graph.insert(train_id, (section_id, holders));
+            }
+        }
+
+        graph
+    }
+
+    /// All cycles of trains waiting for each other.
+    pub fn deadlocks(&self) -> Vec<Deadlock> {
+        let graph = self.wait_for_graph();
+        let mut deadlocks = Vec::new();
+
+        for &start in graph.keys().sorted() {
+            let mut path = vec![start];
+            self.find_cycles(&graph, &mut path, &mut deadlocks);
+        }
+
+        deadlocks
+    }
+
+    fn find_cycles(
+        &self,
+        graph: &HashMap<TrainId, (SectionId, Vec<TrainId>)>,
+        path: &mut Vec<TrainId>,
+        deadlocks: &mut Vec<Deadlock>,
+    ) {
+        let Some((_, holders)) = path.last().and_then(|train_id| graph.get(train_id)) else {
+            return;
+        };
+
+        for &holder in holders {
+            if holder == path[0] {
+                // only report each cycle once, starting at its smallest train
+                if path.iter().min() == Some(&path[0]) {
+                    deadlocks.push(Deadlock {
+                        trains: path.clone(),
+                        sections: path.iter().map(|train_id| graph[train_id].0).collect(),
+                    });
+                }
+                continue;
+            }
+
+            if path.contains(&holder) {
+                continue;
+            }
+
+            path.push(holder);
+            self.find_cycles(graph, path, deadlocks);
+            path.pop();
+        }
+    }
+
+    /// Report new deadlocks and the ones that were resolved, and try to resolve the new ones.
+    pub(super) fn check_deadlocks(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let deadlocks = self.deadlocks();
+
+        for resolved in self
+            .known_deadlocks
+            .extract_if(.., |deadlock| !deadlocks.contains(deadlock))
+            .collect::<Vec<_>>()
+        {
+            log::info!("deadlock resolved: {}", resolved);
+            self.emit_ui(UiControllerEvent::DeadlockResolved(resolved));
+        }
+
+        for deadlock in deadlocks {
+            if self.known_deadlocks.contains(&deadlock) {
+                continue;
+            }
+
+            log::warn!("{}", deadlock);
+            self.emit_ui(UiControllerEvent::Deadlock(deadlock.clone()));
+            self.known_deadlocks.push(deadlock.clone());
+
+            self.resolve_deadlock(&deadlock, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_deadlock(
+        &mut self,
+        deadlock: &Deadlock,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.deadlock_resolution == DeadlockResolution::Report {
+            return Ok(());
+        }
+
+        for (&train_id, &blocked_section) in deadlock.trains.iter().zip(&deadlock.sections) {
+            if self.reroute_waiting_train(train_id, blocked_section, ctx)? {
+                return Ok(());
+            }
+        }
+
+        if self.deadlock_resolution == DeadlockResolution::RerouteOrBackOut {
+            for (&train_id, &blocked_section) in deadlock.trains.iter().zip(&deadlock.sections) {
+                if self.back_out_waiting_train(train_id, blocked_section, ctx)? {
+                    return Ok(());
+                }
+            }
+        }
+
+        log::warn!("couldn't resolve the deadlock");
+        Ok(())
+    }
+
+    /// Send the waiting train to the end of its route, without driving through the blocked section.
+    fn reroute_waiting_train(
+        &mut self,
+        train_id: TrainId,
+        blocked_section: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<bool, ControllerError> {
+        let train = self.train(train_id)?;
+
+        // closed routes don't have an end to reroute to
+        let Some(destination) = train
+            .route()
+            .filter(|route| !route.is_closed())
+            .and_then(|route| route.vias().last().copied())
+        else {
+            return Ok(false);
+        };
+
+        let Some(route) = self.route_to_avoiding(train_id, destination, &[blocked_section]) else {
+            return Ok(false);
+        };
+
+        log::info!(
+            "rerouting train {} around section {}: {}",
+            train_id,
+            blocked_section,
+            route.pretty_print(&self.track)
+        );
+
+        self.leave_section_queue(train_id, blocked_section);
+        self.depart_on_route(train_id, route, ctx)?;
+
+        Ok(true)
+    }
+
+    /// Reverse the waiting train and let it drive back into the section it came from.
+    fn back_out_waiting_train(
+        &mut self,
+        train_id: TrainId,
+        blocked_section: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<bool, ControllerError> {
+        let train = self.train(train_id)?;
+        if train.state() != TrainState::Waiting || !self.train_power(train_id).is_off() {
+            return Ok(false);
+        }
+
+        let Some(current_section) = train.get_current_section() else {
+            return Ok(false);
+        };
+
+        // the section behind the train
+        let orientation = train.orientation().opposite();
+        let Some(previous_section) = self
+            .track
+            .transitions(current_section, orientation)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|transition| transition.destination())
+            .find(|&section_id| self.is_section_available(section_id, train_id))
+        else {
+            return Ok(false);
+        };
+
+        // the route is built before the train is turned, so it stays untouched, if there is none
+        let Some(route) = self
+            .track
+            .find_path_avoiding(
+                current_section,
+                orientation,
+                previous_section,
+                &[blocked_section],
+            )
+            .filter(|path| path.len() >= 2)
+            .and_then(|path| {
+                let name = format!("{} -> S{}", train.name(), previous_section);
+                Route::new(name, path, orientation, &self.track)
+            })
+        else {
+            return Ok(false);
+        };
+
+        if !self.turn_train(train_id) {
+            return Ok(false);
+        }
+
+        log::info!(
+            "backing train {} out of section {} into section {}",
+            train_id,
+            current_section,
+            previous_section
+        );
+
+        self.leave_section_queue(train_id, blocked_section);
+        self.depart_on_route(train_id, route, ctx)?;
+
+        Ok(true)
+    }
+}
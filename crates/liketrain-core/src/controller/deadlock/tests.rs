@@ -0,0 +1,208 @@
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    ControllerConfig, Direction, Route, Track, Train,
+    comm::Simulation,
+    controller::testing::{config, occupy, route, section, simulate, track, train},
+};
+
+use super::*;
+
+/// Replace the reservations and queues of the controller with the given ones.
+fn hand_build(
+    controller: &mut Controller,
+    reservations: &[(usize, usize)],
+    queues: &[(usize, &[usize])],
+) {
+    controller.section_reservations = reservations
+        .iter()
+        .map(|&(section_id, train_id)| (section(section_id), TrainId::new(train_id)))
+        .collect();
+
+    controller.section_queues = queues
+        .iter()
+        .map(|&(section_id, train_ids)| {
+            let queue = train_ids.iter().copied().map(TrainId::new).collect();
+            (section(section_id), queue)
+        })
+        .collect();
+}
+
+fn deadlocks(
+    trains: impl IntoIterator<Item = (usize, Train)>,
+    reservations: &[(usize, usize)],
+    queues: &[(usize, &[usize])],
+) -> Vec<Deadlock> {
+    let (mut simulation, _ui_event_rx) = simulate(config(track(), trains), []);
+
+    simulation
+        .with_controller(|controller, _| {
+            hand_build(controller, reservations, queues);
+            Ok(controller.deadlocks())
+        })
+        .unwrap()
+}
+
+fn deadlock(trains: &[usize], sections: &[usize]) -> Deadlock {
+    Deadlock {
+        trains: trains.iter().copied().map(TrainId::new).collect(),
+        sections: sections.iter().copied().map(section).collect(),
+    }
+}
+
+#[test]
+fn test_two_train_cycle() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12, 14, 16]))),
+        (2, train("T2", route(&track, &[3, 9, 10]))),
+    ];
+
+    // each train holds the section the other one waits for
+    let deadlocks = deadlocks(trains, &[(14, 1), (16, 2)], &[(16, &[1]), (14, &[2])]);
+    assert_eq!(deadlocks, [deadlock(&[1, 2], &[16, 14])]);
+}
+
+#[test]
+fn test_three_train_cycle() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12, 14, 16]))),
+        (2, train("T2", route(&track, &[3, 9, 10]))),
+        (3, train("T3", route(&track, &[13, 25, 23]))),
+        (4, train("T4", route(&track, &[7, 2, 3]))),
+    ];
+
+    // T4 waits as well, but nothing waits for T4
+    let deadlocks = deadlocks(
+        trains,
+        &[(14, 1), (16, 2), (9, 3)],
+        &[(16, &[1]), (9, &[2]), (14, &[3, 4])],
+    );
+    assert_eq!(deadlocks, [deadlock(&[1, 2, 3], &[16, 9, 14])]);
+}
+
+#[test]
+fn test_no_cycle() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12, 14, 16]))),
+        (2, train("T2", route(&track, &[3, 9, 10]))),
+    ];
+
+    let deadlocks = deadlocks(trains, &[(14, 1), (16, 2)], &[(16, &[1])]);
+    assert!(deadlocks.is_empty());
+}
+
+/// T1 entered S10 and waits for S12, which T2 holds. T2 waits for S10, where T1 is.
+fn simulate_deadlock(track: Track, route: Route, resolution: DeadlockResolution) -> Simulation {
+    let t2_route = self::route(&track, &[13, 25, 23]);
+    let trains = [(1, train("T1", route)), (2, train("T2", t2_route))];
+
+    let config = ControllerConfig {
+        deadlock_resolution: resolution,
+        ..config(track, trains)
+    };
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 10);
+
+    simulation
+        .with_controller(|controller, ctx| {
+            hand_build(controller, &[(12, 2)], &[(12, &[1]), (10, &[2])]);
+
+            // T1 stopped in front of S12
+            controller.set_train_state(TrainId::new(1), TrainState::Waiting)?;
+            controller.ramp_train(TrainId::new(1), HardwareSectionPower::Off, ctx)
+        })
+        .unwrap();
+    simulation.step().unwrap();
+
+    assert_eq!(
+        simulation.controller().deadlocks(),
+        [deadlock(&[1, 2], &[12, 10])]
+    );
+
+    simulation
+        .with_controller(|controller, ctx| controller.check_deadlocks(ctx))
+        .unwrap();
+    simulation.step().unwrap();
+
+    simulation
+}
+
+fn vias(simulation: &Simulation, train_id: usize) -> Vec<SectionId> {
+    let train = simulation
+        .controller()
+        .train(TrainId::new(train_id))
+        .unwrap();
+    train.route().unwrap().vias().to_vec()
+}
+
+#[test]
+fn test_reroute() {
+    let track = track();
+    let route = route(&track, &[10, 12, 14, 16]);
+    let simulation = simulate_deadlock(track, route, DeadlockResolution::Reroute);
+
+    // T1 drives around S12 through S11
+    let expected: Vec<_> = [10, 11, 14, 16].into_iter().map(section).collect();
+    assert_eq!(vias(&simulation, 1), expected);
+    assert_eq!(simulation.controller().deadlocks(), []);
+
+    let t1 = simulation.controller().train(TrainId::new(1)).unwrap();
+    assert_eq!(t1.state(), TrainState::Default);
+    assert!(
+        !simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+}
+
+#[test]
+fn test_closed_route_is_not_rerouted() {
+    let track = track();
+    let route = route(&track, &[10, 12, 14, 16, 9, 10]);
+    assert!(route.is_closed());
+    let simulation = simulate_deadlock(track, route.clone(), DeadlockResolution::Reroute);
+
+    // a closed route has no end to reroute to, the deadlock is only reported
+    assert_eq!(vias(&simulation, 1), route.vias());
+    assert_eq!(
+        simulation.controller().deadlocks(),
+        [deadlock(&[1, 2], &[12, 10])]
+    );
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+}
+
+#[test]
+fn test_back_out() {
+    let track = track();
+    let route = route(&track, &[10, 12, 14, 16, 9, 10]);
+    let mut simulation = simulate_deadlock(track, route, DeadlockResolution::RerouteOrBackOut);
+
+    // T1 can't be rerouted on its closed route, so it backs out into S9
+    let t1 = simulation.controller().train(TrainId::new(1)).unwrap();
+    assert_eq!(t1.orientation(), Direction::Forward);
+    assert_eq!(vias(&simulation, 1), [section(10), section(9)]);
+    assert_eq!(t1.state(), TrainState::Default);
+    assert_eq!(simulation.controller().deadlocks(), []);
+    assert!(
+        !simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+
+    // T1 doesn't wait for S12 anymore, so the deadlock is reported as resolved
+    assert!(!simulation.controller().section_queues[&section(12)].contains(&TrainId::new(1)));
+    simulation
+        .with_controller(|controller, ctx| controller.check_deadlocks(ctx))
+        .unwrap();
+    assert!(simulation.controller().known_deadlocks.is_empty());
+}
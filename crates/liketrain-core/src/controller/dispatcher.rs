@@ -0,0 +1,463 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    time::{self, Duration},
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Controller, ControllerError, Route, ScheduledEvent, SectionId, TrainId, TrainState,
+    ui::UiTrainEvent,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Error)]
+pub enum DispatcherConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DispatcherDestination {
+    pub section_id: SectionId,
+
+    /// The relative probability of this destination being picked.
+    pub weight: u32,
+}
+
+impl DispatcherDestination {
+    pub fn new(section_id: impl Into<SectionId>, weight: u32) -> Self {
+        Self {
+            section_id: section_id.into(),
+            weight,
+        }
+    }
+}
+
+/// Loaded from a JSON file, settings that are left out keep their default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DispatcherConfig {
+    pub destinations: Vec<DispatcherDestination>,
+
+    /// The maximum number of trains that are on their way at the same time.
+    pub max_moving_trains: usize,
+
+    /// How long a train stays at its destination, before it is dispatched again.
+    pub dwell_time: Duration,
+
+    /// If a train is waiting for a section longer than this, it will be sent to another destination.
+    pub max_waiting_time: Duration,
+
+    /// The seed for picking destinations. If `None`, a random seed is used.
+    pub seed: Option<u64>,
+}
+
+impl DispatcherConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DispatcherConfigError> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl Default for DispatcherConfig {
+    fn default() -> Self {
+        Self {
+            destinations: Vec::new(),
+            max_moving_trains: 2,
+            dwell_time: Duration::from_secs(20),
+            max_waiting_time: Duration::from_secs(60),
+            seed: None,
+        }
+    }
+}
+
+pub struct Dispatcher {
+    config: DispatcherConfig,
+    rng: StdRng,
+
+    /// Trains that are ready to be dispatched, in the order they got ready.
+    pending: VecDeque<TrainId>,
+
+    /// The destination of each dispatched train, that hasn't arrived yet.
+    destinations: HashMap<TrainId, SectionId>,
+
+    /// Since when a dispatched train is waiting for a section.
+    waiting_since: HashMap<TrainId, time::Instant>,
+}
+
+impl Dispatcher {
+    pub(super) const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(config: DispatcherConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        Self {
+            config,
+            rng,
+            pending: VecDeque::new(),
+            destinations: HashMap::new(),
+            waiting_since: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DispatcherConfig {
+        &self.config
+    }
+
+    pub fn enqueue(&mut self, train_id: TrainId) {
+        if !self.pending.contains(&train_id) {
+            self.pending.push_back(train_id);
+        }
+    }
+
+    /// Stop taking care of the train, e.g. because it was removed or got a route from the operator.
+    pub(super) fn forget(&mut self, train_id: TrainId) {
+        self.pending.retain(|&id| id != train_id);
+        self.destinations.remove(&train_id);
+        self.waiting_since.remove(&train_id);
+    }
+
+    pub fn pending_trains(&self) -> impl Iterator<Item = TrainId> {
+        self.pending.iter().copied()
+    }
+
+    pub fn destination(&self, train_id: TrainId) -> Option<SectionId> {
+        self.destinations.get(&train_id).copied()
+    }
+
+    /// Pick a random destination, weighted by the configured weights.
+    /// Only destinations for which `is_candidate` returns true are considered.
+    fn pick_destination(
+        &mut self,
+        mut is_candidate: impl FnMut(SectionId) -> bool,
+    ) -> Option<SectionId> {
+        let candidates = self
+            .config
+            .destinations
+            .iter()
+            .filter(|destination| destination.weight > 0)
+            .filter(|destination| {
+                !self
+                    .destinations
+                    .values()
+                    .any(|&id| id == destination.section_id)
+            })
+            .filter(|destination| is_candidate(destination.section_id))
+            .map(|destination| (destination.section_id, destination.weight))
+            .collect::<Vec<_>>();
+
+        let total_weight: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut pick = self.rng.random_range(0..total_weight);
+        for (section_id, weight) in candidates {
+            if pick < weight {
+                return Some(section_id);
+            }
+            pick -= weight;
+        }
+
+        None
+    }
+}
+
+impl Controller {
+    fn moving_trains(&self) -> usize {
+        self.trains
+            .values()
+            .filter(|train| !train.has_finished_route() && !train.state().is_held())
+            .count()
+    }
+
+    /// The train reached the last section of its route.
+    /// After the dwell time it will be handed over to the dispatcher.
+    pub(super) fn train_arrived(&mut self, train_id: TrainId) {
+        let Some(dispatcher) = self.dispatcher.as_mut() else {
+            return;
+        };
+
+        dispatcher.destinations.remove(&train_id);
+        dispatcher.waiting_since.remove(&train_id);
+
+        let dwell_time = dispatcher.config.dwell_time;
+        self.scheduler.schedule_for_train(
+            train_id,
+            dwell_time,
+            ScheduledEvent::DispatchTrain { train_id },
+        );
+    }
+
+    /// Find a route from the current section of the train to the destination.
+    fn route_to(&self, train_id: TrainId, destination: SectionId) -> Option<Route> {
+        self.route_to_avoiding(train_id, destination, &[])
+    }
+
+    pub(super) fn route_to_avoiding(
+        &self,
+        train_id: TrainId,
+        destination: SectionId,
+        avoid: &[SectionId],
+    ) -> Option<Route> {
+        let train = self.trains.get(&train_id)?;
+        let current_section = train.get_current_section()?;
+        let direction = train.orientation();
+
+        let path = self
+            .track
+            .find_path_avoiding(current_section, direction, destination, avoid)?;
+        if path.len() < 2 {
+            return None;
+        }
+
+        let name = format!("{} -> S{}", train.name(), destination);
+        Route::new(name, path, direction, &self.track)
+    }
+
+    /// Dispatch as many pending trains as allowed, the ones that are waiting the longest first.
+    pub(super) fn dispatch_pending_trains(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(dispatcher) = self.dispatcher.as_ref() else {
+            return Ok(());
+        };
+
+        let max_moving_trains = dispatcher.config.max_moving_trains;
+        let pending = dispatcher.pending_trains().collect::<Vec<_>>();
+
+        for train_id in pending {
+            if self.moving_trains() >= max_moving_trains {
+                break;
+            }
+
+            // trains stopped by the operator stay where they are
+            let Some(current_section) = self
+                .trains
+                .get(&train_id)
+                .filter(|train| !train.state().is_held())
+                .and_then(|train| train.get_current_section())
+            else {
+                continue;
+            };
+
+            // only pick destinations that are reachable and not blocked by another train
+            let mut candidate_routes = HashMap::new();
+            let mut dispatcher = self.dispatcher.take().unwrap();
+            let destination = dispatcher.pick_destination(|section_id| {
+                if section_id == current_section || !self.is_section_available(section_id, train_id)
+                {
+                    return false;
+                }
+
+                match self.route_to(train_id, section_id) {
+                    Some(route) => {
+                        candidate_routes.insert(section_id, route);
+                        true
+                    }
+                    None => false,
+                }
+            });
+            self.dispatcher = Some(dispatcher);
+
+            let Some(destination) = destination else {
+                // no destination available right now, try again on the next tick.
+                // Keep the order, so this train is still first in line.
+                continue;
+            };
+
+            let route = candidate_routes.remove(&destination).unwrap();
+
+            let dispatcher = self.dispatcher.as_mut().unwrap();
+            dispatcher.pending.retain(|&id| id != train_id);
+            dispatcher.destinations.insert(train_id, destination);
+
+            log::info!(
+                "dispatching train {} to section {}: {}",
+                train_id,
+                destination,
+                route.pretty_print(&self.track)
+            );
+
+            self.depart_on_route(train_id, route, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    /// Assign a new route to a train that is standing in the first section of the route and let it depart.
+    pub(super) fn depart_on_route(
+        &mut self,
+        train_id: TrainId,
+        route: Route,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if !self.change_route(train_id, route)? {
+            return Ok(());
+        }
+
+        let train = self.train_mut(train_id)?;
+        let previous_state = train.state();
+        let power = train.target_power();
+
+        train.set_state(TrainState::Default);
+        if previous_state != TrainState::Default {
+            self.emit_ui(UiTrainEvent::StateChanged {
+                train_id,
+                state: TrainState::Default,
+            });
+        }
+
+        // the train might have been stopped in its current section.
+        // Accelerate it, if the sections ahead could be reserved.
+        if self.request_sections_ahead(train_id, ctx)? {
+            self.ramp_train(train_id, power, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    /// Let the train drive a new route, starting at the section it is currently in, and report it to the ui.
+    /// Returns false, if the route starts in the other direction than the train is facing.
+    pub(super) fn change_route(
+        &mut self,
+        train_id: TrainId,
+        route: Route,
+    ) -> Result<bool, ControllerError> {
+        let train = self.train_mut(train_id)?;
+        if !train.set_route(route.clone()) {
+            log::warn!(
+                "train {} can't drive route {}, it is facing {}",
+                train_id,
+                route.name(),
+                train.orientation()
+            );
+            return Ok(false);
+        }
+
+        self.emit_ui(UiTrainEvent::RouteChanged { train_id, route });
+
+        Ok(true)
+    }
+
+    pub(super) fn dispatcher_tick(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(dispatcher) = self.dispatcher.as_mut() else {
+            return Ok(());
+        };
+
+        // keep track of how long the dispatched trains are waiting for a section
+        let now = self.scheduler.now();
+        let max_waiting_time = dispatcher.config.max_waiting_time;
+
+        let mut starving_trains = Vec::new();
+        for (&train_id, train) in self.trains.iter() {
+            if !dispatcher.destinations.contains_key(&train_id)
+                || train.state() != TrainState::Waiting
+            {
+                dispatcher.waiting_since.remove(&train_id);
+                continue;
+            }
+
+            let waiting_since = *dispatcher.waiting_since.entry(train_id).or_insert(now);
+            if now - waiting_since >= max_waiting_time {
+                starving_trains.push(train_id);
+            }
+        }
+
+        // give trains, that are waiting for too long, another destination
+        for train_id in starving_trains {
+            self.redispatch_waiting_train(train_id, ctx)?;
+        }
+
+        self.dispatch_pending_trains(ctx)
+    }
+
+    /// Send a train, that waits for a blocked section, to another destination that doesn't require the blocked section.
+    fn redispatch_waiting_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let train = self.train(train_id)?;
+        let (Some(current_section), Some(blocked_section)) =
+            (train.get_current_section(), train.get_next_section())
+        else {
+            return Ok(());
+        };
+
+        let mut candidate_routes = HashMap::new();
+        let mut dispatcher = self.dispatcher.take().unwrap();
+
+        // the current destination of this train is not reachable for now
+        let previous_destination = dispatcher.destinations.remove(&train_id);
+
+        let destination = dispatcher.pick_destination(|section_id| {
+            if section_id == current_section
+                || Some(section_id) == previous_destination
+                || !self.is_section_available(section_id, train_id)
+            {
+                return false;
+            }
+
+            match self.route_to_avoiding(train_id, section_id, &[blocked_section]) {
+                Some(route) => {
+                    candidate_routes.insert(section_id, route);
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if destination.is_none()
+            && let Some(previous_destination) = previous_destination
+        {
+            dispatcher
+                .destinations
+                .insert(train_id, previous_destination);
+        }
+
+        self.dispatcher = Some(dispatcher);
+
+        let Some(destination) = destination else {
+            log::debug!(
+                "train {} is waiting for too long, but there is no other destination",
+                train_id
+            );
+            return Ok(());
+        };
+
+        log::info!(
+            "train {} is waiting for section {} for too long, sending it to section {}",
+            train_id,
+            blocked_section,
+            destination
+        );
+
+        let dispatcher = self.dispatcher.as_mut().unwrap();
+        dispatcher.destinations.insert(train_id, destination);
+        dispatcher.waiting_since.remove(&train_id);
+
+        self.leave_section_queue(train_id, blocked_section);
+
+        let route = candidate_routes.remove(&destination).unwrap();
+        self.depart_on_route(train_id, route, ctx)
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    controller::testing::{config, drive, occupy, route, section, simulate, track, train},
+    ui::UiCommand,
+};
+
+use super::*;
+
+fn dispatcher_config(destinations: &[(usize, u32)]) -> DispatcherConfig {
+    DispatcherConfig {
+        destinations: destinations
+            .iter()
+            .map(|&(section_id, weight)| DispatcherDestination::new(section_id, weight))
+            .collect(),
+        dwell_time: Duration::from_secs(1),
+        max_waiting_time: Duration::from_secs(5),
+        seed: Some(42),
+        ..Default::default()
+    }
+}
+
+/// The trains a dispatcher sent to a destination.
+fn dispatched_trains(dispatcher: &Dispatcher) -> Vec<TrainId> {
+    let mut trains = dispatcher.destinations.keys().copied().collect::<Vec<_>>();
+    trains.sort();
+    trains
+}
+
+#[test]
+fn test_weighted_destinations() {
+    let mut dispatcher = Dispatcher::new(dispatcher_config(&[(9, 3), (10, 1), (5, 0)]));
+
+    let mut picks = HashMap::new();
+    for _ in 0..4000 {
+        let destination = dispatcher.pick_destination(|_| true).unwrap();
+        *picks.entry(destination).or_insert(0) += 1;
+    }
+
+    // destinations without weight are never picked, the others as often as their weight says
+    assert!(!picks.contains_key(&section(5)));
+    let share = picks[&section(9)] as f32 / 4000.0;
+    assert!((0.7..0.8).contains(&share), "S9 was picked {}", share);
+
+    // only candidates are picked
+    assert_eq!(
+        dispatcher.pick_destination(|section_id| section_id == section(10)),
+        Some(section(10))
+    );
+    assert_eq!(
+        dispatcher.pick_destination(|section_id| section_id == section(5)),
+        None
+    );
+}
+
+#[test]
+fn test_dispatcher_config() {
+    let config: DispatcherConfig =
+        serde_json::from_str(include_str!("../../../../../resources/dispatcher.json")).unwrap();
+    assert_eq!(config.destinations.len(), 2);
+    assert_eq!(config.destinations[0].section_id, section(12));
+
+    // left out settings keep their default
+    assert_eq!(config.seed, None);
+}
+
+#[test]
+fn test_max_moving_trains() {
+    let track = track();
+    let trains = [(1, 12), (2, 11), (3, 13)].map(|(train_id, section_id)| {
+        let name = format!("T{}", train_id);
+        (train_id, train(&name, route(&track, &[section_id])))
+    });
+
+    let mut config = config(track, trains);
+    config.dispatcher = Some(DispatcherConfig {
+        max_moving_trains: 1,
+        ..dispatcher_config(&[(9, 1), (10, 1), (5, 1)])
+    });
+
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    for section_id in [12, 11, 13] {
+        occupy(&mut simulation, section_id);
+    }
+
+    // all trains are ready after their dwell time, only one of them is sent off
+    simulation.run_for(Duration::from_secs(3)).unwrap();
+
+    let dispatcher = simulation.controller().dispatcher.as_ref().unwrap();
+    let dispatched = dispatched_trains(dispatcher);
+    assert_eq!(dispatched.len(), 1);
+    assert_eq!(dispatcher.pending_trains().count(), 2);
+
+    // the next train departs, once the first one doesn't count as moving anymore
+    simulation
+        .command(UiCommand::PauseTrain {
+            train_id: dispatched[0],
+        })
+        .unwrap();
+    simulation.run_for(Duration::from_secs(2)).unwrap();
+
+    let dispatcher = simulation.controller().dispatcher.as_ref().unwrap();
+    assert_eq!(dispatched_trains(dispatcher).len(), 2);
+    assert_eq!(dispatcher.pending_trains().count(), 1);
+}
+
+#[test]
+fn test_redispatch_starving_train() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12]))),
+        (2, train("T2", route(&track, &[10]))),
+    ];
+
+    let mut config = config(track, trains);
+    config.dispatcher = Some(DispatcherConfig {
+        dwell_time: Duration::from_secs(3600),
+        ..dispatcher_config(&[(11, 1), (5, 1)])
+    });
+
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    let train_id = TrainId::new(1);
+
+    // the second train stays in S10, which every way to S11 leads through
+    occupy(&mut simulation, 12);
+    occupy(&mut simulation, 10);
+    simulation
+        .command(UiCommand::PauseTrain {
+            train_id: TrainId::new(2),
+        })
+        .unwrap();
+
+    simulation
+        .with_controller(|controller, ctx| {
+            let route = controller.route_to(train_id, section(11)).unwrap();
+            let dispatcher = controller.dispatcher.as_mut().unwrap();
+            dispatcher.destinations.insert(train_id, section(11));
+
+            controller.depart_on_route(train_id, route, ctx)
+        })
+        .unwrap();
+
+    // the train drives up to S10 and has to wait there
+    drive(&mut simulation, &[12, 14, 16, 9]);
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.state(), TrainState::Waiting);
+
+    // after waiting for too long, it is sent to the destination, that doesn't need S10
+    simulation.run_for(Duration::from_secs(7)).unwrap();
+
+    let controller = simulation.controller();
+    let dispatcher = controller.dispatcher.as_ref().unwrap();
+    assert_eq!(dispatcher.destination(train_id), Some(section(5)));
+
+    let train = controller.train(train_id).unwrap();
+    assert_eq!(train.route().unwrap().vias(), &[section(9), section(5)]);
+    assert_eq!(train.state(), TrainState::Default);
+}
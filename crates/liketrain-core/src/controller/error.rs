@@ -2,7 +2,7 @@ use crossbeam::channel::{RecvError, SendError};
 use liketrain_hardware::{command::HardwareCommand, event::HardwareEvent};
 use thiserror::Error;
 
-use crate::TrainId;
+use crate::{ControllerSnapshotError, JournalError, TrainId};
 
 #[derive(Debug, Error)]
 pub enum ControllerError {
@@ -20,4 +20,14 @@ pub enum ControllerError {
 
     #[error("Serial communication error: {0}")]
     Serial(#[from] serialport::Error),
+
+    #[error("Journal error: {0}")]
+    Journal(#[from] JournalError),
+
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] ControllerSnapshotError),
+
+    /// A clock, that only moves when it is advanced, like the `ManualClock`, can only be used in a `Simulation`.
+    #[error("The clock doesn't move on its own, use a Simulation to run on it")]
+    ClockNotRealTime,
 }
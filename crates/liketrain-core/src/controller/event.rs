@@ -1,8 +1,9 @@
 use liketrain_hardware::event::HardwareEvent;
+use serde::{Deserialize, Serialize};
 
 use crate::{SectionId, TrainId, TrainSpeed};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ScheduledEvent {
     TrainEnteredSection {
         train_id: TrainId,
@@ -16,9 +17,79 @@ pub enum ScheduledEvent {
         train_id: TrainId,
         speed: TrainSpeed,
     },
+
+    /// The next power step of a running acceleration or deceleration ramp.
+    TrainRampStep {
+        train_id: TrainId,
+    },
+
+    /// The train finished its dwell time at its destination and can be dispatched again.
+    DispatchTrain {
+        train_id: TrainId,
+    },
+    DispatcherTick,
+
+    /// Restart a train, that was driving before an emergency stop.
+    ResumeTrain {
+        train_id: TrainId,
+    },
+
+    /// Check whether the train reached the end of its section in time.
+    WatchdogCheck {
+        train_id: TrainId,
+    },
+
+    /// Power the sections of an overdue train like before its power pulse.
+    WatchdogPulseEnd {
+        train_id: TrainId,
+    },
+
+    /// Look for trains waiting for each other.
+    DeadlockCheck,
+
+    /// Publish the estimated positions of the moving trains.
+    PositionEstimateTick,
+
+    /// Save the state of the controller to the snapshot file.
+    SaveSnapshot,
+
+    /// Check whether the hardware detected the trains of a restored snapshot.
+    ReconcileSnapshot,
+
+    /// Check the automation scripts for changes.
+    ReloadScripts,
+
+    /// Call a function of a script, that it scheduled with `after`.
+    ScriptTimer {
+        script: String,
+        function: String,
+    },
+}
+
+impl ScheduledEvent {
+    /// The train this event is about, if any.
+    pub fn train_id(&self) -> Option<TrainId> {
+        match self {
+            ScheduledEvent::TrainEnteredSection { train_id, .. }
+            | ScheduledEvent::TrainLeftSection { train_id, .. }
+            | ScheduledEvent::TrainSpeedChanged { train_id, .. }
+            | ScheduledEvent::TrainRampStep { train_id, .. }
+            | ScheduledEvent::DispatchTrain { train_id }
+            | ScheduledEvent::ResumeTrain { train_id }
+            | ScheduledEvent::WatchdogCheck { train_id, .. }
+            | ScheduledEvent::WatchdogPulseEnd { train_id } => Some(*train_id),
+            ScheduledEvent::DispatcherTick
+            | ScheduledEvent::DeadlockCheck
+            | ScheduledEvent::PositionEstimateTick
+            | ScheduledEvent::SaveSnapshot
+            | ScheduledEvent::ReconcileSnapshot
+            | ScheduledEvent::ReloadScripts
+            | ScheduledEvent::ScriptTimer { .. } => None,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ControllerEvent {
     Scheduled(ScheduledEvent),
     Hardware(HardwareEvent),
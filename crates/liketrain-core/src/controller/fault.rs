@@ -0,0 +1,202 @@
+use liketrain_hardware::event::HardwareSectionPower;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Controller, ControllerError, SectionId, TrainId, TrainState,
+    ui::{UiControllerEvent, UiTrainEvent},
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+/// Something happened on the track, that the controller didn't expect.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum ControllerFault {
+    /// A section was occupied, but no train was inbound for it, e.g. a wagon was left behind.
+    #[error("Section {section_id} was occupied, but no train was inbound")]
+    UnexpectedOccupancy { section_id: SectionId },
+
+    /// A section was occupied, but multiple trains were inbound for it.
+    #[error("Section {section_id} was occupied, but trains {trains:?} were inbound")]
+    AmbiguousEntry {
+        section_id: SectionId,
+        trains: Vec<TrainId>,
+    },
+
+    /// A train entered a neighbouring section instead of its next section,
+    /// e.g. because it ran past a switch that wasn't set correctly.
+    #[error("Train {train_id} entered section {actual} instead of section {expected}")]
+    WrongSection {
+        train_id: TrainId,
+        expected: SectionId,
+        actual: SectionId,
+    },
+
+    /// A train of a restored snapshot wasn't detected in its section after the restart,
+    /// e.g. because it was taken off the track while the controller was down.
+    #[error("Train {train_id} wasn't detected in section {section_id} after the restart")]
+    MissingTrain {
+        train_id: TrainId,
+        section_id: SectionId,
+    },
+}
+
+impl ControllerFault {
+    /// The section, in which the fault was detected.
+    pub fn section_id(&self) -> SectionId {
+        match self {
+            Self::UnexpectedOccupancy { section_id } | Self::AmbiguousEntry { section_id, .. } => {
+                *section_id
+            }
+            Self::WrongSection { actual, .. } => *actual,
+            Self::MissingTrain { section_id, .. } => *section_id,
+        }
+    }
+
+    /// The trains, that are involved in the fault.
+    pub fn train_ids(&self) -> Vec<TrainId> {
+        match self {
+            Self::UnexpectedOccupancy { .. } => Vec::new(),
+            Self::AmbiguousEntry { trains, .. } => trains.clone(),
+            Self::WrongSection { train_id, .. } | Self::MissingTrain { train_id, .. } => {
+                vec![*train_id]
+            }
+        }
+    }
+}
+
+/// How the controller reacts to a fault.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FaultReaction {
+    /// Only report the fault.
+    Ignore,
+
+    /// Power off the section of the fault and its neighbouring sections,
+    /// and stop the trains driving on them.
+    #[default]
+    StopAdjacent,
+
+    /// Stop everything, like an emergency stop.
+    StopAll,
+}
+
+/// The reaction to each kind of fault.
+#[derive(Debug, Clone)]
+pub struct FaultReactions {
+    pub unexpected_occupancy: FaultReaction,
+    pub ambiguous_entry: FaultReaction,
+    pub wrong_section: FaultReaction,
+    pub missing_train: FaultReaction,
+}
+
+impl Default for FaultReactions {
+    fn default() -> Self {
+        Self {
+            unexpected_occupancy: FaultReaction::StopAdjacent,
+            ambiguous_entry: FaultReaction::StopAll,
+            wrong_section: FaultReaction::StopAll,
+            missing_train: FaultReaction::StopAdjacent,
+        }
+    }
+}
+
+impl FaultReactions {
+    pub fn reaction(&self, fault: &ControllerFault) -> FaultReaction {
+        match fault {
+            ControllerFault::UnexpectedOccupancy { .. } => self.unexpected_occupancy,
+            ControllerFault::AmbiguousEntry { .. } => self.ambiguous_entry,
+            ControllerFault::WrongSection { .. } => self.wrong_section,
+            ControllerFault::MissingTrain { .. } => self.missing_train,
+        }
+    }
+}
+
+impl Controller {
+    /// Report the fault to the UI and react to it, as configured.
+    pub(super) fn report_fault(
+        &mut self,
+        fault: ControllerFault,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let reaction = self.fault_reactions.reaction(&fault);
+        log::error!("{} ({:?})", fault, reaction);
+
+        self.emit_ui(UiControllerEvent::Fault(fault.clone()));
+
+        let mut stopped_trains = match reaction {
+            FaultReaction::Ignore => return Ok(()),
+            FaultReaction::StopAdjacent => self.stop_adjacent_sections(fault.section_id(), ctx)?,
+            FaultReaction::StopAll => {
+                self.emergency_stop(ctx)?;
+                Vec::new()
+            }
+        };
+
+        // the trains stay where they are, until the operator checked them
+        stopped_trains.extend(fault.train_ids());
+        for train_id in stopped_trains {
+            if !self.trains.contains_key(&train_id) {
+                continue;
+            }
+
+            self.scheduler.cancel_train(train_id);
+            self.set_train_state(train_id, TrainState::Faulted)?;
+        }
+
+        Ok(())
+    }
+
+    /// The operator checked the train after a fault. It gives up the sections ahead of it
+    /// and stays stopped, until it is started again.
+    pub(super) fn acknowledge_fault(&mut self, train_id: TrainId) -> Result<(), ControllerError> {
+        if self.train(train_id)?.state() != TrainState::Faulted {
+            return Ok(());
+        }
+
+        log::info!("the fault of train {} was acknowledged", train_id);
+
+        self.release_sections_ahead(train_id);
+        self.set_train_state(train_id, TrainState::Stopped)?;
+        self.emit_ui(UiTrainEvent::Stopped { train_id });
+
+        Ok(())
+    }
+
+    /// Power off the section and its neighbouring sections. Trains driving on them are stopped
+    /// immediately, without their deceleration ramp. Returns the stopped trains.
+    fn stop_adjacent_sections(
+        &mut self,
+        section_id: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<Vec<TrainId>, ControllerError> {
+        let mut sections = self.track.neighbouring_sections(section_id);
+        sections.push(section_id);
+
+        let affected_trains = self
+            .trains
+            .keys()
+            .copied()
+            .filter(|&train_id| {
+                self.train_powered_sections(train_id)
+                    .iter()
+                    .any(|section_id| sections.contains(section_id))
+            })
+            .collect::<Vec<_>>();
+
+        for &train_id in &affected_trains {
+            log::warn!("stopping train {}", train_id);
+
+            self.cancel_ramp(train_id);
+            self.apply_train_power(train_id, HardwareSectionPower::Off, ctx)?;
+        }
+
+        for section_id in sections {
+            self.power_section(section_id, HardwareSectionPower::Off, None, ctx)?;
+        }
+
+        Ok(affected_trains)
+    }
+}
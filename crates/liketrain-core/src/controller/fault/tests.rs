@@ -0,0 +1,158 @@
+use std::{sync::mpsc, time::Duration};
+
+use crate::{
+    comm::Simulation,
+    controller::testing::{config, drive, occupy, route, section, simulate, track, train},
+    ui::{UiCommand, UiEvent},
+};
+
+use super::*;
+
+/// The faults, that were reported to the ui.
+fn faults(ui_event_rx: &mpsc::Receiver<UiEvent>) -> Vec<ControllerFault> {
+    ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiControllerEvent(UiControllerEvent::Fault(fault)) => Some(fault),
+            _ => None,
+        })
+        .collect()
+}
+
+fn train_state(simulation: &Simulation, train_id: usize) -> TrainState {
+    let train = simulation.controller().train(TrainId::new(train_id));
+    train.unwrap().state()
+}
+
+/// A faulted train can't be started, until its fault was acknowledged.
+fn assert_faulted(simulation: &mut Simulation, train_id: usize) {
+    assert_eq!(train_state(simulation, train_id), TrainState::Faulted);
+
+    let train_id = TrainId::new(train_id);
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    assert!(simulation.controller().train_power(train_id).is_off());
+
+    simulation
+        .command(UiCommand::AcknowledgeFault { train_id })
+        .unwrap();
+    assert_eq!(
+        simulation.controller().train(train_id).unwrap().state(),
+        TrainState::Stopped
+    );
+}
+
+#[test]
+fn test_unexpected_occupancy() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 12);
+
+    // nothing was inbound for S16, the train is stopped, because it drives in the neighbouring S14
+    occupy(&mut simulation, 16);
+    assert_eq!(
+        faults(&ui_event_rx),
+        [ControllerFault::UnexpectedOccupancy {
+            section_id: section(16)
+        }]
+    );
+    assert!(!simulation.controller().is_halted());
+    assert_faulted(&mut simulation, 1);
+
+    // once acknowledged, the train can be started again
+    simulation.step().unwrap();
+    let train_id = TrainId::new(1);
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    assert_eq!(train_state(&simulation, 1), TrainState::Default);
+    assert!(!simulation.controller().train_power(train_id).is_off());
+}
+
+#[test]
+fn test_ambiguous_entry() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12, 14]))),
+        (2, train("T2", route(&track, &[12, 14]))),
+    ];
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, trains), []);
+
+    // both trains start in S12
+    occupy(&mut simulation, 12);
+    assert_eq!(
+        faults(&ui_event_rx),
+        [ControllerFault::AmbiguousEntry {
+            section_id: section(12),
+            trains: vec![TrainId::new(1), TrainId::new(2)],
+        }]
+    );
+    assert!(simulation.controller().is_halted());
+
+    // resuming doesn't restart the trains of the fault
+    simulation.command(UiCommand::Resume).unwrap();
+    simulation.run_for(Duration::from_secs(2)).unwrap();
+    assert_faulted(&mut simulation, 1);
+    assert_faulted(&mut simulation, 2);
+}
+
+#[test]
+fn test_wrong_section() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 12);
+
+    // the train ran into S10 instead of S14
+    occupy(&mut simulation, 10);
+    assert_eq!(
+        faults(&ui_event_rx),
+        [ControllerFault::WrongSection {
+            train_id: TrainId::new(1),
+            expected: section(14),
+            actual: section(10),
+        }]
+    );
+    assert!(simulation.controller().is_halted());
+    assert_faulted(&mut simulation, 1);
+}
+
+#[test]
+fn test_missing_train() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, _ui_event_rx) = simulate(config(track.clone(), [(1, train.clone())]), []);
+    occupy(&mut simulation, 12);
+    drive(&mut simulation, &[12, 14]);
+    let snapshot = simulation.controller().snapshot();
+
+    // after the restart, the train isn't detected in S14
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation =
+        Simulation::restore(config(track, [(1, train)]), snapshot, [], ui_event_tx).unwrap();
+    simulation
+        .run_for(Controller::RECONCILE_TIMEOUT + Duration::from_millis(100))
+        .unwrap();
+
+    assert_eq!(
+        faults(&ui_event_rx),
+        [ControllerFault::MissingTrain {
+            train_id: TrainId::new(1),
+            section_id: section(14),
+        }]
+    );
+    assert!(!simulation.controller().is_halted());
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+    assert_faulted(&mut simulation, 1);
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use liketrain_hardware::{command::HardwareCommand, event::HardwareSectionPower};
+
+use crate::{
+    Controller, ControllerError, ScheduledEvent, TrainId, TrainState, ui::UiControllerEvent,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+/// What the controller was doing, before it was halted by an emergency stop.
+#[derive(Debug, Default)]
+pub(super) struct ControllerHalt {
+    /// The trains that were driving or accelerating. They are restarted with the power for their
+    /// speed at the time they are resumed, so a speed changed during the halt isn't lost.
+    driving_trains: Vec<TrainId>,
+}
+
+impl Controller {
+    /// The delay between restarting two trains after an emergency stop.
+    pub const RESUME_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Whether the controller was halted by an emergency stop.
+    pub fn is_halted(&self) -> bool {
+        self.halt.is_some()
+    }
+
+    /// Power off all sections before any other queued hardware command and freeze the scheduler,
+    /// until the controller is resumed.
+    pub(super) fn emergency_stop(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.is_halted() {
+            return Ok(());
+        }
+
+        log::warn!("emergency stop");
+
+        for section_id in self.track.section_ids().collect::<Vec<_>>() {
+            ctx.exec_priority(HardwareCommand::SetSectionPower {
+                section_id: section_id.as_u32(),
+                power: HardwareSectionPower::Off,
+                polarity: self.section_polarity(section_id),
+            })?;
+
+            self.section_states
+                .entry(section_id)
+                .or_default()
+                .power_reason = None;
+        }
+
+        let mut driving_trains = Vec::new();
+
+        for train_id in self.trains.keys().copied().collect::<Vec<_>>() {
+            // a train accelerating is restarted as well, one braking to a stop isn't
+            let power = self
+                .cancel_ramp(train_id)
+                .unwrap_or_else(|| self.train_power(train_id));
+            if !power.is_off() {
+                driving_trains.push(train_id);
+            }
+
+            // the train drove with its previous power until now
+            self.advance_position_estimate(train_id);
+            self.invalidate_section_timing(train_id);
+            self.train_powers
+                .insert(train_id, HardwareSectionPower::Off);
+        }
+
+        self.scheduler.freeze();
+        self.halt = Some(ControllerHalt { driving_trains });
+
+        self.emit_ui(UiControllerEvent::Halted);
+
+        Ok(())
+    }
+
+    /// Continue after an emergency stop. The trains that were driving are restarted one after
+    /// another, the ones that can continue into their reserved next section first.
+    pub(super) fn resume(&mut self) {
+        let Some(halt) = self.halt.take() else {
+            return;
+        };
+
+        log::info!("resuming after emergency stop");
+
+        self.scheduler.resume();
+
+        let mut trains = halt.driving_trains;
+        trains.sort_by_key(|&train_id| (!self.has_reserved_next_section(train_id), train_id));
+
+        for (idx, train_id) in trains.into_iter().enumerate() {
+            self.scheduler.schedule_for_train(
+                train_id,
+                Self::RESUME_INTERVAL * idx as u32,
+                ScheduledEvent::ResumeTrain { train_id },
+            );
+        }
+
+        self.emit_ui(UiControllerEvent::Resumed);
+    }
+
+    /// Restart a train, that was driving before the emergency stop.
+    pub(super) fn resume_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        // the controller was halted again, the train is resumed with the other trains
+        if self.is_halted() {
+            return Ok(());
+        }
+
+        let train = self.train(train_id)?;
+
+        // waiting trains are restarted, once their next section is free.
+        // Trains at the end of their route or stopped by the operator stay where they are.
+        if train.state() == TrainState::Waiting
+            || train.state().is_held()
+            || train.get_next_section().is_none()
+        {
+            return Ok(());
+        }
+
+        let target_power = train.target_power();
+
+        // reserve the sections ahead again, or let the train wait for them
+        if !self.request_sections_ahead(train_id, ctx)? {
+            return Ok(());
+        }
+
+        self.ramp_train(train_id, target_power, ctx)
+    }
+
+    fn has_reserved_next_section(&self, train_id: TrainId) -> bool {
+        self.trains
+            .get(&train_id)
+            .and_then(|train| train.get_next_section())
+            .is_some_and(|next_section| self.section_reservation(next_section) == Some(train_id))
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+use liketrain_hardware::event::HardwareSectionPolarity;
+
+use crate::{
+    TrainSpeed,
+    controller::testing::{config, occupy, record_power, route, section, simulate, track, train},
+    ui::UiCommand,
+};
+
+use super::*;
+
+#[test]
+fn test_emergency_stop() {
+    let track = track();
+    let section_ids = track.section_ids().collect::<HashSet<_>>();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 12);
+    assert!(
+        !simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+    record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    // the emergency stop overtakes a power command, that is still queued
+    simulation
+        .with_controller(|controller, ctx| {
+            ctx.exec(HardwareCommand::SetSectionPower {
+                section_id: 16,
+                power: HardwareSectionPower::Full,
+                polarity: HardwareSectionPolarity::Normal,
+            })?;
+
+            controller.emergency_stop(ctx)
+        })
+        .unwrap();
+
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    // every section is powered off once and the queued command was dropped
+    assert_eq!(powers.len(), section_ids.len());
+    assert!(
+        powers
+            .iter()
+            .all(|(_, _, power)| *power == HardwareSectionPower::Off)
+    );
+    let powered_off = powers
+        .iter()
+        .map(|&(_, section_id, _)| section_id)
+        .collect::<HashSet<_>>();
+    assert_eq!(powered_off, section_ids);
+
+    let controller = simulation.controller();
+    assert!(controller.is_halted());
+    assert!(controller.train_power(TrainId::new(1)).is_off());
+}
+
+#[test]
+fn test_resume() {
+    let track = track();
+    let trains = [
+        (1, train("T1", route(&track, &[12, 14, 16, 9]))),
+        (2, train("T2", route(&track, &[13, 25, 23]))),
+    ];
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, trains), []);
+    occupy(&mut simulation, 12);
+    occupy(&mut simulation, 13);
+
+    simulation.command(UiCommand::EmergencyStop).unwrap();
+
+    // the first train lost its next section during the emergency stop
+    simulation
+        .with_controller(|controller, _| {
+            controller.release_reservation(section(14), TrainId::new(1));
+            Ok(())
+        })
+        .unwrap();
+    record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    simulation.command(UiCommand::Resume).unwrap();
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_secs(2));
+
+    let powered_at = |section_id: usize| {
+        powers
+            .iter()
+            .find(|&&(_, id, power)| id == section(section_id) && !power.is_off())
+            .map(|&(at, ..)| at)
+            .unwrap()
+    };
+
+    // the train, that can continue into its next section, is restarted first
+    assert!(powered_at(13) < Duration::from_millis(100));
+    assert!(powered_at(25) < Duration::from_millis(100));
+
+    // the other one a resume interval later
+    let restarted = Controller::RESUME_INTERVAL..Controller::RESUME_INTERVAL * 2;
+    assert!(restarted.contains(&powered_at(12)), "{:?}", powers);
+    assert!(restarted.contains(&powered_at(14)), "{:?}", powers);
+    assert_eq!(
+        simulation.controller().section_reservation(section(14)),
+        Some(TrainId::new(1))
+    );
+}
+
+#[test]
+fn test_speed_changed_while_halted() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+    let train_id = TrainId::new(1);
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 12);
+    assert_eq!(
+        simulation.controller().train_power(train_id),
+        HardwareSectionPower::Full
+    );
+
+    simulation.command(UiCommand::EmergencyStop).unwrap();
+    simulation
+        .command(UiCommand::SetTrainSpeed {
+            train_id,
+            speed: TrainSpeed::Medium,
+        })
+        .unwrap();
+    record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    // the train is restarted with the speed it was given during the halt
+    simulation.command(UiCommand::Resume).unwrap();
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    assert!(
+        powers
+            .iter()
+            .any(|&(_, section_id, power)| section_id == section(12)
+                && power == HardwareSectionPower::Half),
+        "{:?}",
+        powers
+    );
+    assert!(
+        powers
+            .iter()
+            .all(|&(_, _, power)| power != HardwareSectionPower::Full),
+        "{:?}",
+        powers
+    );
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Controller, ControllerError, SectionId, SwitchId, TrainId,
+    ui::{UiCommand, UiControllerEvent},
+};
+
+use super::{EventExecutionContext, JournalRecord};
+
+#[cfg(test)]
+mod tests;
+
+/// Why a manual command was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum InterlockingViolation {
+    #[error("The controller is halted by an emergency stop")]
+    Halted,
+
+    #[error("Section {section_id} is reserved by train {train_id}")]
+    SectionReserved {
+        section_id: SectionId,
+        train_id: TrainId,
+    },
+
+    #[error("Section {section_id} is occupied by train {train_id}")]
+    SectionOccupied {
+        section_id: SectionId,
+        train_id: TrainId,
+    },
+
+    /// The switch is on the path a train has reserved, or a train is standing on it.
+    #[error("Switch {switch_id} is locked by train {train_id}")]
+    SwitchLocked {
+        switch_id: SwitchId,
+        train_id: TrainId,
+    },
+
+    #[error("Switch {switch_id} doesn't exist")]
+    UnknownSwitch { switch_id: SwitchId },
+
+    /// The hardware only supports switch ids of up to `HARDWARE_SWITCH_ID_MAX_LEN` bytes.
+    #[error("Switch id {switch_id} is too long for the hardware")]
+    SwitchIdTooLong { switch_id: SwitchId },
+
+    #[error("The interlocking can only be overridden with a reason")]
+    MissingOverrideReason,
+}
+
+/// A manual command, that was executed despite violating the interlocking.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterlockingOverride {
+    pub command: UiCommand,
+    pub violation: InterlockingViolation,
+
+    /// Why the operator overrode the interlocking, e.g. for maintenance.
+    pub reason: String,
+}
+
+impl Controller {
+    /// Check a manual command against the reservations and the occupancy of the track.
+    pub(super) fn check_interlocking(
+        &self,
+        command: &UiCommand,
+    ) -> Result<(), InterlockingViolation> {
+        match command {
+            // turning a section off is always safe, even during an emergency stop
+            UiCommand::SetSectionPower { power, .. } if power.is_off() => Ok(()),
+            UiCommand::SetSectionPower { section_id, .. } => {
+                if self.is_halted() {
+                    return Err(InterlockingViolation::Halted);
+                }
+
+                self.check_section_unused(*section_id)
+            }
+            UiCommand::SetSwitchState { switch_id, .. } => {
+                if self.track.switch(switch_id).is_none() {
+                    return Err(InterlockingViolation::UnknownSwitch {
+                        switch_id: switch_id.clone(),
+                    });
+                }
+
+                self.check_switch_unlocked(switch_id)
+            }
+            // starting or reversing a train would power its sections
+            UiCommand::StartTrain { .. }
+            | UiCommand::AssignRoute { .. }
+            | UiCommand::ReverseTrain { .. }
+                if self.is_halted() =>
+            {
+                Err(InterlockingViolation::Halted)
+            }
+            // the new train can't be placed on a section, that is used by another train
+            UiCommand::AddTrain { route, .. } => route
+                .vias
+                .first()
+                .map_or(Ok(()), |&section_id| self.check_section_unused(section_id)),
+            UiCommand::Override { reason, .. } if reason.trim().is_empty() => {
+                Err(InterlockingViolation::MissingOverrideReason)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_section_unused(&self, section_id: SectionId) -> Result<(), InterlockingViolation> {
+        if let Some(train_id) = self.train_covering_section(section_id) {
+            return Err(InterlockingViolation::SectionOccupied {
+                section_id,
+                train_id,
+            });
+        }
+
+        if let Some(train_id) = self.section_reservation(section_id) {
+            return Err(InterlockingViolation::SectionReserved {
+                section_id,
+                train_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_switch_unlocked(&self, switch_id: &SwitchId) -> Result<(), InterlockingViolation> {
+        let locked = |train_id| InterlockingViolation::SwitchLocked {
+            switch_id: switch_id.clone(),
+            train_id,
+        };
+
+        // a train is standing on the switch, or is about to drive onto it
+        if let Some(section_id) = self.track.switch_section_id(switch_id)
+            && let Err(
+                InterlockingViolation::SectionOccupied { train_id, .. }
+                | InterlockingViolation::SectionReserved { train_id, .. },
+            ) = self.check_section_unused(section_id)
+        {
+            return Err(locked(train_id));
+        }
+
+        // a train has reserved a path over the switch
+        for (&train_id, train) in &self.trains {
+            let on_reserved_path = train
+                .upcoming_transitions()
+                .take_while(|transition| {
+                    self.section_reservation(transition.destination()) == Some(train_id)
+                })
+                .flat_map(|transition| transition.required_switch_changes())
+                .any(|change| change.switch_id == *switch_id);
+
+            if on_reserved_path {
+                return Err(locked(train_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Report a manual command, that isn't executed, to the ui.
+    pub(super) fn reject_command(&self, command: UiCommand, violation: InterlockingViolation) {
+        log::warn!("rejected {:?}: {}", command, violation);
+
+        self.emit_ui(UiControllerEvent::CommandRejected {
+            command: Box::new(command),
+            violation,
+        });
+    }
+
+    /// Execute a manual command, even if it violates the interlocking.
+    /// Overriding a violation is logged, written to the journal and reported to the ui.
+    pub(super) fn override_interlocking(
+        &mut self,
+        command: UiCommand,
+        reason: String,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if let Err(violation) = self.check_interlocking(&command) {
+            log::warn!(
+                "interlocking overridden ({}): {:?} despite: {}",
+                reason,
+                command,
+                violation
+            );
+
+            let interlocking_override = InterlockingOverride {
+                command: command.clone(),
+                violation,
+                reason,
+            };
+
+            ctx.record(|| JournalRecord::InterlockingOverride(interlocking_override.clone()));
+            self.emit_ui(UiControllerEvent::InterlockingOverridden(
+                interlocking_override,
+            ));
+        }
+
+        self.exec_ui_command(command, ctx)
+    }
+}
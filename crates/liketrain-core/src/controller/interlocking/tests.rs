@@ -0,0 +1,221 @@
+use std::sync::mpsc;
+
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    ControllerConfig, Journal, JournalRecord, SwitchState,
+    comm::Simulation,
+    controller::testing::{config, occupy, route, section, simulate, track, train},
+    ui::UiEvent,
+};
+
+use super::*;
+
+/// T1 stands in S12 and has reserved S14.
+fn simulate_train(
+    config: impl FnOnce(ControllerConfig) -> ControllerConfig,
+) -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, ui_event_rx) = simulate(config(self::config(track, [(1, train)])), []);
+    occupy(&mut simulation, 12);
+    ui_event_rx.try_iter().count();
+
+    (simulation, ui_event_rx)
+}
+
+fn set_power(section_id: usize, power: HardwareSectionPower) -> UiCommand {
+    UiCommand::SetSectionPower {
+        section_id: section(section_id),
+        power,
+    }
+}
+
+fn override_command(command: UiCommand, reason: &str) -> UiCommand {
+    UiCommand::Override {
+        command: Box::new(command),
+        reason: reason.to_string(),
+    }
+}
+
+fn rejections(ui_event_rx: &mpsc::Receiver<UiEvent>) -> Vec<InterlockingViolation> {
+    ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiControllerEvent(UiControllerEvent::CommandRejected {
+                violation, ..
+            }) => Some(violation),
+            _ => None,
+        })
+        .collect()
+}
+
+fn section_power(simulation: &Simulation, section_id: usize) -> HardwareSectionPower {
+    simulation
+        .controller()
+        .section_states
+        .get(&section(section_id))
+        .map(|state| state.power)
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_power_off_always_passes() {
+    let (mut simulation, ui_event_rx) = simulate_train(|config| config);
+
+    // S14 is reserved by T1, it can't be powered, but turned off
+    simulation
+        .command(set_power(14, HardwareSectionPower::Full))
+        .unwrap();
+    assert_eq!(
+        rejections(&ui_event_rx),
+        [InterlockingViolation::SectionReserved {
+            section_id: section(14),
+            train_id: TrainId::new(1),
+        }]
+    );
+
+    simulation
+        .command(set_power(14, HardwareSectionPower::Off))
+        .unwrap();
+    simulation.step().unwrap();
+    assert_eq!(rejections(&ui_event_rx), []);
+    assert!(section_power(&simulation, 14).is_off());
+
+    // also during an emergency stop
+    simulation.command(UiCommand::EmergencyStop).unwrap();
+    simulation
+        .command(set_power(12, HardwareSectionPower::Off))
+        .unwrap();
+    simulation
+        .command(set_power(25, HardwareSectionPower::Full))
+        .unwrap();
+    assert_eq!(rejections(&ui_event_rx), [InterlockingViolation::Halted]);
+}
+
+#[test]
+fn test_unknown_switch() {
+    let (mut simulation, ui_event_rx) = simulate_train(|config| config);
+
+    let set_switch = |switch_id: &str| UiCommand::SetSwitchState {
+        switch_id: switch_id.into(),
+        state: SwitchState::Right,
+    };
+    let too_long = "S".repeat(33);
+
+    for switch_id in ["Z", too_long.as_str()] {
+        simulation.command(set_switch(switch_id)).unwrap();
+        simulation.step().unwrap();
+
+        assert_eq!(
+            rejections(&ui_event_rx),
+            [InterlockingViolation::UnknownSwitch {
+                switch_id: switch_id.into()
+            }]
+        );
+    }
+
+    // an override passes the interlocking, but the id still doesn't fit the hardware
+    simulation
+        .command(override_command(set_switch(&too_long), "maintenance"))
+        .unwrap();
+    simulation.step().unwrap();
+
+    assert_eq!(
+        rejections(&ui_event_rx),
+        [InterlockingViolation::SwitchIdTooLong {
+            switch_id: too_long.as_str().into()
+        }]
+    );
+}
+
+#[test]
+fn test_override_needs_reason() {
+    let (mut simulation, ui_event_rx) = simulate_train(|config| config);
+
+    // S25 can't be powered during an emergency stop
+    simulation.command(UiCommand::EmergencyStop).unwrap();
+    simulation.step().unwrap();
+
+    for reason in ["", "  "] {
+        simulation
+            .command(override_command(
+                set_power(25, HardwareSectionPower::Full),
+                reason,
+            ))
+            .unwrap();
+        simulation.step().unwrap();
+
+        assert_eq!(
+            rejections(&ui_event_rx),
+            [InterlockingViolation::MissingOverrideReason]
+        );
+        assert!(section_power(&simulation, 25).is_off());
+    }
+
+    simulation
+        .command(override_command(
+            set_power(25, HardwareSectionPower::Full),
+            "maintenance",
+        ))
+        .unwrap();
+    simulation.step().unwrap();
+
+    assert_eq!(rejections(&ui_event_rx), []);
+    assert_eq!(section_power(&simulation, 25), HardwareSectionPower::Full);
+}
+
+#[test]
+fn test_override_is_journaled() {
+    let journal_path = std::env::temp_dir().join(format!(
+        "liketrain-test-override-{}.jsonl",
+        std::process::id()
+    ));
+
+    let (mut simulation, ui_event_rx) = simulate_train(|config| ControllerConfig {
+        journal: Some(journal_path.clone()),
+        ..config
+    });
+
+    simulation
+        .command(override_command(
+            set_power(14, HardwareSectionPower::Full),
+            "maintenance",
+        ))
+        .unwrap();
+
+    let overridden = ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiControllerEvent(UiControllerEvent::InterlockingOverridden(
+                interlocking_override,
+            )) => Some(interlocking_override),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(overridden.len(), 1);
+
+    let journal = Journal::load(&journal_path).unwrap();
+    std::fs::remove_file(&journal_path).unwrap();
+
+    let journaled = journal
+        .entries()
+        .iter()
+        .filter_map(|entry| match &entry.record {
+            JournalRecord::InterlockingOverride(interlocking_override) => {
+                Some(interlocking_override)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(journaled.len(), 1);
+    assert_eq!(journaled[0].reason, "maintenance");
+    assert_eq!(
+        journaled[0].violation,
+        InterlockingViolation::SectionReserved {
+            section_id: section(14),
+            train_id: TrainId::new(1),
+        }
+    );
+}
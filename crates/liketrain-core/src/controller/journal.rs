@@ -0,0 +1,272 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
+
+use liketrain_hardware::{command::HardwareCommand, event::HardwareEvent};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Clock, ControllerConfig, ControllerError, ControllerEvent, InterlockingOverride,
+    comm::Simulation, ui::UiCommand,
+};
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum JournalReplayError {
+    #[error("Controller error: {0}")]
+    Controller(#[from] ControllerError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Journal entry {index} differs, expected {expected:?}, got {produced:?}")]
+    EntryMismatch {
+        index: usize,
+        expected: Option<Box<JournalRecord>>,
+        produced: Option<Box<JournalRecord>>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalRecord {
+    /// An event the controller handled, either from the hardware or from the scheduler.
+    Event(ControllerEvent),
+
+    /// A command the controller received from the ui.
+    UiCommand(UiCommand),
+
+    /// A command the controller sent to the hardware.
+    HardwareCommand(HardwareCommand),
+
+    /// A manual command, that was executed despite violating the interlocking.
+    InterlockingOverride(InterlockingOverride),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Milliseconds since the controller started talking to the hardware.
+    pub at: u64,
+
+    #[serde(flatten)]
+    pub record: JournalRecord,
+}
+
+/// Writes the journal of a running controller, one JSON object per line.
+/// Every entry is flushed right away, so the journal is complete up to a crash.
+pub(super) struct JournalWriter {
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    writer: RefCell<JournalTarget>,
+}
+
+enum JournalTarget {
+    File(BufWriter<File>),
+
+    /// Kept in memory, to compare a replay with the recorded run.
+    Memory(Vec<JournalEntry>),
+}
+
+impl JournalWriter {
+    /// Start a new journal, replacing the journal of a previous run.
+    pub(super) fn create(path: &Path, clock: Arc<dyn Clock>) -> Result<Self, JournalError> {
+        let file = File::create(path)?;
+
+        Ok(Self {
+            started: clock.now(),
+            clock,
+            writer: RefCell::new(JournalTarget::File(BufWriter::new(file))),
+        })
+    }
+
+    pub(super) fn in_memory(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            started: clock.now(),
+            clock,
+            writer: RefCell::new(JournalTarget::Memory(Vec::new())),
+        }
+    }
+
+    /// The entries recorded so far, if the journal is kept in memory.
+    pub(super) fn entries(&self) -> Vec<JournalEntry> {
+        match &*self.writer.borrow() {
+            JournalTarget::File(_) => Vec::new(),
+            JournalTarget::Memory(entries) => entries.clone(),
+        }
+    }
+
+    pub(super) fn record(&self, record: JournalRecord) {
+        let entry = JournalEntry {
+            at: (self.clock.now() - self.started).as_millis() as u64,
+            record,
+        };
+
+        if let Err(err) = self.write(&entry) {
+            log::error!("failed to write to the journal: {}", err);
+        }
+    }
+
+    fn write(&self, entry: &JournalEntry) -> Result<(), JournalError> {
+        match &mut *self.writer.borrow_mut() {
+            JournalTarget::File(writer) => {
+                serde_json::to_writer(&mut *writer, entry)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            }
+            JournalTarget::Memory(entries) => entries.push(entry.clone()),
+        }
+
+        Ok(())
+    }
+}
+
+/// A recorded run of the controller.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new(entries: impl IntoIterator<Item = JournalEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            entries.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// The commands the controller sent to the hardware, in the order they were sent.
+    pub fn hardware_commands(&self) -> impl Iterator<Item = &HardwareCommand> {
+        self.entries.iter().filter_map(|entry| match &entry.record {
+            JournalRecord::HardwareCommand(command) => Some(command),
+            _ => None,
+        })
+    }
+
+    /// Run a fresh controller in a simulation with the recorded hardware events and ui commands,
+    /// each at the time it was recorded, and check that it records the same journal.
+    /// Returns the journal of the replay.
+    ///
+    /// The config has to describe the same track and trains as the recorded run.
+    /// The clock is moved in the ticks of the simulation, so only a run recorded in a simulation
+    /// is replayed exactly. A run on the `SystemClock` is replayed with the timing rounded to the ticks,
+    /// so its scheduled events can end up in a different order. The snapshot a run was restored from
+    /// isn't recorded either, the replay starts with the trains of the config. Neither is the seed
+    /// of the dispatcher, a run with `DispatcherConfig::seed` unset picks other destinations
+    /// in the replay and can't be replayed.
+    pub fn replay(&self, mut config: ControllerConfig) -> Result<Journal, JournalReplayError> {
+        // the replay must not overwrite the files of the recorded run
+        config.journal = None;
+        config.snapshot = None;
+
+        // the commands of the scripts were recorded as ui commands, their timers aren't recorded
+        config.scripts = None;
+
+        let (ui_event_tx, _ui_event_rx) = mpsc::channel();
+        let mut simulation = Simulation::replay(config, ui_event_tx)?;
+
+        // whether the scheduled events of the current tick were handled, like a simulation
+        // does after the hardware events of a tick and before the ui commands
+        let mut tick_finished = true;
+        let mut now = 0;
+
+        for entry in &self.entries {
+            if entry.at > now {
+                if !tick_finished {
+                    simulation.finish_tick()?;
+                }
+
+                simulation.advance_to(Duration::from_millis(entry.at))?;
+                tick_finished = false;
+                now = entry.at;
+            }
+
+            match &entry.record {
+                // the handshake is answered by the simulation
+                JournalRecord::Event(ControllerEvent::Hardware(
+                    HardwareEvent::Slaves { .. } | HardwareEvent::Pong { .. },
+                )) => {}
+                JournalRecord::Event(ControllerEvent::Hardware(event)) => {
+                    simulation.hardware_event(event.clone())?;
+                }
+                JournalRecord::UiCommand(command) => {
+                    if !tick_finished {
+                        simulation.finish_tick()?;
+                        tick_finished = true;
+                    }
+
+                    simulation.command(command.clone())?;
+                }
+                // these are produced by the controller itself
+                JournalRecord::Event(ControllerEvent::Scheduled(_))
+                | JournalRecord::HardwareCommand(_)
+                | JournalRecord::InterlockingOverride(_) => {}
+            }
+        }
+
+        // the recorded run might have handled the scheduled events of its last tick as well
+        if !tick_finished && simulation.journal().len() < self.entries.len() {
+            simulation.finish_tick()?;
+        }
+
+        let replayed = Journal::new(simulation.journal());
+        self.compare(&replayed)?;
+
+        Ok(replayed)
+    }
+
+    /// Check that the other journal has the same entries in the same order.
+    fn compare(&self, replayed: &Journal) -> Result<(), JournalReplayError> {
+        let len = self.entries.len().max(replayed.entries.len());
+
+        for index in 0..len {
+            let expected = self.entries.get(index).map(|entry| &entry.record);
+            let produced = replayed.entries.get(index).map(|entry| &entry.record);
+
+            let expected_json = expected.map(serde_json::to_value).transpose()?;
+            let produced_json = produced.map(serde_json::to_value).transpose()?;
+
+            if expected_json != produced_json {
+                return Err(JournalReplayError::EntryMismatch {
+                    index,
+                    expected: expected.cloned().map(Box::new),
+                    produced: produced.cloned().map(Box::new),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    Controller, ControllerError, Route, ScheduledEvent, SectionPowerReason, Train, TrainId,
+    TrainState, ui::UiTrainEvent,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+impl Controller {
+    pub(super) fn set_train_state(
+        &mut self,
+        train_id: TrainId,
+        state: TrainState,
+    ) -> Result<(), ControllerError> {
+        let train = self.train_mut(train_id)?;
+        if train.state() != state {
+            train.set_state(state);
+            self.emit_ui(UiTrainEvent::StateChanged { train_id, state });
+        }
+
+        Ok(())
+    }
+
+    /// Whether the train stands still or is braking to a stop.
+    fn is_train_stopping(&self, train_id: TrainId) -> bool {
+        let power = self.train_power(train_id);
+        self.train_power_reason(train_id, power) == SectionPowerReason::Stopped(train_id)
+    }
+
+    /// Start a train, that was stopped or paused by the operator, or let a stopping train drive on.
+    pub(super) fn start_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let train = self.train(train_id)?;
+        let state = train.state();
+        let power = train.target_power();
+        let detected = train.get_current_section().is_some();
+        let finished = train.has_finished_route();
+
+        if state == TrainState::Faulted {
+            log::warn!(
+                "can't start train {}, its fault has to be acknowledged first",
+                train_id
+            );
+            return Ok(());
+        }
+
+        if !state.is_held() && state != TrainState::Stopping {
+            log::debug!("train {} is already running", train_id);
+            return Ok(());
+        }
+
+        log::info!("starting train {}", train_id);
+        self.set_train_state(train_id, TrainState::Default)?;
+
+        if state == TrainState::Stopping {
+            // the train is still driving, it just doesn't stop in its next section anymore
+            let power = self.train_power(train_id);
+            self.emit_ui(UiTrainEvent::Started { train_id, power });
+            return Ok(());
+        }
+
+        if !detected {
+            // the train wasn't detected yet, power its initial section like on startup
+            self.ramp_train(train_id, power, ctx)?;
+        } else if finished {
+            // there is nowhere to drive, let the dispatcher pick a new destination
+            self.train_arrived(train_id);
+            return Ok(());
+        } else if self.request_sections_ahead(train_id, ctx)? {
+            self.ramp_train(train_id, power, ctx)?;
+        } else {
+            // the train waits for its next section and is restarted, once it is free
+            return Ok(());
+        }
+
+        self.emit_ui(UiTrainEvent::Started { train_id, power });
+
+        Ok(())
+    }
+
+    /// Stop the train, once it entered its next section. A standing train is stopped right away.
+    pub(super) fn stop_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let state = self.train(train_id)?.state();
+        if matches!(
+            state,
+            TrainState::Stopping | TrainState::Stopped | TrainState::Faulted
+        ) {
+            return Ok(());
+        }
+
+        if self.is_train_stopping(train_id) {
+            return self.stop_in_current_section(train_id, ctx);
+        }
+
+        log::info!("train {} stops in its next section", train_id);
+        self.set_train_state(train_id, TrainState::Stopping)
+    }
+
+    /// Stop the train in its current section and give up the sections ahead of it.
+    pub(super) fn stop_in_current_section(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        log::info!("train {} stopped", train_id);
+
+        // e.g. the dispatcher doesn't send it off after its dwell time anymore
+        self.scheduler.cancel_train(train_id);
+
+        self.ramp_train(train_id, HardwareSectionPower::Off, ctx)?;
+        self.release_sections_ahead(train_id);
+
+        self.set_train_state(train_id, TrainState::Stopped)?;
+        self.emit_ui(UiTrainEvent::Stopped { train_id });
+
+        Ok(())
+    }
+
+    /// Release the sections the train has reserved, but doesn't cover yet, and stop waiting for any section.
+    pub(super) fn release_sections_ahead(&mut self, train_id: TrainId) {
+        self.leave_section_queues(train_id);
+
+        let covered = self.train_sections(train_id);
+        let ahead = self
+            .section_reservations
+            .iter()
+            .filter(|&(section_id, &holder)| holder == train_id && !covered.contains(section_id))
+            .map(|(&section_id, _)| section_id)
+            .collect::<Vec<_>>();
+
+        for section_id in ahead {
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id,
+                });
+        }
+
+        // the train might have been part of a deadlock
+        if !self.known_deadlocks.is_empty() {
+            self.scheduler.schedule_now(ScheduledEvent::DeadlockCheck);
+        }
+    }
+
+    /// Stop the train immediately, where it is. It keeps the sections it has reserved
+    /// and drives on, once it is started again.
+    pub(super) fn pause_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.train(train_id)?.state().is_held() {
+            return Ok(());
+        }
+
+        log::info!("pausing train {}", train_id);
+
+        self.scheduler.cancel_train(train_id);
+        self.cancel_ramp(train_id);
+        self.apply_train_power(train_id, HardwareSectionPower::Off, ctx)?;
+
+        self.set_train_state(train_id, TrainState::Paused)?;
+        self.emit_ui(UiTrainEvent::Stopped { train_id });
+
+        Ok(())
+    }
+
+    /// Let the train drive a new route, starting at the section it is currently in.
+    /// A running train departs on it right away, a stopped or paused train once it is started again.
+    pub(super) fn assign_route(
+        &mut self,
+        train_id: TrainId,
+        route: Route,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let train = self.train(train_id)?;
+        let state = train.state();
+
+        let Some(current_section) = train.get_current_section() else {
+            log::warn!(
+                "can't assign a route to train {}, it wasn't detected yet",
+                train_id
+            );
+            return Ok(());
+        };
+
+        if route.via(0) != Some(current_section) {
+            log::warn!(
+                "can't assign route {} to train {}, it doesn't start in section {}",
+                route.name(),
+                train_id,
+                current_section
+            );
+            return Ok(());
+        }
+
+        if route.starting_direction() != train.orientation() {
+            log::warn!(
+                "can't assign route {} to train {}, it is facing {}. Reverse the train first",
+                route.name(),
+                train_id,
+                train.orientation()
+            );
+            return Ok(());
+        }
+
+        log::info!(
+            "train {} drives route {}",
+            train_id,
+            route.pretty_print(&self.track)
+        );
+
+        // the dispatcher takes care of the train again, once it finished this route
+        if let Some(dispatcher) = self.dispatcher.as_mut() {
+            dispatcher.forget(train_id);
+        }
+        self.scheduler.cancel_train(train_id);
+
+        // the sections the train was waiting for might not be on the new route
+        self.leave_section_queues(train_id);
+
+        if state.is_held() {
+            self.change_route(train_id, route)?;
+
+            // sections ahead on the previous route aren't needed anymore
+            for section_id in self.stale_reservations(train_id) {
+                self.scheduler
+                    .schedule_now(ScheduledEvent::TrainLeftSection {
+                        train_id,
+                        section_id,
+                    });
+            }
+
+            return Ok(());
+        }
+
+        self.depart_on_route(train_id, route, ctx)?;
+
+        // the train still stops in its next section
+        if state == TrainState::Stopping {
+            self.set_train_state(train_id, TrainState::Stopping)?;
+        }
+
+        Ok(())
+    }
+
+    /// Place a new train on the track. It stays stopped in the first section of its route,
+    /// until it is started.
+    pub(super) fn add_train(&mut self, train_id: TrainId, mut train: Train) {
+        if self.trains.contains_key(&train_id) {
+            log::warn!("can't add train {}, the id is already used", train_id);
+            return;
+        }
+
+        log::info!(
+            "placing train {} ({}) in section {}",
+            train_id,
+            train.name(),
+            train
+                .get_initial_section()
+                .map(|section_id| section_id.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+
+        train.set_state(TrainState::Stopped);
+
+        self.emit_ui(UiTrainEvent::Added {
+            train_id,
+            train: Box::new(train.clone()),
+        });
+        self.trains.insert(train_id, train);
+    }
+
+    /// Take the train off the track. All of its sections are released, so the trains waiting for them can drive on.
+    pub(super) fn remove_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if !self.trains.contains_key(&train_id) {
+            return Ok(());
+        }
+
+        log::info!("removing train {}", train_id);
+
+        self.scheduler.cancel_train(train_id);
+        self.cancel_ramp(train_id);
+        self.apply_train_power(train_id, HardwareSectionPower::Off, ctx)?;
+        self.disarm_watchdog(train_id, ctx)?;
+        self.leave_section_queues(train_id);
+
+        if let Some(dispatcher) = self.dispatcher.as_mut() {
+            dispatcher.forget(train_id);
+        }
+
+        let mut sections = self.train_sections(train_id);
+        for (&section_id, &holder) in &self.section_reservations {
+            if holder == train_id && !sections.contains(&section_id) {
+                sections.push(section_id);
+            }
+        }
+
+        self.trains.remove(&train_id);
+        self.train_powers.remove(&train_id);
+        self.train_positions.remove(&train_id);
+        self.train_tails.remove(&train_id);
+        self.section_timings.remove(&train_id);
+
+        for section_id in sections {
+            if let Some(state) = self.section_states.get_mut(&section_id)
+                && state.occupied == Some(train_id)
+            {
+                state.occupied = None;
+            }
+
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id,
+                });
+        }
+
+        self.emit_ui(UiTrainEvent::Removed { train_id });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::mpsc;
+
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    Direction, SectionId, TrainData, TrainRosterRoute,
+    comm::Simulation,
+    controller::testing::{config, occupy, route, section, simulate, track, train},
+    ui::{UiCommand, UiEvent},
+};
+
+use super::*;
+
+/// T1 entered S10 and has reserved S12 ahead of it.
+fn simulate_train() -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let track = track();
+    let train = train("T1", route(&track, &[10, 12, 14, 16]));
+
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 10);
+    assert_eq!(reservation(&simulation, 12), Some(TrainId::new(1)));
+
+    (simulation, ui_event_rx)
+}
+
+fn roster_route(vias: &[usize]) -> TrainRosterRoute {
+    TrainRosterRoute {
+        name: "test".to_string(),
+        vias: vias.iter().copied().map(section).collect(),
+        starting_direction: Direction::Backward,
+    }
+}
+
+fn reservation(simulation: &Simulation, section_id: usize) -> Option<TrainId> {
+    simulation
+        .controller()
+        .section_reservation(section(section_id))
+}
+
+fn state(simulation: &Simulation, train_id: usize) -> TrainState {
+    let train = simulation.controller().train(TrainId::new(train_id));
+    train.unwrap().state()
+}
+
+fn vias(simulation: &Simulation, train_id: usize) -> Vec<SectionId> {
+    let train = simulation
+        .controller()
+        .train(TrainId::new(train_id))
+        .unwrap();
+    train.route().unwrap().vias().to_vec()
+}
+
+fn section_power(simulation: &Simulation, section_id: usize) -> HardwareSectionPower {
+    simulation
+        .controller()
+        .section_states
+        .get(&section(section_id))
+        .map(|state| state.power)
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_stop_train() {
+    let (mut simulation, _ui_event_rx) = simulate_train();
+    let train_id = TrainId::new(1);
+
+    // the train drives on into its next section
+    simulation
+        .command(UiCommand::StopTrain { train_id })
+        .unwrap();
+    assert_eq!(state(&simulation, 1), TrainState::Stopping);
+    assert!(!simulation.controller().train_power(train_id).is_off());
+
+    // and stops there, without reserving more sections
+    occupy(&mut simulation, 12);
+    simulation.step().unwrap();
+    assert_eq!(state(&simulation, 1), TrainState::Stopped);
+    assert!(simulation.controller().train_power(train_id).is_off());
+    assert_eq!(reservation(&simulation, 14), None);
+
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    assert_eq!(state(&simulation, 1), TrainState::Default);
+    assert_eq!(reservation(&simulation, 14), Some(train_id));
+    assert!(!simulation.controller().train_power(train_id).is_off());
+}
+
+#[test]
+fn test_assign_route_to_running_train() {
+    let (mut simulation, _ui_event_rx) = simulate_train();
+    let train_id = TrainId::new(1);
+
+    // the route has to start in the section of the train
+    simulation
+        .command(UiCommand::AssignRoute {
+            train_id,
+            route: roster_route(&[12, 14]),
+        })
+        .unwrap();
+    assert_eq!(
+        vias(&simulation, 1),
+        route(&track(), &[10, 12, 14, 16]).vias()
+    );
+
+    // the train drives through S11 now, so S12 isn't needed anymore
+    simulation
+        .command(UiCommand::AssignRoute {
+            train_id,
+            route: roster_route(&[10, 11, 13]),
+        })
+        .unwrap();
+    simulation.step().unwrap();
+
+    assert_eq!(
+        vias(&simulation, 1),
+        [section(10), section(11), section(13)]
+    );
+    assert_eq!(reservation(&simulation, 12), None);
+    assert_eq!(reservation(&simulation, 11), Some(train_id));
+    assert_eq!(state(&simulation, 1), TrainState::Default);
+}
+
+#[test]
+fn test_assign_route_to_held_train() {
+    let (mut simulation, _ui_event_rx) = simulate_train();
+    let train_id = TrainId::new(1);
+
+    simulation
+        .command(UiCommand::PauseTrain { train_id })
+        .unwrap();
+    simulation
+        .command(UiCommand::AssignRoute {
+            train_id,
+            route: roster_route(&[10, 11, 13]),
+        })
+        .unwrap();
+    simulation.step().unwrap();
+
+    // the train only departs on the new route, once it is started
+    assert_eq!(
+        vias(&simulation, 1),
+        [section(10), section(11), section(13)]
+    );
+    assert_eq!(state(&simulation, 1), TrainState::Paused);
+    assert_eq!(reservation(&simulation, 12), None);
+    assert_eq!(reservation(&simulation, 11), None);
+
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    assert_eq!(reservation(&simulation, 11), Some(train_id));
+}
+
+#[test]
+fn test_add_train() {
+    let (mut simulation, _ui_event_rx) = simulate_train();
+    let train_id = TrainId::new(2);
+
+    simulation
+        .command(UiCommand::AddTrain {
+            train_id,
+            data: Box::new(TrainData::new("T2")),
+            route: roster_route(&[13, 25, 23]),
+        })
+        .unwrap();
+    assert_eq!(state(&simulation, 2), TrainState::Stopped);
+    assert!(simulation.controller().train_power(train_id).is_off());
+
+    // the id is already used
+    simulation
+        .command(UiCommand::AddTrain {
+            train_id,
+            data: Box::new(TrainData::new("T3")),
+            route: roster_route(&[9, 10]),
+        })
+        .unwrap();
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.name(), "T2");
+
+    // S10 is occupied by T1
+    simulation
+        .command(UiCommand::AddTrain {
+            train_id: TrainId::new(3),
+            data: Box::new(TrainData::new("T3")),
+            route: roster_route(&[10, 12]),
+        })
+        .unwrap();
+    assert!(simulation.controller().train(TrainId::new(3)).is_err());
+
+    // once started, the initial section of the new train is powered
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    simulation
+        .run_for(std::time::Duration::from_secs(2))
+        .unwrap();
+    assert!(!section_power(&simulation, 13).is_off());
+}
+
+#[test]
+fn test_remove_train() {
+    let (mut simulation, ui_event_rx) = simulate_train();
+    let train_id = TrainId::new(1);
+
+    simulation
+        .command(UiCommand::RemoveTrain { train_id })
+        .unwrap();
+    simulation.step().unwrap();
+
+    assert!(simulation.controller().train(train_id).is_err());
+    assert_eq!(reservation(&simulation, 12), None);
+    assert!(!simulation.controller().is_section_occupied(section(10)));
+    assert!(section_power(&simulation, 10).is_off());
+    assert!(section_power(&simulation, 12).is_off());
+
+    let removed = ui_event_rx.try_iter().any(|event| {
+        matches!(
+            event,
+            UiEvent::UiTrainEvent(UiTrainEvent::Removed { train_id: removed }) if removed == train_id
+        )
+    });
+    assert!(removed);
+}
@@ -0,0 +1,140 @@
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{Controller, SectionId, SectionTransition, TrainId};
+
+use super::ramp::step_towards;
+
+#[cfg(test)]
+mod tests;
+
+/// How far ahead of each train sections are reserved.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReservationLookahead {
+    /// Reserve the given number of sections ahead of the train.
+    Sections(usize),
+
+    /// Reserve enough sections for the train to stop within them from its target speed,
+    /// but at least the given number of sections.
+    BrakingDistance { min_sections: usize },
+}
+
+impl Default for ReservationLookahead {
+    fn default() -> Self {
+        Self::Sections(1)
+    }
+}
+
+impl ReservationLookahead {
+    /// The number of sections reserved at least. A train always needs its next section.
+    fn min_sections(&self) -> usize {
+        match *self {
+            Self::Sections(sections) => sections,
+            Self::BrakingDistance { min_sections } => min_sections,
+        }
+        .max(1)
+    }
+}
+
+impl Controller {
+    /// The distance in meters the train needs to stop from its target power,
+    /// when its power is stepped down according to its ramp profile.
+    /// `None`, if the speed of the train isn't known.
+    pub fn braking_distance(&self, train_id: TrainId) -> Option<f32> {
+        let train = self.trains.get(&train_id)?;
+        let step = train.ramp_profile().deceleration_step.as_secs_f32();
+
+        let mut power = train.target_power();
+        let mut distance = 0.0;
+
+        while !power.is_off() {
+            distance += train.calibration().estimate_speed(power)? * step;
+            power = step_towards(power, HardwareSectionPower::Off);
+        }
+
+        Some(distance)
+    }
+
+    /// The transitions into the sections, that the train should have reserved ahead of it.
+    /// Contains the transition to the next section, if the train has one.
+    pub(super) fn lookahead_transitions(&self, train_id: TrainId) -> Vec<SectionTransition> {
+        let Some(train) = self.trains.get(&train_id) else {
+            return Vec::new();
+        };
+
+        let min_sections = self.lookahead.min_sections();
+        let braking_distance = match self.lookahead {
+            ReservationLookahead::Sections(_) => None,
+            ReservationLookahead::BrakingDistance { .. } => self.braking_distance(train_id),
+        };
+
+        let mut transitions = Vec::new();
+        let mut distance = 0.0;
+
+        for transition in train.upcoming_transitions() {
+            if transitions.len() >= min_sections
+                && braking_distance.is_none_or(|braking_distance| distance >= braking_distance)
+            {
+                break;
+            }
+
+            distance += self.section_length(transition.destination());
+            transitions.push(transition.clone());
+        }
+
+        transitions
+    }
+
+    /// Whether the train can't stop within the sections it reserved ahead of it.
+    pub(super) fn exceeds_reserved_distance(
+        &self,
+        train_id: TrainId,
+        reserved: &[SectionId],
+    ) -> bool {
+        if reserved.is_empty() {
+            return true;
+        }
+
+        let ReservationLookahead::BrakingDistance { .. } = self.lookahead else {
+            return false;
+        };
+
+        let reserved_distance = reserved
+            .iter()
+            .map(|&section_id| self.section_length(section_id))
+            .sum::<f32>();
+
+        self.braking_distance(train_id)
+            .is_some_and(|braking_distance| reserved_distance < braking_distance)
+    }
+
+    /// Reservations of the train, that aren't on its route anymore, e.g. because it was rerouted.
+    pub(super) fn stale_reservations(&self, train_id: TrainId) -> Vec<SectionId> {
+        let Some(train) = self.trains.get(&train_id) else {
+            return Vec::new();
+        };
+
+        let upcoming = train
+            .upcoming_transitions()
+            .map(|transition| transition.destination())
+            .collect::<Vec<_>>();
+        let covered = self.train_sections(train_id);
+
+        self.section_reservations
+            .iter()
+            .filter(|&(section_id, &holder)| {
+                holder == train_id
+                    && !upcoming.contains(section_id)
+                    && !covered.contains(section_id)
+            })
+            .map(|(&section_id, _)| section_id)
+            .collect()
+    }
+
+    /// The length of the section in meters, or zero if the section has no geometry.
+    fn section_length(&self, section_id: SectionId) -> f32 {
+        self.track
+            .section_geo(&section_id)
+            .map(|geo| geo.length)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,189 @@
+use std::{sync::mpsc, time::Duration};
+
+use crate::{
+    ControllerConfig, Train, TrainCalibration, TrainRampProfile, TrainState,
+    comm::Simulation,
+    controller::testing::{config, occupy, route, section, simulate, track, train},
+    ui::{UiEvent, UiSectionEvent, UiSwitchEvent},
+};
+
+use super::*;
+
+fn lookahead_config(
+    trains: impl IntoIterator<Item = (usize, Train)>,
+    lookahead: ReservationLookahead,
+) -> ControllerConfig {
+    ControllerConfig {
+        lookahead,
+        ..config(track(), trains)
+    }
+}
+
+/// A calibrated train, that needs more than one section to stop from full power.
+fn braking_train(name: &str, vias: &[usize]) -> Train {
+    train(name, route(&track(), vias))
+        .with_calibration(TrainCalibration::linear(10.0))
+        .with_ramp_profile(TrainRampProfile::new(
+            Duration::ZERO,
+            Duration::from_secs(2),
+        ))
+}
+
+fn reserved_sections(simulation: &Simulation, train_id: usize) -> Vec<SectionId> {
+    let mut sections = simulation
+        .controller()
+        .section_reservations
+        .iter()
+        .filter(|&(_, &holder)| holder == TrainId::new(train_id))
+        .map(|(&section_id, _)| section_id)
+        .collect::<Vec<_>>();
+    sections.sort();
+    sections
+}
+
+fn sections(section_ids: &[usize]) -> Vec<SectionId> {
+    let mut sections = section_ids.iter().copied().map(section).collect::<Vec<_>>();
+    sections.sort();
+    sections
+}
+
+#[test]
+fn test_claim_multiple_sections() {
+    let train = train("T1", route(&track(), &[12, 14, 16, 9, 10]));
+    let config = lookahead_config([(1, train)], ReservationLookahead::Sections(3));
+
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 12);
+    assert_eq!(reserved_sections(&simulation, 1), sections(&[14, 16, 9]));
+
+    // one more section is claimed, when the train moves on
+    occupy(&mut simulation, 14);
+    assert_eq!(
+        reserved_sections(&simulation, 1),
+        sections(&[14, 16, 9, 10])
+    );
+}
+
+#[test]
+fn test_switches_are_set_before_powering() {
+    let route = route(&track(), &[16, 9, 10, 11, 13]);
+    let switches = (0..3)
+        .filter_map(|idx| route.transition(idx))
+        .flat_map(|transition| transition.required_switch_changes())
+        .count();
+    assert!(switches >= 2);
+
+    let config = lookahead_config([(1, train("T1", route))], ReservationLookahead::Sections(3));
+    let (mut simulation, ui_event_rx) = simulate(config, []);
+    ui_event_rx.try_iter().count();
+
+    occupy(&mut simulation, 16);
+    simulation.step().unwrap();
+
+    #[derive(Debug, PartialEq)]
+    enum Change {
+        Switch,
+        Power(SectionId),
+    }
+
+    let changes = ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiSwitchEvent(UiSwitchEvent::SetState { .. }) => Some(Change::Switch),
+            UiEvent::UiSectionEvent(UiSectionEvent::SetPower { section_id, .. })
+                if section_id != section(16) =>
+            {
+                Some(Change::Power(section_id))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // all switches along the claimed sections are set, before any of them is powered
+    let mut expected = (0..switches).map(|_| Change::Switch).collect::<Vec<_>>();
+    expected.extend([9, 10, 11].map(|id| Change::Power(section(id))));
+    assert_eq!(changes, expected);
+}
+
+#[test]
+fn test_braking_distance() {
+    let config = lookahead_config(
+        [(1, braking_train("T1", &[12, 14, 16, 9, 10]))],
+        ReservationLookahead::BrakingDistance { min_sections: 1 },
+    );
+
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    let braking_distance = simulation
+        .controller()
+        .braking_distance(TrainId::new(1))
+        .unwrap();
+
+    // S14 alone is too short to stop in, S14 and S16 are long enough
+    let length = |id| simulation.controller().section_length(section(id));
+    assert!(length(14) < braking_distance);
+    assert!(length(14) + length(16) >= braking_distance);
+
+    occupy(&mut simulation, 12);
+    assert_eq!(reserved_sections(&simulation, 1), sections(&[14, 16]));
+}
+
+/// T2 stands in S16, so T1 in S12 can only reserve S14 ahead of it.
+fn simulate_blocked(lookahead: ReservationLookahead) -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let trains = [
+        (1, braking_train("T1", &[12, 14, 16, 9])),
+        (2, train("T2", route(&track(), &[16, 9, 10]))),
+    ];
+
+    let (mut simulation, ui_event_rx) = simulate(lookahead_config(trains, lookahead), []);
+    occupy(&mut simulation, 16);
+    occupy(&mut simulation, 12);
+    assert_eq!(reserved_sections(&simulation, 1), sections(&[14]));
+
+    (simulation, ui_event_rx)
+}
+
+fn request_sections_ahead(simulation: &mut Simulation) -> bool {
+    simulation
+        .with_controller(|controller, ctx| controller.request_sections_ahead(TrainId::new(1), ctx))
+        .unwrap()
+}
+
+#[test]
+fn test_partially_free_path_stops_train() {
+    let (mut simulation, _ui_event_rx) =
+        simulate_blocked(ReservationLookahead::BrakingDistance { min_sections: 1 });
+
+    // the train can't stop within S14, so it has to wait for S16
+    assert!(!request_sections_ahead(&mut simulation));
+    simulation.run_for(Duration::from_secs(20)).unwrap();
+
+    let t1 = simulation.controller().train(TrainId::new(1)).unwrap();
+    assert_eq!(t1.state(), TrainState::Waiting);
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+    assert_eq!(
+        simulation.controller().section_queue(section(16)),
+        Some(&[TrainId::new(1)].into())
+    );
+}
+
+#[test]
+fn test_partially_free_path_with_fixed_lookahead() {
+    let (mut simulation, _ui_event_rx) = simulate_blocked(ReservationLookahead::Sections(3));
+
+    // the train waits for S16, but keeps driving through S14 until it gets it
+    assert!(request_sections_ahead(&mut simulation));
+
+    let t1 = simulation.controller().train(TrainId::new(1)).unwrap();
+    assert_eq!(t1.state(), TrainState::Default);
+    assert!(
+        !simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+}
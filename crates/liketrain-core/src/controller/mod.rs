@@ -1,20 +1,25 @@
 use std::{
     collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
     time::Duration,
 };
 
 use crate::{
     SectionId, SectionTransitionSwitchChange, SwitchId, SwitchState, Track, Train, TrainId,
-    TrainState,
+    TrainRoster, TrainRosterError, TrainState,
     controller::comm::{ControllerHardwareCommunication, ControllerHardwareCommunicationChannels},
-    ui::{UiCommand, UiEvent, UiSectionEvent, UiSwitchEvent, UiTrainEvent},
+    ui::{UiCommand, UiControllerEvent, UiEvent, UiSectionEvent, UiSwitchEvent, UiTrainEvent},
 };
 
 mod state;
 use itertools::Itertools;
 use liketrain_hardware::{
     command::HardwareCommand,
-    event::{HardwareEvent, HardwareSectionPower, HardwareSwitchId, SectionEventType},
+    event::{
+        HardwareEvent, HardwareSectionPolarity, HardwareSectionPower, HardwareSwitchId,
+        SectionEventType,
+    },
 };
 pub use state::*;
 
@@ -23,23 +28,144 @@ pub mod comm;
 mod error;
 pub use error::*;
 
+mod clock;
+pub use clock::*;
+
 mod scheduler;
 pub use scheduler::*;
 
 mod event;
 pub use event::*;
 
+mod dispatcher;
+pub use dispatcher::*;
+
+mod ramp;
+use ramp::TrainRamp;
+
+mod calibration;
+use calibration::SectionTiming;
+
+mod position;
+use position::TrainPosition;
+pub use position::TrainPositionEstimate;
+
+mod occupancy;
+use occupancy::TrainTailSection;
+
+mod orientation;
+
+mod halt;
+use halt::ControllerHalt;
+
+mod fault;
+pub use fault::*;
+
+mod watchdog;
+use watchdog::TrainWatchdog;
+pub use watchdog::WatchdogConfig;
+
+mod deadlock;
+pub use deadlock::*;
+
+mod lookahead;
+pub use lookahead::*;
+
+mod interlocking;
+pub use interlocking::*;
+
+mod lifecycle;
+
+mod snapshot;
+use snapshot::RestoredSnapshot;
+pub use snapshot::{
+    ControllerSnapshot, ControllerSnapshotError, SectionSnapshot, SnapshotConfig, SwitchSnapshot,
+    TrainSnapshot,
+};
+
+mod journal;
+use journal::JournalWriter;
+pub use journal::{Journal, JournalEntry, JournalError, JournalRecord, JournalReplayError};
+
+mod script;
+use script::ScriptHost;
+pub use script::{ScriptConfig, ScriptError};
+
 pub mod ui;
 
+#[cfg(test)]
+mod testing;
+
 pub struct ControllerConfig {
     pub track: Track,
     pub trains: HashMap<TrainId, Train>,
+
+    /// If set, trains will pick their next destination on their own, once they finished their route.
+    pub dispatcher: Option<DispatcherConfig>,
+
+    /// Whether to measure the speed of the trains while they are driving and add it to their calibration.
+    pub record_calibration: bool,
+
+    /// How to react to unexpected events on the track.
+    pub fault_reactions: FaultReactions,
+
+    /// Watch for trains that don't reach their next section in time. `None` disables the watchdog.
+    pub watchdog: Option<WatchdogConfig>,
+
+    /// What to do, when trains are waiting for each other.
+    pub deadlock_resolution: DeadlockResolution,
+
+    /// How far ahead of each train sections are reserved.
+    pub lookahead: ReservationLookahead,
+
+    /// Where to save the state of the controller, to continue after a restart. `None` disables saving it.
+    pub snapshot: Option<SnapshotConfig>,
+
+    /// Where to record the events, ui commands and hardware commands of a run, to replay it later.
+    /// Only runs in a `Simulation`, that didn't restore a snapshot, are replayed exactly.
+    /// `None` disables the journal.
+    pub journal: Option<PathBuf>,
+
+    /// Automation scripts, that react to events and issue commands. `None` disables scripting.
+    pub scripts: Option<ScriptConfig>,
+
+    /// Where the controller gets the time from. Simulations can use a `ManualClock`.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl ControllerConfig {
+    /// Place the trains on the track, everything else is set to its default.
+    pub fn new(track: Track, trains: HashMap<TrainId, Train>) -> Self {
+        Self {
+            track,
+            trains,
+            dispatcher: None,
+            record_calibration: false,
+            fault_reactions: FaultReactions::default(),
+            watchdog: Some(WatchdogConfig::default()),
+            deadlock_resolution: DeadlockResolution::default(),
+            lookahead: ReservationLookahead::default(),
+            snapshot: None,
+            journal: None,
+            scripts: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Place all trains of the roster, that have a default route, on the track.
+    pub fn from_roster(track: Track, roster: &TrainRoster) -> Result<Self, TrainRosterError> {
+        let trains = roster.trains(&track)?;
+
+        Ok(Self::new(track, trains))
+    }
 }
 
 #[derive(Copy, Clone)]
 struct EventExecutionContext<'a> {
     command_tx: &'a crossbeam::channel::Sender<HardwareCommand>,
+    priority_command_tx: &'a crossbeam::channel::Sender<HardwareCommand>,
     event_rx: &'a crossbeam::channel::Receiver<HardwareEvent>,
+    journal: Option<&'a JournalWriter>,
 }
 
 impl<'a> EventExecutionContext<'a> {
@@ -47,9 +173,38 @@ impl<'a> EventExecutionContext<'a> {
         let command = command.into();
         log::debug!("sending hw command: {:?}", command);
 
+        self.record(|| JournalRecord::HardwareCommand(command.clone()));
         self.command_tx.send(command)?;
         Ok(())
     }
+
+    /// Send the command before all commands, that are still queued.
+    pub fn exec_priority(
+        &self,
+        command: impl Into<HardwareCommand>,
+    ) -> Result<(), ControllerError> {
+        let command = command.into();
+        log::debug!("sending priority hw command: {:?}", command);
+
+        self.record(|| JournalRecord::HardwareCommand(command.clone()));
+        self.priority_command_tx.send(command)?;
+        Ok(())
+    }
+
+    /// Wait for the next event from the hardware.
+    pub fn recv_event(&self) -> Result<HardwareEvent, ControllerError> {
+        let event = self.event_rx.recv()?;
+
+        self.record(|| JournalRecord::Event(event.clone().into()));
+        Ok(event)
+    }
+
+    /// Append to the journal, if it is recorded.
+    fn record(&self, record: impl FnOnce() -> JournalRecord) {
+        if let Some(journal) = self.journal {
+            journal.record(record());
+        }
+    }
 }
 
 pub struct Controller {
@@ -63,6 +218,43 @@ pub struct Controller {
 
     section_queues: HashMap<SectionId, VecDeque<TrainId>>,
     section_reservations: HashMap<SectionId, TrainId>,
+    lookahead: ReservationLookahead,
+
+    /// The polarity each section is powered with, so the train on it drives in the right direction.
+    section_polarities: HashMap<SectionId, HardwareSectionPolarity>,
+
+    train_powers: HashMap<TrainId, HardwareSectionPower>,
+    train_ramps: HashMap<TrainId, TrainRamp>,
+
+    section_timings: HashMap<TrainId, SectionTiming>,
+    record_calibration: bool,
+
+    train_positions: HashMap<TrainId, TrainPosition>,
+
+    /// The sections behind the head of each train, that are still covered by the train. Oldest first.
+    train_tails: HashMap<TrainId, VecDeque<TrainTailSection>>,
+
+    dispatcher: Option<Dispatcher>,
+
+    fault_reactions: FaultReactions,
+
+    watchdog_config: Option<WatchdogConfig>,
+    train_watchdogs: HashMap<TrainId, TrainWatchdog>,
+
+    /// The deadlocks, that were already reported to the ui.
+    known_deadlocks: Vec<Deadlock>,
+    deadlock_resolution: DeadlockResolution,
+
+    /// Set while the controller is halted by an emergency stop.
+    halt: Option<ControllerHalt>,
+
+    snapshot_config: Option<SnapshotConfig>,
+    journal_path: Option<PathBuf>,
+
+    scripts: Option<ScriptHost>,
+
+    /// Set after restoring a snapshot, until the positions of its trains were reconciled with the hardware.
+    restored: Option<RestoredSnapshot>,
 
     hardware_comm: Box<dyn ControllerHardwareCommunication>,
 
@@ -78,6 +270,10 @@ impl Controller {
         ui_event_tx: std::sync::mpsc::Sender<UiEvent>,
         ui_command_rx: crossbeam::channel::Receiver<UiCommand>,
     ) -> Self {
+        let scripts = config
+            .scripts
+            .map(|scripts| ScriptHost::new(scripts, config.clock.now()));
+
         Self {
             section_states: config
                 .track
@@ -91,9 +287,28 @@ impl Controller {
                 .collect(),
             track: config.track,
             trains: config.trains,
-            scheduler: Scheduler::default(),
+            scheduler: Scheduler::new(config.clock),
             section_queues: HashMap::new(),
             section_reservations: HashMap::new(),
+            lookahead: config.lookahead,
+            section_polarities: HashMap::new(),
+            train_powers: HashMap::new(),
+            train_ramps: HashMap::new(),
+            section_timings: HashMap::new(),
+            record_calibration: config.record_calibration,
+            train_positions: HashMap::new(),
+            train_tails: HashMap::new(),
+            dispatcher: config.dispatcher.map(Dispatcher::new),
+            fault_reactions: config.fault_reactions,
+            watchdog_config: config.watchdog,
+            train_watchdogs: HashMap::new(),
+            known_deadlocks: Vec::new(),
+            deadlock_resolution: config.deadlock_resolution,
+            halt: None,
+            snapshot_config: config.snapshot,
+            journal_path: config.journal,
+            scripts,
+            restored: None,
             hardware_comm: Box::new(hardware_comm),
             ui_event_tx,
             ui_command_rx,
@@ -137,11 +352,30 @@ impl Controller {
     pub fn section_queue(&self, section_id: SectionId) -> Option<&VecDeque<TrainId>> {
         self.section_queues.get(&section_id)
     }
+
+    pub fn section_polarity(&self, section_id: SectionId) -> HardwareSectionPolarity {
+        self.section_polarities
+            .get(&section_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn section_power_reason(&self, section_id: SectionId) -> Option<SectionPowerReason> {
+        self.section_states
+            .get(&section_id)
+            .and_then(|state| state.power_reason)
+    }
 }
 
 impl Controller {
     fn emit_ui(&self, event: impl Into<UiEvent>) {
-        let _ = self.ui_event_tx.send(event.into());
+        let event = event.into();
+
+        if let Some(scripts) = self.scripts.as_ref() {
+            scripts.handle_event(&event);
+        }
+
+        let _ = self.ui_event_tx.send(event);
     }
 }
 
@@ -193,6 +427,33 @@ impl Controller {
         }
     }
 
+    /// The train doesn't wait for the section anymore.
+    fn leave_section_queue(&mut self, train_id: TrainId, section_id: SectionId) {
+        if let Some(queue) = self.section_queues.get_mut(&section_id)
+            && queue.contains(&train_id)
+        {
+            queue.retain(|&id| id != train_id);
+            self.emit_ui(UiSectionEvent::QueueDequeued {
+                section_id,
+                train_id,
+            });
+        }
+    }
+
+    /// The train doesn't wait for any section anymore.
+    fn leave_section_queues(&mut self, train_id: TrainId) {
+        let sections = self
+            .section_queues
+            .iter()
+            .filter(|(_, queue)| queue.contains(&train_id))
+            .map(|(&section_id, _)| section_id)
+            .collect::<Vec<_>>();
+
+        for section_id in sections {
+            self.leave_section_queue(train_id, section_id);
+        }
+    }
+
     fn is_section_reserved_by_other(&self, section_id: SectionId, train_id: TrainId) -> bool {
         self.section_reservations
             .get(&section_id)
@@ -214,8 +475,8 @@ impl Controller {
         &mut self,
         section_id: SectionId,
         occupied: bool,
-        _ctx: EventExecutionContext,
-    ) {
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
         let state = self.section_states.entry(section_id).or_default();
         let previous_occupied = state.occupied.take();
 
@@ -225,6 +486,18 @@ impl Controller {
                 // TODO: how to handle this??
             }
 
+            // the train already covers this section and was detected again,
+            // e.g. because it is powered again after waiting
+            if let Some(train_id) = self.train_covering_section(section_id) {
+                self.section_states.entry(section_id).or_default().occupied = Some(train_id);
+                return self.confirm_restored_train(train_id, section_id, ctx);
+            }
+
+            // after a restart, a train might stand next to the section it was saved in
+            if self.detect_restored_train_elsewhere(section_id) {
+                return Ok(());
+            }
+
             let mut inbound_trains = self
                 .trains
                 .iter_mut()
@@ -236,53 +509,253 @@ impl Controller {
                 .collect::<Vec<_>>();
 
             if inbound_trains.is_empty() {
-                // there are no inbound trains, which is weird
-                // TODO: probably stop everything? something went wrong
-                return;
+                // there are no inbound trains, so either a train went the wrong way
+                // or something was left on the track
+                let fault = self
+                    .wrong_section_fault(section_id)
+                    .unwrap_or(ControllerFault::UnexpectedOccupancy { section_id });
+                return self.report_fault(fault, ctx);
             }
 
             if inbound_trains.len() > 1 {
-                // multiple trains are inbound for the same section??? something went wrong
-                // TODO: probably stop everything? something went wrong
-                return;
+                // multiple trains are inbound for the same section, we can't tell which one it is
+                let mut trains = inbound_trains
+                    .iter()
+                    .map(|(train_id, _)| **train_id)
+                    .collect::<Vec<_>>();
+                trains.sort();
+
+                return self
+                    .report_fault(ControllerFault::AmbiguousEntry { section_id, trains }, ctx);
             }
 
             let (inbound_train_id, inbound_train) = &mut inbound_trains[0];
-
-            if let Some(inbound_train_curent_section) = inbound_train.get_current_section() {
-                self.scheduler
-                    .schedule_now(ScheduledEvent::TrainLeftSection {
-                        train_id: **inbound_train_id,
-                        section_id: inbound_train_curent_section,
-                    });
-            }
+            let inbound_train_id = **inbound_train_id;
+            let previous_section = inbound_train.get_current_section();
 
             // it's just one train, so this must be the train that just entered this section
             inbound_train.entered_section(section_id);
+
+            // the previous section is released, once the tail of the train left it as well
+            if let Some(previous_section) = previous_section {
+                self.head_left_section(inbound_train_id, previous_section);
+            }
+
             self.scheduler
                 .schedule_now(ScheduledEvent::TrainEnteredSection {
-                    train_id: **inbound_train_id,
+                    train_id: inbound_train_id,
                     section_id,
                 });
 
-            state.occupied = Some(**inbound_train_id);
+            self.section_states.entry(section_id).or_default().occupied = Some(inbound_train_id);
         } else {
-            // Don't emit a TrainLeftSection event right here.
+            // Don't emit a TrainLeftSection event for the head of a train right here.
             // If we stop a train because it has to wait, the hardware
-            // will also emit a SectionFreed event
+            // will also emit a SectionFreed event.
+            // Only the tail of a moving train can be trusted to have left the section.
+            self.tail_section_freed(section_id);
         }
+
+        Ok(())
+    }
+
+    /// The fault for a moving train, that entered the given section instead of its next section.
+    fn wrong_section_fault(&self, section_id: SectionId) -> Option<ControllerFault> {
+        let neighbours = self.track.neighbouring_sections(section_id);
+
+        self.trains
+            .iter()
+            .filter(|&(&train_id, _)| !self.train_power(train_id).is_off())
+            .find_map(|(&train_id, train)| {
+                let current_section = train.get_current_section()?;
+                let expected = train.get_next_section()?;
+
+                neighbours
+                    .contains(&current_section)
+                    .then_some(ControllerFault::WrongSection {
+                        train_id,
+                        expected,
+                        actual: section_id,
+                    })
+            })
     }
 
     fn set_section_power(
         &mut self,
         section_id: SectionId,
         power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
         _ctx: EventExecutionContext,
     ) {
         let state = self.section_states.entry(section_id).or_default();
         state.power = power;
+        state.polarity = polarity;
 
-        self.emit_ui(UiSectionEvent::SetPower { section_id, power });
+        self.emit_ui(UiSectionEvent::SetPower {
+            section_id,
+            power,
+            polarity,
+        });
+    }
+
+    /// Power a section and remember why it is powered.
+    fn power_section(
+        &mut self,
+        section_id: SectionId,
+        power: HardwareSectionPower,
+        reason: Option<SectionPowerReason>,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        self.section_states
+            .entry(section_id)
+            .or_default()
+            .power_reason = reason;
+
+        ctx.exec(HardwareCommand::SetSectionPower {
+            section_id: section_id.as_u32(),
+            power,
+            polarity: self.section_polarity(section_id),
+        })
+    }
+
+    /// Reserve the sections ahead of the train on its route, as far as the lookahead reaches,
+    /// set the switches along them and power them. The train queues for the first section, that isn't available.
+    /// If the train can't stop within the sections it has reserved, it is stopped until that section is free.
+    ///
+    /// Returns whether the train can drive on.
+    fn request_sections_ahead(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<bool, ControllerError> {
+        let train = self.train(train_id)?;
+
+        if train.get_transition_to_next_section().is_none() {
+            // the train reached the end of its route, stop it
+            self.ramp_train(train_id, HardwareSectionPower::Off, ctx)?;
+            return Ok(false);
+        }
+
+        // sections, that the train doesn't drive through anymore, are handled like it left them
+        for section_id in self.stale_reservations(train_id) {
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id,
+                });
+        }
+
+        let mut reserved = Vec::new();
+        let mut claimed = Vec::new();
+        let mut blocked_section = None;
+
+        for transition in self.lookahead_transitions(train_id) {
+            let section_id = transition.destination();
+
+            if self.section_reservation(section_id) == Some(train_id) {
+                reserved.push(section_id);
+                continue;
+            }
+
+            // the sections have to be driven through in order, so don't reserve anything after this one
+            if !self.is_section_available(section_id, train_id) {
+                blocked_section = Some(section_id);
+                break;
+            }
+
+            self.try_reserve_section(section_id, train_id);
+            reserved.push(section_id);
+            claimed.push(transition);
+        }
+
+        log::debug!(
+            "train {} reserved sections ahead: {}",
+            train_id,
+            reserved.iter().join(", ")
+        );
+
+        // set the switches along the whole newly reserved path first
+        for transition in &claimed {
+            for SectionTransitionSwitchChange {
+                switch_id,
+                required_state,
+                ..
+            } in transition.required_switch_changes()
+            {
+                let hw_switch_id: HardwareSwitchId = switch_id.try_into().unwrap();
+                ctx.exec(HardwareCommand::SetSwitchState {
+                    switch_id: hw_switch_id,
+                    state: required_state.into(),
+                })?;
+            }
+        }
+
+        for transition in &claimed {
+            let section_id = transition.destination();
+
+            // the polarity of the section depends on the end the train enters it at
+            let polarity = transition
+                .destination_section_end()
+                .entering_direction()
+                .into();
+            self.section_polarities.insert(section_id, polarity);
+
+            // then power the section like the current one. If the train is
+            // accelerating, the remaining ramp steps will also be applied to this section.
+            let power = self.train_power(train_id);
+            let reason = self.train_power_reason(train_id, power);
+            self.power_section(section_id, power, Some(reason), ctx)?;
+        }
+
+        let Some(blocked_section) = blocked_section else {
+            return Ok(true);
+        };
+
+        self.enqueue_for_section(blocked_section, train_id);
+
+        if !self.exceeds_reserved_distance(train_id, &reserved) {
+            // the train can drive on and might get the section, before it has to stop
+            return Ok(true);
+        }
+
+        // stop the train
+        self.ramp_train(train_id, HardwareSectionPower::Off, ctx)?;
+
+        let train = self.train_mut(train_id)?;
+        match train.state() {
+            TrainState::Default => {
+                train.set_state(TrainState::Waiting);
+                self.emit_ui(UiTrainEvent::StateChanged {
+                    train_id,
+                    state: TrainState::Waiting,
+                });
+            }
+            // the train was about to stop anyway, so it stays where it is
+            TrainState::Stopping => self.stop_in_current_section(train_id, ctx)?,
+            TrainState::Waiting
+            | TrainState::Stopped
+            | TrainState::Paused
+            | TrainState::Faulted => {}
+        }
+
+        Ok(false)
+    }
+
+    /// Append the train to the waiting trains of the section, unless it is already waiting for it.
+    fn enqueue_for_section(&mut self, section_id: SectionId, train_id: TrainId) {
+        let queue = self.section_queues.entry(section_id).or_default();
+        if queue.contains(&train_id) {
+            return;
+        }
+
+        queue.push_back(train_id);
+        self.emit_ui(UiSectionEvent::QueueEnqueued {
+            section_id,
+            train_id,
+        });
+
+        // the section might be held by a train, that waits for this one
+        self.scheduler.schedule_now(ScheduledEvent::DeadlockCheck);
     }
 }
 
@@ -299,16 +772,18 @@ impl Controller {
 
                 match section_event.event_type {
                     SectionEventType::Occupied => {
-                        self.set_section_occupied(section_event.section_id.into(), true, ctx)
+                        self.set_section_occupied(section_event.section_id.into(), true, ctx)?
                     }
                     SectionEventType::Freed => {
-                        self.set_section_occupied(section_event.section_id.into(), false, ctx)
+                        self.set_section_occupied(section_event.section_id.into(), false, ctx)?
                     }
                 }
             }
-            HardwareEvent::SectionPowerChanged { section_id, power } => {
-                self.set_section_power(section_id.into(), power, ctx)
-            }
+            HardwareEvent::SectionPowerChanged {
+                section_id,
+                power,
+                polarity,
+            } => self.set_section_power(section_id.into(), power, polarity, ctx),
             HardwareEvent::SwitchStateChanged { switch_id, state } => {
                 let switch_id = SwitchId::from_hardware_id(&switch_id);
 
@@ -329,6 +804,17 @@ impl Controller {
         event: ScheduledEvent,
         ctx: EventExecutionContext,
     ) -> Result<(), ControllerError> {
+        // the train might have been removed, since the event was scheduled.
+        // The sections it left still have to be released.
+        if !matches!(event, ScheduledEvent::TrainLeftSection { .. })
+            && event
+                .train_id()
+                .is_some_and(|train_id| !self.trains.contains_key(&train_id))
+        {
+            log::debug!("ignoring {:?}, the train was removed", event);
+            return Ok(());
+        }
+
         match event {
             ScheduledEvent::TrainEnteredSection {
                 train_id,
@@ -343,70 +829,19 @@ impl Controller {
                     section_id: current_section_id,
                 });
 
-                let train = self.train(train_id)?;
-                let current_train_speed = train.speed();
-
-                if let Some(transition) = train.get_transition_to_next_section().cloned() {
-                    let next_section = transition.destination();
-
-                    log::debug!("next section is: {}", next_section);
-
-                    // don't just check, if the section is occupied, but also if there are other trains inbound
-                    // for the next section. if there are other trains inbound, we need to resolve the conflict.
-                    // Probably have some sort of waiting queue for each section, and if there are other trains inbound
-                    // just append this train to the queue.
-
-                    if self.is_section_available(next_section, train_id) {
-                        self.try_reserve_section(next_section, train_id);
-
-                        // set required switches to the next section first
-                        for SectionTransitionSwitchChange {
-                            switch_id,
-                            required_state,
-                            ..
-                        } in transition.required_switch_changes()
-                        {
-                            let hw_switch_id: HardwareSwitchId = switch_id.try_into().unwrap();
-                            ctx.exec(HardwareCommand::SetSwitchState {
-                                switch_id: hw_switch_id,
-                                state: required_state.into(),
-                            })?;
-                        }
-
-                        // get the next section power from the trains speed
-                        let next_section_power = current_train_speed.into();
-
-                        // then set power to next section
-                        ctx.exec(HardwareCommand::SetSectionPower {
-                            section_id: next_section.as_u32(),
-                            power: next_section_power,
-                        })?;
-                    } else {
-                        // stop the train
-                        ctx.exec(HardwareCommand::SetSectionPower {
-                            section_id: current_section_id.as_u32(),
-                            power: HardwareSectionPower::Off,
-                        })?;
-
-                        self.trains
-                            .get_mut(&train_id)
-                            .unwrap()
-                            .set_state(TrainState::Waiting);
-                        self.emit_ui(UiTrainEvent::StateChanged {
-                            train_id,
-                            state: TrainState::Waiting,
-                        });
+                self.record_section_timing(train_id, current_section_id);
+                self.reset_position_estimate(train_id, current_section_id);
+                self.arm_watchdog(train_id, current_section_id, ctx)?;
 
-                        // append it to the waiting trains
-                        self.section_queues
-                            .entry(next_section)
-                            .or_default()
-                            .push_back(train_id);
-                        self.emit_ui(UiSectionEvent::QueueEnqueued {
-                            section_id: next_section,
-                            train_id,
-                        });
-                    }
+                if self.train(train_id)?.state() == TrainState::Stopping {
+                    self.stop_in_current_section(train_id, ctx)?;
+                    return Ok(());
+                }
+
+                self.request_sections_ahead(train_id, ctx)?;
+
+                if self.dispatcher.is_some() && self.train(train_id)?.has_finished_route() {
+                    self.train_arrived(train_id);
                 }
             }
             ScheduledEvent::TrainLeftSection {
@@ -419,6 +854,11 @@ impl Controller {
                 });
                 self.release_reservation(section_id, train_id);
 
+                // the waiting trains might not be stuck anymore
+                if !self.known_deadlocks.is_empty() {
+                    self.scheduler.schedule_now(ScheduledEvent::DeadlockCheck);
+                }
+
                 while let Some(waiting_train_id) = self
                     .section_queues
                     .get_mut(&section_id)
@@ -432,75 +872,83 @@ impl Controller {
                     // this train was on the queue for this section id
                     // this means, either the section was occupied before
                     // or there was another train inbound
-                    let train = self.trains.get(&waiting_train_id).unwrap();
-                    let current_train_speed = train.speed();
+                    let wants_section = self
+                        .lookahead_transitions(waiting_train_id)
+                        .iter()
+                        .any(|transition| transition.destination() == section_id);
 
-                    if train
-                        .get_next_section()
-                        .is_none_or(|next_section| next_section != section_id)
-                    {
+                    if !wants_section {
                         // this train has changed its mind? it doesn't want to go to this section anymore
                         // check if there's another train waiting on the queue
                         continue;
                     }
 
-                    let current_section = train.get_current_section().unwrap();
-                    let transition = train.get_transition_to_next_section().cloned().unwrap(); // safe to unwrap
-
-                    self.try_reserve_section(section_id, train_id);
+                    let train = self.train(waiting_train_id)?;
+                    let was_waiting = train.state() == TrainState::Waiting;
+                    let target_power = train.target_power();
 
-                    // get the next section power from the trains speed
-                    let section_power = current_train_speed.into();
-
-                    // restart the train (repower its current section)
-                    ctx.exec(HardwareCommand::SetSectionPower {
-                        section_id: current_section.as_u32(),
-                        power: section_power,
-                    })?;
-
-                    // also update the state (we are not on `Waiting` anymore)
-                    self.trains
-                        .get_mut(&train_id)
-                        .unwrap()
-                        .set_state(TrainState::Default);
-                    self.emit_ui(UiTrainEvent::StateChanged {
-                        train_id,
-                        state: TrainState::Default,
-                    });
+                    // reserve this section and the ones after it, that are available now
+                    if self.request_sections_ahead(waiting_train_id, ctx)? && was_waiting {
+                        // also update the state (we are not on `Waiting` anymore)
+                        self.train_mut(waiting_train_id)?
+                            .set_state(TrainState::Default);
+                        self.emit_ui(UiTrainEvent::StateChanged {
+                            train_id: waiting_train_id,
+                            state: TrainState::Default,
+                        });
 
-                    // set required switches to the next section
-                    for SectionTransitionSwitchChange {
-                        switch_id,
-                        required_state,
-                        ..
-                    } in transition.required_switch_changes()
-                    {
-                        let hw_switch_id: HardwareSwitchId = switch_id.try_into().unwrap();
-                        ctx.exec(HardwareCommand::SetSwitchState {
-                            switch_id: hw_switch_id,
-                            state: required_state.into(),
-                        })?;
+                        // restart the train, this powers its current and the reserved sections
+                        self.ramp_train(waiting_train_id, target_power, ctx)?;
                     }
 
-                    // power the next section
-                    ctx.exec(HardwareCommand::SetSectionPower {
-                        section_id: section_id.as_u32(),
-                        power: section_power,
-                    })?;
-
                     return Ok(());
                 }
 
                 // there are now waiting trains, unpower this section
-                ctx.exec(HardwareCommand::SetSectionPower {
-                    section_id: section_id.as_u32(),
-                    power: HardwareSectionPower::Off,
-                })?;
+                self.power_section(section_id, HardwareSectionPower::Off, None, ctx)?;
             }
-            ScheduledEvent::TrainSpeedChanged { .. } => {
-                // TODO: update the power for the trains current and reserved sections
-                // the problem with this currently is, that we don't know if the train maybe is
-                // waiting (section powered off). In this case we should not update the power.
+            ScheduledEvent::TrainSpeedChanged { train_id, .. } => {
+                self.train_speed_changed(train_id, ctx)?;
+            }
+            ScheduledEvent::TrainRampStep { train_id } => {
+                self.ramp_step(train_id, ctx)?;
+            }
+            ScheduledEvent::DispatchTrain { train_id } => {
+                if let Some(dispatcher) = self.dispatcher.as_mut() {
+                    dispatcher.enqueue(train_id);
+                }
+
+                self.dispatch_pending_trains(ctx)?;
+            }
+            ScheduledEvent::DispatcherTick => {
+                self.dispatcher_tick(ctx)?;
+            }
+            ScheduledEvent::ResumeTrain { train_id } => {
+                self.resume_train(train_id, ctx)?;
+            }
+            ScheduledEvent::WatchdogCheck { train_id } => {
+                self.watchdog_check(train_id, ctx)?;
+            }
+            ScheduledEvent::WatchdogPulseEnd { train_id } => {
+                self.watchdog_pulse_end(train_id, ctx)?;
+            }
+            ScheduledEvent::DeadlockCheck => {
+                self.check_deadlocks(ctx)?;
+            }
+            ScheduledEvent::PositionEstimateTick => {
+                self.position_estimate_tick();
+            }
+            ScheduledEvent::SaveSnapshot => {
+                self.snapshot_tick();
+            }
+            ScheduledEvent::ReconcileSnapshot => {
+                self.reconcile_snapshot(ctx)?;
+            }
+            ScheduledEvent::ReloadScripts => {
+                self.reload_scripts();
+            }
+            ScheduledEvent::ScriptTimer { script, function } => {
+                self.script_timer(script, function);
             }
         }
 
@@ -515,6 +963,20 @@ impl Controller {
         let event = event.into();
         log::debug!("handling event: {:?}", event);
 
+        // the housekeeping of the controller isn't recorded, a replay derives the position estimates
+        // from the other events again, doesn't save snapshots and doesn't run scripts
+        if !matches!(
+            event,
+            ControllerEvent::Scheduled(
+                ScheduledEvent::PositionEstimateTick
+                    | ScheduledEvent::SaveSnapshot
+                    | ScheduledEvent::ReloadScripts
+                    | ScheduledEvent::ScriptTimer { .. }
+            )
+        ) {
+            ctx.record(|| JournalRecord::Event(event.clone()));
+        }
+
         match event {
             ControllerEvent::Scheduled(scheduled_event) => {
                 self.handle_scheduled_event(scheduled_event, ctx)?
@@ -534,17 +996,39 @@ impl Controller {
         &mut self,
         command: UiCommand,
         ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        ctx.record(|| JournalRecord::UiCommand(command.clone()));
+
+        if let Err(violation) = self.check_interlocking(&command) {
+            self.reject_command(command, violation);
+            return Ok(());
+        }
+
+        self.exec_ui_command(command, ctx)
+    }
+
+    fn exec_ui_command(
+        &mut self,
+        command: UiCommand,
+        ctx: EventExecutionContext,
     ) -> Result<(), ControllerError> {
         match command {
             UiCommand::SetSectionPower { section_id, power } => {
-                ctx.exec(HardwareCommand::SetSectionPower {
-                    section_id: section_id.as_u32(),
-                    power,
-                })?;
+                let reason = (!power.is_off()).then_some(SectionPowerReason::Manual);
+                self.power_section(section_id, power, reason, ctx)?;
             }
             UiCommand::SetSwitchState { switch_id, state } => {
+                // an override can name a switch, that isn't in the track
+                let Ok(hw_switch_id) = HardwareSwitchId::try_from(switch_id.clone()) else {
+                    let violation = InterlockingViolation::SwitchIdTooLong {
+                        switch_id: switch_id.clone(),
+                    };
+                    self.reject_command(UiCommand::SetSwitchState { switch_id, state }, violation);
+                    return Ok(());
+                };
+
                 ctx.exec(HardwareCommand::SetSwitchState {
-                    switch_id: switch_id.try_into().unwrap(),
+                    switch_id: hw_switch_id,
                     state: state.into(),
                 })?;
             }
@@ -552,6 +1036,9 @@ impl Controller {
                 if let Some(train) = self.trains.get_mut(&train_id) {
                     train.set_speed(speed);
 
+                    // the speed is limited to the maximum speed of the train
+                    let speed = train.speed();
+
                     // this scheduled event will re-set the power for the section that is currently
                     // occupied by the train and any section that is reserved for this train
                     self.scheduler
@@ -560,11 +1047,70 @@ impl Controller {
                     self.emit_ui(UiTrainEvent::SpeedChanged { train_id, speed });
                 }
             }
+            UiCommand::StartTrain { train_id } => self.start_train(train_id, ctx)?,
+            UiCommand::StopTrain { train_id } => self.stop_train(train_id, ctx)?,
+            UiCommand::PauseTrain { train_id } => self.pause_train(train_id, ctx)?,
+            UiCommand::AssignRoute { train_id, route } => match route.resolve(&self.track) {
+                Some(route) => self.assign_route(train_id, route, ctx)?,
+                None => log::warn!(
+                    "can't assign route {} to train {}, it isn't valid on this track",
+                    route.name,
+                    train_id
+                ),
+            },
+            UiCommand::AddTrain {
+                train_id,
+                data,
+                route,
+            } => match route.resolve(&self.track) {
+                Some(route) => self.add_train(train_id, Train::new(*data, route)),
+                None => log::warn!(
+                    "can't add train {}, route {} isn't valid on this track",
+                    train_id,
+                    route.name
+                ),
+            },
+            UiCommand::RemoveTrain { train_id } => self.remove_train(train_id, ctx)?,
+            UiCommand::ReverseTrain { train_id } => self.reverse_train(train_id, ctx)?,
+            UiCommand::AcknowledgeFault { train_id } => self.acknowledge_fault(train_id)?,
+            UiCommand::EmergencyStop => self.emergency_stop(ctx)?,
+            UiCommand::Override { command, reason } => {
+                self.override_interlocking(*command, reason, ctx)?
+            }
+            UiCommand::Resume => self.resume(),
+            UiCommand::RequestSnapshot => {
+                self.emit_ui(UiControllerEvent::Snapshot(Box::new(self.ui_snapshot())));
+            }
+            UiCommand::SetTrainData { train_id, data } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    let previous_speed = train.speed();
+
+                    train.set_data(*data);
+
+                    let data = Box::new(train.data().clone());
+                    let speed = train.speed();
+
+                    self.emit_ui(UiTrainEvent::DataChanged { train_id, data });
+
+                    // the new maximum speed might be lower than the current speed
+                    if speed != previous_speed {
+                        self.scheduler
+                            .schedule_now(ScheduledEvent::TrainSpeedChanged { train_id, speed });
+                        self.emit_ui(UiTrainEvent::SpeedChanged { train_id, speed });
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// The sequence number of the pings sent during `init`.
+    const PING_SEQ: u32 = 1337;
+
+    /// How long the hardware takes to start, before it answers the first command.
+    const HARDWARE_STARTUP: Duration = Duration::from_secs(5);
+
     fn resolve_pending_events(
         &mut self,
         ctx: EventExecutionContext,
@@ -579,7 +1125,7 @@ impl Controller {
         log::debug!("getting slaves from master");
         ctx.exec(HardwareCommand::GetSlaves)?;
 
-        let event = ctx.event_rx.recv()?;
+        let event = ctx.recv_event()?;
         let HardwareEvent::Slaves { n_slaves } = event else {
             log::debug!("expected HardwareEvent::Slaves, got {:?}", event);
             return Err(ControllerError::ExpectedHardwareEvent(
@@ -589,7 +1135,7 @@ impl Controller {
 
         log::debug!("we have {} slaves", n_slaves);
 
-        let ping_seq = 1337;
+        let ping_seq = Self::PING_SEQ;
 
         // send pings
         for device_id in 0..=n_slaves {
@@ -600,7 +1146,7 @@ impl Controller {
                 seq: ping_seq,
             })?;
 
-            if let HardwareEvent::Pong { slave_id, seq } = ctx.event_rx.recv()?
+            if let HardwareEvent::Pong { slave_id, seq } = ctx.recv_event()?
                 && slave_id == device_id
                 && seq == ping_seq
             {
@@ -619,17 +1165,22 @@ impl Controller {
         // reset everything
         ctx.exec(HardwareCommand::ResetAll)?;
 
-        // initialize all the trains
-        for (_, train) in &self.trains {
-            let Some(initial_section) = train.get_initial_section() else {
+        // initialize all the trains, unless they continue where a restored snapshot left off
+        let train_ids = if self.continue_restored(ctx)? {
+            Vec::new()
+        } else {
+            self.trains.keys().copied().collect::<Vec<_>>()
+        };
+        for train_id in train_ids {
+            let train = &self.trains[&train_id];
+            if train.get_initial_section().is_none() || train.state().is_held() {
                 continue;
-            };
+            }
 
-            // power on the current section
-            ctx.exec(HardwareCommand::SetSectionPower {
-                section_id: initial_section.as_u32(),
-                power: HardwareSectionPower::Full,
-            })?;
+            // power on the initial section, accelerating the train to its speed
+            let power = train.target_power();
+            self.ramp_train(train_id, power, ctx)?;
+            self.emit_ui(UiTrainEvent::Started { train_id, power });
 
             // powering up the initial section will cause the train to trigger the train detection sensor
             // and cause a SectionOccupied event. Because the trains current section will be set to None,
@@ -645,6 +1196,28 @@ impl Controller {
                 });*/
         }
 
+        if self.dispatcher.is_some() {
+            self.scheduler
+                .schedule_every(Dispatcher::TICK_INTERVAL, ScheduledEvent::DispatcherTick);
+        }
+
+        self.scheduler.schedule_every(
+            Self::POSITION_ESTIMATE_INTERVAL,
+            ScheduledEvent::PositionEstimateTick,
+        );
+
+        if let Some(config) = self.snapshot_config.as_ref() {
+            self.scheduler
+                .schedule_every(config.interval, ScheduledEvent::SaveSnapshot);
+        }
+
+        if let Some(scripts) = self.scripts.as_ref() {
+            let interval = scripts.config().reload_interval;
+            self.scheduler.schedule_now(ScheduledEvent::ReloadScripts);
+            self.scheduler
+                .schedule_every(interval, ScheduledEvent::ReloadScripts);
+        }
+
         Ok(())
     }
 
@@ -690,26 +1263,31 @@ impl Controller {
         ctx.exec(HardwareCommand::SetSectionPower {
             section_id: section_id.as_u32(),
             power: HardwareSectionPower::Quarter,
+            polarity: HardwareSectionPolarity::Normal,
         })?;
         std::thread::sleep(Duration::from_secs(1));
         ctx.exec(HardwareCommand::SetSectionPower {
             section_id: section_id.as_u32(),
             power: HardwareSectionPower::Half,
+            polarity: HardwareSectionPolarity::Normal,
         })?;
         std::thread::sleep(Duration::from_secs(1));
         ctx.exec(HardwareCommand::SetSectionPower {
             section_id: section_id.as_u32(),
             power: HardwareSectionPower::ThreeQuarters,
+            polarity: HardwareSectionPolarity::Normal,
         })?;
         std::thread::sleep(Duration::from_secs(1));
         ctx.exec(HardwareCommand::SetSectionPower {
             section_id: section_id.as_u32(),
             power: HardwareSectionPower::Full,
+            polarity: HardwareSectionPolarity::Normal,
         })?;
         std::thread::sleep(Duration::from_secs(1));
         ctx.exec(HardwareCommand::SetSectionPower {
             section_id: section_id.as_u32(),
             power: HardwareSectionPower::Off,
+            polarity: HardwareSectionPolarity::Normal,
         })?;
 
         std::thread::sleep(Duration::from_secs(5));
@@ -730,8 +1308,15 @@ impl Controller {
         Ok(())
     }
 
+    /// Run the controller in the calling thread, until the ui goes away.
+    /// The controller waits for events in real time, so it needs a clock, that moves on its own.
     pub fn start(mut self) -> Result<(), ControllerError> {
+        if !self.scheduler.clock().is_real_time() {
+            return Err(ControllerError::ClockNotRealTime);
+        }
+
         let (command_tx, command_rx) = crossbeam::channel::unbounded();
+        let (priority_command_tx, priority_command_rx) = crossbeam::channel::unbounded();
         let (event_tx, event_rx) = crossbeam::channel::unbounded();
 
         // start the hardware communication
@@ -739,18 +1324,38 @@ impl Controller {
             .start(ControllerHardwareCommunicationChannels {
                 event_tx,
                 command_rx,
+                priority_command_rx,
             })?;
 
-        std::thread::sleep(Duration::from_secs(5));
+        // give the hardware time to boot
+        self.scheduler.clock().sleep(Self::HARDWARE_STARTUP);
+
+        let journal = self
+            .journal_path
+            .as_deref()
+            .map(|path| JournalWriter::create(path, self.scheduler.clock().clone()))
+            .transpose()?;
 
         let ctx = EventExecutionContext {
             command_tx: &command_tx,
+            priority_command_tx: &priority_command_tx,
             event_rx: &event_rx,
+            journal: journal.as_ref(),
         };
 
         // initialize the controller
         self.init(ctx)?;
 
+        let result = self.run(ctx);
+
+        // continue from here on the next start
+        self.save_snapshot();
+
+        result
+    }
+
+    /// Handle events and commands, until the ui goes away.
+    fn run(&mut self, ctx: EventExecutionContext) -> Result<(), ControllerError> {
         // test the sections
         // self.test_sections(ctx)?;
         // Self::test_section(26_usize.into(), ctx)?;
@@ -772,20 +1377,25 @@ impl Controller {
                 .unwrap_or(Duration::from_millis(500));
 
             crossbeam::select! {
-                recv(event_rx) -> event => {
+                recv(ctx.event_rx) -> event => {
                     if let Ok(event) = event {
                         self.handle_event(event, ctx)?;
                     }
                 }
                 recv(self.ui_command_rx) -> command => {
-                    if let Ok(command) = command {
-                        self.handle_ui_command(command, ctx)?;
-                    }
+                    let Ok(command) = command else {
+                        log::info!("no more commands can be received, stopping the controller");
+                        return Ok(());
+                    };
+
+                    self.handle_ui_command(command, ctx)?;
                 }
                 default(event_timeout)  => {
                     self.resolve_pending_events(ctx)?;
                 }
             }
+
+            self.run_scripts(ctx)?;
         }
     }
 }
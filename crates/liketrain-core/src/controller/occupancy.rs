@@ -0,0 +1,154 @@
+use crate::{Controller, ScheduledEvent, SectionId, TrainId};
+
+#[cfg(test)]
+mod tests;
+
+/// A section behind the head of a train, that is still covered by the rest of the train.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct TrainTailSection {
+    section_id: SectionId,
+
+    /// The odometer of the train, when its head left this section.
+    left_at: f32,
+}
+
+impl Controller {
+    /// The sections the train covers, from its tail to its head.
+    pub fn train_sections(&self, train_id: TrainId) -> Vec<SectionId> {
+        let mut sections = self
+            .train_tails
+            .get(&train_id)
+            .map(|tail| tail.iter().map(|tail| tail.section_id).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        if let Some(current_section) = self
+            .trains
+            .get(&train_id)
+            .and_then(|train| train.get_current_section())
+        {
+            sections.push(current_section);
+        }
+
+        sections
+    }
+
+    /// The train, whose head or tail is in the given section.
+    pub(super) fn train_covering_section(&self, section_id: SectionId) -> Option<TrainId> {
+        self.trains
+            .keys()
+            .copied()
+            .find(|&train_id| self.train_sections(train_id).contains(&section_id))
+    }
+
+    /// The head of the train left the given section. The section is released once the tail
+    /// of the train left it as well. Trains without a length are assumed to be shorter than any section.
+    pub(super) fn head_left_section(&mut self, train_id: TrainId, section_id: SectionId) {
+        let length = self
+            .trains
+            .get(&train_id)
+            .map(|train| train.data().length)
+            .unwrap_or_default();
+
+        if length <= 0.0 {
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id,
+                });
+            return;
+        }
+
+        let left_at = self.section_end_odometer(train_id, section_id);
+        self.train_tails
+            .entry(train_id)
+            .or_default()
+            .push_back(TrainTailSection {
+                section_id,
+                left_at,
+            });
+    }
+
+    /// Cover the given sections behind the head of the train, e.g. after restoring a snapshot.
+    /// Where exactly the train left them isn't known anymore, so they are released
+    /// once the train drove its full length from where it is now.
+    pub(super) fn restore_tail_sections(&mut self, train_id: TrainId, sections: &[SectionId]) {
+        let left_at = self.train_odometer(train_id).unwrap_or_default();
+
+        self.train_tails.insert(
+            train_id,
+            sections
+                .iter()
+                .map(|&section_id| TrainTailSection {
+                    section_id,
+                    left_at,
+                })
+                .collect(),
+        );
+    }
+
+    /// Release all tail sections, that the train has driven its full length past.
+    pub(super) fn release_cleared_tail_sections(&mut self, train_id: TrainId) {
+        let Some(odometer) = self.train_odometer(train_id) else {
+            return;
+        };
+
+        let length = self
+            .trains
+            .get(&train_id)
+            .map(|train| train.data().length)
+            .unwrap_or_default();
+
+        let Some(tail) = self.train_tails.get_mut(&train_id) else {
+            return;
+        };
+
+        while let Some(tail_section) = tail
+            .front()
+            .filter(|tail_section| odometer >= tail_section.left_at + length)
+            .copied()
+        {
+            tail.pop_front();
+
+            log::debug!(
+                "the tail of train {} cleared section {}",
+                train_id,
+                tail_section.section_id
+            );
+
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id: tail_section.section_id,
+                });
+        }
+    }
+
+    /// The hardware doesn't detect a train in the given section anymore. If it is a tail section
+    /// of a moving train, the tail cleared this section and all sections behind it.
+    pub(super) fn tail_section_freed(&mut self, section_id: SectionId) {
+        let Some((train_id, idx)) = self.train_tails.iter().find_map(|(&train_id, tail)| {
+            tail.iter()
+                .position(|tail_section| tail_section.section_id == section_id)
+                .map(|idx| (train_id, idx))
+        }) else {
+            return;
+        };
+
+        // a stopped train doesn't draw any current, so it can't be detected
+        if self.train_power(train_id).is_off() {
+            return;
+        }
+
+        let Some(tail) = self.train_tails.get_mut(&train_id) else {
+            return;
+        };
+
+        for tail_section in tail.drain(..=idx).collect::<Vec<_>>() {
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id: tail_section.section_id,
+                });
+        }
+    }
+}
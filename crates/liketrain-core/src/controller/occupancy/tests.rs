@@ -0,0 +1,76 @@
+use crate::{
+    Clock, TrainCalibration,
+    comm::{SimTrain, Simulation},
+    controller::testing::{config, route, section, simulate, track, train},
+};
+
+use super::*;
+
+/// Step the simulation, until the sections covered by the train changed.
+fn next_sections(simulation: &mut Simulation, train_id: TrainId) -> Vec<SectionId> {
+    let sections = simulation.controller().train_sections(train_id);
+    for _ in 0..1000 {
+        simulation.step().unwrap();
+
+        let next = simulation.controller().train_sections(train_id);
+        if next != sections {
+            return next;
+        }
+    }
+
+    panic!("the train is stuck in {:?}", sections);
+}
+
+#[test]
+fn test_long_train_tail_release() {
+    let track = track();
+
+    // a 30 m long train, driving 10 m/s at full power. S14 is only 15.1 m long.
+    let train = train("T1", route(&track, &[12, 14, 16, 9]))
+        .with_length(30.0)
+        .with_calibration(TrainCalibration::linear(10.0));
+    let sim_train = SimTrain::from_train(&train, &track).unwrap();
+
+    let (mut simulation, _ui_event_rx) = simulate(config(track, [(1, train)]), [sim_train]);
+    let train_id = TrainId::new(1);
+
+    assert_eq!(next_sections(&mut simulation, train_id), [section(12)]);
+
+    // the head entered S14, the rest of the train is still in S12
+    assert_eq!(
+        next_sections(&mut simulation, train_id),
+        [section(12), section(14)]
+    );
+    let entered_at = simulation.clock().now();
+
+    // the head already passed the short S14, the tail didn't leave S12 yet
+    assert_eq!(
+        next_sections(&mut simulation, train_id),
+        [section(12), section(14), section(16)]
+    );
+    let other_train = TrainId::new(2);
+    assert!(
+        !simulation
+            .controller()
+            .is_section_available(section(12), other_train)
+    );
+    assert_eq!(
+        simulation.controller().section_reservation(section(14)),
+        Some(train_id)
+    );
+
+    // S12 is released, once the train drove its full length past it
+    assert_eq!(
+        next_sections(&mut simulation, train_id),
+        [section(14), section(16)]
+    );
+    let cleared_after = (simulation.clock().now() - entered_at).as_secs_f32();
+    assert!((3.0..3.1).contains(&cleared_after), "{}", cleared_after);
+    assert!(
+        simulation
+            .controller()
+            .is_section_available(section(12), other_train)
+    );
+
+    assert_eq!(next_sections(&mut simulation, train_id), [section(16)]);
+}
@@ -0,0 +1,105 @@
+use crate::{Controller, ControllerError, Route, ScheduledEvent, TrainId, ui::UiTrainEvent};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+impl Controller {
+    /// Swap the front and the back of a stopped train. It drives its route back to where it came from,
+    /// so the sections it reserved ahead are released. A train, that isn't held, requests
+    /// the sections ahead of it again right away.
+    pub(super) fn reverse_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        // the train would power its sections again
+        if self.is_halted() {
+            log::warn!(
+                "can't reverse train {}, while the controller is halted",
+                train_id
+            );
+            return Ok(());
+        }
+
+        if !self.train_power(train_id).is_off() {
+            log::warn!("can't reverse train {}, while it is moving", train_id);
+            return Ok(());
+        }
+
+        if !self.turn_train(train_id) {
+            return Ok(());
+        }
+
+        let train = self.train(train_id)?;
+        if train.state().is_held() {
+            return Ok(());
+        }
+
+        let power = train.target_power();
+        if self.request_sections_ahead(train_id, ctx)? {
+            self.ramp_train(train_id, power, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reverse the standing train and let it drive its route back to the start.
+    /// Where the route can't be driven back, the train stays in its section until it gets a new route.
+    pub(super) fn turn_train(&mut self, train_id: TrainId) -> bool {
+        let Some(train) = self.trains.get_mut(&train_id) else {
+            return false;
+        };
+
+        let Some(route) = train.route() else {
+            return false;
+        };
+
+        let orientation = train.orientation().opposite();
+        let current_via_idx = train.current_via_idx();
+
+        let idx = current_via_idx.unwrap_or_default();
+        let Some(route) = route.inverted(idx, orientation, &self.track).or_else(|| {
+            let section_id = route.via(idx)?;
+            Route::new(route.name(), [section_id], orientation, &self.track)
+        }) else {
+            log::warn!(
+                "can't reverse train {}, no route leads back from its section",
+                train_id
+            );
+            return false;
+        };
+
+        train.reverse();
+
+        // the train didn't enter the first section of the route yet, if it wasn't detected
+        train.resume_route(route.clone(), current_via_idx.map(|_| 0), orientation);
+
+        log::info!(
+            "train {} is now facing {}, driving {}",
+            train_id,
+            orientation,
+            route.name()
+        );
+
+        self.reverse_position_estimate(train_id);
+        self.emit_ui(UiTrainEvent::OrientationChanged {
+            train_id,
+            orientation,
+        });
+        self.emit_ui(UiTrainEvent::RouteChanged { train_id, route });
+
+        // the sections ahead are behind the train now
+        self.leave_section_queues(train_id);
+        for section_id in self.stale_reservations(train_id) {
+            self.scheduler
+                .schedule_now(ScheduledEvent::TrainLeftSection {
+                    train_id,
+                    section_id,
+                });
+        }
+
+        true
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::{
+    Direction, Route, SectionId, TrainRosterRoute,
+    comm::Simulation,
+    controller::testing::{
+        config, drive, occupy, record_power, route, section, simulate, track, train,
+    },
+    ui::{UiCommand, UiEvent},
+};
+
+use super::*;
+
+/// The sections of the route the train drives.
+fn vias(simulation: &Simulation, train_id: TrainId) -> Vec<SectionId> {
+    let train = simulation.controller().train(train_id).unwrap();
+    train.route().unwrap().vias().to_vec()
+}
+
+#[test]
+fn test_assign_route_keeps_orientation() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14]));
+    let forward_route =
+        Route::new("S12 -> S10", [12_usize, 10], Direction::Forward, &track).unwrap();
+
+    let (mut simulation, _ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    let train_id = TrainId::new(1);
+
+    occupy(&mut simulation, 12);
+    simulation
+        .command(UiCommand::PauseTrain { train_id })
+        .unwrap();
+
+    // the route starts the other way than the train is facing
+    let assign_route = UiCommand::AssignRoute {
+        train_id,
+        route: TrainRosterRoute::from(&forward_route),
+    };
+    simulation.command(assign_route.clone()).unwrap();
+
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.orientation(), Direction::Backward);
+    assert_eq!(vias(&simulation, train_id), [section(12), section(14)]);
+
+    // once the train was reversed, it can drive the route
+    simulation
+        .command(UiCommand::ReverseTrain { train_id })
+        .unwrap();
+    assert_eq!(vias(&simulation, train_id), [section(12)]);
+
+    simulation.command(assign_route).unwrap();
+
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.orientation(), Direction::Forward);
+    assert_eq!(vias(&simulation, train_id), [section(12), section(10)]);
+}
+
+#[test]
+fn test_reverse_train() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    let train_id = TrainId::new(1);
+
+    occupy(&mut simulation, 12);
+    drive(&mut simulation, &[12, 14, 16]);
+    assert_eq!(
+        simulation.controller().section_reservation(section(9)),
+        Some(train_id)
+    );
+
+    // a moving train isn't reversed
+    simulation
+        .command(UiCommand::ReverseTrain { train_id })
+        .unwrap();
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.orientation(), Direction::Backward);
+
+    simulation
+        .command(UiCommand::PauseTrain { train_id })
+        .unwrap();
+    ui_event_rx.try_iter().for_each(drop);
+
+    simulation
+        .command(UiCommand::ReverseTrain { train_id })
+        .unwrap();
+    simulation.step().unwrap();
+
+    // the train drives back the way it came, the section ahead isn't needed anymore
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.orientation(), Direction::Forward);
+    assert_eq!(
+        vias(&simulation, train_id),
+        [section(16), section(14), section(12)]
+    );
+    assert_eq!(
+        simulation.controller().section_reservation(section(9)),
+        None
+    );
+    assert!(ui_event_rx.try_iter().any(|event| matches!(
+        event,
+        UiEvent::UiTrainEvent(UiTrainEvent::OrientationChanged {
+            orientation: Direction::Forward,
+            ..
+        })
+    )));
+
+    // started again, it requests the sections behind it
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+    assert_eq!(
+        simulation.controller().section_reservation(section(14)),
+        Some(train_id)
+    );
+    assert!(!simulation.controller().train_power(train_id).is_off());
+}
+
+#[test]
+fn test_reverse_train_while_halted() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    let train_id = TrainId::new(1);
+
+    occupy(&mut simulation, 12);
+    drive(&mut simulation, &[12, 14, 16]);
+
+    simulation.command(UiCommand::EmergencyStop).unwrap();
+    record_power(&mut simulation, &ui_event_rx, Duration::from_millis(100));
+
+    // every train is standing, but reversing one would power its sections again
+    simulation
+        .command(UiCommand::ReverseTrain { train_id })
+        .unwrap();
+    simulation
+        .command(UiCommand::Override {
+            command: Box::new(UiCommand::ReverseTrain { train_id }),
+            reason: "maintenance".to_string(),
+        })
+        .unwrap();
+
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_millis(500));
+    assert!(
+        powers.iter().all(|(_, _, power)| power.is_off()),
+        "{:?}",
+        powers
+    );
+
+    let train = simulation.controller().train(train_id).unwrap();
+    assert_eq!(train.orientation(), Direction::Backward);
+    assert!(simulation.controller().is_halted());
+}
@@ -0,0 +1,218 @@
+use std::time::{self, Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Controller, Direction, SectionId, TrainCalibration, TrainId, ui::UiTrainEvent};
+
+#[cfg(test)]
+mod tests;
+
+/// The estimated position of a train within its current section.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainPositionEstimate {
+    pub section_id: SectionId,
+
+    /// In which direction the train drives through the section.
+    pub direction: Direction,
+
+    /// The distance the train has driven into the section in meters (already in respect to the tracks scale)
+    pub distance: f32,
+
+    /// The length of the section in meters (already in respect to the tracks scale)
+    pub section_length: f32,
+
+    /// The estimated time until the train reaches the end of the section.
+    /// `None`, if the train is stopped or its speed isn't known.
+    pub eta: Option<Duration>,
+}
+
+impl TrainPositionEstimate {
+    /// How far the train has driven through the section, from 0.0 to 1.0.
+    pub fn progress(&self) -> f32 {
+        if self.section_length <= 0.0 {
+            return 0.0;
+        }
+
+        (self.distance / self.section_length).clamp(0.0, 1.0)
+    }
+}
+
+/// The position estimate of a train and when it was last advanced.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct TrainPosition {
+    estimate: TrainPositionEstimate,
+    updated_at: time::Instant,
+
+    /// The total distance the train drove, before it entered the current section.
+    section_start: f32,
+
+    /// The train isn't calibrated, its distance in the section is estimated with the nominal speed.
+    nominal: bool,
+}
+
+impl TrainPosition {
+    /// The total distance the train drove. A distance estimated with the nominal speed isn't
+    /// counted, the train might be slower and still cover the sections behind it.
+    fn odometer(&self) -> f32 {
+        if self.nominal {
+            return self.section_start;
+        }
+
+        self.section_start + self.estimate.distance
+    }
+
+    /// The total distance the train will have driven, once it reaches the end of the current section.
+    fn section_end(&self) -> f32 {
+        self.section_start + self.estimate.section_length
+    }
+}
+
+impl Controller {
+    /// How often the position estimates of the moving trains are published.
+    pub const POSITION_ESTIMATE_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn position_estimate(&self, train_id: TrainId) -> Option<TrainPositionEstimate> {
+        self.train_positions
+            .get(&train_id)
+            .map(|position| position.estimate)
+    }
+
+    /// The speed the train is currently driving with in meters per second, based on its calibration,
+    /// and whether it is only the nominal speed, because the train wasn't calibrated yet.
+    fn estimated_train_speed(&self, train_id: TrainId) -> Option<(f32, bool)> {
+        let train = self.trains.get(&train_id)?;
+        let power = self.train_power(train_id);
+
+        if let Some(speed) = train.calibration().estimate_speed(power) {
+            return Some((speed, false));
+        }
+
+        TrainCalibration::linear(TrainCalibration::NOMINAL_FULL_SPEED)
+            .estimate_speed(power)
+            .map(|speed| (speed, true))
+    }
+
+    /// The total distance the train drove, as far as it can be estimated.
+    pub(super) fn train_odometer(&self, train_id: TrainId) -> Option<f32> {
+        self.train_positions
+            .get(&train_id)
+            .map(TrainPosition::odometer)
+    }
+
+    /// The total distance the train drove, when it left the given section.
+    pub(super) fn section_end_odometer(&self, train_id: TrainId, section_id: SectionId) -> f32 {
+        match self.train_positions.get(&train_id) {
+            Some(position) if position.estimate.section_id == section_id => position.section_end(),
+            Some(position) => position.odometer(),
+            None => 0.0,
+        }
+    }
+
+    /// The train entered a new section, so its position is at the start of that section.
+    pub(super) fn reset_position_estimate(&mut self, train_id: TrainId, section_id: SectionId) {
+        let Some(section_geo) = self.track.section_geo(&section_id) else {
+            self.train_positions.remove(&train_id);
+            return;
+        };
+
+        let Some(train) = self.trains.get(&train_id) else {
+            return;
+        };
+
+        // continue counting from the end of the previous section
+        let section_start = match self.train_positions.get(&train_id) {
+            Some(position) if position.estimate.section_id != section_id => position.section_end(),
+            Some(position) => position.section_start,
+            None => 0.0,
+        };
+
+        self.train_positions.insert(
+            train_id,
+            TrainPosition {
+                estimate: TrainPositionEstimate {
+                    section_id,
+                    direction: train.orientation(),
+                    distance: 0.0,
+                    section_length: section_geo.length,
+                    eta: None,
+                },
+                updated_at: self.scheduler.now(),
+                section_start,
+                nominal: false,
+            },
+        );
+
+        self.advance_position_estimate(train_id);
+    }
+
+    /// The train was reversed, so it now faces the other end of its section.
+    pub(super) fn reverse_position_estimate(&mut self, train_id: TrainId) {
+        let Some(orientation) = self.trains.get(&train_id).map(|train| train.orientation()) else {
+            return;
+        };
+
+        let Some(position) = self.train_positions.get_mut(&train_id) else {
+            return;
+        };
+
+        let estimate = &mut position.estimate;
+        estimate.direction = orientation;
+        estimate.distance = estimate.section_length - estimate.distance;
+        estimate.eta = None;
+
+        let estimate = *estimate;
+        self.emit_ui(UiTrainEvent::PositionEstimated { train_id, estimate });
+    }
+
+    /// Move the estimated position of the train forward, by the distance it drove with
+    /// its current power since the last update.
+    /// Has to be called before the power of the train changes.
+    pub(super) fn advance_position_estimate(&mut self, train_id: TrainId) {
+        let speed = self.estimated_train_speed(train_id);
+
+        let Some(position) = self.train_positions.get_mut(&train_id) else {
+            return;
+        };
+
+        if let Some((_, nominal)) = speed {
+            position.nominal = nominal;
+        }
+        let speed = speed.map(|(speed, _)| speed);
+
+        let now = self.scheduler.now();
+        let elapsed = now - position.updated_at;
+        position.updated_at = now;
+
+        let estimate = &mut position.estimate;
+
+        if let Some(speed) = speed {
+            // the train can't leave the section, before the next section detected it
+            estimate.distance =
+                (estimate.distance + speed * elapsed.as_secs_f32()).min(estimate.section_length);
+        }
+
+        // a tiny speed takes longer than a `Duration` can hold
+        estimate.eta = speed.filter(|&speed| speed > 0.0).and_then(|speed| {
+            Duration::try_from_secs_f32((estimate.section_length - estimate.distance) / speed).ok()
+        });
+
+        let estimate = *estimate;
+        self.emit_ui(UiTrainEvent::PositionEstimated { train_id, estimate });
+
+        self.release_cleared_tail_sections(train_id);
+    }
+
+    /// Publish the estimated positions of all moving trains.
+    pub(super) fn position_estimate_tick(&mut self) {
+        let moving_trains = self
+            .train_positions
+            .keys()
+            .copied()
+            .filter(|&train_id| !self.train_power(train_id).is_off())
+            .collect::<Vec<_>>();
+
+        for train_id in moving_trains {
+            self.advance_position_estimate(train_id);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::controller::testing::{config, occupy, route, section, simulate, track, train};
+
+use super::*;
+
+/// The estimate of a train, after it drove in S12 for the given time.
+fn estimate_after(calibration: TrainCalibration, duration: Duration) -> TrainPositionEstimate {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14])).with_calibration(calibration);
+
+    let (mut simulation, _ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    occupy(&mut simulation, 12);
+    simulation.run_for(duration).unwrap();
+
+    simulation
+        .controller()
+        .position_estimate(TrainId::new(1))
+        .unwrap()
+}
+
+#[test]
+fn test_calibrated_position_estimate() {
+    let estimate = estimate_after(TrainCalibration::linear(10.0), Duration::from_secs(2));
+    assert_eq!(estimate.section_id, section(12));
+
+    // the estimate is published every tick, the train drives 10 m/s at full power
+    assert!((17.0..=20.5).contains(&estimate.distance), "{:?}", estimate);
+
+    let eta = estimate.eta.unwrap().as_secs_f32();
+    let expected_eta = (estimate.section_length - estimate.distance) / 10.0;
+    assert!((eta - expected_eta).abs() < 0.01, "{:?}", estimate);
+}
+
+#[test]
+fn test_uncalibrated_position_estimate() {
+    // without a calibration, the train is assumed to drive with the nominal speed
+    let estimate = estimate_after(TrainCalibration::default(), Duration::from_secs(2));
+    let nominal = TrainCalibration::NOMINAL_FULL_SPEED;
+    assert!(
+        (nominal * 1.7..=nominal * 2.05).contains(&estimate.distance),
+        "{:?}",
+        estimate
+    );
+    assert!(estimate.eta.is_some());
+
+    // the train doesn't leave the section, before the next section detected it
+    let estimate = estimate_after(TrainCalibration::default(), Duration::from_secs(60));
+    assert_eq!(estimate.distance, estimate.section_length);
+    assert_eq!(estimate.eta, Some(Duration::ZERO));
+}
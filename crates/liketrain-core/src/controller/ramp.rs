@@ -0,0 +1,224 @@
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    Controller, ControllerError, ScheduledEvent, ScheduledEventHandle, SectionId,
+    SectionPowerReason, TrainId, TrainState,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct TrainRamp {
+    target: HardwareSectionPower,
+
+    /// The next step, cancelled when the ramp is stopped or replaced.
+    step: Option<ScheduledEventHandle>,
+}
+
+/// The next power step from `current` in the direction of `target`.
+pub(super) fn step_towards(
+    current: HardwareSectionPower,
+    target: HardwareSectionPower,
+) -> HardwareSectionPower {
+    use HardwareSectionPower::*;
+
+    let steps = [Off, Quarter, Half, ThreeQuarters, Full];
+    let current_idx = current as usize;
+    let target_idx = target as usize;
+
+    if current_idx < target_idx {
+        steps[current_idx + 1]
+    } else if current_idx > target_idx {
+        steps[current_idx - 1]
+    } else {
+        target
+    }
+}
+
+impl Controller {
+    /// The power that is currently applied to the sections of the train.
+    pub fn train_power(&self, train_id: TrainId) -> HardwareSectionPower {
+        self.train_powers
+            .get(&train_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The sections the train is driving on. These are its current section,
+    /// or the initial section if it hasn't been detected yet, and all sections reserved by it.
+    pub(super) fn train_powered_sections(&self, train_id: TrainId) -> Vec<SectionId> {
+        let Some(train) = self.trains.get(&train_id) else {
+            return Vec::new();
+        };
+
+        let mut sections = train
+            .get_current_section()
+            .or_else(|| train.get_initial_section())
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        for (&section_id, &holder) in &self.section_reservations {
+            if holder == train_id && !sections.contains(&section_id) {
+                sections.push(section_id);
+            }
+        }
+
+        sections
+    }
+
+    pub(super) fn apply_train_power(
+        &mut self,
+        train_id: TrainId,
+        power: HardwareSectionPower,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.train_power(train_id) != power {
+            // the train drove with the previous power until now
+            self.advance_position_estimate(train_id);
+            self.invalidate_section_timing(train_id);
+        }
+
+        self.train_powers.insert(train_id, power);
+
+        let current_section = self.trains.get(&train_id).and_then(|train| {
+            train
+                .get_current_section()
+                .or_else(|| train.get_initial_section())
+                .map(|section_id| (section_id, train.orientation()))
+        });
+
+        // the train drives through its current section in its current direction
+        if let Some((section_id, direction)) = current_section {
+            self.section_polarities.insert(section_id, direction.into());
+        }
+
+        let reason = self.train_power_reason(train_id, power);
+        for section_id in self.train_powered_sections(train_id) {
+            self.power_section(section_id, power, Some(reason), ctx)?;
+        }
+
+        Ok(())
+    }
+
+    /// Why the sections of the train are powered, when they are set to the given power.
+    pub(super) fn train_power_reason(
+        &self,
+        train_id: TrainId,
+        power: HardwareSectionPower,
+    ) -> SectionPowerReason {
+        // a running ramp decides, whether the train is about to stop
+        let heading_to = self
+            .train_ramps
+            .get(&train_id)
+            .map(|ramp| ramp.target)
+            .unwrap_or(power);
+
+        if heading_to.is_off() {
+            SectionPowerReason::Stopped(train_id)
+        } else {
+            SectionPowerReason::Driving(train_id)
+        }
+    }
+
+    /// Drive the train with its new target power. Only trains, that have sections powered to drive through them,
+    /// are changed. Waiting or stopped trains keep standing and pick up the new power, once they are restarted.
+    pub(super) fn train_speed_changed(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.is_halted() {
+            return Ok(());
+        }
+
+        let train = self.train(train_id)?;
+        if train.state() == TrainState::Waiting || train.state().is_held() {
+            return Ok(());
+        }
+
+        let target_power = train.target_power();
+
+        let driving = self
+            .train_powered_sections(train_id)
+            .into_iter()
+            .any(|section_id| {
+                self.section_power_reason(section_id) == Some(SectionPowerReason::Driving(train_id))
+            });
+
+        if !driving {
+            return Ok(());
+        }
+
+        log::debug!("train {} changes its power to {:?}", train_id, target_power);
+        self.ramp_train(train_id, target_power, ctx)
+    }
+
+    /// Stop the running ramp of the train, returning the power it was ramping to.
+    pub(super) fn cancel_ramp(&mut self, train_id: TrainId) -> Option<HardwareSectionPower> {
+        let ramp = self.train_ramps.remove(&train_id)?;
+        if let Some(step) = ramp.step {
+            self.scheduler.cancel(step);
+        }
+
+        Some(ramp.target)
+    }
+
+    /// Accelerate or decelerate the train to the target power, according to its ramp profile.
+    /// A running ramp of the train is replaced.
+    pub(super) fn ramp_train(
+        &mut self,
+        train_id: TrainId,
+        target: HardwareSectionPower,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        self.cancel_ramp(train_id);
+
+        self.train_ramps
+            .insert(train_id, TrainRamp { target, step: None });
+        self.ramp_step(train_id, ctx)
+    }
+
+    pub(super) fn ramp_step(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(ramp) = self.train_ramps.get(&train_id).copied() else {
+            return Ok(());
+        };
+
+        let profile = self.train(train_id)?.ramp_profile();
+        let current = self.train_power(train_id);
+
+        let interval = if ramp.target > current {
+            profile.acceleration_step
+        } else {
+            profile.deceleration_step
+        };
+
+        let power = if interval.is_zero() {
+            ramp.target
+        } else {
+            step_towards(current, ramp.target)
+        };
+
+        self.apply_train_power(train_id, power, ctx)?;
+
+        if power == ramp.target {
+            self.train_ramps.remove(&train_id);
+        } else {
+            let step = self
+                .scheduler
+                .schedule_in(interval, ScheduledEvent::TrainRampStep { train_id });
+
+            if let Some(ramp) = self.train_ramps.get_mut(&train_id) {
+                ramp.step = Some(step);
+            }
+        }
+
+        Ok(())
+    }
+}
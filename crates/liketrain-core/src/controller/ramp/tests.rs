@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use crate::{
+    TrainRampProfile,
+    controller::testing::{config, record_power, route, section, simulate, track, train},
+};
+
+use super::*;
+
+#[test]
+fn test_step_towards() {
+    use HardwareSectionPower::*;
+
+    assert_eq!(step_towards(Off, Full), Quarter);
+    assert_eq!(step_towards(Half, Quarter), Quarter);
+    assert_eq!(step_towards(Full, Off), ThreeQuarters);
+    assert_eq!(step_towards(Half, Half), Half);
+}
+
+#[test]
+fn test_ramp_steps() {
+    let track = track();
+    let profile = TrainRampProfile::new(Duration::from_millis(800), Duration::from_millis(400));
+    let train = train("T1", route(&track, &[12, 14])).with_ramp_profile(profile);
+    let train_id = TrainId::new(1);
+
+    // the train accelerates in its initial section, one power step after another
+    let (mut simulation, ui_event_rx) = simulate(config(track, [(1, train)]), []);
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_secs(5));
+
+    use HardwareSectionPower::*;
+    let steps = powers
+        .iter()
+        .map(|&(_, section_id, power)| (section_id, power))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        [Quarter, Half, ThreeQuarters, Full].map(|power| (section(12), power))
+    );
+
+    for pair in powers.windows(2) {
+        assert_eq!(pair[1].0 - pair[0].0, profile.acceleration_step);
+    }
+
+    // decelerating uses its own interval
+    simulation
+        .with_controller(|controller, ctx| controller.ramp_train(train_id, Off, ctx))
+        .unwrap();
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_millis(500));
+    let steps = powers
+        .iter()
+        .map(|&(at, _, power)| (at, power))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        [
+            (Duration::from_millis(10), ThreeQuarters),
+            (Duration::from_millis(410), Half)
+        ]
+    );
+
+    // a cancelled ramp stays at the power it reached
+    let target = simulation
+        .with_controller(|controller, _| Ok(controller.cancel_ramp(train_id)))
+        .unwrap();
+    assert_eq!(target, Some(Off));
+
+    let powers = record_power(&mut simulation, &ui_event_rx, Duration::from_secs(5));
+    assert!(powers.is_empty());
+    assert_eq!(simulation.controller().train_power(train_id), Half);
+}
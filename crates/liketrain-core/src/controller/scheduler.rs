@@ -1,18 +1,29 @@
 use std::{
-    collections::BinaryHeap,
+    collections::{BinaryHeap, HashMap},
+    sync::Arc,
     time::{self, Duration},
 };
 
-use crate::ScheduledEvent;
+use crate::{Clock, ScheduledEvent, SystemClock, TrainId};
 
+/// Identifies a scheduled event, to cancel or reschedule it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ScheduledEventHandle(u64);
+
+/// When a pending event is due. Entries of cancelled or rescheduled events are left in the heap
+/// and skipped, once they come up.
 pub struct TimedEvent {
     when: time::Instant,
-    event: ScheduledEvent,
+    id: u64,
 }
 
 impl Ord for TimedEvent {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.when.cmp(&self.when)
+        // events that are due at the same time are handed out in the order they were scheduled
+        other
+            .when
+            .cmp(&self.when)
+            .then_with(|| other.id.cmp(&self.id))
     }
 }
 
@@ -24,30 +35,230 @@ impl PartialOrd for TimedEvent {
 
 impl PartialEq for TimedEvent {
     fn eq(&self, other: &Self) -> bool {
-        self.when == other.when
+        self.when == other.when && self.id == other.id
     }
 }
 
 impl Eq for TimedEvent {}
 
-#[derive(Default)]
+struct PendingEvent {
+    when: time::Instant,
+    event: ScheduledEvent,
+
+    /// Repeat the event with this period, until it is cancelled.
+    period: Option<Duration>,
+
+    /// The train this event is a pending action of, see `Scheduler::cancel_train`.
+    train_id: Option<TrainId>,
+
+    /// Whether the event was pending, when the scheduler was frozen. It is delayed by the time the scheduler was frozen.
+    frozen: bool,
+}
+
 pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+
     time_events: BinaryHeap<TimedEvent>,
+    pending_events: HashMap<u64, PendingEvent>,
+    next_id: u64,
+
+    /// When the scheduler was frozen.
+    frozen_at: Option<time::Instant>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
 }
 
 impl Scheduler {
-    pub fn schedule(&mut self, when: time::Instant, event: impl Into<ScheduledEvent>) {
-        let event = event.into();
-        self.time_events.push(TimedEvent { when, event });
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            time_events: BinaryHeap::new(),
+            pending_events: HashMap::new(),
+            next_id: 0,
+            frozen_at: None,
+        }
+    }
+
+    pub fn now(&self) -> time::Instant {
+        self.clock.now()
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn schedule(
+        &mut self,
+        when: time::Instant,
+        event: impl Into<ScheduledEvent>,
+    ) -> ScheduledEventHandle {
+        self.insert(when, event.into(), None, None)
+    }
+
+    pub fn schedule_now(&mut self, event: impl Into<ScheduledEvent>) -> ScheduledEventHandle {
+        self.schedule(self.now(), event)
+    }
+
+    pub fn schedule_in(
+        &mut self,
+        delay: Duration,
+        event: impl Into<ScheduledEvent>,
+    ) -> ScheduledEventHandle {
+        self.schedule(self.now() + delay, event)
+    }
+
+    /// Hand out the event every `period`, starting one period from now, until it is cancelled.
+    pub fn schedule_every(
+        &mut self,
+        period: Duration,
+        event: impl Into<ScheduledEvent>,
+    ) -> ScheduledEventHandle {
+        self.insert(self.now() + period, event.into(), Some(period), None)
+    }
+
+    /// Schedule a pending action of the train, that is dropped by `cancel_train`,
+    /// e.g. when the train is stopped or gets a new route.
+    pub fn schedule_for_train(
+        &mut self,
+        train_id: TrainId,
+        delay: Duration,
+        event: impl Into<ScheduledEvent>,
+    ) -> ScheduledEventHandle {
+        self.insert(self.now() + delay, event.into(), None, Some(train_id))
+    }
+
+    fn insert(
+        &mut self,
+        when: time::Instant,
+        event: ScheduledEvent,
+        period: Option<Duration>,
+        train_id: Option<TrainId>,
+    ) -> ScheduledEventHandle {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.pending_events.insert(
+            id,
+            PendingEvent {
+                when,
+                event,
+                period,
+                train_id,
+                frozen: false,
+            },
+        );
+        self.time_events.push(TimedEvent { when, id });
+
+        ScheduledEventHandle(id)
+    }
+
+    /// Whether the event wasn't handed out or cancelled yet. Periodic events are pending, until they are cancelled.
+    pub fn is_pending(&self, handle: ScheduledEventHandle) -> bool {
+        self.pending_events.contains_key(&handle.0)
+    }
+
+    /// Drop the event. Returns false, if it was already handed out or cancelled.
+    pub fn cancel(&mut self, handle: ScheduledEventHandle) -> bool {
+        let cancelled = self.pending_events.remove(&handle.0).is_some();
+        self.discard_stale();
+
+        cancelled
+    }
+
+    /// Drop all pending actions of the train. Returns how many there were.
+    pub fn cancel_train(&mut self, train_id: TrainId) -> usize {
+        let before = self.pending_events.len();
+        self.pending_events
+            .retain(|_, pending| pending.train_id != Some(train_id));
+        self.discard_stale();
+
+        before - self.pending_events.len()
+    }
+
+    /// Move the event to another time. Returns false, if it was already handed out or cancelled.
+    pub fn reschedule(&mut self, handle: ScheduledEventHandle, when: time::Instant) -> bool {
+        let Some(pending) = self.pending_events.get_mut(&handle.0) else {
+            return false;
+        };
+
+        pending.when = when;
+        pending.frozen = false;
+        self.time_events.push(TimedEvent { when, id: handle.0 });
+        self.discard_stale();
+
+        true
+    }
+
+    pub fn reschedule_in(&mut self, handle: ScheduledEventHandle, delay: Duration) -> bool {
+        self.reschedule(handle, self.now() + delay)
+    }
+
+    /// Pop the entries of cancelled and rescheduled events, so the next entry is always pending.
+    fn discard_stale(&mut self) {
+        while let Some(next) = self.time_events.peek() {
+            let is_pending = self
+                .pending_events
+                .get(&next.id)
+                .is_some_and(|pending| pending.when == next.when);
+            if is_pending {
+                break;
+            }
+
+            self.time_events.pop();
+        }
+    }
+
+    /// Stop handing out events. Pending events keep their remaining delay until the scheduler
+    /// is resumed. Events scheduled while frozen are handed out after resuming.
+    pub fn freeze(&mut self) {
+        if self.frozen_at.is_some() {
+            return;
+        }
+
+        for pending in self.pending_events.values_mut() {
+            pending.frozen = true;
+        }
+
+        self.frozen_at = Some(self.now());
+    }
+
+    pub fn resume(&mut self) {
+        let Some(frozen_at) = self.frozen_at.take() else {
+            return;
+        };
+
+        let frozen_for = self.now().saturating_duration_since(frozen_at);
+        for pending in self.pending_events.values_mut() {
+            if std::mem::take(&mut pending.frozen) {
+                pending.when += frozen_for;
+            }
+        }
+
+        self.time_events = self
+            .pending_events
+            .iter()
+            .map(|(&id, pending)| TimedEvent {
+                when: pending.when,
+                id,
+            })
+            .collect();
     }
 
-    pub fn schedule_now(&mut self, event: impl Into<ScheduledEvent>) {
-        self.schedule(time::Instant::now(), event);
+    pub fn is_frozen(&self) -> bool {
+        self.frozen_at.is_some()
     }
 
     pub fn next_event_duration(&self) -> Option<Duration> {
+        if self.is_frozen() {
+            return None;
+        }
+
         self.time_events.peek().map(|event| {
-            let now = time::Instant::now();
+            let now = self.now();
             if event.when <= now {
                 Duration::ZERO
             } else {
@@ -57,9 +268,9 @@ impl Scheduler {
     }
 
     pub fn next_event(&mut self) -> Option<ScheduledEvent> {
-        let now = time::Instant::now();
+        let now = self.now();
 
-        if self.time_events.is_empty() {
+        if self.is_frozen() || self.time_events.is_empty() {
             return None;
         }
 
@@ -71,7 +282,26 @@ impl Scheduler {
             return None;
         }
 
-        let event = self.time_events.pop().unwrap();
-        Some(event.event)
+        // the next entry is always pending, see `discard_stale`
+        let next = self.time_events.pop().unwrap();
+        let pending = self.pending_events.get_mut(&next.id).unwrap();
+
+        let event = match pending.period {
+            Some(period) => {
+                // a periodic event, that is late, isn't handed out multiple times to catch up
+                pending.when = (next.when + period).max(now);
+                self.time_events.push(TimedEvent {
+                    when: pending.when,
+                    id: next.id,
+                });
+
+                pending.event.clone()
+            }
+            None => self.pending_events.remove(&next.id).unwrap().event,
+        };
+
+        self.discard_stale();
+
+        Some(event)
     }
 }
@@ -0,0 +1,813 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::{NaiveTime, TimeDelta, Timelike};
+use liketrain_hardware::event::HardwareSectionPower;
+use rhai::{
+    AST, CallFnOptions, Dynamic, Engine, EvalAltResult, FLOAT, INT, Map, Scope,
+    module_resolvers::DummyModuleResolver,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    Controller, ControllerError, ScheduledEvent, SectionId, SwitchId, SwitchState, TrainId,
+    TrainSpeed,
+    ui::{
+        UiCommand, UiControllerEvent, UiEvent, UiSectionEvent, UiSnapshot, UiSwitchEvent,
+        UiTrainEvent,
+    },
+};
+
+use super::EventExecutionContext;
+
+/// Where the automation scripts are and how often they are checked for changes.
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    /// A directory of Rhai scripts (`.rhai` files).
+    pub dir: PathBuf,
+
+    /// How often the directory is checked for new, changed and removed scripts.
+    pub reload_interval: Duration,
+
+    /// The time of day the controller starts at, as `hour()` and `minute()` see it.
+    /// `None` takes the local time. Simulations can set it, so their scripts see the same times in every run.
+    pub start_time: Option<NaiveTime>,
+}
+
+impl ScriptConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            reload_interval: Duration::from_secs(1),
+            start_time: None,
+        }
+    }
+}
+
+/// A script failed to load or one of its handlers failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptError {
+    /// The file name of the script.
+    pub script: String,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Script {}: {}", self.script, self.message)
+    }
+}
+
+/// Something a script asked the controller to do.
+#[derive(Debug)]
+enum ScriptAction {
+    Command(UiCommand),
+
+    /// Call a function of the script later.
+    After {
+        delay: Duration,
+        script: String,
+        function: String,
+    },
+}
+
+/// What the functions of the api see and collect, while a handler runs.
+#[derive(Default)]
+struct ScriptContext {
+    /// The state of the controller, before the handlers were called.
+    snapshot: UiSnapshot,
+
+    /// The time of day on the clock of the controller, when the handlers were called.
+    time_of_day: NaiveTime,
+
+    /// The script, whose handler is running.
+    script: String,
+
+    actions: Vec<ScriptAction>,
+}
+
+/// A function of the scripts to call.
+struct ScriptCall {
+    /// Only call the function of this script, e.g. for a timer. `None` calls it in every script, that defines it.
+    script: Option<String>,
+    function: String,
+    args: Vec<Dynamic>,
+}
+
+struct CompiledScript {
+    ast: AST,
+
+    /// The names of the functions, the script defines.
+    functions: HashSet<String>,
+
+    /// Bound to `this` in every call, so the script can keep values between calls.
+    state: Dynamic,
+}
+
+struct LoadedScript {
+    modified: SystemTime,
+
+    /// `None`, if the script failed to compile. It is compiled again, once it changes.
+    compiled: Option<CompiledScript>,
+}
+
+/// Runs the automation scripts. The scripts can only read the state of the controller and
+/// issue ui commands, they can't access files or the network.
+pub(super) struct ScriptHost {
+    config: ScriptConfig,
+    engine: Engine,
+
+    /// By file name.
+    scripts: BTreeMap<String, LoadedScript>,
+
+    /// Whether the directory couldn't be read the last time, so it is only reported once.
+    unreadable: bool,
+
+    context: Arc<Mutex<ScriptContext>>,
+
+    /// The calls queued while the controller handled events, they are made once it is done.
+    pending: RefCell<VecDeque<ScriptCall>>,
+
+    /// When the controller started on its clock, and the time of day it started at.
+    started: (Instant, NaiveTime),
+}
+
+impl ScriptHost {
+    /// How many operations a single call may take, so a script can't hang the controller.
+    const MAX_OPERATIONS: u64 = 100_000;
+
+    pub(super) fn new(config: ScriptConfig, now: Instant) -> Self {
+        let start_time = config
+            .start_time
+            .unwrap_or_else(|| chrono::Local::now().time());
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(Self::MAX_OPERATIONS)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval");
+
+        let print_context = context.clone();
+        engine.on_print(move |text| {
+            log::info!("script {}: {}", print_context.lock().unwrap().script, text);
+        });
+        let debug_context = context.clone();
+        engine.on_debug(move |text, _, position| {
+            log::debug!(
+                "script {} ({}): {}",
+                debug_context.lock().unwrap().script,
+                position,
+                text
+            );
+        });
+
+        register_api(&mut engine, &context);
+
+        Self {
+            config,
+            engine,
+            scripts: BTreeMap::new(),
+            unreadable: false,
+            context,
+            pending: RefCell::new(VecDeque::new()),
+            started: (now, start_time),
+        }
+    }
+
+    pub(super) fn config(&self) -> &ScriptConfig {
+        &self.config
+    }
+
+    /// Compile the scripts, that are new or changed since the last reload, and forget the removed ones.
+    pub(super) fn reload(&mut self) -> Vec<ScriptError> {
+        let entries = match std::fs::read_dir(&self.config.dir) {
+            Ok(entries) => {
+                self.unreadable = false;
+                entries
+            }
+            Err(_) if self.unreadable => return Vec::new(),
+            Err(err) => {
+                self.unreadable = true;
+                return vec![ScriptError {
+                    script: self.config.dir.display().to_string(),
+                    message: format!("failed to read the scripts: {}", err),
+                }];
+            }
+        };
+
+        let mut files = BTreeMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "rhai") {
+                continue;
+            }
+
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) else {
+                continue;
+            };
+
+            files.insert(name.to_string(), (path.clone(), modified));
+        }
+
+        self.scripts.retain(|name, _| {
+            let exists = files.contains_key(name);
+            if !exists {
+                log::info!("script {} was removed", name);
+            }
+
+            exists
+        });
+
+        let mut errors = Vec::new();
+        for (name, (path, modified)) in files {
+            if self
+                .scripts
+                .get(&name)
+                .is_some_and(|script| script.modified == modified)
+            {
+                continue;
+            }
+
+            let compiled = std::fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|source| self.engine.compile(source).map_err(|err| err.to_string()));
+
+            let compiled = match compiled {
+                Ok(ast) => {
+                    log::info!("loaded script {}", name);
+
+                    let functions = ast
+                        .iter_functions()
+                        .map(|function| function.name.to_string())
+                        .collect::<HashSet<_>>();
+                    if functions.contains("on_load") {
+                        self.pending.borrow_mut().push_back(ScriptCall {
+                            script: Some(name.clone()),
+                            function: "on_load".to_string(),
+                            args: Vec::new(),
+                        });
+                    }
+
+                    Some(CompiledScript {
+                        ast,
+                        functions,
+                        state: Dynamic::from_map(Map::new()),
+                    })
+                }
+                Err(message) => {
+                    errors.push(ScriptError {
+                        script: name.clone(),
+                        message,
+                    });
+                    None
+                }
+            };
+
+            self.scripts
+                .insert(name, LoadedScript { modified, compiled });
+        }
+
+        errors
+    }
+
+    fn defines(&self, script: Option<&str>, function: &str) -> bool {
+        self.scripts
+            .iter()
+            .filter(|(name, _)| script.is_none_or(|script| script == name.as_str()))
+            .filter_map(|(_, script)| script.compiled.as_ref())
+            .any(|compiled| compiled.functions.contains(function))
+    }
+
+    /// Queue a call of the function, if a script defines it.
+    pub(super) fn call(&self, script: Option<String>, function: &str, args: Vec<Dynamic>) {
+        if !self.defines(script.as_deref(), function) {
+            return;
+        }
+
+        self.pending.borrow_mut().push_back(ScriptCall {
+            script,
+            function: function.to_string(),
+            args,
+        });
+    }
+
+    /// Queue the handlers of the event.
+    pub(super) fn handle_event(&self, event: &UiEvent) {
+        if let Some((function, args)) = event_handler(event) {
+            self.call(None, function, args);
+        }
+    }
+
+    pub(super) fn has_pending(&self) -> bool {
+        !self.pending.borrow().is_empty()
+    }
+
+    /// The time of day at the given time of the clock of the controller.
+    fn time_of_day(&self, now: Instant) -> NaiveTime {
+        let (started_at, start_time) = self.started;
+        let elapsed = TimeDelta::from_std(now.saturating_duration_since(started_at))
+            .unwrap_or(TimeDelta::MAX);
+
+        // wraps around at midnight
+        start_time + elapsed
+    }
+
+    /// Make the queued calls, with the given state of the controller at the given time of its clock.
+    fn run(&mut self, snapshot: UiSnapshot, now: Instant) -> (Vec<ScriptAction>, Vec<ScriptError>) {
+        let calls = self.pending.take();
+        let time_of_day = self.time_of_day(now);
+        {
+            let mut context = self.context.lock().unwrap();
+            context.snapshot = snapshot;
+            context.time_of_day = time_of_day;
+        }
+
+        let mut errors = Vec::new();
+        for call in calls {
+            for (name, script) in self.scripts.iter_mut() {
+                if call.script.as_ref().is_some_and(|script| script != name) {
+                    continue;
+                }
+
+                let Some(compiled) = script.compiled.as_mut() else {
+                    continue;
+                };
+                if !compiled.functions.contains(&call.function) {
+                    if call.script.is_some() {
+                        errors.push(ScriptError {
+                            script: name.clone(),
+                            message: format!("there is no function {}", call.function),
+                        });
+                    }
+                    continue;
+                }
+
+                self.context.lock().unwrap().script = name.clone();
+
+                let options = CallFnOptions::new()
+                    .eval_ast(false)
+                    .bind_this_ptr(&mut compiled.state);
+                let result = self.engine.call_fn_with_options::<Dynamic>(
+                    options,
+                    &mut Scope::new(),
+                    &compiled.ast,
+                    &call.function,
+                    call.args.clone(),
+                );
+
+                if let Err(err) = result {
+                    errors.push(ScriptError {
+                        script: name.clone(),
+                        message: format!("{} failed: {}", call.function, err),
+                    });
+                }
+            }
+        }
+
+        let actions = std::mem::take(&mut self.context.lock().unwrap().actions);
+        (actions, errors)
+    }
+}
+
+/// The handler of the scripts for an event and its arguments.
+fn event_handler(event: &UiEvent) -> Option<(&'static str, Vec<Dynamic>)> {
+    let handler = match event {
+        UiEvent::UiTrainEvent(UiTrainEvent::EnteredSection {
+            train_id,
+            section_id,
+        }) => (
+            "on_train_entered",
+            vec![train_id.into_dynamic(), section_id.into_dynamic()],
+        ),
+        UiEvent::UiTrainEvent(UiTrainEvent::Started { train_id, .. }) => {
+            ("on_train_started", vec![train_id.into_dynamic()])
+        }
+        UiEvent::UiTrainEvent(UiTrainEvent::Stopped { train_id }) => {
+            ("on_train_stopped", vec![train_id.into_dynamic()])
+        }
+        UiEvent::UiTrainEvent(UiTrainEvent::SpeedChanged { train_id, speed }) => (
+            "on_train_speed_changed",
+            vec![train_id.into_dynamic(), name(speed).into()],
+        ),
+        UiEvent::UiTrainEvent(UiTrainEvent::StateChanged { train_id, state }) => (
+            "on_train_state_changed",
+            vec![train_id.into_dynamic(), name(state).into()],
+        ),
+        UiEvent::UiTrainEvent(UiTrainEvent::Overdue {
+            train_id,
+            section_id,
+        }) => (
+            "on_train_overdue",
+            vec![train_id.into_dynamic(), section_id.into_dynamic()],
+        ),
+        UiEvent::UiSectionEvent(UiSectionEvent::Occupied {
+            section_id,
+            train_id: Some(train_id),
+        }) => (
+            "on_section_occupied",
+            vec![section_id.into_dynamic(), train_id.into_dynamic()],
+        ),
+        UiEvent::UiSectionEvent(UiSectionEvent::Occupied {
+            section_id,
+            train_id: None,
+        }) => ("on_section_freed", vec![section_id.into_dynamic()]),
+        UiEvent::UiSwitchEvent(UiSwitchEvent::SetState { id, state }) => (
+            "on_switch_changed",
+            vec![id.to_string().into(), name(state).into()],
+        ),
+        UiEvent::UiControllerEvent(UiControllerEvent::Halted) => ("on_halted", Vec::new()),
+        UiEvent::UiControllerEvent(UiControllerEvent::Resumed) => ("on_resumed", Vec::new()),
+        UiEvent::UiControllerEvent(UiControllerEvent::Fault(fault)) => {
+            ("on_fault", vec![fault.to_string().into()])
+        }
+        UiEvent::UiControllerEvent(UiControllerEvent::Deadlock(deadlock)) => {
+            ("on_deadlock", vec![deadlock.to_string().into()])
+        }
+        UiEvent::UiControllerEvent(UiControllerEvent::CommandRejected { violation, .. }) => {
+            ("on_command_rejected", vec![violation.to_string().into()])
+        }
+        _ => return None,
+    };
+
+    Some(handler)
+}
+
+trait IntoDynamic {
+    fn into_dynamic(self) -> Dynamic;
+}
+
+impl IntoDynamic for &TrainId {
+    fn into_dynamic(self) -> Dynamic {
+        (self.as_usize() as INT).into()
+    }
+}
+
+impl IntoDynamic for &SectionId {
+    fn into_dynamic(self) -> Dynamic {
+        (self.as_usize() as INT).into()
+    }
+}
+
+/// The name of a variant, as in the JSON of the api, e.g. `Medium` or `Left`.
+fn name(value: impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn parse<T: DeserializeOwned>(kind: &str, name: &str) -> Result<T, Box<EvalAltResult>> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| format!("unknown {} {:?}", kind, name).into())
+}
+
+impl ScriptContext {
+    fn train_id(&self, id: INT) -> Result<TrainId, Box<EvalAltResult>> {
+        usize::try_from(id)
+            .ok()
+            .map(TrainId::from)
+            .filter(|train_id| {
+                self.snapshot
+                    .trains
+                    .iter()
+                    .any(|train| train.id == *train_id)
+            })
+            .ok_or_else(|| format!("there is no train {}", id).into())
+    }
+
+    fn section_id(&self, id: INT) -> Result<SectionId, Box<EvalAltResult>> {
+        usize::try_from(id)
+            .ok()
+            .map(SectionId::from)
+            .filter(|section_id| {
+                self.snapshot
+                    .sections
+                    .iter()
+                    .any(|section| section.id == *section_id)
+            })
+            .ok_or_else(|| format!("there is no section {}", id).into())
+    }
+
+    fn switch_state(&self, id: &str) -> Result<(SwitchId, SwitchState), Box<EvalAltResult>> {
+        self.snapshot
+            .switches
+            .iter()
+            .find(|switch| switch.id.matches(id))
+            .map(|switch| (switch.id.clone(), switch.state))
+            .ok_or_else(|| format!("there is no switch {}", id).into())
+    }
+
+    fn command(&mut self, command: UiCommand) {
+        self.actions.push(ScriptAction::Command(command));
+    }
+}
+
+/// The longest a script can wait with `after`.
+const MAX_SCRIPT_DELAY: Duration = Duration::from_secs(24 * 3600);
+
+/// The functions the scripts can call.
+fn register_api(engine: &mut Engine, context: &Arc<Mutex<ScriptContext>>) {
+    let ctx = context.clone();
+    engine.register_fn("trains", move || {
+        let context = ctx.lock().unwrap();
+        context
+            .snapshot
+            .trains
+            .iter()
+            .map(|train| train.id.into_dynamic())
+            .collect::<rhai::Array>()
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("train", move |id: INT| -> Result<Map, Box<EvalAltResult>> {
+        let context = ctx.lock().unwrap();
+        let train_id = context.train_id(id)?;
+        let Some(train) = context
+            .snapshot
+            .trains
+            .iter()
+            .find(|train| train.id == train_id)
+        else {
+            return Err(format!("there is no train {}", id).into());
+        };
+
+        let mut map = Map::new();
+        map.insert("id".into(), id.into());
+        map.insert("name".into(), train.train.data().name.clone().into());
+        map.insert("speed".into(), name(train.train.speed()).into());
+        map.insert("state".into(), name(train.train.state()).into());
+        map.insert(
+            "section".into(),
+            train
+                .train
+                .get_current_section()
+                .map(|section_id| section_id.into_dynamic())
+                .unwrap_or(Dynamic::UNIT),
+        );
+
+        Ok(map)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "section",
+        move |id: INT| -> Result<Map, Box<EvalAltResult>> {
+            let context = ctx.lock().unwrap();
+            let section_id = context.section_id(id)?;
+            let Some(section) = context
+                .snapshot
+                .sections
+                .iter()
+                .find(|section| section.id == section_id)
+            else {
+                return Err(format!("there is no section {}", id).into());
+            };
+
+            let train = |train_id: Option<TrainId>| {
+                train_id
+                    .map(|train_id| train_id.into_dynamic())
+                    .unwrap_or(Dynamic::UNIT)
+            };
+
+            let mut map = Map::new();
+            map.insert("id".into(), id.into());
+            map.insert("power".into(), name(section.power).into());
+            map.insert("occupant".into(), train(section.occupant));
+            map.insert("reserved_by".into(), train(section.reserved_by));
+
+            Ok(map)
+        },
+    );
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "switch",
+        move |id: &str| -> Result<String, Box<EvalAltResult>> {
+            let (_, state) = ctx.lock().unwrap().switch_state(id)?;
+            Ok(name(state))
+        },
+    );
+
+    let ctx = context.clone();
+    engine.register_fn("is_halted", move || ctx.lock().unwrap().snapshot.halted);
+
+    let ctx = context.clone();
+    engine.register_fn("hour", move || {
+        ctx.lock().unwrap().time_of_day.hour() as INT
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("minute", move || {
+        ctx.lock().unwrap().time_of_day.minute() as INT
+    });
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "set_switch",
+        move |id: &str, state: &str| -> Result<(), Box<EvalAltResult>> {
+            let mut context = ctx.lock().unwrap();
+            let (switch_id, _) = context.switch_state(id)?;
+            let state = parse("switch state", state)?;
+
+            context.command(UiCommand::SetSwitchState { switch_id, state });
+            Ok(())
+        },
+    );
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "toggle_switch",
+        move |id: &str| -> Result<(), Box<EvalAltResult>> {
+            let mut context = ctx.lock().unwrap();
+            let (switch_id, state) = context.switch_state(id)?;
+            let state = match state {
+                SwitchState::Left => SwitchState::Right,
+                SwitchState::Right => SwitchState::Left,
+            };
+
+            context.command(UiCommand::SetSwitchState { switch_id, state });
+            Ok(())
+        },
+    );
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "set_speed",
+        move |id: INT, speed: &str| -> Result<(), Box<EvalAltResult>> {
+            let mut context = ctx.lock().unwrap();
+            let train_id = context.train_id(id)?;
+            let speed: TrainSpeed = parse("speed", speed)?;
+
+            context.command(UiCommand::SetTrainSpeed { train_id, speed });
+            Ok(())
+        },
+    );
+
+    let train_command = |command: fn(TrainId) -> UiCommand| {
+        let ctx = context.clone();
+        move |id: INT| -> Result<(), Box<EvalAltResult>> {
+            let mut context = ctx.lock().unwrap();
+            let train_id = context.train_id(id)?;
+
+            context.command(command(train_id));
+            Ok(())
+        }
+    };
+    engine.register_fn(
+        "start_train",
+        train_command(|train_id| UiCommand::StartTrain { train_id }),
+    );
+    engine.register_fn(
+        "stop_train",
+        train_command(|train_id| UiCommand::StopTrain { train_id }),
+    );
+    engine.register_fn(
+        "pause_train",
+        train_command(|train_id| UiCommand::PauseTrain { train_id }),
+    );
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "set_section_power",
+        move |id: INT, power: &str| -> Result<(), Box<EvalAltResult>> {
+            let mut context = ctx.lock().unwrap();
+            let section_id = context.section_id(id)?;
+            let power: HardwareSectionPower = parse("power", power)?;
+
+            context.command(UiCommand::SetSectionPower { section_id, power });
+            Ok(())
+        },
+    );
+
+    let ctx = context.clone();
+    engine.register_fn("emergency_stop", move || {
+        ctx.lock().unwrap().command(UiCommand::EmergencyStop);
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("resume", move || {
+        ctx.lock().unwrap().command(UiCommand::Resume);
+    });
+
+    let after = |ctx: Arc<Mutex<ScriptContext>>| {
+        move |seconds: FLOAT, function: &str| -> Result<(), Box<EvalAltResult>> {
+            let Some(delay) = Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|&delay| delay <= MAX_SCRIPT_DELAY)
+            else {
+                return Err(format!("can't wait {} seconds", seconds).into());
+            };
+
+            let mut context = ctx.lock().unwrap();
+            let script = context.script.clone();
+            context.actions.push(ScriptAction::After {
+                delay,
+                script,
+                function: function.to_string(),
+            });
+            Ok(())
+        }
+    };
+    engine.register_fn("after", after(context.clone()));
+
+    let after_float = after(context.clone());
+    engine.register_fn("after", move |seconds: INT, function: &str| {
+        after_float(seconds as FLOAT, function)
+    });
+}
+
+impl Controller {
+    /// The scripts can trigger each other with their commands. Handlers queued after this many
+    /// rounds are called the next time.
+    const MAX_SCRIPT_ROUNDS: usize = 8;
+
+    /// Load the new and changed scripts and forget the removed ones.
+    pub(super) fn reload_scripts(&mut self) {
+        let Some(scripts) = self.scripts.as_mut() else {
+            return;
+        };
+
+        for error in scripts.reload() {
+            self.report_script_error(error);
+        }
+    }
+
+    /// Call a function of a script, that it scheduled with `after`.
+    pub(super) fn script_timer(&mut self, script: String, function: String) {
+        let Some(scripts) = self.scripts.as_ref() else {
+            return;
+        };
+
+        if !scripts.defines(Some(&script), &function) {
+            self.report_script_error(ScriptError {
+                script,
+                message: format!("there is no function {}", function),
+            });
+            return;
+        }
+
+        scripts.call(Some(script), &function, Vec::new());
+    }
+
+    /// Call the handlers of the scripts for the events since the last call and execute the commands they issue.
+    /// Like commands from the ui, they are checked against the interlocking.
+    pub(super) fn run_scripts(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        for _ in 0..Self::MAX_SCRIPT_ROUNDS {
+            if !self.scripts.as_ref().is_some_and(ScriptHost::has_pending) {
+                return Ok(());
+            }
+
+            let snapshot = self.ui_snapshot();
+            let now = self.scheduler.clock().now();
+            let Some(scripts) = self.scripts.as_mut() else {
+                return Ok(());
+            };
+
+            let (actions, errors) = scripts.run(snapshot, now);
+            for error in errors {
+                self.report_script_error(error);
+            }
+
+            for action in actions {
+                match action {
+                    ScriptAction::Command(command) => self.handle_ui_command(command, ctx)?,
+                    ScriptAction::After {
+                        delay,
+                        script,
+                        function,
+                    } => {
+                        self.scheduler
+                            .schedule_in(delay, ScheduledEvent::ScriptTimer { script, function });
+                    }
+                }
+            }
+        }
+
+        log::warn!("the scripts keep reacting to their own commands, postponing their handlers");
+
+        Ok(())
+    }
+
+    fn report_script_error(&self, error: ScriptError) {
+        log::warn!("{}", error);
+        self.emit_ui(UiControllerEvent::ScriptError(error));
+    }
+}
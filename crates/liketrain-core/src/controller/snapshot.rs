@@ -0,0 +1,616 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use liketrain_hardware::{command::HardwareCommand, event::HardwareSectionPolarity};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Controller, ControllerError, ControllerFault, Direction, ScheduledEvent, SectionId, SwitchId,
+    SwitchState, Train, TrainData, TrainId, TrainRosterRoute, TrainSpeed, TrainState,
+    ui::{UiSectionEvent, UiSwitchEvent, UiTrainEvent},
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Error)]
+pub enum ControllerSnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("The route of train {0} is not valid on this track")]
+    InvalidRoute(TrainId),
+
+    #[error("Section {0} is not part of this track")]
+    UnknownSection(SectionId),
+
+    #[error("Switch {0} is not part of this track")]
+    UnknownSwitch(SwitchId),
+
+    #[error("Train {0} is not part of the snapshot")]
+    UnknownTrain(TrainId),
+}
+
+/// Where and how often the state of the controller is saved.
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,
+
+    /// How often the state is saved while the controller is running.
+    /// It is also saved, when the controller stops.
+    pub interval: Duration,
+}
+
+impl SnapshotConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainSnapshot {
+    pub id: TrainId,
+
+    /// The data of the train, for trains that were added while the controller was running.
+    /// Trains that are configured keep their configured data.
+    pub data: TrainData,
+
+    pub route: TrainRosterRoute,
+
+    /// How far the train got on its route. `None`, if it wasn't detected yet.
+    pub current_via_idx: Option<usize>,
+
+    pub orientation: Direction,
+    pub speed: TrainSpeed,
+    pub state: TrainState,
+
+    /// The sections behind the head of the train, that are still covered by it. Oldest first.
+    #[serde(default)]
+    pub tail: Vec<SectionId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionSnapshot {
+    pub id: SectionId,
+
+    #[serde(default)]
+    pub occupied: Option<TrainId>,
+
+    #[serde(default)]
+    pub reserved: Option<TrainId>,
+
+    /// The trains waiting for this section, in the order they are served.
+    #[serde(default)]
+    pub queue: Vec<TrainId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwitchSnapshot {
+    pub id: SwitchId,
+    pub state: SwitchState,
+}
+
+/// The state of the controller, to continue where it left off after a restart. Saved to and loaded from a JSON file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ControllerSnapshot {
+    pub trains: Vec<TrainSnapshot>,
+
+    /// Only the sections that are occupied, reserved or waited for.
+    pub sections: Vec<SectionSnapshot>,
+
+    pub switches: Vec<SwitchSnapshot>,
+}
+
+impl ControllerSnapshot {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ControllerSnapshotError> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ControllerSnapshotError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Write the snapshot to a temporary file first, so a crash while saving doesn't destroy the previous snapshot.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ControllerSnapshotError> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, json)?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+/// The trains of a restored snapshot, that weren't detected by the hardware yet.
+#[derive(Debug, Default)]
+pub(super) struct RestoredSnapshot {
+    unconfirmed: HashMap<TrainId, SectionId>,
+
+    /// The trains, that were driving before the restart. They are held, until they are detected.
+    held: HashSet<TrainId>,
+
+    /// Occupied sections next to an unconfirmed train, that is probably not where the snapshot says.
+    detected_elsewhere: HashMap<TrainId, SectionId>,
+}
+
+impl Controller {
+    /// How long the hardware has to detect the trains of a restored snapshot.
+    pub const RECONCILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn snapshot_config(&self) -> Option<&SnapshotConfig> {
+        self.snapshot_config.as_ref()
+    }
+
+    /// The current state of the trains, sections and switches.
+    pub fn snapshot(&self) -> ControllerSnapshot {
+        let mut trains = self
+            .trains
+            .iter()
+            .filter_map(|(&train_id, train)| {
+                let mut tail = self.train_sections(train_id);
+                if train.get_current_section().is_some() {
+                    tail.pop();
+                }
+
+                Some(TrainSnapshot {
+                    id: train_id,
+                    data: train.data().clone(),
+                    route: train.route()?.into(),
+                    current_via_idx: train.current_via_idx(),
+                    orientation: train.orientation(),
+                    speed: train.speed(),
+                    state: train.state(),
+                    tail,
+                })
+            })
+            .collect::<Vec<_>>();
+        trains.sort_by_key(|train| train.id);
+
+        let mut sections = self
+            .track
+            .section_ids()
+            .filter_map(|section_id| {
+                let section = SectionSnapshot {
+                    id: section_id,
+                    occupied: self
+                        .section_states
+                        .get(&section_id)
+                        .and_then(|state| state.occupied),
+                    reserved: self.section_reservation(section_id),
+                    queue: self
+                        .section_queue(section_id)
+                        .map(|queue| queue.iter().copied().collect())
+                        .unwrap_or_default(),
+                };
+
+                (section.occupied.is_some()
+                    || section.reserved.is_some()
+                    || !section.queue.is_empty())
+                .then_some(section)
+            })
+            .collect::<Vec<_>>();
+        sections.sort_by_key(|section| section.id);
+
+        let mut switches = self
+            .switch_states()
+            .map(|(id, state)| SwitchSnapshot { id, state })
+            .collect::<Vec<_>>();
+        switches.sort_by(|a, b| a.id.cmp(&b.id));
+
+        ControllerSnapshot {
+            trains,
+            sections,
+            switches,
+        }
+    }
+
+    /// Save the current state to the configured snapshot file.
+    pub(super) fn save_snapshot(&self) {
+        let Some(config) = self.snapshot_config.as_ref() else {
+            return;
+        };
+
+        match self.snapshot().save(&config.path) {
+            Ok(()) => log::debug!("saved snapshot to {}", config.path.display()),
+            Err(err) => log::error!("failed to save snapshot: {}", err),
+        }
+    }
+
+    /// Save the state periodically, while the controller is running.
+    pub(super) fn snapshot_tick(&mut self) {
+        if self.snapshot_config.is_some() {
+            self.save_snapshot();
+        }
+    }
+
+    /// Continue where the snapshot left off. Has to be called before the controller is started.
+    /// Once started, the positions of the trains are reconciled with the occupancy the hardware reports.
+    ///
+    /// The trains of the snapshot replace the configured trains. Configured trains keep their data.
+    pub fn restore(&mut self, snapshot: ControllerSnapshot) -> Result<(), ControllerSnapshotError> {
+        // check everything first, so a snapshot that doesn't fit the track changes nothing
+        let mut trains = HashMap::new();
+        for train_snapshot in &snapshot.trains {
+            let train_id = train_snapshot.id;
+            let route = train_snapshot
+                .route
+                .resolve(&self.track)
+                .ok_or(ControllerSnapshotError::InvalidRoute(train_id))?;
+
+            for &section_id in &train_snapshot.tail {
+                self.check_snapshot_section(section_id)?;
+            }
+
+            let data = self
+                .trains
+                .get(&train_id)
+                .map(|train| train.data().clone())
+                .unwrap_or_else(|| train_snapshot.data.clone());
+
+            let mut train = Train::new(data, route.clone());
+            train.resume_route(
+                route,
+                train_snapshot.current_via_idx,
+                train_snapshot.orientation,
+            );
+            train.set_speed(train_snapshot.speed);
+            train.set_state(train_snapshot.state);
+
+            trains.insert(train_id, train);
+        }
+
+        for section in &snapshot.sections {
+            self.check_snapshot_section(section.id)?;
+
+            for train_id in section
+                .occupied
+                .iter()
+                .chain(section.reserved.iter())
+                .chain(section.queue.iter())
+            {
+                if !trains.contains_key(train_id) {
+                    return Err(ControllerSnapshotError::UnknownTrain(*train_id));
+                }
+            }
+        }
+
+        for switch in &snapshot.switches {
+            if !self.switch_states.contains_key(&switch.id) {
+                return Err(ControllerSnapshotError::UnknownSwitch(switch.id.clone()));
+            }
+        }
+
+        let previous_trains = self.trains.keys().copied().collect::<Vec<_>>();
+
+        self.trains = trains;
+        self.section_reservations.clear();
+        self.section_queues.clear();
+        self.train_tails.clear();
+        for state in self.section_states.values_mut() {
+            state.occupied = None;
+        }
+
+        for section in snapshot.sections {
+            self.section_states.entry(section.id).or_default().occupied = section.occupied;
+
+            if let Some(train_id) = section.reserved {
+                self.section_reservations.insert(section.id, train_id);
+            }
+
+            if !section.queue.is_empty() {
+                self.section_queues
+                    .insert(section.id, section.queue.into_iter().collect());
+            }
+        }
+
+        for train_snapshot in &snapshot.trains {
+            self.restore_tail_sections(train_snapshot.id, &train_snapshot.tail);
+        }
+
+        for switch in snapshot.switches {
+            self.switch_states.insert(switch.id, switch.state);
+        }
+
+        // the trains are only trusted to be where the snapshot says, once the hardware detected them there
+        let unconfirmed = self
+            .trains
+            .iter()
+            .filter_map(|(&train_id, train)| {
+                train
+                    .get_current_section()
+                    .map(|section_id| (train_id, section_id))
+            })
+            .collect();
+        self.restored = Some(RestoredSnapshot {
+            unconfirmed,
+            ..Default::default()
+        });
+
+        log::info!("restored {} trains from the snapshot", self.trains.len());
+
+        self.emit_restored_state(previous_trains);
+
+        Ok(())
+    }
+
+    fn check_snapshot_section(&self, section_id: SectionId) -> Result<(), ControllerSnapshotError> {
+        if self.section_states.contains_key(&section_id) {
+            Ok(())
+        } else {
+            Err(ControllerSnapshotError::UnknownSection(section_id))
+        }
+    }
+
+    /// Tell the ui about the restored state, replacing the trains it knew before.
+    fn emit_restored_state(&self, previous_trains: Vec<TrainId>) {
+        for train_id in previous_trains {
+            self.emit_ui(UiTrainEvent::Removed { train_id });
+        }
+
+        for (&train_id, train) in &self.trains {
+            self.emit_ui(UiTrainEvent::Added {
+                train_id,
+                train: Box::new(train.clone()),
+            });
+
+            if let Some(section_id) = train.get_current_section() {
+                self.emit_ui(UiTrainEvent::EnteredSection {
+                    train_id,
+                    section_id,
+                });
+            }
+        }
+
+        for (&section_id, state) in &self.section_states {
+            if let Some(train_id) = state.occupied {
+                self.emit_ui(UiSectionEvent::Occupied {
+                    section_id,
+                    train_id: Some(train_id),
+                });
+            }
+        }
+
+        for (&section_id, &train_id) in &self.section_reservations {
+            self.emit_ui(UiSectionEvent::Reserved {
+                section_id,
+                train_id: Some(train_id),
+            });
+        }
+
+        for (&section_id, queue) in &self.section_queues {
+            for &train_id in queue {
+                self.emit_ui(UiSectionEvent::QueueEnqueued {
+                    section_id,
+                    train_id,
+                });
+            }
+        }
+
+        for (id, state) in self.switch_states() {
+            self.emit_ui(UiSwitchEvent::SetState { id, state });
+        }
+    }
+
+    /// Bring the hardware into the state of the restored snapshot. The trains, that were driving
+    /// before the restart, continue once the hardware detected them in their sections.
+    /// Returns false, if no snapshot was restored.
+    pub(super) fn continue_restored(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<bool, ControllerError> {
+        if self.restored.is_none() {
+            return Ok(false);
+        }
+
+        // the hardware was reset, so the switches have to be set again
+        for (switch_id, state) in self.switch_states().collect::<Vec<_>>() {
+            ctx.exec(HardwareCommand::SetSwitchState {
+                switch_id: switch_id.try_into().unwrap(),
+                state: state.into(),
+            })?;
+        }
+
+        let mut train_ids = self.trains.keys().copied().collect::<Vec<_>>();
+        train_ids.sort();
+
+        for train_id in train_ids {
+            self.restore_reserved_polarities(train_id);
+
+            let train = &self.trains[&train_id];
+            let power = train.target_power();
+            let state = train.state();
+
+            if state.is_held() || state == TrainState::Waiting {
+                // the train keeps standing, until it is started or its next section is free
+                continue;
+            }
+
+            if train.get_current_section().is_none() {
+                // the train wasn't detected before the restart, it starts like on a fresh start
+                self.ramp_train(train_id, power, ctx)?;
+                self.emit_ui(UiTrainEvent::Started { train_id, power });
+                continue;
+            }
+
+            // don't drive a train, that might not be where the snapshot says
+            log::info!(
+                "train {} waits to be detected, before it continues",
+                train_id
+            );
+            if let Some(restored) = self.restored.as_mut() {
+                restored.held.insert(train_id);
+            }
+        }
+
+        self.scheduler
+            .schedule_in(Self::RECONCILE_TIMEOUT, ScheduledEvent::ReconcileSnapshot);
+
+        Ok(true)
+    }
+
+    /// The sections the train has reserved ahead of it are entered from the end, its route leads it to.
+    fn restore_reserved_polarities(&mut self, train_id: TrainId) {
+        let Some(train) = self.trains.get(&train_id) else {
+            return;
+        };
+
+        let polarities = train
+            .upcoming_transitions()
+            .take_while(|transition| {
+                self.section_reservation(transition.destination()) == Some(train_id)
+            })
+            .map(|transition| {
+                let polarity: HardwareSectionPolarity = transition
+                    .destination_section_end()
+                    .entering_direction()
+                    .into();
+                (transition.destination(), polarity)
+            })
+            .collect::<Vec<_>>();
+
+        self.section_polarities.extend(polarities);
+    }
+
+    /// Let a train continue, that was driving before the restart and was detected again.
+    fn continue_restored_train(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let train = self.train(train_id)?;
+        let power = train.target_power();
+
+        // e.g. the train was stopped, while it wasn't detected yet
+        if train.state() != TrainState::Default {
+            return Ok(());
+        }
+
+        if train.has_finished_route() {
+            self.train_arrived(train_id);
+            return Ok(());
+        }
+
+        if self.request_sections_ahead(train_id, ctx)? {
+            self.ramp_train(train_id, power, ctx)?;
+            self.emit_ui(UiTrainEvent::Started { train_id, power });
+        }
+
+        Ok(())
+    }
+
+    /// The hardware detected a train of the restored snapshot in one of its sections.
+    /// A train, that was held until then, continues.
+    pub(super) fn confirm_restored_train(
+        &mut self,
+        train_id: TrainId,
+        section_id: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(restored) = self.restored.as_mut() else {
+            return Ok(());
+        };
+
+        if restored.unconfirmed.remove(&train_id).is_none() {
+            return Ok(());
+        }
+
+        log::info!(
+            "train {} was detected in section {} after the restart",
+            train_id,
+            section_id
+        );
+
+        if restored.held.remove(&train_id) {
+            self.continue_restored_train(train_id, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    /// A section next to an unconfirmed train of the restored snapshot was detected as occupied.
+    /// The train is probably there instead of in its section, which is reported on the reconcile timeout.
+    /// Returns false, if there is no such train.
+    pub(super) fn detect_restored_train_elsewhere(&mut self, section_id: SectionId) -> bool {
+        let neighbours = self.track.neighbouring_sections(section_id);
+
+        let Some(restored) = self.restored.as_mut() else {
+            return false;
+        };
+
+        let Some(train_id) = restored
+            .unconfirmed
+            .iter()
+            .filter(|&(_, snapshot_section)| neighbours.contains(snapshot_section))
+            .map(|(&train_id, _)| train_id)
+            .min()
+        else {
+            return false;
+        };
+
+        log::warn!(
+            "section {} is occupied, train {} might be there instead of in section {}",
+            section_id,
+            train_id,
+            restored.unconfirmed[&train_id]
+        );
+        restored.detected_elsewhere.insert(train_id, section_id);
+
+        true
+    }
+
+    /// Report the driving trains of the restored snapshot, that the hardware didn't detect in time
+    /// or detected in another section.
+    pub(super) fn reconcile_snapshot(
+        &mut self,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(restored) = self.restored.take() else {
+            return Ok(());
+        };
+
+        let mut unconfirmed = restored.unconfirmed.into_iter().collect::<Vec<_>>();
+        unconfirmed.sort();
+
+        for (train_id, section_id) in unconfirmed {
+            let fault = if let Some(&actual) = restored.detected_elsewhere.get(&train_id) {
+                ControllerFault::WrongSection {
+                    train_id,
+                    expected: section_id,
+                    actual,
+                }
+            } else if restored.held.contains(&train_id) {
+                ControllerFault::MissingTrain {
+                    train_id,
+                    section_id,
+                }
+            } else {
+                // the train was standing before the restart and can't be detected without power
+                log::warn!(
+                    "train {} can't be detected in section {}, while it is standing",
+                    train_id,
+                    section_id
+                );
+                continue;
+            };
+
+            self.report_fault(fault, ctx)?;
+        }
+
+        Ok(())
+    }
+}
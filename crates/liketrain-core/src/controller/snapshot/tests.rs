@@ -0,0 +1,118 @@
+use std::{sync::mpsc, time::Duration};
+
+use liketrain_hardware::event::HardwareSectionPower;
+
+use crate::{
+    comm::Simulation,
+    controller::testing::{config, drive, occupy, route, section, simulate, track, train},
+    ui::{UiControllerEvent, UiEvent},
+};
+
+use super::*;
+
+/// Restart the controller, after T1 drove from S12 into S14.
+fn restart() -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+
+    let (mut simulation, _ui_event_rx) = simulate(config(track.clone(), [(1, train.clone())]), []);
+    occupy(&mut simulation, 12);
+    drive(&mut simulation, &[12, 14]);
+    let snapshot = simulation.controller().snapshot();
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation =
+        Simulation::restore(config(track, [(1, train)]), snapshot, [], ui_event_tx).unwrap();
+    simulation.step().unwrap();
+
+    // the train doesn't drive, before it was detected
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+    assert!(section_power(&simulation, 16).is_off());
+
+    (simulation, ui_event_rx)
+}
+
+fn faults(ui_event_rx: &mpsc::Receiver<UiEvent>) -> Vec<ControllerFault> {
+    ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiControllerEvent(UiControllerEvent::Fault(fault)) => Some(fault),
+            _ => None,
+        })
+        .collect()
+}
+
+fn section_power(simulation: &Simulation, section_id: usize) -> HardwareSectionPower {
+    simulation
+        .controller()
+        .section_states
+        .get(&section(section_id))
+        .map(|state| state.power)
+        .unwrap_or_default()
+}
+
+fn train_state(simulation: &Simulation) -> TrainState {
+    let train = simulation.controller().train(TrainId::new(1));
+    train.unwrap().state()
+}
+
+#[test]
+fn test_reconcile_detected_train() {
+    let (mut simulation, ui_event_rx) = restart();
+
+    // the train is detected, where the snapshot says, so it continues
+    simulation.run_for(Duration::from_secs(1)).unwrap();
+    occupy(&mut simulation, 14);
+    simulation.step().unwrap();
+
+    assert!(
+        !simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+    assert!(!section_power(&simulation, 16).is_off());
+
+    simulation.run_for(Controller::RECONCILE_TIMEOUT).unwrap();
+    assert_eq!(faults(&ui_event_rx), []);
+    assert_eq!(train_state(&simulation), TrainState::Default);
+}
+
+#[test]
+fn test_reconcile_train_in_wrong_section() {
+    let (mut simulation, ui_event_rx) = restart();
+
+    // something is detected next to the section of the train
+    occupy(&mut simulation, 16);
+    assert_eq!(faults(&ui_event_rx), []);
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+
+    simulation
+        .run_for(Controller::RECONCILE_TIMEOUT + Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(
+        faults(&ui_event_rx),
+        [ControllerFault::WrongSection {
+            train_id: TrainId::new(1),
+            expected: section(14),
+            actual: section(16),
+        }]
+    );
+    assert_eq!(train_state(&simulation), TrainState::Faulted);
+    assert!(
+        simulation
+            .controller()
+            .train_power(TrainId::new(1))
+            .is_off()
+    );
+}
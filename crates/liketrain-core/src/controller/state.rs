@@ -1,11 +1,39 @@
-use liketrain_hardware::event::HardwareSectionPower;
+use liketrain_hardware::event::{HardwareSectionPolarity, HardwareSectionPower};
 
 use crate::TrainId;
 
+/// Why the controller powered a section.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectionPowerReason {
+    /// The train drives through the section, or is about to.
+    Driving(TrainId),
+
+    /// The train was stopped by the controller, e.g. because it waits for its next section
+    /// or reached the end of its route.
+    Stopped(TrainId),
+
+    /// The section was powered from the ui.
+    Manual,
+}
+
+impl SectionPowerReason {
+    pub fn train_id(&self) -> Option<TrainId> {
+        match self {
+            Self::Driving(train_id) | Self::Stopped(train_id) => Some(*train_id),
+            Self::Manual => None,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct SectionState {
     pub(super) occupied: Option<TrainId>,
     pub(super) power: HardwareSectionPower,
+    pub(super) polarity: HardwareSectionPolarity,
+
+    /// Why the controller last set the power of this section. `None` once it was powered off,
+    /// unless a train was stopped in it.
+    pub(super) power_reason: Option<SectionPowerReason>,
 }
 
 impl SectionState {
@@ -24,4 +52,12 @@ impl SectionState {
     pub fn power(&self) -> HardwareSectionPower {
         self.power
     }
+
+    pub fn polarity(&self) -> HardwareSectionPolarity {
+        self.polarity
+    }
+
+    pub fn power_reason(&self) -> Option<SectionPowerReason> {
+        self.power_reason
+    }
 }
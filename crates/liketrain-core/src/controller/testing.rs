@@ -0,0 +1,127 @@
+//! The track and helpers, that the tests of the controller share.
+
+use std::{sync::mpsc, time::Duration};
+
+use chumsky::Parser;
+use liketrain_hardware::event::{HardwareEvent, HardwareSectionPower, SectionEvent};
+
+use crate::{
+    Clock, ControllerConfig, Direction, Route, SectionId, Track, TrackGeometry, Train, TrainId,
+    TrainRampProfile,
+    comm::{SimTrain, Simulation},
+    parser::{eval::Evaluator, parser},
+    ui::{UiEvent, UiSectionEvent},
+};
+
+/// The track of the layout in `resources`, with its geometry.
+pub(super) fn track() -> Track {
+    let track_defs = parser()
+        .parse(include_str!("../../../../resources/track.ltt"))
+        .into_result()
+        .unwrap();
+    let mut track = Evaluator::default().evaluate(track_defs).unwrap();
+
+    let track_geo: TrackGeometry =
+        serde_json::from_str(include_str!("../../../../resources/geo.json")).unwrap();
+    track.set_geometry(track_geo);
+
+    track
+}
+
+/// A route driving backward, like the trains of the show do.
+pub(super) fn route(track: &Track, vias: &[usize]) -> Route {
+    let name = format!("S{} -> S{}", vias[0], vias[vias.len() - 1]);
+    Route::new(name, vias.iter().copied(), Direction::Backward, track).unwrap()
+}
+
+/// A train without ramps, so every power change is sent right away.
+pub(super) fn train(name: &str, route: Route) -> Train {
+    Train::from_route(name, route).with_ramp_profile(TrainRampProfile::NONE)
+}
+
+/// The trains on the track, without a watchdog, the trains of a test are moved by hand.
+pub(super) fn config(
+    track: Track,
+    trains: impl IntoIterator<Item = (usize, Train)>,
+) -> ControllerConfig {
+    let trains = trains
+        .into_iter()
+        .map(|(train_id, train)| (TrainId::new(train_id), train))
+        .collect();
+
+    ControllerConfig {
+        watchdog: None,
+        ..ControllerConfig::new(track, trains)
+    }
+}
+
+pub(super) fn simulate(
+    config: ControllerConfig,
+    trains: impl IntoIterator<Item = SimTrain>,
+) -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let simulation = Simulation::new(config, trains, ui_event_tx).unwrap();
+
+    (simulation, ui_event_rx)
+}
+
+/// Step the simulation for the duration and collect the section powers, that the hardware
+/// reported, with the time since the recording started.
+pub(super) fn record_power(
+    simulation: &mut Simulation,
+    rx: &mpsc::Receiver<UiEvent>,
+    duration: Duration,
+) -> Vec<(Duration, SectionId, HardwareSectionPower)> {
+    let started = simulation.clock().now();
+    let mut powers = Vec::new();
+
+    loop {
+        let at = simulation.clock().now() - started;
+        for event in rx.try_iter() {
+            if let UiEvent::UiSectionEvent(UiSectionEvent::SetPower {
+                section_id, power, ..
+            }) = event
+            {
+                powers.push((at, section_id, power));
+            }
+        }
+
+        if at >= duration {
+            return powers;
+        }
+
+        simulation.step().unwrap();
+    }
+}
+
+/// Report the section as occupied, like the train detection does, and let the controller react.
+pub(super) fn occupy(simulation: &mut Simulation, section_id: usize) {
+    simulation
+        .hardware_event(HardwareEvent::SectionEvent(SectionEvent::occupied(
+            section_id as u32,
+        )))
+        .unwrap();
+    simulation.step().unwrap();
+}
+
+/// Report the section as free again, and let the controller react.
+pub(super) fn free(simulation: &mut Simulation, section_id: usize) {
+    simulation
+        .hardware_event(HardwareEvent::SectionEvent(SectionEvent::freed(
+            section_id as u32,
+        )))
+        .unwrap();
+    simulation.step().unwrap();
+}
+
+/// Move a train along the sections, like a short train without a simulated counterpart.
+pub(super) fn drive(simulation: &mut Simulation, sections: &[usize]) {
+    for pair in sections.windows(2) {
+        occupy(simulation, pair[1]);
+        free(simulation, pair[0]);
+    }
+}
+
+pub(super) fn section(section_id: usize) -> SectionId {
+    SectionId::new(section_id)
+}
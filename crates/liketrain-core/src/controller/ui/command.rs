@@ -1,9 +1,16 @@
 use liketrain_hardware::event::HardwareSectionPower;
+use serde::{Deserialize, Serialize};
 
-use crate::{SectionId, SwitchId, SwitchState, TrainId, TrainSpeed};
+use crate::{SectionId, SwitchId, SwitchState, TrainData, TrainId, TrainRosterRoute, TrainSpeed};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiCommand {
+    /// Immediately power off all sections and halt the controller.
+    EmergencyStop,
+
+    /// Restart the trains, that were driving before the emergency stop.
+    Resume,
+
     SetSectionPower {
         section_id: SectionId,
         power: HardwareSectionPower,
@@ -18,4 +25,52 @@ pub enum UiCommand {
         train_id: TrainId,
         speed: TrainSpeed,
     },
+
+    /// Start a stopped or paused train, or let a stopping train drive on.
+    StartTrain { train_id: TrainId },
+
+    /// Stop the train, once it entered its next section. The sections ahead of it are released.
+    StopTrain { train_id: TrainId },
+
+    /// Stop the train immediately, where it is. It keeps the sections it has reserved.
+    PauseTrain { train_id: TrainId },
+
+    /// Let the train drive a new route, starting at the section it is currently in.
+    AssignRoute {
+        train_id: TrainId,
+        route: TrainRosterRoute,
+    },
+
+    /// Place a new train on the track, in the first section of its route.
+    /// It stays stopped, until it is started.
+    AddTrain {
+        train_id: TrainId,
+        data: Box<TrainData>,
+        route: TrainRosterRoute,
+    },
+
+    /// Take a train off the track, releasing all of its sections.
+    RemoveTrain { train_id: TrainId },
+
+    /// Swap the front and the back of a stopped train.
+    ReverseTrain { train_id: TrainId },
+
+    /// The operator checked a train, that was stopped by a fault. It stays stopped, until it is started.
+    AcknowledgeFault { train_id: TrainId },
+
+    /// Change the metadata of a train, e.g. after editing it in the roster.
+    SetTrainData {
+        train_id: TrainId,
+        data: Box<TrainData>,
+    },
+
+    /// Ask for the current state of the controller. It is sent as `UiControllerEvent::Snapshot`.
+    RequestSnapshot,
+
+    /// Execute the command, even if it violates the interlocking, e.g. during maintenance.
+    /// The override is logged and reported to the ui.
+    Override {
+        command: Box<UiCommand>,
+        reason: String,
+    },
 }
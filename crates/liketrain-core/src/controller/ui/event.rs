@@ -1,11 +1,18 @@
 use liketrain_hardware::{
     command::HardwareCommand,
-    event::{HardwareSectionPower, SectionEvent},
+    event::{HardwareSectionPolarity, HardwareSectionPower, SectionEvent},
 };
+use serde::{Deserialize, Serialize};
 
-use crate::{SectionId, SwitchId, SwitchState, TrainId, TrainSpeed, TrainState};
+use crate::{
+    ControllerFault, Deadlock, Direction, InterlockingOverride, InterlockingViolation, Route,
+    ScriptError, SectionId, SwitchId, SwitchState, Train, TrainCalibration, TrainData, TrainId,
+    TrainPositionEstimate, TrainSpeed, TrainState,
+};
+
+use super::{UiCommand, UiSnapshot};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiSectionEvent {
     QueueEnqueued {
         section_id: SectionId,
@@ -31,15 +38,16 @@ pub enum UiSectionEvent {
     SetPower {
         section_id: SectionId,
         power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiSwitchEvent {
     SetState { id: SwitchId, state: SwitchState },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiTrainEvent {
     Started {
         train_id: TrainId,
@@ -61,19 +69,104 @@ pub enum UiTrainEvent {
         state: TrainState,
     },
 
+    RouteChanged {
+        train_id: TrainId,
+        route: Route,
+    },
+
+    /// The train was reversed or started a route in the other direction.
+    OrientationChanged {
+        train_id: TrainId,
+        orientation: Direction,
+    },
+
+    CalibrationChanged {
+        train_id: TrainId,
+        calibration: TrainCalibration,
+    },
+
+    DataChanged {
+        train_id: TrainId,
+        data: Box<TrainData>,
+    },
+
+    /// Where the train is estimated to be within its current section.
+    /// Reset, whenever the train enters a new section.
+    PositionEstimated {
+        train_id: TrainId,
+        estimate: TrainPositionEstimate,
+    },
+
+    /// The train didn't reach the end of its section in time, e.g. because it stalled on dirty track.
+    /// Cleared, once the train enters the next section.
+    Overdue {
+        train_id: TrainId,
+        section_id: SectionId,
+    },
+
     Stopped {
         train_id: TrainId,
     },
+
+    /// A train was placed on the track.
+    Added {
+        train_id: TrainId,
+        train: Box<Train>,
+    },
+
+    /// A train was taken off the track.
+    Removed {
+        train_id: TrainId,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum UiControllerEvent {
+    /// All sections were powered off by an emergency stop.
+    Halted,
+
+    Resumed,
+
+    /// Something unexpected happened on the track.
+    Fault(ControllerFault),
+
+    /// Trains are waiting for each other.
+    Deadlock(Deadlock),
+
+    /// The trains of a reported deadlock aren't waiting for each other anymore.
+    DeadlockResolved(Deadlock),
+
+    /// A manual command was rejected, because it would interfere with a train.
+    CommandRejected {
+        command: Box<UiCommand>,
+        violation: InterlockingViolation,
+    },
+
+    /// A manual command was executed despite violating the interlocking.
+    InterlockingOverridden(InterlockingOverride),
+
+    /// The current state of the controller, as requested by `UiCommand::RequestSnapshot`.
+    Snapshot(Box<UiSnapshot>),
+
+    /// An automation script failed to load or run.
+    ScriptError(ScriptError),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiEvent {
+    UiControllerEvent(UiControllerEvent),
     UiSectionEvent(UiSectionEvent),
     UiSwitchEvent(UiSwitchEvent),
     UiTrainEvent(UiTrainEvent),
     HardwareCommand(HardwareCommand),
 }
 
+impl From<UiControllerEvent> for UiEvent {
+    fn from(value: UiControllerEvent) -> Self {
+        UiEvent::UiControllerEvent(value)
+    }
+}
+
 impl From<UiSectionEvent> for UiEvent {
     fn from(value: UiSectionEvent) -> Self {
         UiEvent::UiSectionEvent(value)
@@ -3,3 +3,6 @@ pub use event::*;
 
 mod command;
 pub use command::*;
+
+mod snapshot;
+pub use snapshot::*;
@@ -0,0 +1,76 @@
+use liketrain_hardware::event::{HardwareSectionPolarity, HardwareSectionPower};
+use serde::{Deserialize, Serialize};
+
+use crate::{Controller, Deadlock, SectionId, SwitchSnapshot, Train, TrainId};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiTrainSnapshot {
+    pub id: TrainId,
+    pub train: Train,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiSectionSnapshot {
+    pub id: SectionId,
+    pub power: HardwareSectionPower,
+    pub polarity: HardwareSectionPolarity,
+    pub occupant: Option<TrainId>,
+    pub reserved_by: Option<TrainId>,
+
+    /// The trains waiting for this section, in the order they are served.
+    pub queue: Vec<TrainId>,
+}
+
+/// Everything a ui shows about the controller, e.g. when it connects to a controller that is already running.
+/// Unlike `ControllerSnapshot`, it can't be restored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiSnapshot {
+    pub trains: Vec<UiTrainSnapshot>,
+    pub sections: Vec<UiSectionSnapshot>,
+    pub switches: Vec<SwitchSnapshot>,
+    pub halted: bool,
+    pub deadlocks: Vec<Deadlock>,
+}
+
+impl Controller {
+    pub fn ui_snapshot(&self) -> UiSnapshot {
+        let mut trains = self
+            .trains()
+            .map(|(id, train)| UiTrainSnapshot {
+                id,
+                train: train.clone(),
+            })
+            .collect::<Vec<_>>();
+        trains.sort_by_key(|train| train.id);
+
+        let mut sections = self
+            .section_states()
+            .map(|(id, state)| UiSectionSnapshot {
+                id,
+                power: state.power(),
+                polarity: state.polarity(),
+                occupant: state.occupant(),
+                reserved_by: self.section_reservation(id),
+                queue: self
+                    .section_queue(id)
+                    .map(|queue| queue.iter().copied().collect())
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        sections.sort_by_key(|section| section.id);
+
+        let mut switches = self
+            .switch_states()
+            .map(|(id, state)| SwitchSnapshot { id, state })
+            .collect::<Vec<_>>();
+        switches.sort_by(|a, b| a.id.cmp(&b.id));
+
+        UiSnapshot {
+            trains,
+            sections,
+            switches,
+            halted: self.is_halted(),
+            deadlocks: self.deadlocks(),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use liketrain_hardware::{command::HardwareCommand, event::HardwareSectionPower};
+
+use crate::{
+    Controller, ControllerError, ScheduledEvent, ScheduledEventHandle, SectionId, TrainId,
+    TrainState, ui::UiTrainEvent,
+};
+
+use super::EventExecutionContext;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// A train is overdue, when it takes this many times longer than expected
+    /// to reach the end of its section.
+    pub tolerance: f32,
+
+    /// Added to the expected travel time, so short sections and inaccurate calibrations
+    /// don't raise false alarms.
+    pub slack: Duration,
+
+    /// How often an overdue train is powered with full power for a short time,
+    /// to get it over dirty track.
+    pub pulse_retries: u32,
+    pub pulse_duration: Duration,
+
+    /// Stop the trains approaching an overdue train, until it moves again.
+    pub stop_following_trains: bool,
+
+    /// How long a train may take through a section, when its travel time can't be estimated,
+    /// e.g. because it isn't calibrated yet. `None` doesn't watch such trains.
+    pub fallback_timeout: Option<Duration>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 1.5,
+            slack: Duration::from_secs(2),
+            pulse_retries: 0,
+            pulse_duration: Duration::from_millis(300),
+            stop_following_trains: false,
+            fallback_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Watches a train, while it drives through a section.
+#[derive(Debug, Clone)]
+pub(super) struct TrainWatchdog {
+    section_id: SectionId,
+
+    /// The next check, cancelled when the watchdog is disarmed.
+    check: Option<ScheduledEventHandle>,
+
+    overdue: bool,
+    pulses: u32,
+
+    /// The trains that were stopped, because they were following this train.
+    held_trains: Vec<TrainId>,
+}
+
+impl Controller {
+    /// Whether the train didn't reach its next section in time.
+    pub fn is_train_overdue(&self, train_id: TrainId) -> bool {
+        self.train_watchdogs
+            .get(&train_id)
+            .is_some_and(|watchdog| watchdog.overdue)
+    }
+
+    /// How long the train should take at most, to reach the end of its current section.
+    /// Falls back to the configured timeout, if this can't be estimated,
+    /// e.g. because the train isn't calibrated.
+    fn watchdog_timeout(&self, train_id: TrainId) -> Option<Duration> {
+        let config = self.watchdog_config.as_ref()?;
+        let train = self.trains.get(&train_id)?;
+
+        let timeout = self.position_estimate(train_id).and_then(|estimate| {
+            let remaining = (estimate.section_length - estimate.distance).max(0.0);
+            let travel_time = train
+                .calibration()
+                .travel_time(remaining, train.target_power())?;
+
+            // a tiny calibrated speed can overflow the timeout
+            Duration::try_from_secs_f32(travel_time.as_secs_f32() * config.tolerance)
+                .ok()?
+                .checked_add(config.slack)
+        });
+
+        if timeout.is_some() {
+            return timeout;
+        }
+
+        let timeout = config.fallback_timeout?;
+        log::info!(
+            "can't estimate the travel time of train {}, using the fallback timeout of {:?}",
+            train_id,
+            timeout
+        );
+        Some(timeout)
+    }
+
+    /// The train entered a new section, start watching whether it reaches the next one in time.
+    pub(super) fn arm_watchdog(
+        &mut self,
+        train_id: TrainId,
+        section_id: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        if self.watchdog_config.is_none() {
+            return Ok(());
+        }
+
+        if self
+            .train_watchdogs
+            .get(&train_id)
+            .is_some_and(|previous| previous.overdue)
+        {
+            log::info!("train {} is moving again", train_id);
+        }
+
+        // the train arrived, so the trains behind it can continue
+        self.disarm_watchdog(train_id, ctx)?;
+
+        self.train_watchdogs.insert(
+            train_id,
+            TrainWatchdog {
+                section_id,
+                check: None,
+                overdue: false,
+                pulses: 0,
+                held_trains: Vec::new(),
+            },
+        );
+
+        self.schedule_watchdog_check(train_id);
+
+        Ok(())
+    }
+
+    /// Stop watching the train and let the trains continue, that were held behind it.
+    pub(super) fn disarm_watchdog(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(watchdog) = self.train_watchdogs.remove(&train_id) else {
+            return Ok(());
+        };
+
+        if let Some(check) = watchdog.check {
+            self.scheduler.cancel(check);
+        }
+
+        for held_train_id in watchdog.held_trains {
+            let Some(held_train) = self.trains.get(&held_train_id) else {
+                continue;
+            };
+
+            if held_train.state() == TrainState::Default {
+                let target_power = held_train.target_power();
+                self.ramp_train(held_train_id, target_power, ctx)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn schedule_watchdog_check(&mut self, train_id: TrainId) {
+        let Some(timeout) = self.watchdog_timeout(train_id) else {
+            log::debug!(
+                "can't estimate the travel time of train {}, it isn't watched",
+                train_id
+            );
+            return;
+        };
+
+        self.schedule_watchdog_check_in(train_id, timeout);
+    }
+
+    fn schedule_watchdog_check_in(&mut self, train_id: TrainId, delay: Duration) {
+        let check = self
+            .scheduler
+            .schedule_in(delay, ScheduledEvent::WatchdogCheck { train_id });
+
+        if let Some(watchdog) = self.train_watchdogs.get_mut(&train_id) {
+            watchdog.check = Some(check);
+        }
+    }
+
+    pub(super) fn watchdog_check(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let Some(config) = self.watchdog_config.clone() else {
+            return Ok(());
+        };
+
+        let Some(watchdog) = self.train_watchdogs.get(&train_id).cloned() else {
+            return Ok(());
+        };
+
+        // the train reached the end of its route, it won't enter another section
+        if self
+            .trains
+            .get(&train_id)
+            .is_none_or(|train| train.get_next_section().is_none())
+        {
+            return Ok(());
+        }
+
+        self.advance_position_estimate(train_id);
+        let reached_end = self
+            .position_estimate(train_id)
+            .is_some_and(|estimate| estimate.distance >= estimate.section_length);
+
+        // a stopped or slowed down train isn't overdue, just wait longer
+        if self.train_power(train_id).is_off() || !reached_end {
+            self.schedule_watchdog_check(train_id);
+            return Ok(());
+        }
+
+        if !watchdog.overdue {
+            log::warn!(
+                "train {} is overdue in section {}",
+                train_id,
+                watchdog.section_id
+            );
+
+            self.emit_ui(UiTrainEvent::Overdue {
+                train_id,
+                section_id: watchdog.section_id,
+            });
+
+            let held_trains = if config.stop_following_trains {
+                self.hold_following_trains(train_id, watchdog.section_id, ctx)?
+            } else {
+                Vec::new()
+            };
+
+            if let Some(watchdog) = self.train_watchdogs.get_mut(&train_id) {
+                watchdog.overdue = true;
+                watchdog.held_trains = held_trains;
+            }
+        }
+
+        if watchdog.pulses < config.pulse_retries {
+            log::info!("pulsing train {} with full power", train_id);
+
+            if let Some(watchdog) = self.train_watchdogs.get_mut(&train_id) {
+                watchdog.pulses += 1;
+            }
+
+            self.set_train_sections_power(train_id, HardwareSectionPower::Full, ctx)?;
+
+            self.scheduler.schedule_in(
+                config.pulse_duration,
+                ScheduledEvent::WatchdogPulseEnd { train_id },
+            );
+            self.schedule_watchdog_check_in(train_id, config.pulse_duration + config.slack);
+        }
+
+        Ok(())
+    }
+
+    /// Set the sections of the train back to its power after a pulse.
+    pub(super) fn watchdog_pulse_end(
+        &mut self,
+        train_id: TrainId,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        let power = self.train_power(train_id);
+        self.set_train_sections_power(train_id, power, ctx)
+    }
+
+    /// Power the sections of the train, without changing the power the train drives with.
+    fn set_train_sections_power(
+        &mut self,
+        train_id: TrainId,
+        power: HardwareSectionPower,
+        ctx: EventExecutionContext,
+    ) -> Result<(), ControllerError> {
+        for section_id in self.train_powered_sections(train_id) {
+            ctx.exec(HardwareCommand::SetSectionPower {
+                section_id: section_id.as_u32(),
+                power,
+                polarity: self.section_polarity(section_id),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop the moving trains, that are about to enter the section of the overdue train
+    /// or one of its neighbouring sections.
+    fn hold_following_trains(
+        &mut self,
+        train_id: TrainId,
+        section_id: SectionId,
+        ctx: EventExecutionContext,
+    ) -> Result<Vec<TrainId>, ControllerError> {
+        let mut sections = self.track.neighbouring_sections(section_id);
+        sections.push(section_id);
+
+        let following_trains = self
+            .trains
+            .iter()
+            .filter(|&(&other_id, train)| {
+                other_id != train_id
+                    && train.state() == TrainState::Default
+                    && !self.train_power(other_id).is_off()
+                    && train
+                        .get_next_section()
+                        .is_some_and(|next_section| sections.contains(&next_section))
+            })
+            .map(|(&other_id, _)| other_id)
+            .collect::<Vec<_>>();
+
+        for &other_id in &following_trains {
+            log::info!(
+                "holding train {}, until train {} moves again",
+                other_id,
+                train_id
+            );
+            self.ramp_train(other_id, HardwareSectionPower::Off, ctx)?;
+        }
+
+        Ok(following_trains)
+    }
+}
@@ -0,0 +1,176 @@
+use std::{sync::mpsc, time::Duration};
+
+use crate::{
+    Clock, ControllerConfig, TrainCalibration, TrainSpeed,
+    comm::Simulation,
+    controller::testing::{config, occupy, record_power, route, section, simulate, track, train},
+    ui::UiEvent,
+};
+
+use super::*;
+
+fn watchdog_config(config: ControllerConfig, watchdog: WatchdogConfig) -> ControllerConfig {
+    ControllerConfig {
+        watchdog: Some(watchdog),
+        ..config
+    }
+}
+
+/// Step the simulation until the train is overdue, and return how long that took.
+fn run_until_overdue(simulation: &mut Simulation, train_id: TrainId, limit: Duration) -> Duration {
+    let started = simulation.clock().now();
+
+    while !simulation.controller().is_train_overdue(train_id) {
+        let elapsed = simulation.clock().now() - started;
+        assert!(
+            elapsed < limit,
+            "train {} isn't overdue after {:?}",
+            train_id,
+            elapsed
+        );
+        simulation.step().unwrap();
+    }
+
+    simulation.clock().now() - started
+}
+
+fn overdue_events(ui_event_rx: &mpsc::Receiver<UiEvent>) -> Vec<(TrainId, SectionId)> {
+    ui_event_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::UiTrainEvent(UiTrainEvent::Overdue {
+                train_id,
+                section_id,
+            }) => Some((train_id, section_id)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_timeout() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]))
+        .with_calibration(TrainCalibration::linear(10.0));
+    let watchdog = WatchdogConfig::default();
+
+    let expected = {
+        let section_length = track.section_geo(&section(12)).unwrap().length;
+        let travel_time = train
+            .calibration()
+            .travel_time(section_length, train.target_power())
+            .unwrap();
+        travel_time.mul_f32(watchdog.tolerance) + watchdog.slack
+    };
+
+    let config = watchdog_config(config(track, [(1, train)]), watchdog);
+    let (mut simulation, ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 12);
+
+    // the train never reaches S14
+    let overdue_after = run_until_overdue(&mut simulation, TrainId::new(1), expected * 2);
+    assert!(overdue_after.abs_diff(expected) < Duration::from_millis(100));
+    assert_eq!(
+        overdue_events(&ui_event_rx),
+        [(TrainId::new(1), section(12))]
+    );
+
+    // once it arrives, it is watched in the new section
+    occupy(&mut simulation, 14);
+    assert!(!simulation.controller().is_train_overdue(TrainId::new(1)));
+}
+
+#[test]
+fn test_fallback_timeout() {
+    let track = track();
+    let train = train("T1", route(&track, &[12, 14, 16, 9]));
+    let watchdog = WatchdogConfig {
+        fallback_timeout: Some(Duration::from_secs(20)),
+        ..WatchdogConfig::default()
+    };
+
+    let config = watchdog_config(config(track, [(1, train)]), watchdog);
+    let (mut simulation, ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 12);
+
+    // the train isn't calibrated, so it is only overdue after the fallback timeout
+    let overdue_after =
+        run_until_overdue(&mut simulation, TrainId::new(1), Duration::from_secs(30));
+    assert!(overdue_after.abs_diff(Duration::from_secs(20)) < Duration::from_millis(100));
+    assert_eq!(
+        overdue_events(&ui_event_rx),
+        [(TrainId::new(1), section(12))]
+    );
+}
+
+#[test]
+fn test_pulse_retry() {
+    let track = track();
+    let mut train = train("T1", route(&track, &[12, 14, 16, 9]))
+        .with_calibration(TrainCalibration::linear(10.0));
+    // the pulses are only visible, if the train doesn't drive with full power anyway
+    train.set_speed(TrainSpeed::Slow);
+
+    let watchdog = WatchdogConfig {
+        pulse_retries: 2,
+        ..WatchdogConfig::default()
+    };
+    let retry_after = watchdog.pulse_duration + watchdog.slack;
+
+    let config = watchdog_config(config(track, [(1, train)]), watchdog.clone());
+    let (mut simulation, ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 12);
+    run_until_overdue(&mut simulation, TrainId::new(1), Duration::from_secs(60));
+
+    let power = simulation.controller().train_power(TrainId::new(1));
+    let powers = record_power(&mut simulation, &ui_event_rx, retry_after * 3)
+        .into_iter()
+        .filter(|&(_, section_id, _)| section_id == section(12))
+        .collect::<Vec<_>>();
+
+    // two pulses with full power, each followed by the power of the train
+    let pulses = powers
+        .iter()
+        .filter(|&&(_, _, power)| power == HardwareSectionPower::Full)
+        .map(|&(at, _, _)| at)
+        .collect::<Vec<_>>();
+    assert_eq!(pulses.len(), 2);
+    assert!(pulses[1] - pulses[0] >= retry_after);
+    assert_eq!(powers.last().map(|&(_, _, power)| power), Some(power));
+}
+
+#[test]
+fn test_hold_following_trains() {
+    let track = track();
+    let trains = [
+        (
+            1,
+            train("T1", route(&track, &[12, 14, 16, 9]))
+                .with_calibration(TrainCalibration::linear(10.0)),
+        ),
+        (2, train("T2", route(&track, &[9, 10, 12]))),
+    ];
+    let watchdog = WatchdogConfig {
+        stop_following_trains: true,
+        fallback_timeout: None,
+        ..WatchdogConfig::default()
+    };
+
+    let config = watchdog_config(config(track, trains), watchdog);
+    let (mut simulation, _ui_event_rx) = simulate(config, []);
+    occupy(&mut simulation, 12);
+    occupy(&mut simulation, 9);
+
+    // T2 is about to enter S10, next to the section of T1
+    let t2 = TrainId::new(2);
+    assert!(!simulation.controller().train_power(t2).is_off());
+
+    run_until_overdue(&mut simulation, TrainId::new(1), Duration::from_secs(60));
+    simulation.step().unwrap();
+    assert!(simulation.controller().train_power(t2).is_off());
+
+    // T2 continues, once T1 moves again
+    occupy(&mut simulation, 14);
+    simulation.step().unwrap();
+    assert!(!simulation.controller().train_power(t2).is_off());
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+use liketrain_hardware::event::HardwareSectionPolarity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Direction {
     Forward,
     Backward,
@@ -13,6 +16,16 @@ impl Direction {
     }
 }
 
+/// Trains drive forward through a section with normal polarity and backward with reversed polarity.
+impl From<Direction> for HardwareSectionPolarity {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Forward => HardwareSectionPolarity::Normal,
+            Direction::Backward => HardwareSectionPolarity::Reversed,
+        }
+    }
+}
+
 impl std::fmt::Display for Direction {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -1,8 +1,9 @@
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
 use crate::{Direction, SectionEnd, SectionId, SectionTransition, Track};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Route {
     vias: Vec<SectionId>,
     starting_direction: Direction,
@@ -100,6 +101,22 @@ impl Route {
         self.vias.get(idx).copied()
     }
 
+    /// The route back from the via at the given index to the first via, for a train that was reversed
+    /// and faces the given direction now. `None`, if the vias can't be driven through the other way.
+    pub fn inverted(&self, mut idx: usize, direction: Direction, track: &Track) -> Option<Route> {
+        if self.is_closed() {
+            // a train on a closed route drives back to the first via of its current lap
+            idx %= self.vias.len() - 1;
+        }
+
+        let vias = (0..=idx)
+            .rev()
+            .filter_map(|idx| self.via(idx))
+            .collect::<Vec<_>>();
+
+        Route::new(format!("{} (reversed)", self.name), vias, direction, track)
+    }
+
     pub fn vias(&self) -> &[SectionId] {
         &self.vias
     }
@@ -66,6 +66,7 @@ fn test_serial_mega_sim() {
     port.write_command(HardwareCommand::SetSectionPower {
         section_id: 24,
         power: HardwareSectionPower::Half,
+        polarity: HardwareSectionPolarity::Normal,
     })
     .unwrap();
 
@@ -1,5 +1,5 @@
 mod section;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 pub use section::*;
 
@@ -18,6 +18,8 @@ pub use error::*;
 mod geo;
 pub use geo::*;
 
+use itertools::Itertools;
+
 use crate::Direction;
 
 #[derive(Debug, Clone, Default)]
@@ -146,6 +148,17 @@ impl Track {
             })
     }
 
+    /// The sections, that can be reached from a section in either direction.
+    pub fn neighbouring_sections(&self, section_id: SectionId) -> Vec<SectionId> {
+        [Direction::Forward, Direction::Backward]
+            .into_iter()
+            .filter_map(|direction| self.transitions(section_id, direction).ok())
+            .flatten()
+            .map(|transition| transition.destination())
+            .unique()
+            .collect()
+    }
+
     /// Get all transitions from a section in a given direction.
     pub fn transitions(
         &self,
@@ -213,3 +226,75 @@ impl Track {
         Ok(next_sections)
     }
 }
+
+impl Track {
+    /// Find the shortest path (in number of sections) from a section, driving in the given direction,
+    /// to a target section. The returned path contains the start and target section.
+    ///
+    /// Trains can't reverse on their own, so only paths in the given direction are considered.
+    pub fn find_path(
+        &self,
+        from: SectionId,
+        direction: Direction,
+        to: SectionId,
+    ) -> Option<Vec<SectionId>> {
+        self.find_path_avoiding(from, direction, to, &[])
+    }
+
+    /// Same as [`Track::find_path`], but the path will never go through one of the `avoid` sections.
+    pub fn find_path_avoiding(
+        &self,
+        from: SectionId,
+        direction: Direction,
+        to: SectionId,
+        avoid: &[SectionId],
+    ) -> Option<Vec<SectionId>> {
+        if from == to {
+            return Some(vec![from]);
+        }
+
+        let mut visited = HashSet::from([(from, direction)]);
+        let mut previous: HashMap<(SectionId, Direction), (SectionId, Direction)> = HashMap::new();
+        let mut queue = VecDeque::from([(from, direction)]);
+
+        while let Some((section_id, section_direction)) = queue.pop_front() {
+            let Ok(transitions) = self.transitions(section_id, section_direction) else {
+                continue;
+            };
+
+            for transition in transitions {
+                let next_section = transition.destination();
+                if avoid.contains(&next_section) {
+                    continue;
+                }
+
+                // depending on which end of the section we're going to, our direction relative to the section changes
+                let next_direction = transition.destination_section_end().entering_direction();
+
+                let next = (next_section, next_direction);
+                if !visited.insert(next) {
+                    continue;
+                }
+
+                previous.insert(next, (section_id, section_direction));
+
+                if next_section == to {
+                    let mut path = vec![next_section];
+                    let mut current = next;
+
+                    while let Some(&prev) = previous.get(&current) {
+                        path.push(prev.0);
+                        current = prev;
+                    }
+
+                    path.reverse();
+                    return Some(path);
+                }
+
+                queue.push_back(next);
+            }
+        }
+
+        None
+    }
+}
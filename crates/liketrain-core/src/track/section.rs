@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize, de::Visitor};
 
 use crate::{Connection, Direction};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SectionEnd {
     /// The start of the section. Going forward means getting to the end.
     Start,
@@ -19,6 +19,14 @@ impl SectionEnd {
             Direction::Backward => SectionEnd::Start,
         }
     }
+
+    /// The direction you drive through the section, when entering it at this end.
+    pub fn entering_direction(&self) -> Direction {
+        match self {
+            SectionEnd::Start => Direction::Forward,
+            SectionEnd::End => Direction::Backward,
+        }
+    }
 }
 
 impl std::fmt::Display for SectionEnd {
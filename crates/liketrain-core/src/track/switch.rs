@@ -3,6 +3,7 @@ use std::sync::Arc;
 use liketrain_hardware::event::{
     HARDWARE_SWITCH_ID_MAX_LEN, HardwareSwitchId, HardwareSwitchState,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{SectionEnd, SectionId, Track};
 
@@ -48,7 +49,25 @@ impl std::fmt::Display for SwitchId {
     }
 }
 
-#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+impl Serialize for SwitchId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SwitchId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(SwitchId::from)
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SwitchState {
     #[default]
     Left,
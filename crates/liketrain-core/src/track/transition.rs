@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{SectionEnd, SectionId, SwitchId, SwitchState, Track};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SectionTransition {
     Direct {
         section_id: SectionId,
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use liketrain_hardware::event::HardwareSectionPower;
+use serde::{Deserialize, Serialize};
+
+use crate::TrainSpeed;
+
+const POWER_LEVELS: [HardwareSectionPower; 4] = [
+    HardwareSectionPower::Quarter,
+    HardwareSectionPower::Half,
+    HardwareSectionPower::ThreeQuarters,
+    HardwareSectionPower::Full,
+];
+
+/// The fraction of the full power, that the given power level corresponds to.
+fn power_fraction(power: HardwareSectionPower) -> f32 {
+    power as u8 as f32 / HardwareSectionPower::Full as u8 as f32
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainCalibrationEntry {
+    /// The measured speed in meters per second (already in respect to the tracks scale)
+    pub speed: f32,
+
+    /// The number of measurements, this speed is averaged over.
+    pub samples: u32,
+}
+
+/// The measured speed of a train for each power level.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainCalibration {
+    entries: [Option<TrainCalibrationEntry>; 4],
+}
+
+impl TrainCalibration {
+    /// The speed at full power, that is assumed for trains that were never measured,
+    /// in meters per second (already in respect to the tracks scale)
+    pub const NOMINAL_FULL_SPEED: f32 = 8.0;
+
+    /// A calibration, where the speed grows linearly with the power level.
+    ///
+    /// * `full_speed` - the speed of the train at full power in meters per second
+    pub fn linear(full_speed: f32) -> Self {
+        let mut calibration = Self::default();
+        for power in POWER_LEVELS {
+            calibration.set_speed(power, full_speed * power_fraction(power));
+        }
+        calibration
+    }
+
+    fn entry_idx(power: HardwareSectionPower) -> Option<usize> {
+        POWER_LEVELS.iter().position(|&level| level == power)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (HardwareSectionPower, TrainCalibrationEntry)> {
+        POWER_LEVELS
+            .into_iter()
+            .zip(self.entries)
+            .filter_map(|(power, entry)| entry.map(|entry| (power, entry)))
+    }
+
+    /// The measured speed at the given power level in meters per second.
+    pub fn speed(&self, power: HardwareSectionPower) -> Option<f32> {
+        if power.is_off() {
+            return Some(0.0);
+        }
+
+        Self::entry_idx(power)
+            .and_then(|idx| self.entries[idx])
+            .map(|entry| entry.speed)
+    }
+
+    /// Overwrite the speed at the given power level.
+    pub fn set_speed(&mut self, power: HardwareSectionPower, speed: f32) {
+        if let Some(idx) = Self::entry_idx(power) {
+            self.entries[idx] = Some(TrainCalibrationEntry { speed, samples: 1 });
+        }
+    }
+
+    /// Add a measurement to the given power level. The speed is averaged over all measurements.
+    pub fn record(&mut self, power: HardwareSectionPower, speed: f32) {
+        let Some(idx) = Self::entry_idx(power) else {
+            return;
+        };
+
+        let entry = self.entries[idx].get_or_insert_default();
+        entry.samples += 1;
+        entry.speed += (speed - entry.speed) / entry.samples as f32;
+    }
+
+    /// The speed at the given power level. If this level wasn't measured,
+    /// the speed is estimated from the nearest measured level.
+    pub fn estimate_speed(&self, power: HardwareSectionPower) -> Option<f32> {
+        if let Some(speed) = self.speed(power) {
+            return Some(speed);
+        }
+
+        let (nearest_power, nearest_entry) = self
+            .entries()
+            .min_by_key(|(level, _)| (*level as i8 - power as i8).abs())?;
+
+        Some(nearest_entry.speed * power_fraction(power) / power_fraction(nearest_power))
+    }
+
+    /// The time it takes to travel the given distance at the given power level.
+    /// `None`, if the train doesn't move or it takes longer than a `Duration` can hold.
+    pub fn travel_time(&self, distance: f32, power: HardwareSectionPower) -> Option<Duration> {
+        let speed = self.estimate_speed(power)?;
+        if speed <= 0.0 {
+            return None;
+        }
+
+        Duration::try_from_secs_f32(distance / speed).ok()
+    }
+}
+
+/// Which power level is used for which speed of a train.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrainPowerMapping {
+    pub slow: HardwareSectionPower,
+    pub medium: HardwareSectionPower,
+    pub almost_fast: HardwareSectionPower,
+    pub fast: HardwareSectionPower,
+}
+
+impl TrainPowerMapping {
+    pub fn power(&self, speed: TrainSpeed) -> HardwareSectionPower {
+        match speed {
+            TrainSpeed::Slow => self.slow,
+            TrainSpeed::Medium => self.medium,
+            TrainSpeed::AlmostFast => self.almost_fast,
+            TrainSpeed::Fast => self.fast,
+        }
+    }
+
+    pub fn set_power(&mut self, speed: TrainSpeed, power: HardwareSectionPower) {
+        match speed {
+            TrainSpeed::Slow => self.slow = power,
+            TrainSpeed::Medium => self.medium = power,
+            TrainSpeed::AlmostFast => self.almost_fast = power,
+            TrainSpeed::Fast => self.fast = power,
+        }
+    }
+}
+
+impl Default for TrainPowerMapping {
+    fn default() -> Self {
+        Self {
+            slow: TrainSpeed::Slow.into(),
+            medium: TrainSpeed::Medium.into(),
+            almost_fast: TrainSpeed::AlmostFast.into(),
+            fast: TrainSpeed::Fast.into(),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum TrainClass {
+    HighSpeed,
+    Express,
+
+    #[default]
+    Regional,
+
+    Freight,
+    Shunting,
+}
+
+impl TrainClass {
+    pub const ALL: [TrainClass; 5] = [
+        TrainClass::HighSpeed,
+        TrainClass::Express,
+        TrainClass::Regional,
+        TrainClass::Freight,
+        TrainClass::Shunting,
+    ];
+
+    /// Trains with a higher priority are preferred, when multiple trains want the same section.
+    pub fn priority(&self) -> u8 {
+        match self {
+            TrainClass::HighSpeed => 4,
+            TrainClass::Express => 3,
+            TrainClass::Regional => 2,
+            TrainClass::Freight => 1,
+            TrainClass::Shunting => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for TrainClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrainClass::HighSpeed => write!(f, "High Speed"),
+            TrainClass::Express => write!(f, "Express"),
+            TrainClass::Regional => write!(f, "Regional"),
+            TrainClass::Freight => write!(f, "Freight"),
+            TrainClass::Shunting => write!(f, "Shunting"),
+        }
+    }
+}
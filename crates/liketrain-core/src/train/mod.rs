@@ -1,21 +1,43 @@
+mod calibration;
+pub use calibration::*;
+
+mod class;
+pub use class::*;
+
 mod mode;
 pub use mode::*;
 
+mod ramp;
+pub use ramp::*;
+
+mod roster;
+pub use roster::*;
+
 mod speed;
 pub use speed::*;
 
 mod state;
 pub use state::*;
 
-use crate::{Direction, Route, SectionEnd, SectionId, SectionTransition};
+use liketrain_hardware::event::HardwareSectionPower;
+use serde::{Deserialize, Serialize};
+
+use crate::{Direction, Route, SectionId, SectionTransition};
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
 pub struct TrainId(usize);
 
 impl TrainId {
     pub fn new(id: usize) -> Self {
         TrainId(id)
     }
+
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
 }
 
 impl std::fmt::Display for TrainId {
@@ -36,47 +58,148 @@ impl From<u32> for TrainId {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainData {
     pub name: String,
+
+    /// The length of the train in meters (already in respect to the tracks scale)
+    #[serde(default)]
+    pub length: f32,
+
+    #[serde(default)]
+    pub class: TrainClass,
+
+    /// The train never drives faster than this.
+    #[serde(default)]
+    pub max_speed: TrainSpeed,
+
+    /// Path to an image of the train, that is shown in the UI.
+    #[serde(default)]
+    pub icon: Option<String>,
+
+    #[serde(default)]
+    pub ramp_profile: TrainRampProfile,
+
+    #[serde(default)]
+    pub calibration: TrainCalibration,
+
+    #[serde(default)]
+    pub power_mapping: TrainPowerMapping,
+}
+
+impl TrainData {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            length: 0.0,
+            class: TrainClass::default(),
+            max_speed: TrainSpeed::Fast,
+            icon: None,
+            ramp_profile: TrainRampProfile::default(),
+            calibration: TrainCalibration::default(),
+            power_mapping: TrainPowerMapping::default(),
+        }
+    }
+
+    /// The estimated speed in meters per second, when driving with the given speed.
+    pub fn estimate_speed(&self, speed: TrainSpeed) -> Option<f32> {
+        self.calibration
+            .estimate_speed(self.power_mapping.power(speed))
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Train {
     data: TrainData,
 
     speed: TrainSpeed,
     state: TrainState,
 
+    /// The direction the front of the train is facing, relative to the section it is in.
+    /// Unlike the driving mode, this is kept when the train changes its route.
+    orientation: Direction,
+
     mode: TrainDrivingMode,
 }
 
 impl Train {
     pub fn from_route(name: impl Into<String>, route: Route) -> Self {
+        Self::new(TrainData::new(name), route)
+    }
+
+    pub fn new(data: TrainData, route: Route) -> Self {
         Self {
-            data: TrainData { name: name.into() },
-            speed: TrainSpeed::default(),
+            speed: TrainSpeed::default().min(data.max_speed),
+            data,
             state: TrainState::default(),
+            orientation: route.starting_direction(),
             mode: route.into(),
         }
     }
 }
 
+impl Train {
+    pub fn with_ramp_profile(mut self, ramp_profile: TrainRampProfile) -> Self {
+        self.data.ramp_profile = ramp_profile;
+        self
+    }
+
+    /// * `length` - the length of the train in meters (already in respect to the tracks scale)
+    pub fn with_length(mut self, length: f32) -> Self {
+        self.data.length = length;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: TrainCalibration) -> Self {
+        self.data.calibration = calibration;
+        self
+    }
+
+    pub fn with_power_mapping(mut self, power_mapping: TrainPowerMapping) -> Self {
+        self.data.power_mapping = power_mapping;
+        self
+    }
+}
+
 impl Train {
     pub fn data(&self) -> &TrainData {
         &self.data
     }
 
+    /// Replace the data of the train. The speed is limited to the new maximum speed.
+    pub fn set_data(&mut self, data: TrainData) {
+        self.data = data;
+        self.speed = self.speed.min(self.data.max_speed);
+    }
+
     pub fn name(&self) -> &str {
         &self.data.name
     }
 
+    pub fn ramp_profile(&self) -> TrainRampProfile {
+        self.data.ramp_profile
+    }
+
+    pub fn calibration(&self) -> &TrainCalibration {
+        &self.data.calibration
+    }
+
+    pub fn calibration_mut(&mut self) -> &mut TrainCalibration {
+        &mut self.data.calibration
+    }
+
+    /// The power level for the current speed of the train.
+    pub fn target_power(&self) -> HardwareSectionPower {
+        self.data.power_mapping.power(self.speed)
+    }
+
     pub fn speed(&self) -> TrainSpeed {
         self.speed
     }
 
+    /// Set the speed of the train, limited to its maximum speed.
     pub fn set_speed(&mut self, speed: TrainSpeed) {
-        self.speed = speed;
+        self.speed = speed.min(self.data.max_speed);
     }
 
     pub fn state(&self) -> TrainState {
@@ -99,6 +222,56 @@ impl Train {
         &self.mode
     }
 
+    /// Let the train drive a new route, starting at the section it is currently in.
+    /// The train keeps facing the same way, so a route starting in the other direction is rejected
+    /// and `false` is returned. The train has to be reversed first.
+    pub fn set_route(&mut self, route: Route) -> bool {
+        if route.starting_direction() != self.orientation {
+            return false;
+        }
+
+        self.mode = TrainDrivingMode::route_from_current(route);
+        true
+    }
+
+    /// Continue the route at the given via, e.g. after a restart, with the train facing the given direction.
+    /// `None` continues before the first section of the route, like a train that wasn't detected yet.
+    pub fn resume_route(
+        &mut self,
+        route: Route,
+        current_via_idx: Option<usize>,
+        orientation: Direction,
+    ) {
+        self.orientation = orientation;
+        self.mode = TrainDrivingMode::Route {
+            route,
+            current_via_idx,
+        };
+    }
+
+    /// How far the train got on its route. `None`, if it didn't enter the first section yet.
+    pub fn current_via_idx(&self) -> Option<usize> {
+        match &self.mode {
+            TrainDrivingMode::Route {
+                current_via_idx, ..
+            } => *current_via_idx,
+        }
+    }
+
+    pub fn has_finished_route(&self) -> bool {
+        self.mode.is_finished()
+    }
+
+    /// The direction the front of the train is facing, relative to the section it is in.
+    pub fn orientation(&self) -> Direction {
+        self.orientation
+    }
+
+    /// Swap the front and the back of the train, e.g. for a push-pull train changing ends.
+    pub fn reverse(&mut self) {
+        self.orientation = self.orientation.opposite();
+    }
+
     pub fn get_initial_section(&self) -> Option<SectionId> {
         self.mode.get_initial_section()
     }
@@ -122,6 +295,28 @@ impl Train {
         }
     }
 
+    /// The transitions the train will drive through after its current section, in order.
+    /// On closed routes, this stops before the train would reach its current section again.
+    pub fn upcoming_transitions(&self) -> impl Iterator<Item = &SectionTransition> {
+        match &self.mode {
+            TrainDrivingMode::Route {
+                route,
+                current_via_idx,
+            } => {
+                let remaining = if route.is_closed() {
+                    route.vias().len().saturating_sub(2)
+                } else {
+                    usize::MAX
+                };
+
+                current_via_idx
+                    .iter()
+                    .flat_map(move |&idx| (idx..).map_while(move |idx| route.transition(idx)))
+                    .take(remaining)
+            }
+        }
+    }
+
     pub fn entered_section(&mut self, section_id: SectionId) {
         let transition = self.get_transition_to_next_section().cloned();
 
@@ -134,23 +329,16 @@ impl Train {
 
         match &mut self.mode {
             TrainDrivingMode::Route {
-                current_via_idx,
-                current_section_direction,
-                route,
-            } => {
-                match current_via_idx {
-                    Some(idx) => *idx += 1,
-                    None => *current_via_idx = Some(0),
-                }
-
-                match transition.map(|trans| trans.destination_section_end()) {
-                    None => *current_section_direction = route.starting_direction(),
-                    Some(section_end) => match section_end {
-                        SectionEnd::End => *current_section_direction = Direction::Backward,
-                        SectionEnd::Start => *current_section_direction = Direction::Forward,
-                    },
-                };
-            }
+                current_via_idx, ..
+            } => match current_via_idx {
+                Some(idx) => *idx += 1,
+                None => *current_via_idx = Some(0),
+            },
+        }
+
+        // without a transition, the train keeps facing the direction it started in
+        if let Some(transition) = transition {
+            self.orientation = transition.destination_section_end().entering_direction();
         }
     }
 }
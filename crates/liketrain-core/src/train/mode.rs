@@ -1,11 +1,12 @@
-use crate::{Direction, Route, SectionId};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::{Route, SectionId};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TrainDrivingMode {
     Route {
         route: Route,
 
-        current_section_direction: Direction,
         current_via_idx: Option<usize>,
     },
 }
@@ -27,6 +28,21 @@ impl TrainDrivingMode {
         }
     }
 
+    /// Whether the train has reached the last section of its route.
+    /// Closed routes are never finished.
+    pub fn is_finished(&self) -> bool {
+        match self {
+            Self::Route {
+                route,
+                current_via_idx,
+                ..
+            } => {
+                !route.is_closed()
+                    && current_via_idx.is_some_and(|idx| idx + 1 >= route.vias().len())
+            }
+        }
+    }
+
     pub fn get_next_section(&self) -> Option<SectionId> {
         match self {
             Self::Route {
@@ -38,10 +54,19 @@ impl TrainDrivingMode {
     }
 }
 
+impl TrainDrivingMode {
+    /// Drive the given route, with the train already standing in the first section of it.
+    pub fn route_from_current(route: Route) -> Self {
+        Self::Route {
+            route,
+            current_via_idx: Some(0),
+        }
+    }
+}
+
 impl From<Route> for TrainDrivingMode {
     fn from(route: Route) -> Self {
         Self::Route {
-            current_section_direction: route.starting_direction(),
             route,
             current_via_idx: None,
         }
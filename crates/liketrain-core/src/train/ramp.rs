@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How fast a train is accelerated and decelerated.
+/// The power is changed one [`HardwareSectionPower`](liketrain_hardware::event::HardwareSectionPower) step at a time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrainRampProfile {
+    /// Time between two power steps, when accelerating.
+    pub acceleration_step: Duration,
+
+    /// Time between two power steps, when decelerating.
+    pub deceleration_step: Duration,
+}
+
+impl TrainRampProfile {
+    /// A profile without ramps, the power is changed immediately.
+    pub const NONE: Self = Self {
+        acceleration_step: Duration::ZERO,
+        deceleration_step: Duration::ZERO,
+    };
+
+    pub fn new(acceleration_step: Duration, deceleration_step: Duration) -> Self {
+        Self {
+            acceleration_step,
+            deceleration_step,
+        }
+    }
+}
+
+impl Default for TrainRampProfile {
+    fn default() -> Self {
+        Self {
+            acceleration_step: Duration::from_millis(800),
+            deceleration_step: Duration::from_millis(400),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{Direction, Route, SectionId, Track, Train, TrainData, TrainId};
+
+#[derive(Debug, Error)]
+pub enum TrainRosterError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Duplicate train id: {0}")]
+    DuplicateTrain(TrainId),
+
+    #[error("The default route of train {0} is not valid on this track")]
+    InvalidRoute(TrainId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainRosterRoute {
+    pub name: String,
+    pub vias: Vec<SectionId>,
+    pub starting_direction: Direction,
+}
+
+impl TrainRosterRoute {
+    pub fn resolve(&self, track: &Track) -> Option<Route> {
+        Route::new(
+            self.name.clone(),
+            self.vias.iter().copied(),
+            self.starting_direction,
+            track,
+        )
+    }
+}
+
+impl From<&Route> for TrainRosterRoute {
+    fn from(route: &Route) -> Self {
+        Self {
+            name: route.name().to_string(),
+            vias: route.vias().to_vec(),
+            starting_direction: route.starting_direction(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainRosterEntry {
+    pub id: TrainId,
+
+    #[serde(flatten)]
+    pub data: TrainData,
+
+    /// The route the train drives after startup.
+    /// Trains without a default route are not placed on the track.
+    #[serde(default)]
+    pub default_route: Option<TrainRosterRoute>,
+}
+
+/// All trains that are known, with their metadata. Loaded from and saved to a JSON file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrainRoster {
+    trains: Vec<TrainRosterEntry>,
+}
+
+impl TrainRoster {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TrainRosterError> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, TrainRosterError> {
+        let roster: Self = serde_json::from_str(json)?;
+
+        for (idx, entry) in roster.trains.iter().enumerate() {
+            if roster.trains[..idx]
+                .iter()
+                .any(|other| other.id == entry.id)
+            {
+                return Err(TrainRosterError::DuplicateTrain(entry.id));
+            }
+        }
+
+        Ok(roster)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TrainRosterError> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TrainRosterEntry> {
+        self.trains.iter()
+    }
+
+    pub fn entry(&self, train_id: TrainId) -> Option<&TrainRosterEntry> {
+        self.trains.iter().find(|entry| entry.id == train_id)
+    }
+
+    /// Add a train to the roster, or replace the train with the same id.
+    pub fn insert(&mut self, entry: TrainRosterEntry) {
+        match self.trains.iter_mut().find(|other| other.id == entry.id) {
+            Some(existing) => *existing = entry,
+            None => self.trains.push(entry),
+        }
+    }
+
+    /// Update the data of a train in the roster. Returns false, if there is no such train.
+    pub fn set_data(&mut self, train_id: TrainId, data: TrainData) -> bool {
+        match self.trains.iter_mut().find(|entry| entry.id == train_id) {
+            Some(entry) => {
+                entry.data = data;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Build the trains, that are placed on the track with their default route.
+    pub fn trains(&self, track: &Track) -> Result<HashMap<TrainId, Train>, TrainRosterError> {
+        let mut trains = HashMap::new();
+
+        for entry in self.trains.iter() {
+            let Some(default_route) = entry.default_route.as_ref() else {
+                log::info!(
+                    "train {} ({}) has no default route and is not placed on the track",
+                    entry.id,
+                    entry.data.name
+                );
+                continue;
+            };
+
+            let route = default_route
+                .resolve(track)
+                .ok_or(TrainRosterError::InvalidRoute(entry.id))?;
+
+            trains.insert(entry.id, Train::new(entry.data.clone(), route));
+        }
+
+        Ok(trains)
+    }
+}
@@ -1,6 +1,9 @@
 use liketrain_hardware::event::HardwareSectionPower;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
 pub enum TrainSpeed {
     Slow,
     Medium,
@@ -13,7 +16,7 @@ pub enum TrainSpeed {
 impl From<TrainSpeed> for HardwareSectionPower {
     fn from(value: TrainSpeed) -> Self {
         match value {
-            TrainSpeed::Slow => HardwareSectionPower::Off,
+            TrainSpeed::Slow => HardwareSectionPower::Quarter,
             TrainSpeed::Medium => HardwareSectionPower::Half,
             TrainSpeed::AlmostFast => HardwareSectionPower::ThreeQuarters,
             TrainSpeed::Fast => HardwareSectionPower::Full,
@@ -1,7 +1,33 @@
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub enum TrainState {
     #[default]
     Default,
 
     Waiting,
+
+    /// The train stops, once it entered its next section.
+    Stopping,
+
+    /// The train was stopped by the operator and stays, until it is started again.
+    Stopped,
+
+    /// The train was stopped immediately by the operator, keeping the sections it has reserved.
+    Paused,
+
+    /// The train was stopped because of a fault on the track. It can only be started again,
+    /// once the operator acknowledged the fault.
+    Faulted,
+}
+
+impl TrainState {
+    /// Whether the operator stopped the train or it was stopped by a fault,
+    /// and it only drives on, once it is started again.
+    pub fn is_held(&self) -> bool {
+        matches!(
+            self,
+            TrainState::Stopped | TrainState::Paused | TrainState::Faulted
+        )
+    }
 }
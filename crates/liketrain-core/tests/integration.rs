@@ -1,10 +1,18 @@
-use std::sync::mpsc;
+use std::{
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
 
 use chumsky::Parser;
 use liketrain_core::{
-    Controller, ControllerConfig, Direction, Route, TrackGeometry, Train,
-    comm::{SerialControllerHardwareCommunication, SimHardwareCommunication, SimTrain},
+    Clock, Controller, ControllerConfig, ControllerError, ControllerEvent, ControllerSnapshot,
+    Direction, Journal, JournalRecord, JournalReplayError, ManualClock, Route, ScheduledEvent,
+    Scheduler, ScriptConfig, SectionSnapshot, SnapshotConfig, TrackGeometry, Train,
+    TrainCalibration, TrainId, TrainRampProfile, TrainRoster, TrainSpeed,
+    comm::{SerialControllerHardwareCommunication, SimHardwareCommunication, SimTrain, Simulation},
+    hardware::event::HardwareSectionPower,
     parser::{eval::Evaluator, parser},
+    ui::{UiCommand, UiControllerEvent, UiEvent, UiSectionEvent, UiTrainEvent},
 };
 
 const LTT: &str = r#"
@@ -73,6 +81,826 @@ fn test_route_validation() {
     println!("Route 2 valid: {}", r2.pretty_print(&track));
 }
 
+#[test]
+fn test_path_finding() {
+    let result = parser().parse(LTT).into_result();
+    let track_defs = result.unwrap();
+
+    let eval = Evaluator::default();
+    let track = eval.evaluate(track_defs).unwrap();
+
+    let path = track
+        .find_path(12_usize.into(), Direction::Backward, 10_usize.into())
+        .expect("no path from S12 to S10");
+
+    assert_eq!(path.first(), Some(&12_usize.into()));
+    assert_eq!(path.last(), Some(&10_usize.into()));
+
+    let route = Route::new("S12 -> S10", path, Direction::Backward, &track);
+    assert!(route.is_some(), "path is not a valid route");
+
+    // S12 can only be left through S14 in this direction
+    let avoided = track.find_path_avoiding(
+        12_usize.into(),
+        Direction::Backward,
+        10_usize.into(),
+        &[14_usize.into()],
+    );
+    assert!(avoided.is_none());
+
+    let neighbours = track.neighbouring_sections(12_usize.into());
+    assert!(neighbours.contains(&14_usize.into()));
+    assert!(!neighbours.contains(&12_usize.into()));
+}
+
+#[test]
+fn test_train_orientation() {
+    let result = parser().parse(LTT).into_result();
+    let track_defs = result.unwrap();
+
+    let eval = Evaluator::default();
+    let track = eval.evaluate(track_defs).unwrap();
+
+    let path = track
+        .find_path(12_usize.into(), Direction::Backward, 10_usize.into())
+        .unwrap();
+    let route = Route::new("S12 -> S10", path, Direction::Backward, &track).unwrap();
+    let second_section_direction = route
+        .transition(0)
+        .unwrap()
+        .destination_section_end()
+        .entering_direction();
+
+    let mut train = Train::from_route("RE5", route.clone());
+    assert_eq!(train.orientation(), Direction::Backward);
+
+    train.entered_section(12_usize.into());
+    assert_eq!(train.orientation(), Direction::Backward);
+
+    train.entered_section(route.via(1).unwrap());
+    assert_eq!(train.orientation(), second_section_direction);
+
+    // reversing is kept, until the train enters the next section
+    train.reverse();
+    assert_eq!(train.orientation(), second_section_direction.opposite());
+}
+
+#[test]
+fn test_upcoming_transitions() {
+    let result = parser().parse(LTT).into_result();
+    let track_defs = result.unwrap();
+
+    let eval = Evaluator::default();
+    let track = eval.evaluate(track_defs).unwrap();
+
+    let path = track
+        .find_path(12_usize.into(), Direction::Backward, 10_usize.into())
+        .unwrap();
+    let route = Route::new("S12 -> S10", path, Direction::Backward, &track).unwrap();
+
+    let mut train = Train::from_route("RE5", route.clone());
+
+    // the train hasn't been detected in its initial section yet
+    assert_eq!(train.upcoming_transitions().count(), 0);
+
+    train.entered_section(12_usize.into());
+    let upcoming = train
+        .upcoming_transitions()
+        .map(|transition| transition.destination())
+        .collect::<Vec<_>>();
+    assert_eq!(upcoming, route.vias()[1..]);
+
+    train.entered_section(route.via(1).unwrap());
+    assert_eq!(train.upcoming_transitions().count(), route.vias().len() - 2);
+}
+
+#[test]
+fn test_train_calibration() {
+    let mut calibration = TrainCalibration::default();
+    assert!(
+        calibration
+            .estimate_speed(HardwareSectionPower::Full)
+            .is_none()
+    );
+
+    calibration.record(HardwareSectionPower::Half, 0.4);
+    calibration.record(HardwareSectionPower::Half, 0.6);
+    assert_eq!(calibration.speed(HardwareSectionPower::Half), Some(0.5));
+
+    // not measured, so it is estimated from the half power speed
+    assert_eq!(calibration.speed(HardwareSectionPower::Full), None);
+    assert_eq!(
+        calibration.estimate_speed(HardwareSectionPower::Full),
+        Some(1.0)
+    );
+
+    assert_eq!(
+        calibration.travel_time(2.0, HardwareSectionPower::Half),
+        Some(Duration::from_secs(4))
+    );
+    assert_eq!(
+        calibration.travel_time(2.0, HardwareSectionPower::Off),
+        None
+    );
+
+    // a speed, that was edited to almost nothing, doesn't overflow the travel time
+    calibration.set_speed(HardwareSectionPower::Quarter, 1e-30);
+    assert_eq!(
+        calibration.travel_time(2.0, HardwareSectionPower::Quarter),
+        None
+    );
+}
+
+#[test]
+fn test_scheduler_freeze() {
+    let mut scheduler = Scheduler::default();
+    scheduler.schedule_now(ScheduledEvent::PositionEstimateTick);
+
+    // frozen events are kept, until the scheduler is resumed
+    scheduler.freeze();
+    assert!(scheduler.next_event().is_none());
+    assert!(scheduler.next_event_duration().is_none());
+
+    scheduler.resume();
+    assert!(matches!(
+        scheduler.next_event(),
+        Some(ScheduledEvent::PositionEstimateTick)
+    ));
+    assert!(scheduler.next_event().is_none());
+}
+
+#[test]
+fn test_scheduler_handles() {
+    let mut scheduler = Scheduler::default();
+    let train_id: TrainId = 1_u32.into();
+
+    let cancelled = scheduler.schedule_now(ScheduledEvent::DeadlockCheck);
+    let postponed = scheduler.schedule_now(ScheduledEvent::SaveSnapshot);
+    let periodic = scheduler.schedule_every(Duration::ZERO, ScheduledEvent::PositionEstimateTick);
+    scheduler.schedule_for_train(
+        train_id,
+        Duration::ZERO,
+        ScheduledEvent::DispatchTrain { train_id },
+    );
+
+    assert!(scheduler.cancel(cancelled));
+    assert!(!scheduler.cancel(cancelled));
+    assert!(scheduler.reschedule_in(postponed, Duration::from_secs(60)));
+    assert_eq!(scheduler.cancel_train(train_id), 1);
+
+    // a periodic event is handed out again and again, until it is cancelled
+    for _ in 0..3 {
+        assert!(matches!(
+            scheduler.next_event(),
+            Some(ScheduledEvent::PositionEstimateTick)
+        ));
+    }
+    assert!(scheduler.is_pending(periodic));
+    assert!(scheduler.cancel(periodic));
+    assert!(scheduler.next_event().is_none());
+
+    // only the postponed event is left
+    assert!(scheduler.is_pending(postponed));
+    assert!(scheduler.next_event_duration().unwrap() > Duration::from_secs(59));
+}
+
+#[test]
+fn test_scheduler_manual_clock() {
+    let clock = ManualClock::new();
+    let mut scheduler = Scheduler::new(Arc::new(clock.clone()));
+    scheduler.schedule_in(Duration::from_secs(3600), ScheduledEvent::SaveSnapshot);
+
+    assert!(scheduler.next_event().is_none());
+
+    // frozen time doesn't count towards the delay
+    scheduler.freeze();
+    clock.advance(Duration::from_secs(1800));
+    scheduler.resume();
+    clock.advance(Duration::from_secs(1800));
+    assert!(scheduler.next_event().is_none());
+
+    clock.advance(Duration::from_secs(1800));
+    assert!(matches!(
+        scheduler.next_event(),
+        Some(ScheduledEvent::SaveSnapshot)
+    ));
+}
+
+#[test]
+fn test_train_roster() {
+    let result = parser().parse(LTT).into_result();
+    let track_defs = result.unwrap();
+
+    let eval = Evaluator::default();
+    let track = eval.evaluate(track_defs).unwrap();
+
+    let roster = include_str!("../../../resources/roster.json");
+    let roster = TrainRoster::from_json(roster).unwrap();
+
+    let trains = roster.trains(&track).unwrap();
+    let train = &trains[&TrainId::new(1)];
+    assert_eq!(train.name(), "RE5");
+    assert_eq!(train.get_initial_section(), Some(12_usize.into()));
+
+    // saving and loading again keeps all trains
+    let json = serde_json::to_string(&roster).unwrap();
+    let reloaded = TrainRoster::from_json(&json).unwrap();
+    assert_eq!(reloaded.entries().count(), roster.entries().count());
+}
+
+#[test]
+fn test_threaded_controller_rejects_manual_clock() {
+    let (mut controller_config, sim_train) = sim_controller_config(&[12, 14, 16]);
+    let clock = ManualClock::new();
+    controller_config.clock = Arc::new(clock.clone());
+
+    // nothing would advance the clock, while the controller waits for events
+    let hardware_comm = SimHardwareCommunication::new([sim_train]).with_clock(Arc::new(clock));
+    let (ui_event_tx, _ui_event_rx) = mpsc::channel();
+    let (_command_tx, command_rx) = crossbeam::channel::unbounded();
+    let controller = Controller::new(controller_config, hardware_comm, ui_event_tx, command_rx);
+
+    assert!(matches!(
+        controller.start(),
+        Err(ControllerError::ClockNotRealTime)
+    ));
+}
+
+/// A single simulated train on the given route.
+fn sim_controller_config(vias: &[usize]) -> (ControllerConfig, SimTrain) {
+    let track_defs = parser().parse(LTT).into_result().unwrap();
+    let mut track = Evaluator::default().evaluate(track_defs).unwrap();
+
+    let track_geo = include_str!("../../../resources/geo.json");
+    let track_geo: TrackGeometry = serde_json::from_str(track_geo).unwrap();
+    track.set_geometry(track_geo);
+
+    let route = Route::new("RE5", vias.iter().copied(), Direction::Backward, &track).unwrap();
+
+    let sim_train = SimTrain::from_route(&route, &track, 10.0);
+    let train = Train::from_route("RE5", route).with_ramp_profile(TrainRampProfile::NONE);
+
+    let controller_config = ControllerConfig {
+        watchdog: None,
+        ..ControllerConfig::new(track, [(1_u32.into(), train)].into())
+    };
+
+    (controller_config, sim_train)
+}
+
+/// Simulate a single train on the given route.
+fn start_simulation(vias: &[usize]) -> (Simulation, mpsc::Receiver<UiEvent>) {
+    let (controller_config, sim_train) = sim_controller_config(vias);
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+
+    (simulation, ui_event_rx)
+}
+
+/// Step the simulation, until a ui event matches or the timeout passed on the simulated clock.
+fn run_until(
+    simulation: &mut Simulation,
+    rx: &mpsc::Receiver<UiEvent>,
+    timeout: Duration,
+    mut matches: impl FnMut(&UiEvent) -> bool,
+) -> bool {
+    let deadline = simulation.clock().now() + timeout;
+
+    loop {
+        if rx.try_iter().any(|event| matches(&event)) {
+            return true;
+        }
+
+        if simulation.clock().now() >= deadline {
+            return false;
+        }
+
+        simulation.step().unwrap();
+    }
+}
+
+fn entered_section(section_id: usize) -> impl FnMut(&UiEvent) -> bool {
+    move |event| {
+        matches!(
+            event,
+            UiEvent::UiTrainEvent(UiTrainEvent::EnteredSection { section_id: entered, .. })
+                if *entered == section_id.into()
+        )
+    }
+}
+
+fn powered_on(event: &UiEvent) -> bool {
+    matches!(
+        event,
+        UiEvent::UiSectionEvent(UiSectionEvent::SetPower { power, .. }) if !power.is_off()
+    )
+}
+
+#[test]
+fn test_train_speed_change() {
+    let (mut simulation, ui_event_rx) = start_simulation(&[12, 14, 16, 9, 10, 12]);
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        entered_section(14)
+    ));
+
+    simulation
+        .command(UiCommand::SetTrainSpeed {
+            train_id: 1_u32.into(),
+            speed: TrainSpeed::Medium,
+        })
+        .unwrap();
+
+    // the driving train is re-powered with its new speed right away
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_millis(100),
+        |event| matches!(
+            event,
+            UiEvent::UiSectionEvent(UiSectionEvent::SetPower {
+                power: HardwareSectionPower::Half,
+                ..
+            })
+        )
+    ));
+}
+
+#[test]
+fn test_stopped_train_speed_change() {
+    let (mut simulation, ui_event_rx) = start_simulation(&[12, 14]);
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        entered_section(14)
+    ));
+
+    simulation
+        .command(UiCommand::SetTrainSpeed {
+            train_id: 1_u32.into(),
+            speed: TrainSpeed::Medium,
+        })
+        .unwrap();
+
+    // the train stopped at the end of its route and has to stay there
+    assert!(!run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(10),
+        powered_on
+    ));
+}
+
+#[test]
+fn test_pause_and_start_train() {
+    let (mut simulation, ui_event_rx) = start_simulation(&[12, 14, 16, 9, 10, 12]);
+    let train_id = 1_u32.into();
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        entered_section(14)
+    ));
+
+    simulation
+        .command(UiCommand::PauseTrain { train_id })
+        .unwrap();
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(2),
+        |event| matches!(event, UiEvent::UiTrainEvent(UiTrainEvent::Stopped { .. }))
+    ));
+
+    // the hardware still reports the commands sent before the train was paused
+    simulation.step().unwrap();
+    while ui_event_rx.try_recv().is_ok() {}
+
+    // the paused train stays where it is
+    assert!(!run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(10),
+        powered_on
+    ));
+
+    simulation
+        .command(UiCommand::StartTrain { train_id })
+        .unwrap();
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_millis(100),
+        powered_on
+    ));
+}
+
+#[test]
+fn test_journal_replay() {
+    let vias = [12, 14, 16, 9, 10, 12];
+    let journal_path = std::env::temp_dir().join("liketrain-test-journal.jsonl");
+
+    let (mut controller_config, sim_train) = sim_controller_config(&vias);
+    controller_config.journal = Some(journal_path.clone());
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+
+    // record the train driving a few sections, with a speed change on the way.
+    // Only a run recorded in a simulation is replayed exactly, see `Journal::replay`.
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        entered_section(14)
+    ));
+
+    simulation
+        .command(UiCommand::SetTrainSpeed {
+            train_id: 1_u32.into(),
+            speed: TrainSpeed::Medium,
+        })
+        .unwrap();
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        entered_section(16)
+    ));
+
+    drop(simulation);
+
+    let journal = Journal::load(&journal_path).unwrap();
+    assert!(journal.entries().iter().any(|entry| matches!(
+        entry.record,
+        JournalRecord::UiCommand(UiCommand::SetTrainSpeed { .. })
+    )));
+    assert!(journal.hardware_commands().count() > 0);
+
+    // the position estimate ticks are left out, the replay derives them again
+    assert!(!journal.entries().iter().any(|entry| matches!(
+        entry.record,
+        JournalRecord::Event(ControllerEvent::Scheduled(
+            ScheduledEvent::PositionEstimateTick
+        ))
+    )));
+
+    // a fresh controller handles the recorded events the same way and sends the same commands
+    let (controller_config, _) = sim_controller_config(&vias);
+    let replayed = journal.replay(controller_config).unwrap();
+
+    let records = |journal: &Journal| {
+        journal
+            .entries()
+            .iter()
+            .map(|entry| (entry.at, serde_json::to_string(&entry.record).unwrap()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(records(&replayed), records(&journal));
+    assert_eq!(
+        replayed.hardware_commands().collect::<Vec<_>>(),
+        journal.hardware_commands().collect::<Vec<_>>()
+    );
+
+    // a journal, that the controller can't have produced, is detected
+    let mut entries = journal.entries().to_vec();
+    let index = entries
+        .iter()
+        .position(|entry| matches!(entry.record, JournalRecord::HardwareCommand(_)))
+        .unwrap();
+    entries.remove(index);
+
+    let (controller_config, _) = sim_controller_config(&vias);
+    assert!(matches!(
+        Journal::new(entries).replay(controller_config),
+        Err(JournalReplayError::EntryMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_journal_replay_with_snapshot() {
+    let vias = [12, 14, 16, 9, 10, 12];
+    let journal_path = std::env::temp_dir().join("liketrain-test-journal-snapshot.jsonl");
+    let snapshot_path = std::env::temp_dir().join("liketrain-test-journal-snapshot.json");
+    let _ = std::fs::remove_file(&snapshot_path);
+
+    let (mut controller_config, sim_train) = sim_controller_config(&vias);
+    controller_config.journal = Some(journal_path.clone());
+    controller_config.snapshot = Some(SnapshotConfig::new(&snapshot_path));
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+
+    // long enough for a few snapshots to be saved
+    run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(25),
+        |_| false,
+    );
+    drop(simulation);
+
+    assert!(snapshot_path.exists());
+    std::fs::remove_file(&snapshot_path).unwrap();
+
+    // saving the snapshots isn't recorded, the replay doesn't save any
+    let journal = Journal::load(&journal_path).unwrap();
+    assert!(!journal.entries().iter().any(|entry| matches!(
+        entry.record,
+        JournalRecord::Event(ControllerEvent::Scheduled(ScheduledEvent::SaveSnapshot))
+    )));
+
+    let (mut controller_config, _) = sim_controller_config(&vias);
+    controller_config.snapshot = Some(SnapshotConfig::new(&snapshot_path));
+    journal.replay(controller_config).unwrap();
+    assert!(!snapshot_path.exists());
+}
+
+#[test]
+fn test_journal_replay_with_scripts() {
+    let vias = [12, 14, 16, 9, 10, 12];
+    let journal_path = std::env::temp_dir().join("liketrain-test-journal-scripts.jsonl");
+    let scripts_dir = std::env::temp_dir().join("liketrain-test-journal-scripts");
+    let _ = std::fs::remove_dir_all(&scripts_dir);
+    std::fs::create_dir_all(&scripts_dir).unwrap();
+
+    std::fs::write(
+        scripts_dir.join("slow.rhai"),
+        r#"
+            fn on_train_entered(train, section) {
+                if section == 14 {
+                    set_speed(train, "Medium");
+                    after(0.5, "pause_later");
+                }
+            }
+
+            fn pause_later() {
+                pause_train(1);
+            }
+        "#,
+    )
+    .unwrap();
+
+    let (mut controller_config, sim_train) = sim_controller_config(&vias);
+    controller_config.journal = Some(journal_path.clone());
+    controller_config.scripts = Some(ScriptConfig::new(&scripts_dir));
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        |event| matches!(event, UiEvent::UiTrainEvent(UiTrainEvent::Stopped { .. }))
+    ));
+    drop(simulation);
+
+    // the commands of the script are recorded, its reloads and timers aren't
+    let journal = Journal::load(&journal_path).unwrap();
+    assert!(journal.entries().iter().any(|entry| matches!(
+        entry.record,
+        JournalRecord::UiCommand(UiCommand::PauseTrain { .. })
+    )));
+    assert!(!journal.entries().iter().any(|entry| matches!(
+        entry.record,
+        JournalRecord::Event(ControllerEvent::Scheduled(
+            ScheduledEvent::ReloadScripts | ScheduledEvent::ScriptTimer { .. }
+        ))
+    )));
+
+    // the replay runs without the scripts and executes their recorded commands instead
+    let (mut controller_config, _) = sim_controller_config(&vias);
+    controller_config.scripts = Some(ScriptConfig::new(&scripts_dir));
+    let replayed = journal.replay(controller_config).unwrap();
+    assert_eq!(
+        replayed.hardware_commands().collect::<Vec<_>>(),
+        journal.hardware_commands().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_snapshot_restore() {
+    let track_defs = parser().parse(LTT).into_result().unwrap();
+    let track = Evaluator::default().evaluate(track_defs).unwrap();
+
+    let route = Route::new(
+        "RE5",
+        [12_usize, 14, 16, 9, 10, 12],
+        Direction::Backward,
+        &track,
+    )
+    .unwrap();
+
+    // the controller isn't started, so there is nothing to simulate
+    let hardware_comm = SimHardwareCommunication::new(Vec::new());
+    let controller_config = ControllerConfig::new(
+        track,
+        [(1_u32.into(), Train::from_route("RE5", route))].into(),
+    );
+
+    let (tx, _rx) = mpsc::channel();
+    let (_command_tx, rx) = crossbeam::channel::unbounded();
+    let mut controller = Controller::new(controller_config, hardware_comm, tx, rx);
+
+    // the train drove from S12 into S14 and already reserved S16
+    let train_id: TrainId = 1_u32.into();
+    let mut snapshot = controller.snapshot();
+    snapshot.trains[0].current_via_idx = Some(1);
+    snapshot.trains[0].tail = vec![12_usize.into()];
+    snapshot.sections = vec![
+        SectionSnapshot {
+            id: 12_usize.into(),
+            occupied: Some(train_id),
+            reserved: Some(train_id),
+            queue: Vec::new(),
+        },
+        SectionSnapshot {
+            id: 14_usize.into(),
+            occupied: Some(train_id),
+            reserved: Some(train_id),
+            queue: Vec::new(),
+        },
+        SectionSnapshot {
+            id: 16_usize.into(),
+            occupied: None,
+            reserved: Some(train_id),
+            queue: Vec::new(),
+        },
+    ];
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let snapshot = ControllerSnapshot::from_json(&json).unwrap();
+
+    // a snapshot that doesn't fit the track is rejected as a whole
+    let mut invalid = snapshot.clone();
+    invalid.sections[0].id = 999_usize.into();
+    assert!(controller.restore(invalid).is_err());
+    assert_eq!(controller.section_reservation(16_usize.into()), None);
+
+    controller.restore(snapshot.clone()).unwrap();
+
+    let train = controller.train(train_id).unwrap();
+    assert_eq!(train.get_current_section(), Some(14_usize.into()));
+    assert_eq!(
+        controller.train_sections(train_id),
+        vec![12_usize.into(), 14_usize.into()]
+    );
+    assert_eq!(
+        controller.section_reservation(16_usize.into()),
+        Some(train_id)
+    );
+
+    // the restored state is saved the same way again
+    let restored = controller.snapshot();
+    assert_eq!(restored.trains[0].current_via_idx, Some(1));
+    assert_eq!(restored.trains[0].tail, snapshot.trains[0].tail);
+    assert_eq!(restored.sections, snapshot.sections);
+}
+
+#[test]
+fn test_scripts() {
+    let scripts_dir = std::env::temp_dir().join("liketrain-test-scripts");
+    let _ = std::fs::remove_dir_all(&scripts_dir);
+    std::fs::create_dir_all(&scripts_dir).unwrap();
+
+    std::fs::write(
+        scripts_dir.join("slow.rhai"),
+        r#"
+            fn on_train_entered(train, section) {
+                if section == 14 && hour() == 22 {
+                    set_speed(train, "Medium");
+                    after(0.1, "stop_train_later");
+                }
+            }
+
+            fn stop_train_later() {
+                pause_train(1);
+            }
+        "#,
+    )
+    .unwrap();
+    std::fs::write(scripts_dir.join("broken.rhai"), "fn broken( {").unwrap();
+    std::fs::write(
+        scripts_dir.join("forever.rhai"),
+        r#"fn on_load() { after(1e300, "on_load"); }"#,
+    )
+    .unwrap();
+
+    let (mut controller_config, sim_train) = sim_controller_config(&[12, 14, 16, 9, 10, 12]);
+    controller_config.scripts = Some(ScriptConfig {
+        start_time: chrono::NaiveTime::from_hms_opt(22, 0, 0),
+        ..ScriptConfig::new(&scripts_dir)
+    });
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+
+    // the broken script is reported, the other one still runs
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        |event| matches!(
+            event,
+            UiEvent::UiControllerEvent(UiControllerEvent::ScriptError(error))
+                if error.script == "broken.rhai"
+        )
+    ));
+
+    // a delay, that is too long, fails the handler instead of the controller
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        |event| matches!(
+            event,
+            UiEvent::UiControllerEvent(UiControllerEvent::ScriptError(error))
+                if error.script == "forever.rhai"
+        )
+    ));
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(30),
+        |event| matches!(
+            event,
+            UiEvent::UiSectionEvent(UiSectionEvent::SetPower {
+                power: HardwareSectionPower::Half,
+                ..
+            })
+        )
+    ));
+
+    assert!(run_until(
+        &mut simulation,
+        &ui_event_rx,
+        Duration::from_secs(2),
+        |event| matches!(event, UiEvent::UiTrainEvent(UiTrainEvent::Stopped { .. }))
+    ));
+}
+
+/// The sections the train entered during the simulation, with the simulated time it entered them,
+/// and the section the controller has the train in at the end.
+fn simulate_show(vias: &[usize], duration: Duration) -> (Vec<(Duration, usize)>, Option<usize>) {
+    let (controller_config, sim_train) = sim_controller_config(vias);
+
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let mut simulation = Simulation::new(controller_config, [sim_train], ui_event_tx).unwrap();
+    let started = simulation.clock().now();
+
+    let mut entered = Vec::new();
+    while simulation.clock().now() - started < duration {
+        simulation.step().unwrap();
+
+        for event in ui_event_rx.try_iter() {
+            if let UiEvent::UiTrainEvent(UiTrainEvent::EnteredSection { section_id, .. }) = event {
+                entered.push((simulation.clock().now() - started, section_id.as_usize()));
+            }
+        }
+    }
+
+    let current_section = simulation
+        .controller()
+        .train(1_u32.into())
+        .unwrap()
+        .get_current_section()
+        .map(|section_id| section_id.as_usize());
+
+    (entered, current_section)
+}
+
+#[test]
+fn test_simulation() {
+    let vias = [12, 14, 16, 9, 10, 12];
+
+    let duration = Duration::from_secs(2 * 3600);
+
+    let started = Instant::now();
+    let (entered, current_section) = simulate_show(&vias, duration);
+    assert!(started.elapsed() < Duration::from_secs(60));
+
+    // the train drove through every section of its route and ended up, where the controller has it
+    for via in vias {
+        assert!(entered.iter().any(|&(_, section)| section == via));
+    }
+    assert_eq!(entered.last().map(|&(_, section)| section), current_section);
+
+    // the same simulation always produces the same events
+    assert_eq!((entered, current_section), simulate_show(&vias, duration));
+}
+
 #[test]
 fn test_controller() {
     #[cfg(debug_assertions)]
@@ -112,12 +940,12 @@ fn test_controller() {
     let hardware_comm = SimHardwareCommunication::new([SimTrain::from_route(&r1, &track, 10.0)]);
 
     let controller_config = ControllerConfig {
-        track,
-        trains: [(1_u32.into(), Train::from_route("RE5", r1))].into(),
+        watchdog: None,
+        ..ControllerConfig::new(track, [(1_u32.into(), Train::from_route("RE5", r1))].into())
     };
 
     let (tx, _) = mpsc::channel();
-    let (_, rx) = crossbeam::channel::unbounded();
+    let (_command_tx, rx) = crossbeam::channel::unbounded();
     let controller = Controller::new(controller_config, hardware_comm, tx, rx);
 
     controller.start().unwrap();
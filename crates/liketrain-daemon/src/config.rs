@@ -0,0 +1,242 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use clap::Parser;
+use liketrain_api::ApiAccess;
+use serde::Deserialize;
+
+/// Run the liketrain controller without the ui.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// A JSON file describing the project. Arguments given on the command line take precedence.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// The track definition (LTT file).
+    #[arg(long)]
+    pub track: Option<PathBuf>,
+
+    /// The geometry of the track (JSON file).
+    #[arg(long)]
+    pub geometry: Option<PathBuf>,
+
+    /// The trains (JSON roster file).
+    #[arg(long)]
+    pub roster: Option<PathBuf>,
+
+    /// The serial port the master is connected to, e.g. /dev/ttyACM0.
+    #[arg(long, conflicts_with = "sim")]
+    pub port: Option<String>,
+
+    /// The baud rate of the serial port, 115200 if not set.
+    #[arg(long)]
+    pub baud_rate: Option<u32>,
+
+    /// Simulate the hardware, even if the config file names a serial port.
+    #[arg(long)]
+    pub sim: bool,
+
+    /// Where to save the state of the controller, to continue after a restart.
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Continue where the previous run left off, if there is a snapshot.
+    #[arg(long)]
+    pub restore: bool,
+
+    /// Where to record the journal of this run.
+    #[arg(long)]
+    pub journal: Option<PathBuf>,
+
+    /// A directory of automation scripts (Rhai), reloaded when they change.
+    #[arg(long)]
+    pub scripts: Option<PathBuf>,
+
+    /// Send the trains to random destinations, as described in this JSON file.
+    #[arg(long)]
+    pub dispatcher: Option<PathBuf>,
+
+    /// Serve the WebSocket API on this address, e.g. 127.0.0.1:8080.
+    #[arg(long)]
+    pub listen: Option<SocketAddr>,
+
+    /// The token clients of the WebSocket API have to send in their hello.
+    /// Prefer the environment variable, other users can see the command line.
+    #[arg(long, env = "LIKETRAIN_API_TOKEN", hide_env_values = true)]
+    pub api_token: Option<String>,
+
+    /// Only let clients of the WebSocket API watch the controller, their commands are rejected.
+    #[arg(long)]
+    pub api_read_only: bool,
+
+    /// Let WiThrottle apps connect on this address, e.g. 127.0.0.1:12090.
+    #[arg(long)]
+    pub withrottle: Option<SocketAddr>,
+
+    /// Let a ui in another process attach over this Unix socket.
+    #[arg(long)]
+    pub ipc: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HardwareConfig {
+    Serial {
+        port: String,
+
+        #[serde(default = "HardwareConfig::default_baud_rate")]
+        baud_rate: u32,
+    },
+    Sim,
+}
+
+impl HardwareConfig {
+    fn default_baud_rate() -> u32 {
+        115200
+    }
+}
+
+/// The project as described in the config file. Paths are relative to the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub track: Option<PathBuf>,
+    pub geometry: Option<PathBuf>,
+    pub roster: Option<PathBuf>,
+
+    /// Without a hardware config, the hardware is simulated.
+    pub hardware: Option<HardwareConfig>,
+
+    pub snapshot: Option<PathBuf>,
+    pub journal: Option<PathBuf>,
+
+    /// The automation scripts. Without it, no scripts are run.
+    pub scripts: Option<PathBuf>,
+
+    /// The destinations of the dispatcher. Without it, trains stop at the end of their route.
+    pub dispatcher: Option<PathBuf>,
+
+    /// Where to serve the WebSocket API. Without it, there is no API.
+    pub listen: Option<SocketAddr>,
+
+    /// The token clients of the WebSocket API have to send in their hello.
+    /// Without it, every client that can connect is accepted.
+    pub api_token: Option<String>,
+
+    /// Whether clients of the WebSocket API can only watch the controller.
+    pub api_read_only: bool,
+
+    /// Where WiThrottle apps connect to. Without it, phones can't be used as throttles.
+    pub withrottle: Option<SocketAddr>,
+
+    /// The Unix socket the ui attaches to. Without it, the ui can't be used with the daemon.
+    pub ipc: Option<PathBuf>,
+}
+
+impl DaemonConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config: Self = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        for path in [
+            &mut config.track,
+            &mut config.geometry,
+            &mut config.roster,
+            &mut config.snapshot,
+            &mut config.journal,
+            &mut config.scripts,
+            &mut config.dispatcher,
+            &mut config.ipc,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = base.join(&*path);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Everything the daemon needs to run, from the command line and the config file.
+#[derive(Debug)]
+pub struct Project {
+    pub track: PathBuf,
+    pub geometry: PathBuf,
+    pub roster: PathBuf,
+    pub hardware: HardwareConfig,
+    pub snapshot: Option<PathBuf>,
+    pub restore: bool,
+    pub journal: Option<PathBuf>,
+    pub scripts: Option<PathBuf>,
+    pub dispatcher: Option<PathBuf>,
+    pub listen: Option<SocketAddr>,
+    pub api_access: ApiAccess,
+    pub withrottle: Option<SocketAddr>,
+    pub ipc: Option<PathBuf>,
+}
+
+impl Project {
+    pub fn from_args(args: Args) -> anyhow::Result<Self> {
+        let config = match args.config.as_deref() {
+            Some(path) => DaemonConfig::load(path)?,
+            None => DaemonConfig::default(),
+        };
+
+        let hardware = if args.sim {
+            HardwareConfig::Sim
+        } else {
+            match (args.port, config.hardware) {
+                (Some(port), _) => HardwareConfig::Serial {
+                    port,
+                    baud_rate: args
+                        .baud_rate
+                        .unwrap_or_else(HardwareConfig::default_baud_rate),
+                },
+                (None, Some(HardwareConfig::Serial { port, baud_rate })) => {
+                    HardwareConfig::Serial {
+                        port,
+                        baud_rate: args.baud_rate.unwrap_or(baud_rate),
+                    }
+                }
+                (None, _) => HardwareConfig::Sim,
+            }
+        };
+
+        let Some(track) = args.track.or(config.track) else {
+            bail!("no track given, use --track or set it in the config file");
+        };
+        let Some(geometry) = args.geometry.or(config.geometry) else {
+            bail!("no geometry given, use --geometry or set it in the config file");
+        };
+        let Some(roster) = args.roster.or(config.roster) else {
+            bail!("no roster given, use --roster or set it in the config file");
+        };
+
+        Ok(Self {
+            track,
+            geometry,
+            roster,
+            hardware,
+            snapshot: args.snapshot.or(config.snapshot),
+            restore: args.restore,
+            journal: args.journal.or(config.journal),
+            scripts: args.scripts.or(config.scripts),
+            dispatcher: args.dispatcher.or(config.dispatcher),
+            listen: args.listen.or(config.listen),
+            api_access: ApiAccess {
+                token: args.api_token.or(config.api_token),
+                read_only: args.api_read_only || config.api_read_only,
+            },
+            withrottle: args.withrottle.or(config.withrottle),
+            ipc: args.ipc.or(config.ipc),
+        })
+    }
+}
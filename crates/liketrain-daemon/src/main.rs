@@ -0,0 +1,363 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use clap::Parser as _;
+use crossbeam::channel::{Receiver, Select};
+use liketrain_api::{ApiServer, Frontend, WiThrottleLayout, WiThrottleServer};
+use liketrain_core::{
+    Controller, ControllerConfig, ControllerSnapshot, DispatcherConfig, ScriptConfig, SectionId,
+    SnapshotConfig, TrackGeometry, TrainRoster,
+    comm::{
+        ControllerHardwareCommunication, SerialControllerHardwareCommunication,
+        SimHardwareCommunication, SimTrain,
+    },
+    hardware::event::HardwareSectionPower,
+    parser::{Parser, eval::Evaluator},
+    ui::{UiCommand, UiControllerEvent, UiEvent, UiSectionEvent},
+};
+
+use crate::config::{Args, HardwareConfig, Project};
+
+mod config;
+
+/// How long the hardware gets to confirm, that all sections are powered off, before the daemon
+/// gives up.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether the controller is halted and which sections the hardware last reported as powered.
+#[derive(Debug, Default)]
+struct PowerState {
+    halted: bool,
+    powered: HashSet<SectionId>,
+}
+
+/// Follows the ui events, to tell when the track is safe to leave after an emergency stop.
+struct PowerWatch {
+    state: Mutex<PowerState>,
+    changed: Condvar,
+}
+
+impl PowerWatch {
+    fn new(controller: &Controller) -> Self {
+        let powered = controller
+            .section_states()
+            .filter(|(_, state)| state.power() != HardwareSectionPower::Off)
+            .map(|(section_id, _)| section_id)
+            .collect();
+
+        Self {
+            state: Mutex::new(PowerState {
+                halted: controller.is_halted(),
+                powered,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn update(&self, event: &UiEvent) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            UiEvent::UiControllerEvent(UiControllerEvent::Halted) => state.halted = true,
+            UiEvent::UiControllerEvent(UiControllerEvent::Resumed) => state.halted = false,
+            UiEvent::UiSectionEvent(UiSectionEvent::SetPower {
+                section_id, power, ..
+            }) => {
+                if *power == HardwareSectionPower::Off {
+                    state.powered.remove(section_id);
+                } else {
+                    state.powered.insert(*section_id);
+                }
+            }
+            _ => return,
+        }
+
+        self.changed.notify_all();
+    }
+
+    /// Wait until the controller is halted and the hardware reported every section as powered off.
+    /// Returns the state at the timeout, if that takes longer than `timeout`.
+    fn wait_powered_off(&self, timeout: Duration) -> Result<(), PowerState> {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |state| {
+                !state.halted || !state.powered.is_empty()
+            })
+            .unwrap();
+
+        if state.halted && state.powered.is_empty() {
+            return Ok(());
+        }
+
+        Err(PowerState {
+            halted: state.halted,
+            powered: state.powered.clone(),
+        })
+    }
+}
+
+fn init_logger() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .target(env_logger::Target::Stdout)
+        .init();
+}
+
+fn main() -> anyhow::Result<()> {
+    init_logger();
+
+    let project = Project::from_args(Args::parse())?;
+    log::info!("loading project {:?}", project);
+
+    let controller_config = load_controller_config(&project)?;
+
+    match project.hardware.clone() {
+        HardwareConfig::Serial { port, baud_rate } => {
+            log::info!("connecting to the master on {} ({} baud)", port, baud_rate);
+            run(
+                &project,
+                controller_config,
+                SerialControllerHardwareCommunication::new(&port, baud_rate),
+            )
+        }
+        HardwareConfig::Sim => {
+            log::info!("simulating the hardware");
+            let sim_trains = controller_config
+                .trains
+                .values()
+                .filter_map(|train| SimTrain::from_train(train, &controller_config.track))
+                .collect::<Vec<_>>();
+
+            run(
+                &project,
+                controller_config,
+                SimHardwareCommunication::new(sim_trains),
+            )
+        }
+    }
+}
+
+fn load_controller_config(project: &Project) -> anyhow::Result<ControllerConfig> {
+    let track_dsl = std::fs::read_to_string(&project.track)
+        .with_context(|| format!("failed to read {}", project.track.display()))?;
+    let track_defs = liketrain_core::parser::parser()
+        .parse(&track_dsl)
+        .into_result()
+        .map_err(|errs| {
+            let errs = errs.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+            anyhow!("failed to parse the track: {}", errs.join(", "))
+        })?;
+
+    let mut track = Evaluator::default()
+        .evaluate(track_defs)
+        .map_err(|err| anyhow!("failed to evaluate the track: {}", err))?;
+
+    let track_geo = std::fs::read_to_string(&project.geometry)
+        .with_context(|| format!("failed to read {}", project.geometry.display()))?;
+    let track_geo: TrackGeometry = serde_json::from_str(&track_geo)
+        .with_context(|| format!("failed to parse {}", project.geometry.display()))?;
+    track.set_geometry(track_geo);
+
+    let roster = TrainRoster::load(&project.roster)
+        .with_context(|| format!("failed to load {}", project.roster.display()))?;
+
+    let mut controller_config = ControllerConfig::from_roster(track, &roster)?;
+    controller_config.snapshot = project.snapshot.clone().map(SnapshotConfig::new);
+    controller_config.journal = project.journal.clone();
+    controller_config.scripts = project.scripts.clone().map(ScriptConfig::new);
+
+    if let Some(path) = project.dispatcher.as_deref() {
+        let dispatcher = DispatcherConfig::load(path)
+            .with_context(|| format!("failed to load {}", path.display()))?;
+        controller_config.dispatcher = Some(dispatcher);
+    }
+
+    Ok(controller_config)
+}
+
+/// Run the controller, until it fails or SIGINT is received.
+fn run(
+    project: &Project,
+    controller_config: ControllerConfig,
+    hardware_comm: impl ControllerHardwareCommunication,
+) -> anyhow::Result<()> {
+    let (ui_event_tx, ui_event_rx) = mpsc::channel();
+    let (ui_command_tx, ui_command_rx) = crossbeam::channel::unbounded();
+
+    let mut controller =
+        Controller::new(controller_config, hardware_comm, ui_event_tx, ui_command_rx);
+
+    if project.restore {
+        restore_snapshot(&mut controller)?;
+    }
+
+    let frontends = bind_frontends(project, &controller)?;
+    let power_watch = Arc::new(PowerWatch::new(&controller));
+
+    // the controller logs everything important itself, the ui events are only for the frontends
+    let event_frontends = frontends.clone();
+    let event_power_watch = power_watch.clone();
+    thread::spawn(move || {
+        for event in ui_event_rx {
+            log_ui_event(&event);
+            event_power_watch.update(&event);
+
+            for frontend in &event_frontends {
+                frontend.broadcast(&event);
+            }
+        }
+    });
+
+    let (shutdown_tx, shutdown_rx) = crossbeam::channel::bounded(1);
+    ctrlc::set_handler(move || {
+        let _ = shutdown_tx.try_send(());
+    })
+    .context("failed to install the SIGINT handler")?;
+
+    let (stopped_tx, stopped_rx) = crossbeam::channel::bounded(1);
+    let controller = thread::spawn(move || {
+        let result = controller.start();
+        let _ = stopped_tx.send(());
+        result
+    });
+
+    let mut commands = frontends
+        .iter()
+        .map(|frontend| frontend.commands().clone())
+        .collect::<Vec<Receiver<UiCommand>>>();
+
+    let mut shutdown_result = Ok(());
+
+    loop {
+        let mut select = Select::new();
+        let shutdown = select.recv(&shutdown_rx);
+        let stopped = select.recv(&stopped_rx);
+        for commands in &commands {
+            select.recv(commands);
+        }
+
+        let operation = select.select();
+        match operation.index() {
+            index if index == shutdown => {
+                let _ = operation.recv(&shutdown_rx);
+                log::info!("shutting down, powering off all sections");
+
+                let _ = ui_command_tx.send(UiCommand::EmergencyStop);
+                shutdown_result = power_watch.wait_powered_off(SHUTDOWN_TIMEOUT);
+                break;
+            }
+            index if index == stopped => {
+                let _ = operation.recv(&stopped_rx);
+                break;
+            }
+            index => {
+                // the frontends come after the shutdown and stopped channels
+                let frontend = index - 2;
+                match operation.recv(&commands[frontend]) {
+                    Ok(command) => {
+                        let _ = ui_command_tx.send(command);
+                    }
+                    Err(_) => {
+                        commands.remove(frontend);
+                    }
+                }
+            }
+        }
+    }
+
+    // the controller stops, once there is nobody to send commands anymore
+    drop(ui_command_tx);
+
+    controller
+        .join()
+        .map_err(|_| anyhow!("the controller panicked"))??;
+
+    log::info!("controller stopped");
+
+    if let Err(state) = shutdown_result {
+        if !state.halted {
+            log::error!("the controller didn't halt within {:?}", SHUTDOWN_TIMEOUT);
+        }
+
+        let mut powered = state.powered.into_iter().collect::<Vec<_>>();
+        powered.sort();
+        if !powered.is_empty() {
+            log::error!(
+                "the hardware didn't confirm powering off sections {:?} within {:?}",
+                powered,
+                SHUTDOWN_TIMEOUT
+            );
+        }
+
+        anyhow::bail!("the track wasn't powered off on shutdown");
+    }
+
+    Ok(())
+}
+
+/// Start serving the frontends, that are configured.
+fn bind_frontends(
+    project: &Project,
+    controller: &Controller,
+) -> anyhow::Result<Vec<Arc<dyn Frontend>>> {
+    let mut frontends: Vec<Arc<dyn Frontend>> = Vec::new();
+
+    if let Some(addr) = project.listen {
+        let api = ApiServer::bind(addr, project.api_access.clone())
+            .with_context(|| format!("failed to listen on {}", addr))?;
+        frontends.push(Arc::new(api));
+    }
+
+    if let Some(addr) = project.withrottle {
+        let withrottle =
+            WiThrottleServer::bind(addr, WiThrottleLayout::from_controller(controller))
+                .with_context(|| format!("failed to listen on {}", addr))?;
+        frontends.push(Arc::new(withrottle));
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = project.ipc.as_ref() {
+        let ipc = liketrain_api::IpcServer::bind(path)
+            .with_context(|| format!("failed to listen on {}", path.display()))?;
+        frontends.push(Arc::new(ipc));
+    }
+
+    #[cfg(not(unix))]
+    if project.ipc.is_some() {
+        anyhow::bail!("the ipc socket is only supported on unix");
+    }
+
+    Ok(frontends)
+}
+
+fn restore_snapshot(controller: &mut Controller) -> anyhow::Result<()> {
+    let Some(config) = controller.snapshot_config() else {
+        log::warn!("there is no snapshot to restore, use --snapshot to set where it is saved");
+        return Ok(());
+    };
+
+    if !config.path.exists() {
+        log::info!(
+            "there is no snapshot at {} yet, starting fresh",
+            config.path.display()
+        );
+        return Ok(());
+    }
+
+    let snapshot = ControllerSnapshot::load(&config.path)
+        .with_context(|| format!("failed to load {}", config.path.display()))?;
+    controller.restore(snapshot)?;
+
+    Ok(())
+}
+
+fn log_ui_event(event: &UiEvent) {
+    match event {
+        UiEvent::UiControllerEvent(event) => log::debug!("{:?}", event),
+        _ => log::trace!("{:?}", event),
+    }
+}
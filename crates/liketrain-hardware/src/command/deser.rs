@@ -40,7 +40,12 @@ impl Deser for HardwareCommand {
             HardwareCommandType::SetSectionPower => {
                 let section_id = buffer.read_u32()?;
                 let power = buffer.read()?;
-                Ok(Self::SetSectionPower { section_id, power })
+                let polarity = buffer.read()?;
+                Ok(Self::SetSectionPower {
+                    section_id,
+                    power,
+                    polarity,
+                })
             }
             HardwareCommandType::SetSwitchState => {
                 let switch_id = buffer.read()?;
@@ -62,9 +67,14 @@ impl Deser for HardwareCommand {
             }
             Self::GetSlaves => Ok(()),
             Self::ResetAll => Ok(()),
-            &Self::SetSectionPower { section_id, power } => {
+            &Self::SetSectionPower {
+                section_id,
+                power,
+                polarity,
+            } => {
                 buffer.write_u32(section_id)?;
                 buffer.write(&power)?;
+                buffer.write(&polarity)?;
                 Ok(())
             }
             &Self::SetSwitchState { switch_id, state } => {
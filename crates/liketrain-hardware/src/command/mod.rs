@@ -1,8 +1,11 @@
-use crate::event::{HardwareSectionPower, HardwareSwitchId, HardwareSwitchState};
+use crate::event::{
+    HardwareSectionPolarity, HardwareSectionPower, HardwareSwitchId, HardwareSwitchState,
+};
 
 pub mod deser;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HardwareCommand {
     Ping {
         slave_id: u32,
@@ -14,6 +17,7 @@ pub enum HardwareCommand {
     SetSectionPower {
         section_id: u32,
         power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
     },
 
     SetSwitchState {
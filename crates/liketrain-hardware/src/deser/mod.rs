@@ -3,6 +3,9 @@ use alloc::vec::Vec;
 
 mod variant;
 
+#[cfg(test)]
+mod tests;
+
 pub struct DeserPayloadReader<'a>(&'a [u8]);
 
 impl DeserPayloadReader<'_> {
@@ -0,0 +1,42 @@
+use crate::{
+    command::HardwareCommand,
+    event::{HardwareEvent, HardwareSectionPolarity, HardwareSectionPower},
+};
+
+use super::DeserHelper;
+
+#[test]
+fn test_section_power_command_polarity() {
+    for polarity in [
+        HardwareSectionPolarity::Normal,
+        HardwareSectionPolarity::Reversed,
+    ] {
+        let command = HardwareCommand::SetSectionPower {
+            section_id: 7,
+            power: HardwareSectionPower::Half,
+            polarity,
+        };
+
+        let buffer = command.serialize().unwrap();
+
+        // variant, section id, power and the polarity as the last byte
+        assert_eq!(buffer, [0x10, 7, 0, 0, 0, 2, polarity as u8]);
+        assert_eq!(HardwareCommand::deserialize(&buffer).unwrap(), command);
+    }
+}
+
+#[test]
+fn test_section_power_event_polarity() {
+    let event = HardwareEvent::SectionPowerChanged {
+        section_id: 300,
+        power: HardwareSectionPower::Full,
+        polarity: HardwareSectionPolarity::Reversed,
+    };
+
+    let buffer = event.serialize().unwrap();
+    assert_eq!(buffer.last(), Some(&1));
+    assert_eq!(HardwareEvent::deserialize(&buffer).unwrap(), event);
+
+    // the polarity is part of the message, a truncated one is rejected
+    assert!(HardwareEvent::deserialize(&buffer[..buffer.len() - 1]).is_err());
+}
@@ -2,7 +2,8 @@ use crate::{
     deser::{Deser, DeserError, DeserPayloadReader, DeserPayloadWriter},
     deser_variant,
     event::{
-        HardwareEvent, HardwareSectionPower, HardwareSwitchId, HardwareSwitchState, SectionEvent,
+        HardwareEvent, HardwareSectionPolarity, HardwareSectionPower, HardwareSwitchId,
+        HardwareSwitchState, SectionEvent,
     },
 };
 
@@ -66,8 +67,13 @@ impl Deser for HardwareEvent {
             HardwareEventType::SectionPowerChanged => {
                 let section_id = payload.read_u32()?;
                 let power: HardwareSectionPower = payload.read()?;
+                let polarity: HardwareSectionPolarity = payload.read()?;
 
-                Ok(Self::SectionPowerChanged { section_id, power })
+                Ok(Self::SectionPowerChanged {
+                    section_id,
+                    power,
+                    polarity,
+                })
             }
         }
     }
@@ -95,9 +101,14 @@ impl Deser for HardwareEvent {
                 buffer.write(state)?;
                 Ok(())
             }
-            Self::SectionPowerChanged { section_id, power } => {
+            Self::SectionPowerChanged {
+                section_id,
+                power,
+                polarity,
+            } => {
                 buffer.write_u32(*section_id)?;
                 buffer.write(power)?;
+                buffer.write(polarity)?;
                 Ok(())
             }
         }
@@ -7,13 +7,15 @@ pub const HARDWARE_SWITCH_ID_MAX_LEN: usize = 32;
 pub type HardwareSwitchId = [u8; 32];
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HardwareSwitchState {
     Left = 0,
     Right = 1,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HardwareEvent {
     Pong {
         slave_id: u32,
@@ -28,6 +30,7 @@ pub enum HardwareEvent {
     SectionPowerChanged {
         section_id: u32,
         power: HardwareSectionPower,
+        polarity: HardwareSectionPolarity,
     },
 
     SwitchStateChanged {
@@ -1,5 +1,6 @@
 #[repr(u8)]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HardwareSectionPower {
     #[default]
     Off = 0,
@@ -16,15 +17,38 @@ impl HardwareSectionPower {
     }
 }
 
+/// The polarity of the voltage applied to a section. Swapping it reverses the running direction
+/// of an analog train.
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HardwareSectionPolarity {
+    #[default]
+    Normal = 0,
+
+    Reversed = 1,
+}
+
+impl HardwareSectionPolarity {
+    pub fn opposite(&self) -> Self {
+        match self {
+            HardwareSectionPolarity::Normal => HardwareSectionPolarity::Reversed,
+            HardwareSectionPolarity::Reversed => HardwareSectionPolarity::Normal,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SectionEventType {
     Occupied = 0,
     Freed = 1,
 }
 
 #[repr(C, packed)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SectionEvent {
     pub section_id: u32,
     pub event_type: SectionEventType,
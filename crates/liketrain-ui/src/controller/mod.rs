@@ -1,13 +1,20 @@
-use std::{collections::HashMap, sync::mpsc, time::Duration};
+use std::{collections::HashMap, path::PathBuf, sync::mpsc, time::Duration};
 
 use gpui::{
     App, AppContext, BorrowAppContext, Context, Entity, EventEmitter, Global, SharedString, Task,
 };
+#[cfg(unix)]
+use liketrain_api::IpcClient;
 use liketrain_core::{
-    Controller, ControllerConfig, SectionId, SwitchId, SwitchState, Track, TrainId,
+    Controller, ControllerConfig, ControllerFault, ControllerSnapshot, ControllerSnapshotError,
+    Deadlock, InterlockingOverride, InterlockingViolation, SectionId, SwitchId, SwitchState, Track,
+    TrainId, TrainRoster, TrainRosterError,
     comm::ControllerHardwareCommunication,
     hardware::event::SectionEventType,
-    ui::{UiCommand, UiEvent, UiSectionEvent, UiSwitchEvent, UiTrainEvent},
+    ui::{
+        UiCommand, UiControllerEvent, UiEvent, UiSectionEvent, UiSnapshot, UiSwitchEvent,
+        UiTrainEvent,
+    },
 };
 
 mod section;
@@ -21,6 +28,9 @@ use crate::layout::ResolvedLayout;
 #[derive(Debug)]
 pub enum ControllerUiLogType {
     UiEvent,
+
+    /// An automation script failed.
+    Script,
 }
 
 #[derive(Debug)]
@@ -31,6 +41,13 @@ pub struct ControllerUiLog {
 
 impl ControllerUiLog {
     fn ui_event(event: &UiEvent) -> Self {
+        if let UiEvent::UiControllerEvent(UiControllerEvent::ScriptError(error)) = event {
+            return Self {
+                log_type: ControllerUiLogType::Script,
+                message: SharedString::from(error.to_string()),
+            };
+        }
+
         Self {
             log_type: ControllerUiLogType::UiEvent,
             message: SharedString::from(format!("{:?}", event)),
@@ -46,6 +63,21 @@ pub struct ControllerUiWrapperState {
     section_states: HashMap<SectionId, UiSectionState>,
     switch_states: HashMap<SwitchId, SwitchState>,
 
+    /// Whether the controller was halted by an emergency stop.
+    halted: bool,
+
+    /// The faults reported by the controller, oldest first.
+    faults: Vec<ControllerFault>,
+
+    /// The trains currently waiting for each other.
+    deadlocks: Vec<Deadlock>,
+
+    /// Why manual commands were rejected, oldest first.
+    rejections: Vec<InterlockingViolation>,
+
+    /// The manual commands, that were executed despite violating the interlocking.
+    overrides: Vec<InterlockingOverride>,
+
     logs: Vec<ControllerUiLog>,
 }
 
@@ -80,6 +112,26 @@ impl ControllerUiWrapperState {
         self.trains.get(&train_id)
     }
 
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn faults(&self) -> &[ControllerFault] {
+        &self.faults
+    }
+
+    pub fn deadlocks(&self) -> &[Deadlock] {
+        &self.deadlocks
+    }
+
+    pub fn rejections(&self) -> &[InterlockingViolation] {
+        &self.rejections
+    }
+
+    pub fn overrides(&self) -> &[InterlockingOverride] {
+        &self.overrides
+    }
+
     pub fn logs(&self) -> &[ControllerUiLog] {
         &self.logs
     }
@@ -87,31 +139,57 @@ impl ControllerUiWrapperState {
 
 impl ControllerUiWrapperState {
     fn from_controller(controller: &Controller) -> Self {
-        Self {
-            track: controller.track().clone(),
-            trains: controller
-                .trains()
-                .map(|(id, train)| (id, train.into()))
-                .collect(),
-            switch_states: controller.switch_states().collect(),
-            section_states: controller
-                .section_states()
-                .map(|(id, state)| {
-                    let reservation = controller.section_reservation(id);
-                    let queue = controller.section_queue(id).cloned().unwrap_or_default();
-
-                    (
-                        id,
-                        UiSectionState {
-                            power: state.power(),
-                            occupant: state.occupant().into(),
-                            reserved_by: reservation,
-                            queue,
-                        },
-                    )
-                })
-                .collect(),
-            logs: Vec::new(),
+        Self::from_snapshot(controller.track().clone(), controller.ui_snapshot())
+    }
+
+    fn from_snapshot(track: Track, snapshot: UiSnapshot) -> Self {
+        let mut state = Self {
+            track,
+            ..Default::default()
+        };
+        state.apply_snapshot(snapshot);
+
+        state
+    }
+
+    /// Replace everything the controller reports, the faults and logs seen so far are kept.
+    fn apply_snapshot(&mut self, snapshot: UiSnapshot) {
+        self.trains = snapshot
+            .trains
+            .iter()
+            .map(|train| (train.id, UiTrain::from(&train.train)))
+            .collect();
+        self.section_states = snapshot
+            .sections
+            .into_iter()
+            .map(|section| (section.id, section.into()))
+            .collect();
+        self.switch_states = snapshot
+            .switches
+            .into_iter()
+            .map(|switch| (switch.id, switch.state))
+            .collect();
+        self.halted = snapshot.halted;
+        self.deadlocks = snapshot.deadlocks;
+    }
+
+    fn handle_controller_event(&mut self, controller_event: UiControllerEvent) {
+        match controller_event {
+            UiControllerEvent::Halted => self.halted = true,
+            UiControllerEvent::Resumed => self.halted = false,
+            UiControllerEvent::Fault(fault) => self.faults.push(fault),
+            UiControllerEvent::Deadlock(deadlock) => self.deadlocks.push(deadlock),
+            UiControllerEvent::DeadlockResolved(deadlock) => {
+                self.deadlocks.retain(|known| *known != deadlock)
+            }
+            UiControllerEvent::CommandRejected { violation, .. } => self.rejections.push(violation),
+            UiControllerEvent::InterlockingOverridden(interlocking_override) => {
+                self.overrides.push(interlocking_override)
+            }
+            // the controller runs in another process, the ui starts with its state
+            UiControllerEvent::Snapshot(snapshot) => self.apply_snapshot(*snapshot),
+            // script errors are only shown in the logs
+            UiControllerEvent::ScriptError(_) => {}
         }
     }
 
@@ -132,8 +210,14 @@ impl ControllerUiWrapperState {
                     .or_default()
                     .reserved_by = train_id;
             }
-            UiSectionEvent::SetPower { section_id, power } => {
-                self.section_states.entry(section_id).or_default().power = power;
+            UiSectionEvent::SetPower {
+                section_id,
+                power,
+                polarity,
+            } => {
+                let state = self.section_states.entry(section_id).or_default();
+                state.power = power;
+                state.polarity = polarity;
             }
             UiSectionEvent::QueueEnqueued {
                 section_id,
@@ -179,31 +263,92 @@ impl ControllerUiWrapperState {
         }
     }
 
+    /// Events of unknown trains are ignored, e.g. when the train was removed in the meantime.
     fn handle_train_event(&mut self, train_event: UiTrainEvent) {
         match train_event {
             UiTrainEvent::EnteredSection {
                 train_id,
                 section_id,
             } => {
-                self.trains.get_mut(&train_id).unwrap().current_section = Some(section_id);
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.current_section = Some(section_id);
+                    train.entered_section_at = Some(std::time::Instant::now());
+                    train.position = None;
+                    train.overdue = false;
+                }
             }
             UiTrainEvent::SpeedChanged { train_id, speed } => {
-                self.trains.get_mut(&train_id).unwrap().speed = speed;
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.speed = speed;
+                }
             }
             UiTrainEvent::StateChanged { train_id, state } => {
-                self.trains.get_mut(&train_id).unwrap().state = state;
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.state = state;
+                }
+            }
+            UiTrainEvent::RouteChanged { train_id, route } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.route = Some(route);
+                }
             }
-            _ => todo!(),
+            UiTrainEvent::OrientationChanged {
+                train_id,
+                orientation,
+            } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.orientation = orientation;
+                }
+            }
+            UiTrainEvent::CalibrationChanged {
+                train_id,
+                calibration,
+            } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.data.calibration = calibration;
+                }
+            }
+            UiTrainEvent::DataChanged { train_id, data } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.data = *data;
+                }
+            }
+            UiTrainEvent::Overdue { train_id, .. } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.overdue = true;
+                }
+            }
+            UiTrainEvent::PositionEstimated { train_id, estimate } => {
+                if let Some(train) = self.trains.get_mut(&train_id) {
+                    train.position = Some((estimate, std::time::Instant::now()));
+                }
+            }
+            UiTrainEvent::Added { train_id, train } => {
+                self.trains.insert(train_id, UiTrain::from(&*train));
+            }
+            UiTrainEvent::Removed { train_id } => {
+                self.trains.remove(&train_id);
+            }
+            // the state of the train is reported separately
+            UiTrainEvent::Started { .. } | UiTrainEvent::Stopped { .. } => {}
         }
     }
 
     fn handle_event(&mut self, event: UiEvent, cx: &mut Context<Self>) {
-        // add to logs
-        self.logs.push(ControllerUiLog::ui_event(&event));
+        // add to logs, except the position estimates that are published multiple times a second
+        if !matches!(
+            event,
+            UiEvent::UiTrainEvent(UiTrainEvent::PositionEstimated { .. })
+        ) {
+            self.logs.push(ControllerUiLog::ui_event(&event));
+        }
         cx.notify();
 
         // update own state based on event
         match event.clone() {
+            UiEvent::UiControllerEvent(controller_event) => {
+                self.handle_controller_event(controller_event)
+            }
             UiEvent::UiSectionEvent(section_event) => self.handle_section_event(section_event),
             UiEvent::UiSwitchEvent(switch_event) => self.handle_switch_event(switch_event),
             UiEvent::UiTrainEvent(train_event) => self.handle_train_event(train_event),
@@ -215,15 +360,30 @@ impl ControllerUiWrapperState {
     }
 }
 
-pub struct ControllerUiWrapper {
+/// Where the controller runs.
+enum ControllerTransport {
     // has to be Option because we need to `.take()` it out when starting
-    controller: Option<Controller>,
+    InProcess(Option<Controller>),
+
+    /// The controller runs in another process, e.g. the daemon, and is already started.
+    #[cfg(unix)]
+    Ipc(IpcClient),
+}
+
+pub struct ControllerUiWrapper {
+    transport: ControllerTransport,
     controller_state: Entity<ControllerUiWrapperState>,
 
     layout: Option<ResolvedLayout>,
 
+    /// The roster the trains were loaded from and where to save it.
+    roster: Option<(TrainRoster, PathBuf)>,
+
     command_tx: crossbeam::channel::Sender<UiCommand>,
 
+    /// While set, manual commands override the interlocking.
+    maintenance: bool,
+
     _task: Option<Task<()>>,
 }
 
@@ -240,6 +400,47 @@ impl ControllerUiWrapper {
 
         let controller_state = cx.new(|_| ControllerUiWrapperState::from_controller(&controller));
 
+        Self::with_transport(
+            cx,
+            ControllerTransport::InProcess(Some(controller)),
+            controller_state,
+            event_rx,
+            command_tx,
+        )
+    }
+
+    /// Attach to a controller running in another process, that serves the ui on `socket_path`.
+    /// The state of the controller arrives shortly after connecting.
+    #[cfg(unix)]
+    pub fn connect(
+        cx: &mut App,
+        track: Track,
+        socket_path: impl Into<PathBuf>,
+    ) -> std::io::Result<Self> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (command_tx, command_rx) = crossbeam::channel::unbounded();
+
+        let client = IpcClient::connect(socket_path, event_tx, command_rx)?;
+
+        let controller_state =
+            cx.new(|_| ControllerUiWrapperState::from_snapshot(track, UiSnapshot::default()));
+
+        Ok(Self::with_transport(
+            cx,
+            ControllerTransport::Ipc(client),
+            controller_state,
+            event_rx,
+            command_tx,
+        ))
+    }
+
+    fn with_transport(
+        cx: &mut App,
+        transport: ControllerTransport,
+        controller_state: Entity<ControllerUiWrapperState>,
+        event_rx: mpsc::Receiver<UiEvent>,
+        command_tx: crossbeam::channel::Sender<UiCommand>,
+    ) -> Self {
         let _task = cx.spawn({
             let controller_state = controller_state.clone();
 
@@ -257,10 +458,12 @@ impl ControllerUiWrapper {
         });
 
         Self {
-            controller: Some(controller),
+            transport,
             command_tx,
             controller_state,
             layout: None,
+            roster: None,
+            maintenance: false,
             _task: Some(_task),
         }
     }
@@ -270,26 +473,92 @@ impl ControllerUiWrapper {
         self
     }
 
+    pub fn with_roster(mut self, roster: TrainRoster, path: impl Into<PathBuf>) -> Self {
+        self.roster = Some((roster, path.into()));
+        self
+    }
+
     pub fn can_start(cx: &App) -> bool {
-        cx.global::<Self>().controller.is_some()
+        cx.global::<Self>().controller().is_some()
     }
 
     pub fn start(cx: &mut App) {
         cx.update_global(|this: &mut Self, _| {
-            let controller = this
-                .controller
-                .take()
-                .expect("controller should be present. Please only call start() once");
+            let controller = match &mut this.transport {
+                ControllerTransport::InProcess(controller) => controller.take(),
+                #[cfg(unix)]
+                ControllerTransport::Ipc(_) => None,
+            }
+            .expect("controller should be present. Please only call start() once");
 
             std::thread::spawn(move || controller.start());
         });
     }
 
+    /// Whether there is a snapshot of a previous run, that can be restored before starting.
+    pub fn can_restore(cx: &App) -> bool {
+        cx.global::<Self>().controller().is_some_and(|controller| {
+            controller
+                .snapshot_config()
+                .is_some_and(|config| config.path.exists())
+        })
+    }
+
+    /// Continue where the previous run left off. The controller reconciles the
+    /// restored trains with the hardware, once it is started.
+    pub fn restore_snapshot(cx: &mut App) -> Result<(), ControllerSnapshotError> {
+        cx.update_global(|this: &mut Self, _| {
+            let ControllerTransport::InProcess(Some(controller)) = &mut this.transport else {
+                return Ok(());
+            };
+            let Some(config) = controller.snapshot_config() else {
+                return Ok(());
+            };
+
+            let snapshot = ControllerSnapshot::load(&config.path)?;
+            controller.restore(snapshot)
+        })
+    }
+
+    /// The controller, until it is started. Controllers in another process are never available.
+    fn controller(&self) -> Option<&Controller> {
+        match &self.transport {
+            ControllerTransport::InProcess(controller) => controller.as_ref(),
+            #[cfg(unix)]
+            ControllerTransport::Ipc(_) => None,
+        }
+    }
+
+    /// Whether the controller is still reachable. It always is, if it runs in this process.
+    pub fn is_connected(cx: &App) -> bool {
+        match &cx.global::<Self>().transport {
+            ControllerTransport::InProcess(_) => true,
+            #[cfg(unix)]
+            ControllerTransport::Ipc(client) => client.is_connected(),
+        }
+    }
+
     pub fn exec(command: impl Into<UiCommand>, cx: &App) {
-        let command = command.into();
+        let mut command = command.into();
+
+        if Self::is_maintenance(cx) {
+            command = UiCommand::Override {
+                command: Box::new(command),
+                reason: "maintenance".to_string(),
+            };
+        }
+
         let _ = cx.global::<Self>().command_tx.send(command);
     }
 
+    pub fn is_maintenance(cx: &App) -> bool {
+        cx.global::<Self>().maintenance
+    }
+
+    pub fn set_maintenance(maintenance: bool, cx: &mut App) {
+        cx.update_global(|this: &mut Self, _| this.maintenance = maintenance);
+    }
+
     pub fn state(cx: &App) -> &Entity<ControllerUiWrapperState> {
         &cx.global::<Self>().controller_state
     }
@@ -297,6 +566,31 @@ impl ControllerUiWrapper {
     pub fn layout(cx: &App) -> Option<&ResolvedLayout> {
         cx.global::<Self>().layout.as_ref()
     }
+
+    pub fn has_roster(cx: &App) -> bool {
+        cx.global::<Self>().roster.is_some()
+    }
+
+    /// Save the current data of all trains back to the roster file.
+    pub fn save_roster(cx: &mut App) -> Result<(), TrainRosterError> {
+        let trains = Self::state(cx)
+            .read(cx)
+            .trains()
+            .map(|(train_id, train)| (train_id, train.data.clone()))
+            .collect::<Vec<_>>();
+
+        cx.update_global(|this: &mut Self, _| {
+            let Some((roster, path)) = this.roster.as_mut() else {
+                return Ok(());
+            };
+
+            for (train_id, data) in trains {
+                roster.set_data(train_id, data);
+            }
+
+            roster.save(path.as_path())
+        })
+    }
 }
 
 impl Global for ControllerUiWrapper {}
@@ -1,6 +1,10 @@
 use std::collections::VecDeque;
 
-use liketrain_core::{TrainId, hardware::event::HardwareSectionPower};
+use liketrain_core::{
+    TrainId,
+    hardware::event::{HardwareSectionPolarity, HardwareSectionPower},
+    ui::UiSectionSnapshot,
+};
 
 #[derive(Default, Copy, Clone)]
 pub enum UiSectionOccupant {
@@ -39,9 +43,22 @@ impl From<Option<TrainId>> for UiSectionOccupant {
 #[derive(Default)]
 pub struct UiSectionState {
     pub power: HardwareSectionPower,
+    pub polarity: HardwareSectionPolarity,
 
     pub occupant: UiSectionOccupant,
 
     pub reserved_by: Option<TrainId>,
     pub queue: VecDeque<TrainId>,
 }
+
+impl From<UiSectionSnapshot> for UiSectionState {
+    fn from(snapshot: UiSectionSnapshot) -> Self {
+        Self {
+            power: snapshot.power,
+            polarity: snapshot.polarity,
+            occupant: snapshot.occupant.into(),
+            reserved_by: snapshot.reserved_by,
+            queue: snapshot.queue.into(),
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use liketrain_core::{Route, SectionId, Train, TrainData, TrainSpeed, TrainState};
+use std::time;
+
+use liketrain_core::{
+    Direction, Route, SectionId, Train, TrainData, TrainPositionEstimate, TrainSpeed, TrainState,
+};
 
 #[derive(Debug, Clone)]
 pub struct UiTrain {
@@ -6,9 +10,19 @@ pub struct UiTrain {
     pub route: Option<Route>,
 
     pub current_section: Option<SectionId>,
+    pub entered_section_at: Option<time::Instant>,
+
+    /// The estimated position within the current section and when it was received.
+    pub position: Option<(TrainPositionEstimate, time::Instant)>,
 
     pub speed: TrainSpeed,
     pub state: TrainState,
+
+    /// The train didn't reach its next section in time.
+    pub overdue: bool,
+
+    /// The direction the front of the train is facing, relative to its current section.
+    pub orientation: Direction,
 }
 
 impl From<&Train> for UiTrain {
@@ -18,7 +32,11 @@ impl From<&Train> for UiTrain {
             route: train.route().cloned(),
             speed: train.speed(),
             state: train.state(),
-            current_section: None,
+            overdue: false,
+            orientation: train.orientation(),
+            current_section: train.get_current_section(),
+            entered_section_at: None,
+            position: None,
         }
     }
 }
@@ -1,7 +1,12 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    time::{self, Duration},
+};
+
+use chrono::Timelike;
 
 use gpui::{
-    Context, Div, Element, FontWeight, InteractiveElement, IntoElement, ParentElement, Pixels,
+    App, Context, Div, Element, FontWeight, InteractiveElement, IntoElement, ParentElement, Pixels,
     Render, ScrollHandle, Size, StatefulInteractiveElement, Styled, Task, Window, div,
     prelude::FluentBuilder, px, size,
 };
@@ -10,8 +15,8 @@ mod theme;
 use gpui_component::{StyledExt, h_flex};
 use itertools::Itertools;
 use liketrain_core::{
-    Direction, Route, SectionEnd, SectionTransition, SwitchId, Track, TrackSectionWaypointType,
-    TrainId,
+    Direction, Route, SectionEnd, SectionId, SectionTransition, SwitchId, Track,
+    TrackSectionWaypointType, TrainId,
 };
 pub use theme::*;
 
@@ -101,6 +106,9 @@ pub struct Ebula {
     /// The precalculated entries. Sorted by offset.
     entries: EbulaEntries,
 
+    /// The meter offset at which each section of the route starts.
+    section_offsets: Vec<(SectionId, f32)>,
+
     content_scroll_handle: ScrollHandle,
 
     _task: Task<()>,
@@ -136,19 +144,22 @@ impl Ebula {
         let track = controller_state.track();
         let train = controller_state.train(train_id).unwrap();
 
-        let entries = Self::calculate_entries(track, train.route.as_ref().unwrap());
+        let (entries, section_offsets) =
+            Self::calculate_entries(track, train.route.as_ref().unwrap());
 
         Self {
             train_id,
             entries,
+            section_offsets,
             theme,
             content_scroll_handle,
             _task,
         }
     }
 
-    fn calculate_entries(track: &Track, route: &Route) -> EbulaEntries {
+    fn calculate_entries(track: &Track, route: &Route) -> (EbulaEntries, Vec<(SectionId, f32)>) {
         let mut entries = EbulaEntries::default();
+        let mut section_offsets = Vec::new();
         let mut current_meter_offset = 0_f32;
 
         let mut current_section_direction = route.starting_direction();
@@ -157,6 +168,8 @@ impl Ebula {
                 continue;
             };
 
+            section_offsets.push((section_id, current_meter_offset));
+
             for waypoint in section_geo.waypoints.iter() {
                 let waypoint_offset = match current_section_direction {
                     Direction::Forward => waypoint.at_meter,
@@ -188,7 +201,69 @@ impl Ebula {
             }
         }
 
-        entries
+        (entries, section_offsets)
+    }
+
+    /// Where the train is on the route and how fast it is going.
+    /// This is used to estimate the arrival times, based on the speed calibration of the train.
+    ///
+    /// The position estimate of the controller is used, if there is one. Otherwise the train is
+    /// assumed to have driven with its current speed since it entered the section.
+    fn train_progress(&self, cx: &App) -> Option<EbulaTrainProgress> {
+        let controller_state = ControllerUiWrapper::state(cx).read(cx);
+        let train = controller_state.train(self.train_id)?;
+
+        let current_section = train.current_section?;
+        let speed = train.data.estimate_speed(train.speed)?;
+
+        let (_, section_offset) = self
+            .section_offsets
+            .iter()
+            .find(|(section_id, _)| *section_id == current_section)?;
+
+        let (distance, measured_at) = match train.position {
+            Some((estimate, received_at)) if estimate.section_id == current_section => {
+                (estimate.distance, received_at)
+            }
+            _ => (0.0, train.entered_section_at?),
+        };
+
+        let since_measured = chrono::Duration::from_std(measured_at.elapsed()).ok()?;
+
+        Some(EbulaTrainProgress {
+            offset: section_offset + distance,
+            measured_at: chrono::Local::now() - since_measured,
+            speed,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct EbulaTrainProgress {
+    /// The meter offset on the route, the train was at `measured_at`
+    offset: f32,
+    measured_at: chrono::DateTime<chrono::Local>,
+
+    /// The speed of the train in meters per second
+    speed: f32,
+}
+
+impl EbulaTrainProgress {
+    /// The estimated time of arrival at the given meter offset.
+    fn arrival_at(&self, m: f32) -> Option<(u32, u32, u32)> {
+        if m < self.offset || self.speed <= 0.0 {
+            return None;
+        }
+
+        let travel_time = time::Duration::try_from_secs_f32((m - self.offset) / self.speed).ok()?;
+        let arrival = self.measured_at + chrono::Duration::from_std(travel_time).ok()?;
+
+        Some((arrival.hour(), arrival.minute(), arrival.second()))
+    }
+
+    /// Whether the train is currently within the given EBuLa offset.
+    fn is_at(&self, offset: EbulaOffset) -> bool {
+        EbulaOffset::from_m(self.offset) == offset
     }
 }
 
@@ -334,8 +409,10 @@ impl Ebula {
     pub fn render_content(
         &mut self,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> impl IntoElement {
+        let train_progress = self.train_progress(cx);
+
         /*let min_offset = self.entries.keys().copied().min().unwrap_or_default();
         let max_offset = self.entries.keys().copied().max().unwrap_or_default();*/
         let (min_offset, max_offset) = self.entries.offset_range().unwrap_or_default();
@@ -376,7 +453,13 @@ impl Ebula {
                                     .flex()
                                     .items_center()
                                     .child(self.m_field(offset.m(), BorderSide::Right).w_24()) // km
-                                    .child(div().w_3()) // the line ?
+                                    .child(
+                                        div().w_3().h_full().when(
+                                            train_progress
+                                                .is_some_and(|progress| progress.is_at(offset)),
+                                            |this| this.bg(self.theme.foreground),
+                                        ),
+                                    ) // the current position of the train
                                     .child(
                                         div()
                                             .h_full()
@@ -450,7 +533,17 @@ impl Ebula {
                                             )
                                             .px_2(),
                                     ) // name
-                                    .child(self.time_field(None, BorderSide::Right).w_20()) // time of arrival
+                                    .child(
+                                        self.time_field(
+                                            train_progress
+                                                .filter(|_| !entries.is_empty())
+                                                .and_then(|progress| {
+                                                    progress.arrival_at(offset.m())
+                                                }),
+                                            BorderSide::Right,
+                                        )
+                                        .w_20(),
+                                    ) // time of arrival
                                     .child(self.time_field(None, BorderSide::None).w_20()) // time of departure
                             }))
                             .child(
@@ -2,9 +2,13 @@ use std::{collections::HashMap, f32};
 
 use gpui::{
     Bounds, Context, InteractiveElement, MouseDownEvent, MouseMoveEvent, ParentElement,
-    PathBuilder, Pixels, Point, Render, ScrollWheelEvent, Styled, Window, canvas, div, point, px,
+    PathBuilder, Pixels, Point, Render, ScrollWheelEvent, Styled, Subscription, Window, canvas,
+    div, point, px,
+};
+use liketrain_core::{
+    Direction, SectionId, SwitchId, Track, TrainPositionEstimate,
+    ui::{UiEvent, UiTrainEvent},
 };
-use liketrain_core::{SectionId, SwitchId, Track};
 use serde::{Deserialize, Serialize};
 use vek::Vec2;
 
@@ -174,6 +178,39 @@ impl LayoutSection {
     fn to(&self) -> Point<Pixels> {
         self.geometries.last().map(|g| g.to).unwrap_or(self.from)
     }
+
+    /// The point at the given progress (0.0 to 1.0) from the start to the end of the section.
+    /// Arcs are approximated by their chords.
+    fn point_at(&self, progress: f32) -> Point<Pixels> {
+        let chords = self
+            .geometries
+            .iter()
+            .scan(self.from, |from, geo| {
+                let chord = (*from, geo.to);
+                *from = geo.to;
+                Some(chord)
+            })
+            .collect::<Vec<_>>();
+
+        let total_length = chords
+            .iter()
+            .map(|(from, to)| (*to - *from).magnitude() as f32)
+            .sum::<f32>();
+
+        let mut remaining = total_length * progress.clamp(0.0, 1.0);
+        for (from, to) in chords {
+            let length = (to - from).magnitude() as f32;
+            if remaining <= length && length > 0.0 {
+                let from = Vec2::from_point(from);
+                let to = Vec2::from_point(to);
+                return (from + (to - from) * (remaining / length)).to_point();
+            }
+
+            remaining -= length;
+        }
+
+        self.to()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -231,11 +268,38 @@ impl ResolvedLayout {
         }
     }
 
-    pub fn renderer(self, _cx: &mut Context<LayoutRenderer>) -> LayoutRenderer {
+    /// Where the train is on the layout, based on the position estimate of the controller.
+    fn train_point(&self, estimate: &TrainPositionEstimate) -> Option<Point<Pixels>> {
+        let section = self.layout.sections.get(&estimate.section_id)?;
+
+        let progress = match estimate.direction {
+            Direction::Forward => estimate.progress(),
+            Direction::Backward => 1.0 - estimate.progress(),
+        };
+
+        Some(section.point_at(progress))
+    }
+
+    pub fn renderer(self, cx: &mut Context<LayoutRenderer>) -> LayoutRenderer {
+        // redraw the trains, whenever they moved
+        let _subscriptions =
+            vec![
+                cx.subscribe(&ControllerUiWrapper::state(cx).clone(), |_, _, evt, cx| {
+                    if let UiEvent::UiTrainEvent(
+                        UiTrainEvent::PositionEstimated { .. }
+                        | UiTrainEvent::EnteredSection { .. },
+                    ) = evt
+                    {
+                        cx.notify();
+                    }
+                }),
+            ];
+
         LayoutRenderer {
             layout: self,
             camera: LayoutCamera::new(Bounds::default()),
             last_mouse_pos: None,
+            _subscriptions,
         }
     }
 }
@@ -255,6 +319,8 @@ pub struct LayoutRenderer {
     last_mouse_pos: Option<Point<Pixels>>,
 
     camera: LayoutCamera,
+
+    _subscriptions: Vec<Subscription>,
 }
 
 impl Render for LayoutRenderer {
@@ -263,6 +329,17 @@ impl Render for LayoutRenderer {
         _: &mut gpui::Window,
         cx: &mut gpui::Context<Self>,
     ) -> impl gpui::IntoElement {
+        let train_points = ControllerUiWrapper::state(cx)
+            .read(cx)
+            .trains()
+            .filter_map(|(_, train)| {
+                let (estimate, _) = train.position.as_ref()?;
+                let point = self.layout.train_point(estimate)?;
+
+                Some((train.data.name.clone(), point))
+            })
+            .collect::<Vec<_>>();
+
         div()
             .size_full()
             .relative()
@@ -357,5 +434,24 @@ impl Render for LayoutRenderer {
                         }),
                 ),
             )
+            .child(div().absolute().top_0().left_0().size_full().children(
+                train_points.into_iter().map(|(name, point)| {
+                    let point = self.camera.project(point);
+
+                    let container_rounding = self.camera.scale(px(1.0));
+                    let text_size = self.camera.scale(px(2.0));
+
+                    div()
+                        .absolute()
+                        .top(point.y)
+                        .left(point.x)
+                        .px(container_rounding)
+                        .bg(gpui::red())
+                        .text_color(gpui::white())
+                        .rounded(container_rounding)
+                        .text_size(text_size)
+                        .child(name)
+                }),
+            ))
     }
 }
@@ -2,7 +2,7 @@ use gpui::{App, Bounds, UpdateGlobal, WindowBounds, WindowOptions, prelude::*, p
 use gpui_component::{Root, Theme, ThemeRegistry};
 use itertools::Itertools;
 use liketrain_core::{
-    ControllerConfig, Direction, Route, TrackGeometry, Train, TrainId,
+    ControllerConfig, DispatcherConfig, ScriptConfig, SnapshotConfig, TrackGeometry, TrainRoster,
     comm::{SerialControllerHardwareCommunication, SimHardwareCommunication, SimTrain},
     parser::Parser,
 };
@@ -31,6 +31,18 @@ fn init_logger() {
 fn main() {
     init_logger();
 
+    // attach to a controller running in another process, e.g. `liketrain-ui --connect liketrain.sock`
+    let connect = std::env::args().skip_while(|arg| arg != "--connect").nth(1);
+
+    // send the trains to random destinations, e.g. `liketrain-ui --dispatcher resources/dispatcher.json`
+    let dispatcher = std::env::args()
+        .skip_while(|arg| arg != "--dispatcher")
+        .nth(1)
+        .map(|path| {
+            DispatcherConfig::load(&path)
+                .unwrap_or_else(|err| panic!("failed to load {}: {}", path, err))
+        });
+
     let track_dsl = include_str!("../../../resources/track.ltt");
     let track_geo = include_str!("../../../resources/geo.json");
 
@@ -49,32 +61,50 @@ fn main() {
 
     log::info!("layout: {:#?}", resolved_layout);
 
-    let test_route = Route::new(
-        "RE5",
-        [12_usize, 14, 16, 9, 10, 12],
-        Direction::Backward,
-        &track,
-    )
-    .unwrap();
-
-    let test_sim_train = SimTrain::from_route(&test_route, &track, 8.0);
-    let test_train = Train::from_route("RE5", test_route);
-
-    let controller_config = ControllerConfig {
-        track,
-        trains: [(TrainId::new(1), test_train.clone())]
-            .into_iter()
-            .collect(),
-    };
+    let roster_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../resources/roster.json");
+    let roster = TrainRoster::load(roster_path).unwrap();
+
+    let mut controller_config = ControllerConfig::from_roster(track, &roster).unwrap();
+    controller_config.record_calibration = true;
+    controller_config.dispatcher = dispatcher;
+    controller_config.snapshot = Some(SnapshotConfig::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../resources/snapshot.json"
+    )));
+    controller_config.scripts = Some(ScriptConfig::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../resources/scripts"
+    )));
+
+    let sim_trains = controller_config
+        .trains
+        .values()
+        .filter_map(|train| SimTrain::from_train(train, &controller_config.track))
+        .collect::<Vec<_>>();
 
     // let hardware_comm = SerialControllerHardwareCommunication::new("/dev/cu.usbmodem11401", 115200);
-    let hardware_comm = SimHardwareCommunication::new([test_sim_train]);
+    let hardware_comm = SimHardwareCommunication::new(sim_trains);
 
     gpui_platform::application()
         .with_assets(assets::Assets)
         .run(move |cx: &mut App| {
-            let controller = ControllerUiWrapper::new(cx, controller_config, hardware_comm)
-                .with_layout(resolved_layout);
+            let controller = match connect {
+                #[cfg(unix)]
+                Some(socket_path) => {
+                    let track = controller_config.track.clone();
+                    ControllerUiWrapper::connect(cx, track, &socket_path).unwrap_or_else(|err| {
+                        panic!(
+                            "failed to connect to the controller at {}: {}",
+                            socket_path, err
+                        )
+                    })
+                }
+                #[cfg(not(unix))]
+                Some(_) => panic!("attaching to another process is only supported on unix"),
+                None => ControllerUiWrapper::new(cx, controller_config, hardware_comm),
+            }
+            .with_layout(resolved_layout)
+            .with_roster(roster, roster_path);
 
             cx.set_global(controller);
 
@@ -1,10 +1,14 @@
-use gpui::{Context, EventEmitter, FocusHandle, Focusable, ParentElement, Render, Styled};
+use gpui::{
+    Context, EventEmitter, FocusHandle, Focusable, ParentElement, Render, Styled, Subscription,
+    prelude::FluentBuilder,
+};
 use gpui_component::{
-    Disableable, IconName,
+    ActiveTheme, Disableable, IconName,
     button::{Button, ButtonVariant, ButtonVariants},
     dock::{Panel, PanelEvent},
     h_flex,
 };
+use liketrain_core::ui::{UiCommand, UiEvent};
 
 use crate::{
     controller::ControllerUiWrapper, window::controls::panel_type::ControlsWindowPanelType,
@@ -12,12 +16,24 @@ use crate::{
 
 pub struct ControllerPanel {
     focus_handle: FocusHandle,
+
+    _subscriptions: Vec<Subscription>,
 }
 
 impl ControllerPanel {
     pub fn new(cx: &mut Context<Self>) -> Self {
+        let _subscriptions =
+            vec![
+                cx.subscribe(&ControllerUiWrapper::state(cx).clone(), |_, _, evt, cx| {
+                    if let UiEvent::UiControllerEvent(_) = evt {
+                        cx.notify();
+                    }
+                }),
+            ];
+
         Self {
             focus_handle: cx.focus_handle(),
+            _subscriptions,
         }
     }
 }
@@ -28,6 +44,38 @@ impl Render for ControllerPanel {
         _window: &mut gpui::Window,
         cx: &mut Context<Self>,
     ) -> impl gpui::IntoElement {
+        let state = ControllerUiWrapper::state(cx).read(cx);
+        let halted = state.is_halted();
+        let connected = ControllerUiWrapper::is_connected(cx);
+        let last_fault = state.faults().last().map(|fault| fault.to_string());
+        let last_rejection = state
+            .rejections()
+            .last()
+            .map(|violation| format!("Rejected: {}", violation));
+        let maintenance = ControllerUiWrapper::is_maintenance(cx);
+
+        let maintenance_button = Button::new("maintenance")
+            .icon(IconName::TriangleAlert)
+            .label(if maintenance {
+                "Maintenance: on"
+            } else {
+                "Maintenance: off"
+            })
+            .on_click(cx.listener(move |_, _, _, cx| {
+                ControllerUiWrapper::set_maintenance(!maintenance, cx);
+                cx.notify();
+            }));
+        let maintenance_button = if maintenance {
+            maintenance_button.with_variant(ButtonVariant::Danger)
+        } else {
+            maintenance_button
+        };
+        let deadlocks = state
+            .deadlocks()
+            .iter()
+            .map(|deadlock| deadlock.to_string())
+            .collect::<Vec<_>>();
+
         h_flex()
             .size_full()
             .p_2()
@@ -46,6 +94,17 @@ impl Render for ControllerPanel {
                         ControllerUiWrapper::start(cx);
                     }),
             )
+            .child(
+                Button::new("restore")
+                    .icon(IconName::Redo)
+                    .label("Restore snapshot")
+                    .disabled(!ControllerUiWrapper::can_restore(cx))
+                    .on_click(|_, _, cx| {
+                        if let Err(err) = ControllerUiWrapper::restore_snapshot(cx) {
+                            log::error!("failed to restore the snapshot: {}", err);
+                        }
+                    }),
+            )
             .child(
                 Button::new("pause")
                     .icon(IconName::Pause)
@@ -58,6 +117,46 @@ impl Render for ControllerPanel {
                     .with_variant(ButtonVariant::Danger)
                     .label("Stop"),
             )
+            .child(
+                Button::new("emergency-stop")
+                    .disabled(ControllerUiWrapper::can_start(cx) || halted)
+                    .with_variant(ButtonVariant::Danger)
+                    .label("Emergency stop")
+                    .on_click(|_, _, cx| ControllerUiWrapper::exec(UiCommand::EmergencyStop, cx)),
+            )
+            .child(
+                Button::new("resume")
+                    .icon(IconName::Play)
+                    .disabled(!halted)
+                    .label("Resume")
+                    .on_click(|_, _, cx| ControllerUiWrapper::exec(UiCommand::Resume, cx)),
+            )
+            .child(maintenance_button)
+            .when(!connected, |this| {
+                this.child(
+                    h_flex()
+                        .text_color(cx.theme().danger)
+                        .child("Lost the connection to the controller"),
+                )
+            })
+            .when(halted, |this| {
+                this.child(
+                    h_flex()
+                        .text_color(cx.theme().danger)
+                        .child("Halted by emergency stop"),
+                )
+            })
+            .when_some(last_fault, |this, fault| {
+                this.child(h_flex().text_color(cx.theme().warning).child(fault))
+            })
+            .when_some(last_rejection, |this, rejection| {
+                this.child(h_flex().text_color(cx.theme().warning).child(rejection))
+            })
+            .children(
+                deadlocks
+                    .into_iter()
+                    .map(|deadlock| h_flex().text_color(cx.theme().danger).child(deadlock)),
+            )
     }
 }
 
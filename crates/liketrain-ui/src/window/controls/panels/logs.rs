@@ -1,6 +1,6 @@
 use gpui::{
     Context, EventEmitter, FocusHandle, Focusable, InteractiveElement, ParentElement, Render,
-    ScrollHandle, StatefulInteractiveElement, Styled, Subscription, div,
+    ScrollHandle, StatefulInteractiveElement, Styled, Subscription, div, prelude::FluentBuilder,
 };
 use gpui_component::{
     ActiveTheme,
@@ -10,7 +10,8 @@ use gpui_component::{
 };
 
 use crate::{
-    controller::ControllerUiWrapper, window::controls::panel_type::ControlsWindowPanelType,
+    controller::{ControllerUiLogType, ControllerUiWrapper},
+    window::controls::panel_type::ControlsWindowPanelType,
 };
 
 pub struct LogsPanel {
@@ -61,6 +62,10 @@ impl Render for LogsPanel {
                             .p_2()
                             .border_b_1()
                             .border_color(cx.theme().border)
+                            .when(
+                                matches!(log.log_type, ControllerUiLogType::Script),
+                                |this| this.text_color(cx.theme().danger),
+                            )
                             .child(log.message.clone())
                     }),
             )
@@ -40,6 +40,7 @@ impl SectionsPanel {
                         reservation: state.reserved_by,
                         queue: state.queue.iter().copied().collect(),
                         power: state.power,
+                        polarity: state.polarity,
                     },
                 )),
                 window,
@@ -11,7 +11,11 @@ use gpui_component::{
     table::{Column, ColumnSort, TableDelegate},
 };
 use itertools::Itertools;
-use liketrain_core::{SectionId, TrainId, hardware::event::HardwareSectionPower, ui::UiCommand};
+use liketrain_core::{
+    SectionId, TrainId,
+    hardware::event::{HardwareSectionPolarity, HardwareSectionPower},
+    ui::UiCommand,
+};
 
 use crate::controller::{ControllerUiWrapper, UiSectionOccupant};
 
@@ -31,6 +35,7 @@ pub struct SectionsTableData {
     pub queue: Vec<TrainId>,
 
     pub power: HardwareSectionPower,
+    pub polarity: HardwareSectionPolarity,
 }
 
 pub struct SectionsTableDelegate {
@@ -52,6 +57,7 @@ impl SectionsTableDelegate {
                 Column::new("reservation", "Reservation"),
                 Column::new("queue", "Queue"),
                 Column::new("power", "Power"),
+                Column::new("polarity", "Polarity"),
             ],
         }
     }
@@ -65,15 +71,16 @@ impl SectionsTableDelegate {
             return;
         };
 
-        let Some(power) = ControllerUiWrapper::state(cx)
+        let Some((power, polarity)) = ControllerUiWrapper::state(cx)
             .read(cx)
             .section_state(section_id)
-            .map(|state| state.power)
+            .map(|state| (state.power, state.polarity))
         else {
             return;
         };
 
         row.power = power;
+        row.polarity = polarity;
     }
 
     pub fn update_section_occupant(&mut self, section_id: SectionId, cx: &App) {
@@ -260,6 +267,7 @@ impl TableDelegate for SectionsTableDelegate {
                     }
                 })
                 .into_any_element(),
+            "polarity" => format!("{:?}", row.polarity).into_any_element(),
             _ => "todo".to_string().into_any_element(),
         }
     }
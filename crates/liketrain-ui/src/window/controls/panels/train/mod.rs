@@ -1,9 +1,14 @@
 use gpui::{
-    AppContext, Context, Entity, EventEmitter, FocusHandle, Focusable, Render, Subscription, Window,
+    AppContext, Context, Entity, EventEmitter, FocusHandle, Focusable, ParentElement, Render,
+    Styled, Subscription, Window,
 };
 use gpui_component::{
+    Disableable,
+    button::Button,
     dock::{Panel, PanelEvent},
+    h_flex,
     table::TableState,
+    v_flex,
 };
 use liketrain_core::{
     TrainId,
@@ -33,15 +38,11 @@ impl TrainsPanel {
         let table_state = cx.new(|cx| {
             let controller_state = ControllerUiWrapper::state(cx).read(cx);
             TableState::new(
-                TrainsTableDelegate::new(controller_state.trains().map(|(train_id, train)| {
-                    TrainsTableData {
-                        id: train_id,
-                        name: train.data.name.clone().into(),
-                        speed: train.speed,
-                        state: train.state,
-                        current_section: train.current_section,
-                    }
-                })),
+                TrainsTableDelegate::new(
+                    controller_state
+                        .trains()
+                        .map(|(train_id, train)| TrainsTableData::new(train_id, train)),
+                ),
                 window,
                 cx,
             )
@@ -52,10 +53,18 @@ impl TrainsPanel {
             |this, _, evt, cx| match evt {
                 UiEvent::UiTrainEvent(train_event) => match *train_event {
                     UiTrainEvent::EnteredSection { train_id, .. } => {
-                        this.update_current_section(train_id, cx)
+                        this.update_current_section(train_id, cx);
+                        this.update_state(train_id, cx);
                     }
+                    UiTrainEvent::Overdue { train_id, .. } => this.update_state(train_id, cx),
                     UiTrainEvent::SpeedChanged { train_id, .. } => this.update_speed(train_id, cx),
                     UiTrainEvent::StateChanged { train_id, .. } => this.update_state(train_id, cx),
+                    UiTrainEvent::DataChanged { train_id, .. } => this.update_data(train_id, cx),
+                    UiTrainEvent::OrientationChanged { train_id, .. } => {
+                        this.update_orientation(train_id, cx)
+                    }
+                    UiTrainEvent::Added { train_id, .. } => this.add_train(train_id, cx),
+                    UiTrainEvent::Removed { train_id } => this.remove_train(train_id, cx),
                     _ => {}
                 },
                 _ => {}
@@ -69,6 +78,22 @@ impl TrainsPanel {
         }
     }
 
+    fn add_train(&self, train_id: TrainId, cx: &mut Context<Self>) {
+        self.table_state.update(cx, |state, cx| {
+            state.delegate_mut().add_train(train_id, cx);
+            cx.notify();
+        });
+        cx.notify();
+    }
+
+    fn remove_train(&self, train_id: TrainId, cx: &mut Context<Self>) {
+        self.table_state.update(cx, |state, cx| {
+            state.delegate_mut().remove_train(train_id);
+            cx.notify();
+        });
+        cx.notify();
+    }
+
     fn update_current_section(&self, train_id: TrainId, cx: &mut Context<Self>) {
         self.table_state.update(cx, |state, cx| {
             state.delegate_mut().update_current_section(train_id, cx);
@@ -77,6 +102,14 @@ impl TrainsPanel {
         cx.notify();
     }
 
+    fn update_data(&self, train_id: TrainId, cx: &mut Context<Self>) {
+        self.table_state.update(cx, |state, cx| {
+            state.delegate_mut().update_data(train_id, cx);
+            cx.notify();
+        });
+        cx.notify();
+    }
+
     fn update_speed(&self, train_id: TrainId, cx: &mut Context<Self>) {
         self.table_state.update(cx, |state, cx| {
             state.delegate_mut().update_speed(train_id, cx);
@@ -92,15 +125,37 @@ impl TrainsPanel {
         });
         cx.notify();
     }
+
+    fn update_orientation(&self, train_id: TrainId, cx: &mut Context<Self>) {
+        self.table_state.update(cx, |state, cx| {
+            state.delegate_mut().update_orientation(train_id, cx);
+            cx.notify();
+        });
+        cx.notify();
+    }
 }
 
 impl Render for TrainsPanel {
     fn render(
         &mut self,
         _window: &mut gpui::Window,
-        _cx: &mut gpui::Context<Self>,
+        cx: &mut gpui::Context<Self>,
     ) -> impl gpui::IntoElement {
-        self.table_state.clone()
+        v_flex()
+            .size_full()
+            .child(
+                h_flex().p_2().gap_2().child(
+                    Button::new("save-roster")
+                        .label("Save roster")
+                        .disabled(!ControllerUiWrapper::has_roster(cx))
+                        .on_click(|_, _, cx| {
+                            if let Err(err) = ControllerUiWrapper::save_roster(cx) {
+                                log::error!("failed to save the roster: {}", err);
+                            }
+                        }),
+                ),
+            )
+            .child(self.table_state.clone())
     }
 }
 
@@ -1,21 +1,24 @@
-use std::rc::Rc;
+use std::{path::PathBuf, rc::Rc};
 
 use gpui::{
     App, AppContext, Bounds, InteractiveElement, IntoElement, ParentElement, SharedString, Styled,
-    WindowBounds, WindowOptions,
+    WindowBounds, WindowOptions, img, prelude::FluentBuilder,
 };
 use gpui_component::{
-    ActiveTheme, IconName,
+    ActiveTheme, Disableable, IconName,
     button::Button,
     h_flex,
     menu::{DropdownMenu, PopupMenuItem},
     table::{Column, ColumnSort, TableDelegate},
 };
 use itertools::Itertools;
-use liketrain_core::{SectionId, TrainId, TrainSpeed, TrainState, ui::UiCommand};
+use liketrain_core::{
+    Direction, SectionId, TrainCalibration, TrainClass, TrainData, TrainId, TrainSpeed, TrainState,
+    hardware::event::HardwareSectionPower, ui::UiCommand,
+};
 
 use crate::{
-    controller::ControllerUiWrapper,
+    controller::{ControllerUiWrapper, UiTrain},
     ebula::{Ebula, EbulaTheme},
 };
 
@@ -26,15 +29,55 @@ const ALL_TRAIN_SPEEDS: [TrainSpeed; 4] = [
     TrainSpeed::Fast,
 ];
 
+/// How much the length of a train is changed with one click, in meters.
+const LENGTH_STEP: f32 = 10.0;
+
 pub struct TrainsTableData {
     pub id: TrainId,
 
     pub name: SharedString,
+    pub icon: Option<SharedString>,
+
+    pub class: TrainClass,
+    pub max_speed: TrainSpeed,
+
+    /// The length of the train in meters (already in respect to the tracks scale)
+    pub length: f32,
+
+    /// The measured speed at full power in meters per second, if the train is calibrated.
+    pub full_speed: Option<f32>,
 
     pub current_section: Option<SectionId>,
     pub speed: TrainSpeed,
 
     pub state: TrainState,
+
+    /// The train didn't reach its next section in time.
+    pub overdue: bool,
+
+    pub orientation: Direction,
+}
+
+impl TrainsTableData {
+    pub fn new(train_id: TrainId, train: &UiTrain) -> Self {
+        Self {
+            id: train_id,
+            name: train.data.name.clone().into(),
+            icon: train.data.icon.clone().map(Into::into),
+            class: train.data.class,
+            max_speed: train.data.max_speed,
+            length: train.data.length,
+            full_speed: train
+                .data
+                .calibration
+                .estimate_speed(HardwareSectionPower::Full),
+            speed: train.speed,
+            state: train.state,
+            overdue: train.overdue,
+            orientation: train.orientation,
+            current_section: train.current_section,
+        }
+    }
 }
 
 pub struct TrainsTableDelegate {
@@ -53,9 +96,15 @@ impl TrainsTableDelegate {
             columns: vec![
                 Column::new("id", "Id").sortable(),
                 Column::new("name", "Name").sortable(),
+                Column::new("class", "Class"),
+                Column::new("max_speed", "Max. Speed"),
+                Column::new("length", "Length"),
+                Column::new("calibration", "Calibration"),
                 Column::new("section", "Section"),
                 Column::new("state", "State"),
+                Column::new("control", "Control"),
                 Column::new("speed", "Speed"),
+                Column::new("orientation", "Orientation"),
                 Column::new("ebula", "EBuLa"),
             ],
         }
@@ -65,6 +114,23 @@ impl TrainsTableDelegate {
         self.data.iter_mut().find(|data| data.id == train_id)
     }
 
+    /// Add a row for a train, that was placed on the track.
+    pub fn add_train(&mut self, train_id: TrainId, cx: &App) {
+        if self.find_row(train_id).is_some() {
+            return;
+        }
+
+        let Some(train) = ControllerUiWrapper::state(cx).read(cx).train(train_id) else {
+            return;
+        };
+
+        self.data.push(TrainsTableData::new(train_id, train));
+    }
+
+    pub fn remove_train(&mut self, train_id: TrainId) {
+        self.data.retain(|data| data.id != train_id);
+    }
+
     pub fn update_current_section(&mut self, train_id: TrainId, cx: &App) {
         let Some(row) = self.find_row(train_id) else {
             return;
@@ -81,6 +147,45 @@ impl TrainsTableDelegate {
         row.current_section = current_section;
     }
 
+    pub fn update_data(&mut self, train_id: TrainId, cx: &App) {
+        let Some(row) = self.find_row(train_id) else {
+            return;
+        };
+        let Some(data) = ControllerUiWrapper::state(cx)
+            .read(cx)
+            .train(train_id)
+            .map(|train| &train.data)
+        else {
+            return;
+        };
+
+        row.name = data.name.clone().into();
+        row.icon = data.icon.clone().map(SharedString::from);
+        row.class = data.class;
+        row.max_speed = data.max_speed;
+        row.length = data.length;
+        row.full_speed = data.calibration.estimate_speed(HardwareSectionPower::Full);
+    }
+
+    /// Change the data of a train in the controller. The change will be reflected in the table,
+    /// once the controller confirms it.
+    fn update_train_data(train_id: TrainId, update: impl FnOnce(&mut TrainData), cx: &App) {
+        let Some(train) = ControllerUiWrapper::state(cx).read(cx).train(train_id) else {
+            return;
+        };
+
+        let mut data = train.data.clone();
+        update(&mut data);
+
+        ControllerUiWrapper::exec(
+            UiCommand::SetTrainData {
+                train_id,
+                data: Box::new(data),
+            },
+            cx,
+        );
+    }
+
     pub fn update_speed(&mut self, train_id: TrainId, cx: &App) {
         let Some(row) = self.find_row(train_id) else {
             return;
@@ -99,14 +204,29 @@ impl TrainsTableDelegate {
         let Some(row) = self.find_row(train_id) else {
             return;
         };
-        let Some(state) = ControllerUiWrapper::state(cx)
+        let Some((state, overdue)) = ControllerUiWrapper::state(cx)
             .read(cx)
             .train(train_id)
-            .map(|train| train.state)
+            .map(|train| (train.state, train.overdue))
         else {
             return;
         };
         row.state = state;
+        row.overdue = overdue;
+    }
+
+    pub fn update_orientation(&mut self, train_id: TrainId, cx: &App) {
+        let Some(row) = self.find_row(train_id) else {
+            return;
+        };
+        let Some(orientation) = ControllerUiWrapper::state(cx)
+            .read(cx)
+            .train(train_id)
+            .map(|train| train.orientation)
+        else {
+            return;
+        };
+        row.orientation = orientation;
     }
 }
 
@@ -172,7 +292,127 @@ impl TableDelegate for TrainsTableDelegate {
                 .h_full()
                 .child(row.id.to_string())
                 .into_any_element(),
-            "name" => h_flex().h_full().child(row.name.clone()).into_any_element(),
+            "name" => h_flex()
+                .h_full()
+                .gap_2()
+                .when_some(row.icon.clone(), |this, icon| {
+                    this.child(img(PathBuf::from(icon.as_ref())).size_6())
+                })
+                .child(row.name.clone())
+                .into_any_element(),
+            "class" => Button::new("class")
+                .icon(IconName::ChevronDown)
+                .label(row.class.to_string())
+                .dropdown_menu({
+                    let current_class = row.class;
+                    let train_id = row.id;
+
+                    move |mut menu, _, _| {
+                        for class in TrainClass::ALL {
+                            menu = menu.item(PopupMenuItem::Item {
+                                icon: None,
+                                label: class.to_string().into(),
+                                disabled: class == current_class,
+                                checked: class == current_class,
+                                is_link: false,
+                                action: None,
+                                handler: Some(Rc::new(move |_, _, cx| {
+                                    Self::update_train_data(
+                                        train_id,
+                                        |data| data.class = class,
+                                        cx,
+                                    );
+                                })),
+                            });
+                        }
+
+                        menu
+                    }
+                })
+                .into_any_element(),
+            "max_speed" => Button::new("max_speed")
+                .icon(IconName::ChevronDown)
+                .label(format!("{:?}", row.max_speed))
+                .dropdown_menu({
+                    let current_max_speed = row.max_speed;
+                    let train_id = row.id;
+
+                    move |mut menu, _, _| {
+                        for max_speed in ALL_TRAIN_SPEEDS {
+                            menu = menu.item(PopupMenuItem::Item {
+                                icon: None,
+                                label: format!("{:?}", max_speed).into(),
+                                disabled: max_speed == current_max_speed,
+                                checked: max_speed == current_max_speed,
+                                is_link: false,
+                                action: None,
+                                handler: Some(Rc::new(move |_, _, cx| {
+                                    Self::update_train_data(
+                                        train_id,
+                                        |data| data.max_speed = max_speed,
+                                        cx,
+                                    );
+                                })),
+                            });
+                        }
+
+                        menu
+                    }
+                })
+                .into_any_element(),
+            "length" => h_flex()
+                .h_full()
+                .gap_2()
+                .child(
+                    Button::new("shorter")
+                        .icon(IconName::Minus)
+                        .disabled(row.length <= 0.0)
+                        .on_click({
+                            let train_id = row.id;
+
+                            move |_, _, cx| {
+                                Self::update_train_data(
+                                    train_id,
+                                    |data| data.length = (data.length - LENGTH_STEP).max(0.0),
+                                    cx,
+                                );
+                            }
+                        }),
+                )
+                .child(format!("{:.0} m", row.length))
+                .child(Button::new("longer").icon(IconName::Plus).on_click({
+                    let train_id = row.id;
+
+                    move |_, _, cx| {
+                        Self::update_train_data(train_id, |data| data.length += LENGTH_STEP, cx);
+                    }
+                }))
+                .into_any_element(),
+            "calibration" => h_flex()
+                .h_full()
+                .gap_2()
+                .child(
+                    row.full_speed
+                        .map(|speed| format!("{:.1} m/s", speed))
+                        .unwrap_or_else(|| "-".to_string()),
+                )
+                .child(
+                    Button::new("reset-calibration")
+                        .label("Reset")
+                        .disabled(row.full_speed.is_none())
+                        .on_click({
+                            let train_id = row.id;
+
+                            move |_, _, cx| {
+                                Self::update_train_data(
+                                    train_id,
+                                    |data| data.calibration = TrainCalibration::default(),
+                                    cx,
+                                );
+                            }
+                        }),
+                )
+                .into_any_element(),
             "section" => h_flex()
                 .h_full()
                 .child(
@@ -181,12 +421,75 @@ impl TableDelegate for TrainsTableDelegate {
                         .unwrap_or_else(|| "-".to_string()),
                 )
                 .into_any_element(),
+            "state" if row.overdue => h_flex()
+                .h_full()
+                .child("Overdue")
+                .text_color(cx.theme().danger)
+                .into_any_element(),
             "state" => h_flex()
                 .h_full()
                 .child(format!("{:?}", row.state))
                 .text_color(match row.state {
                     TrainState::Default => cx.theme().success,
-                    TrainState::Waiting => cx.theme().warning,
+                    TrainState::Waiting | TrainState::Stopping => cx.theme().warning,
+                    TrainState::Stopped | TrainState::Paused | TrainState::Faulted => {
+                        cx.theme().danger
+                    }
+                })
+                .into_any_element(),
+            "control" => h_flex()
+                .h_full()
+                .gap_2()
+                .child(
+                    Button::new("start")
+                        .icon(IconName::Play)
+                        .disabled(
+                            (!row.state.is_held() && row.state != TrainState::Stopping)
+                                || row.state == TrainState::Faulted,
+                        )
+                        .on_click({
+                            let train_id = row.id;
+
+                            move |_, _, cx| {
+                                ControllerUiWrapper::exec(UiCommand::StartTrain { train_id }, cx);
+                            }
+                        }),
+                )
+                .child(
+                    Button::new("pause")
+                        .icon(IconName::Pause)
+                        .disabled(row.state.is_held())
+                        .on_click({
+                            let train_id = row.id;
+
+                            move |_, _, cx| {
+                                ControllerUiWrapper::exec(UiCommand::PauseTrain { train_id }, cx);
+                            }
+                        }),
+                )
+                .child(
+                    Button::new("stop")
+                        .label("Stop")
+                        .disabled(matches!(
+                            row.state,
+                            TrainState::Stopping | TrainState::Stopped | TrainState::Faulted
+                        ))
+                        .on_click({
+                            let train_id = row.id;
+
+                            move |_, _, cx| {
+                                ControllerUiWrapper::exec(UiCommand::StopTrain { train_id }, cx);
+                            }
+                        }),
+                )
+                .when(row.state == TrainState::Faulted, |this| {
+                    this.child(Button::new("acknowledge").label("Acknowledge").on_click({
+                        let train_id = row.id;
+
+                        move |_, _, cx| {
+                            ControllerUiWrapper::exec(UiCommand::AcknowledgeFault { train_id }, cx);
+                        }
+                    }))
                 })
                 .into_any_element(),
             "speed" => Button::new("speed")
@@ -194,6 +497,7 @@ impl TableDelegate for TrainsTableDelegate {
                 .label(format!("{:?}", row.speed))
                 .dropdown_menu({
                     let current_speed = row.speed;
+                    let max_speed = row.max_speed;
                     let train_id = row.id;
 
                     move |mut menu, _, _| {
@@ -201,7 +505,7 @@ impl TableDelegate for TrainsTableDelegate {
                             menu = menu.item(PopupMenuItem::Item {
                                 icon: None,
                                 label: format!("{:?}", speed).into(),
-                                disabled: speed == current_speed,
+                                disabled: speed == current_speed || speed > max_speed,
                                 checked: speed == current_speed,
                                 is_link: false,
                                 action: None,
@@ -218,6 +522,16 @@ impl TableDelegate for TrainsTableDelegate {
                     }
                 })
                 .into_any_element(),
+            "orientation" => Button::new("orientation")
+                .label(row.orientation.to_string())
+                .on_click({
+                    let train_id = row.id;
+
+                    move |_, _, cx| {
+                        ControllerUiWrapper::exec(UiCommand::ReverseTrain { train_id }, cx);
+                    }
+                })
+                .into_any_element(),
             "ebula" => Button::new("ebula")
                 .icon(IconName::ExternalLink)
                 .label("EBuLa")